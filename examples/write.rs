@@ -39,7 +39,7 @@ fn main() -> Result<()> {
         properties: PublicProperties::new().with_is_msil(true),
         offset: DataRegionOffset::new(0, 0),
         name: StrBuf::new("hello"),
-    });
+    })?;
     let sym_builder = sym_builder.finish_publics();
     sym_builder.add(SymbolRecord::Label {
         code_offset: DataRegionOffset::new(0, 0),
@@ -0,0 +1,57 @@
+#ifndef PDB_SDK_H
+#define PDB_SDK_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* the C ABI of the `ffi` feature, see src/ffi.rs */
+
+typedef struct PdbHandle PdbHandle;
+
+typedef enum PdbStatus {
+    PDB_FOUND = 1,
+    PDB_NOT_FOUND = 0,
+    PDB_FAILED = -1,
+} PdbStatus;
+
+/* names are not NUL-terminated and stay valid until the handle is closed */
+typedef struct PdbSymbol {
+    const uint8_t *name;
+    size_t name_len;
+    uint32_t rva;
+    /* zero when unknown, like for publics */
+    uint32_t size;
+    /* the index of the module in the DBI stream, UINT32_MAX for publics */
+    uint32_t module;
+} PdbSymbol;
+
+/* return false to stop the enumeration */
+typedef bool (*PdbModuleCallback)(void *ctx, uint32_t index, const uint8_t *name, size_t name_len);
+typedef bool (*PdbSymbolCallback)(void *ctx, const PdbSymbol *symbol);
+
+/* null on failure, see pdb_last_error */
+PdbHandle *pdb_open(const char *path);
+PdbHandle *pdb_open_bytes(const uint8_t *data, size_t len);
+void pdb_close(PdbHandle *handle);
+
+/* the message of the last error on the calling thread, or null */
+const char *pdb_last_error(void);
+
+/* guid points to 16 bytes, either pointer may be null */
+PdbStatus pdb_guid(const PdbHandle *handle, uint8_t *guid, uint32_t *age);
+PdbStatus pdb_function_at(const PdbHandle *handle, uint32_t rva, PdbSymbol *out);
+PdbStatus pdb_function_by_name(const PdbHandle *handle, const char *name, PdbSymbol *out);
+PdbStatus pdb_public_at(const PdbHandle *handle, uint32_t rva, PdbSymbol *out);
+PdbStatus pdb_enumerate_modules(const PdbHandle *handle, PdbModuleCallback callback, void *ctx);
+PdbStatus pdb_enumerate_publics(const PdbHandle *handle, PdbSymbolCallback callback, void *ctx);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,118 @@
+use std::ops::Range;
+
+use crate::SymbolOffset;
+
+// what covers a range of RVAs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressKind {
+    // a section contribution of the module at the index in the DBI stream
+    Contribution { module: usize },
+    // a procedure of a module, `symbol` is the position of its record in `Module::symbols`
+    Procedure { module: usize, symbol: usize },
+    // a public of the symbol record stream, it's assumed to run up to the next public of its section
+    Public { offset: SymbolOffset },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AddressRange {
+    pub start: u32,
+    pub end: u32,
+    pub kind: AddressKind,
+}
+
+impl AddressRange {
+    pub fn new(start: u32, end: u32, kind: AddressKind) -> Self {
+        Self { start, end, kind }
+    }
+
+    pub fn contains(&self, rva: u32) -> bool {
+        (self.start..self.end).contains(&rva)
+    }
+}
+
+// an interval tree over the ranges sorted by their start, laid out implicitly with the root of every
+// subrange in its middle, each node keeps the greatest end of its subtree so that lookups skip the
+// subtrees ending before the address, queries take O(log n + k) for k results
+#[derive(Debug, Default)]
+pub struct AddressIndex {
+    ranges: Vec<AddressRange>,
+    max_ends: Vec<u32>,
+}
+
+impl AddressIndex {
+    // empty ranges are dropped
+    pub fn new(mut ranges: Vec<AddressRange>) -> Self {
+        ranges.retain(|range| range.start < range.end);
+        ranges.sort_by_key(|range| (range.start, range.end));
+        let mut max_ends = vec![0; ranges.len()];
+        compute_max_ends(&ranges, &mut max_ends, 0, ranges.len());
+        Self { ranges, max_ends }
+    }
+
+    pub fn ranges(&self) -> &[AddressRange] {
+        &self.ranges
+    }
+
+    // every range covering an RVA, ordered by their start
+    pub fn at(&self, rva: u32) -> Vec<&AddressRange> {
+        self.overlapping(rva..rva.saturating_add(1))
+    }
+
+    // every range sharing at least one RVA with a range, ordered by their start
+    pub fn overlapping(&self, range: Range<u32>) -> Vec<&AddressRange> {
+        let mut res = vec![];
+        if range.start < range.end {
+            self.collect(&range, 0, self.ranges.len(), &mut res);
+        }
+        res
+    }
+
+    // the innermost procedure covering an RVA
+    pub fn procedure_at(&self, rva: u32) -> Option<&AddressRange> {
+        self.innermost(rva, |kind| matches!(kind, AddressKind::Procedure { .. }))
+    }
+
+    pub fn contribution_at(&self, rva: u32) -> Option<&AddressRange> {
+        self.innermost(rva, |kind| matches!(kind, AddressKind::Contribution { .. }))
+    }
+
+    pub fn public_at(&self, rva: u32) -> Option<&AddressRange> {
+        self.innermost(rva, |kind| matches!(kind, AddressKind::Public { .. }))
+    }
+
+    fn innermost(&self, rva: u32, pred: impl Fn(&AddressKind) -> bool) -> Option<&AddressRange> {
+        self.at(rva)
+            .into_iter()
+            .filter(|range| pred(&range.kind))
+            .min_by_key(|range| range.end - range.start)
+    }
+
+    fn collect<'a>(&'a self, range: &Range<u32>, lo: usize, hi: usize, res: &mut Vec<&'a AddressRange>) {
+        if lo >= hi {
+            return;
+        }
+        let mid = lo + (hi - lo) / 2;
+        if self.max_ends[mid] <= range.start {
+            return;
+        }
+        self.collect(range, lo, mid, res);
+        let node = &self.ranges[mid];
+        if node.start < range.end {
+            if node.end > range.start {
+                res.push(node);
+            }
+            self.collect(range, mid + 1, hi, res);
+        }
+    }
+}
+
+fn compute_max_ends(ranges: &[AddressRange], max_ends: &mut [u32], lo: usize, hi: usize) -> u32 {
+    if lo >= hi {
+        return 0;
+    }
+    let mid = lo + (hi - lo) / 2;
+    let left = compute_max_ends(ranges, max_ends, lo, mid);
+    let right = compute_max_ends(ranges, max_ends, mid + 1, hi);
+    max_ends[mid] = ranges[mid].end.max(left).max(right);
+    max_ends[mid]
+}
@@ -0,0 +1,281 @@
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::process::ExitCode;
+
+use pdb_sdk::builders::PdbBuilder;
+use pdb_sdk::codeview::symbols::SymbolRecord;
+use pdb_sdk::diagnostics::ParseOptions;
+use pdb_sdk::diff::{diff, ItemDiff};
+use pdb_sdk::info::PdbInfo;
+use pdb_sdk::msf::StreamIndex;
+use pdb_sdk::result::{Error, Result};
+use pdb_sdk::{Guid, PdbFile};
+
+const USAGE: &str = "usage: pdb-sdk <command> [args]
+
+commands:
+  info <pdb>                                 print the headers and the streams of a file
+  dump <pdb> <part>                          print the records of a part of a file, one of
+                                             types, ids, symbols, publics, modules, sections
+  diff <old> <new>                           compare the types, symbols, modules and streams
+  strip <pdb> <output>                       write a copy with only the publics and sections
+  extract-stream <pdb> <stream> [output]     write the contents of a stream to a file or stdout
+  inject-stream <pdb> <stream> <input>       replace the contents of a stream, or add a named one
+
+streams are given by their index or their name";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let res = match args[..] {
+        ["info", path] => info(path),
+        ["dump", path, part] => dump(path, part),
+        ["diff", old, new] => diff_files(old, new),
+        ["strip", path, output] => strip(path, output),
+        ["extract-stream", path, stream] => extract_stream(path, stream, None),
+        ["extract-stream", path, stream, output] => extract_stream(path, stream, Some(output)),
+        ["inject-stream", path, stream, input] => inject_stream(path, stream, input),
+        _ => {
+            eprintln!("{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
+    match res {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("error: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn open(path: &str) -> Result<PdbFile<File>> {
+    PdbFile::open_with_options(File::open(path)?, ParseOptions::lenient())
+}
+
+fn info(path: &str) -> Result<()> {
+    let mut pdb = open(path)?;
+    let mut out = io::stdout().lock();
+    let msf = pdb.msf_info();
+    let info = pdb.get_info()?;
+    let header = info.header();
+    writeln!(out, "version:    {:?}", header.version)?;
+    writeln!(out, "guid:       {}", format_guid(&header.guid))?;
+    writeln!(out, "age:        {}", header.age)?;
+    writeln!(out, "signature:  {:#010x}", header.signature)?;
+    writeln!(out, "features:   {:?}", info.features())?;
+    writeln!(out, "block size: {}", msf.block_size)?;
+    writeln!(out, "blocks:     {}", msf.num_blocks)?;
+
+    let dbi = pdb.get_dbi()?;
+    writeln!(out, "machine:    {:?}", dbi.header().machine_type)?;
+    writeln!(out, "modules:    {}", dbi.modules().len())?;
+    writeln!(out, "types:      {}", pdb.get_raw_tpi()?.record_count())?;
+    writeln!(out, "ids:        {}", pdb.get_raw_ipi()?.record_count())?;
+
+    writeln!(out, "streams:")?;
+    for stream in pdb.get_size_stats(0)?.streams {
+        let bytes = stream.bytes.map_or("nil".to_owned(), |bytes| bytes.to_string());
+        let name = stream.name.unwrap_or_default();
+        writeln!(out, "  {:>5} {:>12} {}", stream.index, bytes, name)?;
+    }
+    report(&mut pdb);
+    Ok(())
+}
+
+fn dump(path: &str, part: &str) -> Result<()> {
+    let mut pdb = open(path)?;
+    let mut out = io::stdout().lock();
+    match part {
+        "types" => {
+            for (index, record) in pdb.get_tpi()?.iter() {
+                writeln!(out, "{index:#x} {record:?}")?;
+            }
+        }
+        "ids" => {
+            for (index, record) in pdb.get_ipi()?.iter() {
+                writeln!(out, "{index:#x} {record:?}")?;
+            }
+        }
+        "symbols" | "publics" => {
+            let dbi = pdb.get_dbi()?;
+            for (offset, record) in pdb.get_symbols(&dbi)?.into_records() {
+                if part == "symbols" || matches!(record, SymbolRecord::Public32(_)) {
+                    writeln!(out, "{offset:?} {record:?}")?;
+                }
+            }
+        }
+        "modules" => {
+            let dbi = pdb.get_dbi()?;
+            for module in dbi.modules() {
+                writeln!(
+                    out,
+                    "{} ({})",
+                    module.module_name.as_ref(),
+                    module.obj_file_name.as_ref()
+                )?;
+                let Ok(symbols) = pdb.iter_module_symbols(module) else {
+                    continue;
+                };
+                for res in symbols {
+                    match res {
+                        Ok((offset, record)) => writeln!(out, "  {offset:?} {record:?}")?,
+                        Err(err) => writeln!(out, "  error: {err}")?,
+                    }
+                }
+            }
+        }
+        "sections" => {
+            let dbi = pdb.get_dbi()?;
+            for (index, header) in (1..).zip(pdb.get_section_headers(&dbi)?.headers()) {
+                writeln!(out, "{index} {header:?}")?;
+            }
+        }
+        _ => {
+            return Err(Error::UnsupportedFeature(
+                "parts other than types, ids, symbols, publics, modules and sections",
+            ))
+        }
+    }
+    report(&mut pdb);
+    Ok(())
+}
+
+fn diff_files(old: &str, new: &str) -> Result<()> {
+    let res = diff(&mut open(old)?, &mut open(new)?)?;
+    let mut out = io::stdout().lock();
+    print_items(&mut out, "types", &res.types, |typ| {
+        format!("{:?} {}", typ.kind, typ.name)
+    })?;
+    print_items(&mut out, "symbols", &res.symbols, |symbol| symbol.name.clone())?;
+    print_items(&mut out, "modules", &res.modules, |module| module.name.clone())?;
+    if !res.streams.is_empty() {
+        writeln!(out, "streams:")?;
+        for stream in &res.streams {
+            writeln!(out, "  {:+12} {}", stream.delta(), stream.name)?;
+        }
+    }
+    Ok(())
+}
+
+fn print_items<A>(
+    out: &mut impl Write,
+    title: &str,
+    items: &ItemDiff<A>,
+    name: impl Fn(&A) -> String,
+) -> io::Result<()> {
+    if items.is_empty() {
+        return Ok(());
+    }
+    writeln!(out, "{title}:")?;
+    for item in &items.added {
+        writeln!(out, "  + {}", name(item))?;
+    }
+    for item in &items.removed {
+        writeln!(out, "  - {}", name(item))?;
+    }
+    for change in &items.changed {
+        writeln!(out, "  ~ {}", name(&change.new))?;
+    }
+    Ok(())
+}
+
+// keeps what a debugger needs to match the file to its image and to name addresses, like the public
+// PDBs linkers write with /PDBSTRIPPED
+fn strip(path: &str, output: &str) -> Result<()> {
+    let mut pdb = open(path)?;
+    let info = pdb.get_info()?;
+    let dbi = pdb.get_dbi()?;
+
+    let mut builder = PdbBuilder::default();
+    builder
+        .info()
+        .signature(info.header().signature)
+        .age(info.header().age)
+        .guid(Guid::new(*info.header().guid.bytes()));
+    builder.dbi().machine_type(dbi.header().machine_type);
+    match pdb.get_section_headers(&dbi) {
+        Ok(headers) => {
+            for header in headers.headers() {
+                builder.dbi().add_section_header(header.clone());
+            }
+        }
+        Err(Error::StreamNotFound(_)) => {}
+        Err(err) => return Err(err),
+    }
+    for entry in &dbi.sec_map().entries {
+        builder.dbi().add_section_entry(entry.clone());
+    }
+    for (_, record) in pdb.get_symbols(&dbi)?.into_records() {
+        if let SymbolRecord::Public32(public) = record {
+            builder.dbi().symbols().add(public)?;
+        }
+    }
+    builder.commit(File::create(output)?)
+}
+
+fn extract_stream(path: &str, stream: &str, output: Option<&str>) -> Result<()> {
+    let mut pdb = open(path)?;
+    let info = pdb.get_info()?;
+    let index = stream_index(&info, stream)?;
+    let data = pdb.get_stream_data(index)?;
+    match output {
+        Some(output) => fs::write(output, data)?,
+        None => io::stdout().lock().write_all(&data)?,
+    }
+    Ok(())
+}
+
+// existing streams are replaced in place, while adding a named stream rebuilds the file
+fn inject_stream(path: &str, stream: &str, input: &str) -> Result<()> {
+    let data = fs::read(input)?;
+    let file = File::options().read(true).write(true).open(path)?;
+    let mut pdb = PdbFile::open_with_options(file, ParseOptions::lenient())?;
+    let info = pdb.get_info()?;
+    match stream_index(&info, stream) {
+        Ok(index) => pdb.replace_stream(index, &data),
+        Err(Error::StreamNotFound(_)) if stream.parse::<u16>().is_err() => {
+            let mut builder = PdbBuilder::from_pdb(&mut pdb)?;
+            builder.add_named_stream(stream, data);
+            let mut output = vec![];
+            builder.commit(io::Cursor::new(&mut output))?;
+            drop(pdb);
+            fs::write(path, output)?;
+            Ok(())
+        }
+        Err(err) => Err(err),
+    }
+}
+
+fn stream_index(info: &PdbInfo, stream: &str) -> Result<StreamIndex> {
+    match stream.parse::<u16>() {
+        Ok(index) => Ok(index.into()),
+        Err(_) => info
+            .named_streams()
+            .get(stream)
+            .ok_or(Error::StreamNotFound("named")),
+    }
+}
+
+fn format_guid(guid: &Guid) -> String {
+    let b = guid.bytes();
+    let hex = |bytes: &[u8]| bytes.iter().map(|byte| format!("{byte:02X}")).collect::<String>();
+    format!(
+        "{:08X}-{:04X}-{:04X}-{}-{}",
+        u32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+        u16::from_le_bytes([b[4], b[5]]),
+        u16::from_le_bytes([b[6], b[7]]),
+        hex(&b[8..10]),
+        hex(&b[10..])
+    )
+}
+
+// records that failed to decode are left out of the output, they're listed at the end
+fn report<R: io::Read + io::Seek>(pdb: &mut PdbFile<R>) {
+    for diagnostic in pdb.take_diagnostics() {
+        eprintln!(
+            "warning: {} at {:#x}: {}",
+            diagnostic.stream, diagnostic.offset, diagnostic.error
+        );
+    }
+}
@@ -0,0 +1,488 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+
+use crate::codeview::types::{BuiltinType, TypeRecord};
+use crate::dbi::MachineType;
+use crate::layout::builtin_size;
+use crate::result::{Error, Result};
+use crate::types::{TpiStream, FIRST_NON_BUILTIN_TYPE};
+use crate::{Integer, TypeIndex};
+
+const KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "dyn", "else", "enum", "extern", "false", "fn",
+    "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref", "return",
+    "static", "struct", "trait", "true", "type", "union", "unsafe", "use", "where", "while", "abstract",
+    "become", "box", "do", "final", "gen", "macro", "override", "priv", "try", "typeof", "unsized",
+    "virtual", "yield",
+];
+
+// renders classes, structs, unions and enums of the TPI as Rust definitions, the types they contain are
+// rendered along with them, structs and unions are `#[repr(C)]` with explicit padding and come with
+// assertions on their size and field offsets, so a layout Rust doesn't reproduce fails to compile
+#[derive(Debug)]
+pub struct BindingGenerator<'a> {
+    tpi: &'a TpiStream,
+    machine: MachineType,
+    follow_pointers: bool,
+    queue: Vec<TypeIndex>,
+    queued: HashSet<u32>,
+    names: HashMap<u32, String>,
+    used_names: HashSet<String>,
+    alignments: HashMap<u32, u64>,
+}
+
+#[derive(Debug)]
+struct Layout {
+    is_union: bool,
+    members: Vec<Member>,
+    // members sharing their storage with an earlier one, which Rust structs can't express
+    skipped: Vec<String>,
+    size: u64,
+    align: u64,
+    is_packed: bool,
+}
+
+#[derive(Debug)]
+struct Member {
+    offset: u64,
+    name: String,
+    ty: String,
+    comment: Option<String>,
+}
+
+impl<'a> BindingGenerator<'a> {
+    pub fn new(tpi: &'a TpiStream, machine: MachineType) -> Self {
+        Self {
+            tpi,
+            machine,
+            follow_pointers: false,
+            queue: vec![],
+            queued: HashSet::new(),
+            names: HashMap::new(),
+            used_names: HashSet::new(),
+            alignments: HashMap::new(),
+        }
+    }
+
+    // renders the types behind pointers as well, otherwise pointers to types that aren't rendered
+    // anyway point to `c_void`
+    pub fn follow_pointers(&mut self, follow: bool) -> &mut Self {
+        self.follow_pointers = follow;
+        self
+    }
+
+    // selects the definition of the class, struct, union or enum with the name
+    pub fn add_type(&mut self, name: &str) -> Result<&mut Self> {
+        let index = self
+            .tpi
+            .iter()
+            .find(|(_, record)| is_definition(record) && record.name() == Some(name))
+            .and_then(|(index, _)| TypeIndex::try_from(index).ok())
+            .ok_or_else(|| Error::TypeNotFound(name.to_owned()))?;
+        self.enqueue(index);
+        Ok(self)
+    }
+
+    pub fn generate(mut self) -> Result<String> {
+        let mut out = String::new();
+        let mut next = 0;
+        while let Some(&index) = self.queue.get(next) {
+            next += 1;
+            let name = self.rust_name(index);
+            match self.tpi.record(index) {
+                Some(TypeRecord::Enum(enum_)) => {
+                    let underlying = self.rust_type(enum_.underlying_type, true)?;
+                    self.render_enum(&mut out, &name, &underlying, enum_.field_list);
+                }
+                _ => {
+                    let layout = self.layout(index)?;
+                    render_layout(&mut out, &name, &layout);
+                }
+            }
+        }
+        Ok(out)
+    }
+
+    fn enqueue(&mut self, index: TypeIndex) {
+        if self.queued.insert(index.into()) {
+            self.queue.push(index);
+        }
+    }
+
+    fn layout(&mut self, index: TypeIndex) -> Result<Layout> {
+        let (is_union, is_packed, field_list, size) = match self.tpi.record(index) {
+            Some(TypeRecord::Class(class) | TypeRecord::Struct(class) | TypeRecord::Interface(class)) => {
+                (false, class.properties.is_packed(), class.field_list, &class.size)
+            }
+            Some(TypeRecord::Union(union)) => {
+                (true, union.properties.is_packed(), union.field_list, &union.size)
+            }
+            _ => {
+                return Err(Error::UnsupportedFeature(
+                    "bindings of types other than classes, structs, unions and enums",
+                ))
+            }
+        };
+        let size = size.as_u64().ok_or(Error::UnsupportedFeature(
+            "user-defined types with a negative size",
+        ))?;
+
+        // (offset, name, type, alignment, size, comment)
+        let mut fields = vec![];
+        let tpi = self.tpi;
+        let list = field_list.map(|list| tpi.fields(list)).unwrap_or_default();
+        for (i, field) in list.into_iter().enumerate() {
+            let (offset, name, field_type) = match field {
+                TypeRecord::BaseClass(base) => (&base.offset, format!("base_{i}"), base.base_type),
+                TypeRecord::DataMember {
+                    field_type: Some(field_type),
+                    offset,
+                    name,
+                    ..
+                } if !matches!(tpi.record(*field_type), Some(TypeRecord::BitField { .. })) => {
+                    (offset, name.as_ref().to_owned(), *field_type)
+                }
+                _ => continue,
+            };
+            let Some(offset) = offset.as_u64() else {
+                continue;
+            };
+            let field_size = self.size_of(field_type)?;
+            let ty = self.rust_type(field_type, true)?;
+            let align = self.align_of(field_type)?;
+            fields.push((offset, name, ty, align, field_size, None));
+        }
+        for (i, unit) in tpi.bit_field_units(index).into_iter().enumerate() {
+            let comment = unit
+                .fields
+                .iter()
+                .map(|field| {
+                    let end = field.bit_offset + field.bit_width;
+                    format!("{}: bits {}..{end}", field.name, field.bit_offset)
+                })
+                .collect::<Vec<_>>()
+                .join(", ");
+            let ty = self.rust_type(unit.underlying_type, true)?;
+            let align = self.align_of(unit.underlying_type)?;
+            let field_size = self.size_of(unit.underlying_type)?;
+            fields.push((
+                unit.byte_offset,
+                format!("_bitfield_{i}"),
+                ty,
+                align,
+                field_size,
+                Some(comment),
+            ));
+        }
+        fields.sort_by_key(|field| field.0);
+
+        let mut layout = Layout {
+            is_union,
+            members: vec![],
+            skipped: vec![],
+            size,
+            align: 1,
+            is_packed,
+        };
+        let mut member_names = HashSet::new();
+        let mut end = 0;
+        for (offset, name, ty, align, field_size, comment) in fields {
+            if !is_union && offset < end {
+                layout.skipped.push(name);
+                continue;
+            }
+            if offset > end && !is_union {
+                layout.members.push(Member {
+                    offset: end,
+                    name: format!("_pad_{end:x}"),
+                    ty: format!("[u8; {}]", offset - end),
+                    comment: None,
+                });
+            }
+            layout.is_packed |= offset % align != 0;
+            layout.align = layout.align.max(align);
+            let mut name = identifier(&name);
+            while !member_names.insert(name.clone()) {
+                name.push('_');
+            }
+            layout.members.push(Member {
+                offset,
+                name,
+                ty,
+                comment,
+            });
+            end = end.max(offset + field_size);
+        }
+        if end > size {
+            return Err(Error::UnsupportedFeature("members past the end of their type"));
+        }
+        if end < size {
+            layout.members.push(Member {
+                offset: if is_union { 0 } else { end },
+                name: if is_union {
+                    "_size".to_owned()
+                } else {
+                    format!("_pad_{end:x}")
+                },
+                ty: format!("[u8; {}]", if is_union { size } else { size - end }),
+                comment: None,
+            });
+        }
+        // Rust rounds the size up to the alignment like C does, unless the type was packed
+        layout.is_packed |= size % layout.align != 0;
+        if layout.is_packed {
+            layout.align = 1;
+        }
+        Ok(layout)
+    }
+
+    fn render_enum(&self, out: &mut String, name: &str, underlying: &str, field_list: TypeIndex) {
+        // a newtype rather than an enum, memory can hold values that have no enumerator
+        let _ = writeln!(out, "#[repr(transparent)]");
+        let _ = writeln!(out, "#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]");
+        let _ = writeln!(out, "pub struct {name}(pub {underlying});\n");
+        let _ = writeln!(out, "impl {name} {{");
+        let mut names = HashSet::new();
+        for field in self.tpi.fields(field_list) {
+            let TypeRecord::Enumerator {
+                value, name: variant, ..
+            } = field
+            else {
+                continue;
+            };
+            let mut variant = identifier(variant.as_ref());
+            while !names.insert(variant.clone()) {
+                variant.push('_');
+            }
+            let value = match *value {
+                Integer::I16(i) if i < 0 => format!("{i}_i64 as {underlying}"),
+                Integer::I32(i) if i < 0 => format!("{i}_i64 as {underlying}"),
+                Integer::I64(i) if i < 0 => format!("{i}_i64 as {underlying}"),
+                ref value => value.as_u64().unwrap_or_default().to_string(),
+            };
+            let _ = writeln!(out, "    pub const {variant}: Self = Self({value});");
+        }
+        let _ = writeln!(out, "}}\n");
+    }
+
+    // `by_value` types are rendered along with the type containing them
+    fn rust_type(&mut self, index: TypeIndex, by_value: bool) -> Result<String> {
+        let raw = u32::from(index);
+        if raw < FIRST_NON_BUILTIN_TYPE {
+            if (raw >> 8) & 0xF != 0 {
+                let pointee = TypeIndex::try_from(raw & 0xFF)
+                    .ok()
+                    .and_then(|pointee| BuiltinType::try_from(pointee).ok())
+                    .and_then(builtin_rust_type)
+                    .unwrap_or("core::ffi::c_void");
+                return Ok(format!("*mut {pointee}"));
+            }
+            return Ok(match BuiltinType::try_from(index) {
+                Ok(BuiltinType::Void) => "core::ffi::c_void".to_owned(),
+                Ok(builtin) => builtin_rust_type(builtin)
+                    .map(str::to_owned)
+                    .or_else(|| Some(format!("[u8; {}]", builtin.size()?)))
+                    .ok_or_else(invalid_type)?,
+                Err(_) => return Err(invalid_type()),
+            });
+        }
+
+        let index = self.tpi.resolve_forward_ref(index);
+        let Some(record) = self.tpi.record(index) else {
+            return Err(invalid_type());
+        };
+        match record {
+            TypeRecord::Modifier { modified_type, .. } => self.rust_type(*modified_type, by_value),
+            TypeRecord::Pointer {
+                referent, properties, ..
+            } if !properties.mode().is_member_pointer() => {
+                let (referent, is_const) = match self.tpi.record(*referent) {
+                    Some(TypeRecord::Modifier {
+                        modified_type,
+                        properties,
+                    }) => (*modified_type, properties.is_const()),
+                    _ => (*referent, false),
+                };
+                let pointee = self.rust_type(referent, false)?;
+                Ok(format!("*{} {pointee}", if is_const { "const" } else { "mut" }))
+            }
+            TypeRecord::Array {
+                element_type,
+                dimensions,
+                ..
+            } => {
+                let size = dimensions
+                    .first()
+                    .and_then(Integer::as_u64)
+                    .ok_or_else(invalid_type)?;
+                let element_size = self.size_of(*element_type)?;
+                if element_size == 0 || size % element_size != 0 {
+                    return Ok(format!("[u8; {size}]"));
+                }
+                let element = self.rust_type(*element_type, true)?;
+                Ok(format!("[{element}; {}]", size / element_size))
+            }
+            record if is_definition(record) => {
+                if by_value || self.follow_pointers {
+                    self.enqueue(index);
+                } else if !self.queued.contains(&u32::from(index)) {
+                    return Ok("core::ffi::c_void".to_owned());
+                }
+                Ok(self.rust_name(index))
+            }
+            // pointees without a definition, like functions or forward references
+            _ if !by_value => Ok("core::ffi::c_void".to_owned()),
+            // member pointers and whatever else has a size but no Rust equivalent
+            _ => Ok(format!("[u8; {}]", self.size_of(index)?)),
+        }
+    }
+
+    fn size_of(&self, index: TypeIndex) -> Result<u64> {
+        self.tpi.type_size(index, self.machine).ok_or_else(invalid_type)
+    }
+
+    // the alignment of the rendered type, which is 1 for opaque byte arrays standing in for others
+    fn align_of(&mut self, index: TypeIndex) -> Result<u64> {
+        let raw = u32::from(index);
+        if raw < FIRST_NON_BUILTIN_TYPE {
+            let size = builtin_size(index).ok_or_else(invalid_type)?;
+            let is_opaque =
+                BuiltinType::try_from(index).is_ok_and(|builtin| builtin_rust_type(builtin).is_none());
+            return Ok(if is_opaque { 1 } else { size });
+        }
+        let index = self.tpi.resolve_forward_ref(index);
+        if let Some(&align) = self.alignments.get(&u32::from(index)) {
+            return Ok(align);
+        }
+        let align = match self.tpi.record(index).ok_or_else(invalid_type)? {
+            TypeRecord::Modifier { modified_type, .. } => self.align_of(*modified_type)?,
+            TypeRecord::Enum(enum_) => self.align_of(enum_.underlying_type)?,
+            TypeRecord::Pointer { properties, .. } if !properties.mode().is_member_pointer() => {
+                self.size_of(index)?
+            }
+            TypeRecord::Array { element_type, .. } => {
+                let rendered = self.rust_type(index, true)?;
+                if rendered.starts_with("[u8;") {
+                    1
+                } else {
+                    self.align_of(*element_type)?
+                }
+            }
+            record if is_definition(record) => self.layout(index)?.align,
+            _ => 1,
+        };
+        self.alignments.insert(index.into(), align);
+        Ok(align)
+    }
+
+    fn rust_name(&mut self, index: TypeIndex) -> String {
+        if let Some(name) = self.names.get(&u32::from(index)) {
+            return name.clone();
+        }
+        let name = match self.tpi.record(index).and_then(TypeRecord::name) {
+            Some(name) if !name.starts_with('<') && !name.contains("<unnamed") => {
+                // paths and template arguments collapse into single underscores
+                let name: Vec<_> = name
+                    .split(|c: char| !c.is_ascii_alphanumeric())
+                    .filter(|part| !part.is_empty())
+                    .collect();
+                identifier(&name.join("_"))
+            }
+            _ => format!("Anonymous_{:x}", u32::from(index)),
+        };
+        let mut unique = name.clone();
+        let mut suffix = 1;
+        while !self.used_names.insert(unique.clone()) {
+            suffix += 1;
+            unique = format!("{name}_{suffix}");
+        }
+        self.names.insert(index.into(), unique.clone());
+        unique
+    }
+}
+
+fn render_layout(out: &mut String, name: &str, layout: &Layout) {
+    let repr = if layout.is_packed { "C, packed" } else { "C" };
+    let kind = if layout.is_union { "union" } else { "struct" };
+    let _ = writeln!(out, "#[repr({repr})]");
+    let _ = writeln!(out, "#[derive(Clone, Copy)]");
+    let _ = writeln!(out, "pub {kind} {name} {{");
+    for member in &layout.members {
+        if let Some(comment) = &member.comment {
+            let _ = writeln!(out, "    // {comment}");
+        }
+        let _ = writeln!(out, "    pub {}: {},", member.name, member.ty);
+    }
+    for skipped in &layout.skipped {
+        let _ = writeln!(out, "    // {skipped} overlaps an earlier member");
+    }
+    let _ = writeln!(out, "}}\n");
+
+    let _ = writeln!(
+        out,
+        "const _: () = assert!(core::mem::size_of::<{name}>() == {});",
+        layout.size
+    );
+    if !layout.is_union {
+        for member in &layout.members {
+            let _ = writeln!(
+                out,
+                "const _: () = assert!(core::mem::offset_of!({name}, {}) == {});",
+                member.name, member.offset
+            );
+        }
+    }
+    out.push('\n');
+}
+
+fn is_definition(record: &TypeRecord) -> bool {
+    match record {
+        TypeRecord::Class(class) | TypeRecord::Struct(class) | TypeRecord::Interface(class) => {
+            !class.properties.is_forward_ref()
+        }
+        TypeRecord::Union(union) => !union.properties.is_forward_ref(),
+        TypeRecord::Enum(enum_) => !enum_.properties.is_forward_ref(),
+        _ => false,
+    }
+}
+
+fn builtin_rust_type(builtin: BuiltinType) -> Option<&'static str> {
+    use BuiltinType::*;
+    let ty = match builtin {
+        SignedChar | NarrowChar | I8 => "i8",
+        UnsignedChar | Char8 | U8 | Bool8 => "u8",
+        I16Short | I16 => "i16",
+        WideChar | Char16 | U16Short | U16 | Bool16 => "u16",
+        HResult | I32Long | I32 => "i32",
+        Char32 | U32Long | U32 | Bool32 => "u32",
+        I64Quad | I64 => "i64",
+        U64Quad | U64 | Bool64 => "u64",
+        I128Oct | I128 => "i128",
+        U128Oct | U128 | Bool128 => "u128",
+        F32 | F32PartialPrecision => "f32",
+        F64 => "f64",
+        _ => return None,
+    };
+    Some(ty)
+}
+
+fn identifier(name: &str) -> String {
+    let mut res: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if res.is_empty() || res.starts_with(|c: char| c.is_ascii_digit()) {
+        res.insert(0, '_');
+    }
+    match res.as_str() {
+        // the keywords that can't be raw identifiers
+        "self" | "Self" | "super" | "crate" | "_" => res.push('_'),
+        keyword if KEYWORDS.contains(&keyword) => res.insert_str(0, "r#"),
+        _ => {}
+    }
+    res
+}
+
+fn invalid_type() -> Error {
+    Error::UnsupportedFeature("types without a known size")
+}
@@ -1,24 +1,38 @@
-use std::collections::BTreeMap;
-use std::io::{self, Write};
+use std::borrow::{Borrow, Cow};
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Seek, Write};
+use std::marker::PhantomData;
+use std::sync::Arc;
 
 use declio::{Encode, EncodedSize};
 
-use crate::codeview::symbols::{Public, SymbolRecord};
+use crate::codeview::symbols::{
+    CompileProperties, Data, DefRangeRegisterRelProperties, FrameCookie, LocalProperties, LocalVariableGap, LocalVariableRange, Procedure, Public, PublicProperties, SourceLanguage, SymbolRecord, ThunkOrdinal, TrampolineType, Version
+};
 use crate::codeview::types::{IdRecord, TypeRecord};
-use crate::codeview::{PrefixedRecord, RECORD_ALIGNMENT};
+use crate::codeview::{
+    encode_prefixed, DataRegionOffset, IndexKind, PrefixedRecord, Register, RECORD_ALIGNMENT
+};
 use crate::dbi::*;
+use crate::diagnostics::Diagnostic;
 use crate::hash::{hash_v1, Table};
-use crate::info::{PdbFeature, PdbInfoHeader, PdbVersion};
-use crate::module::{DebugSubsectionEntry, Module};
+use crate::info::{PdbFeature, PdbInfoHeader, PdbVersion, SRCSRV_STREAM};
+use crate::metrics::{Metrics, Stopwatch};
+use crate::module::{
+    DebugSubsectionEntry, DebugSubsectionRecord, DebugSubsectionRecordType, Module, ModuleLayout
+};
 use crate::msf::*;
 use crate::publics::Publics;
-use crate::result::Result;
-use crate::strings::StringsBuilder;
+use crate::result::{Error, Result};
+use crate::sourcelink::{SourceLink, SOURCE_LINK_STREAM};
+use crate::strings::{HashVersion, StringsBuilder};
 use crate::symbol_map::Globals;
-use crate::types::{TypeHash, TypeStreamHeader, FIRST_NON_BUILTIN_TYPE, HASH_BUCKET_NUMBER};
-use crate::utils::{align_to, StrBuf};
+use crate::types::{
+    contents_hash, id_record_hash, type_record_key, TypeHash, TypeStreamHeader, FIRST_NON_BUILTIN_TYPE, HASH_BUCKET_NUMBER
+};
+use crate::utils::{align_to, join, trace_event, trace_span, Interner, StrBuf};
 use crate::{
-    codecs, constants, BuiltinStream, Guid, MsfStreamLayout, StreamIndex, SymbolOffset, TypeIndex
+    codecs, constants, injected, BuiltinStream, DbgHeader, Guid, MsfStreamLayout, PdbFile, StreamIndex, StringOffset, SymbolOffset, TypeIndex
 };
 
 #[derive(Debug, Default)]
@@ -27,9 +41,72 @@ pub struct PdbBuilder {
     dbi: DbiBuilder,
     tpi: TpiBuilder,
     ipi: IpiBuilder,
+    named_streams: Vec<(String, Vec<u8>)>,
+    injected_sources: Vec<(String, Vec<u8>)>,
+    names: StringsBuilder,
+    padding: StreamPadding,
+    alignment: u32,
 }
 
 impl PdbBuilder {
+    // loads every stream of a PDB for targeted changes before committing it again, types keep their indices
+    // and symbols are laid out like they were, the strings of the /names stream stay at their offsets
+    pub fn from_pdb<R>(pdb: &mut PdbFile<R>) -> Result<Self>
+    where
+        R: io::Read + io::Seek,
+    {
+        let mut builder = Self::default();
+        let info = pdb.get_info()?;
+        let header = info.header();
+        builder
+            .info
+            .signature(header.signature)
+            .age(header.age)
+            .guid(header.guid.clone());
+
+        // the strings keep their offsets, which file checksums and IPI records refer to
+        if info.named_streams().get("/names").is_some() {
+            builder.names = StringsBuilder::from_strings(pdb.get_strings(&info)?);
+        }
+        for source in pdb.get_injected_sources(&info)? {
+            let data = pdb.get_injected_source_data(&info, &source)?;
+            builder.add_source_file(source.name, data);
+        }
+        // the streams of the string table and the injected sources are rebuilt on commit
+        let named_streams: Vec<String> = info
+            .named_streams()
+            .iter()
+            .map(|(name, _)| name)
+            .filter(|&name| {
+                name != "/names"
+                    && name != injected::HEADER_BLOCK_STREAM
+                    && !name.starts_with(injected::FILES_PREFIX)
+            })
+            .map(str::to_owned)
+            .collect();
+        for name in named_streams {
+            let data = pdb.get_named_stream(&info, &name)?;
+            builder.add_named_stream(name, data);
+        }
+
+        for raw in pdb.get_raw_tpi()?.records() {
+            builder.tpi.insert("", &raw.decode_as::<TypeRecord>()?)?;
+        }
+        match pdb.get_raw_ipi() {
+            Ok(ipi) => {
+                for raw in ipi.records() {
+                    builder.ipi().insert("", &raw.decode_as::<IdRecord>()?)?;
+                }
+            }
+            // files older than VC 14 have no IPI stream
+            Err(Error::StreamNotFound(_)) => {}
+            Err(err) => return Err(err),
+        }
+
+        builder.dbi.load(pdb)?;
+        Ok(builder)
+    }
+
     pub fn info(&mut self) -> &mut InfoBuilder {
         &mut self.info
     }
@@ -46,21 +123,139 @@ impl PdbBuilder {
         &mut self.ipi
     }
 
-    pub fn commit<S>(self, mut sink: S) -> Result<()>
+    // carries over streams like /TMCache that the builder does not produce itself
+    pub fn add_named_stream<S: Into<String>>(&mut self, name: S, data: Vec<u8>) -> &mut Self {
+        let name = name.into();
+        self.named_streams.retain(|(existing, _)| *existing != name);
+        self.named_streams.push((name, data));
+        self
+    }
+
+    // the source indexing script, equivalent to `pdbstr -w -s:srcsrv`
+    pub fn srcsrv<S: Into<String>>(&mut self, script: S) -> &mut Self {
+        self.add_named_stream(SRCSRV_STREAM, script.into().into_bytes())
+    }
+
+    pub fn source_link(&mut self, source_link: &SourceLink) -> &mut Self {
+        self.add_named_stream(SOURCE_LINK_STREAM, source_link.to_json().into_bytes())
+    }
+
+    // stored under `/src/files/`, debuggers match it by name against the file checksums of modules
+    pub fn add_source_file<S: Into<String>>(&mut self, name: S, data: Vec<u8>) -> &mut Self {
+        let name = name.into();
+        self.injected_sources
+            .retain(|(existing, _)| !existing.eq_ignore_ascii_case(&name));
+        self.injected_sources.push((name, data));
+        self
+    }
+
+    // the hash used by the /names stream, V1 unless set otherwise
+    pub fn names_hash_version(&mut self, version: HashVersion) -> &mut Self {
+        self.names.set_hash_version(version);
+        self
+    }
+
+    // adds a string to the /names stream, e.g. the path of a source file referenced by file checksums
+    pub fn add_name(&mut self, name: &str) -> Result<StringOffset> {
+        self.names.add(name)
+    }
+
+    // embeds a natvis visualizer, equivalent to the `/NATVIS` linker option
+    pub fn add_natvis<S: Into<String>>(&mut self, name: S, xml: &str) -> &mut Self {
+        self.add_source_file(name, xml.as_bytes().to_vec())
+    }
+
+    // zeros unless told otherwise, sparse padding speeds up writing files with thousands of streams
+    pub fn padding(&mut self, padding: StreamPadding) -> &mut Self {
+        self.padding = padding;
+        self
+    }
+
+    // the number of blocks the first block of every stream is aligned to, one unless told otherwise,
+    // the blocks skipped in between are marked free
+    pub fn stream_alignment(&mut self, blocks: u32) -> &mut Self {
+        self.alignment = blocks;
+        self
+    }
+
+    pub fn commit<S>(self, sink: S) -> Result<()>
     where
         S: io::Write + io::Seek,
     {
+        self.commit_with_metrics(sink)?;
+        Ok(())
+    }
+
+    // like `commit`, but returns the bytes written to the streams and the time spent in every phase
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    pub fn commit_with_metrics<S>(mut self, sink: S) -> Result<Metrics>
+    where
+        S: io::Write + io::Seek,
+    {
+        let mut metrics = Metrics::default();
+        let start = Stopwatch::start();
+        let mut sink = MsfSink::new(sink, self.padding, self.alignment)?;
         let mut allocator = StreamAllocator::default();
-        // superblock
-        sink.write_all(EMPTY_BLOCK)?;
-        // initial FPMs
-        sink.write_all(EMPTY_BLOCK)?;
-        sink.write_all(EMPTY_BLOCK)?;
+        // superblock and initial FPMs
+        sink.pad(3 * u64::from(DEFAULT_BLOCK_SIZE))?;
+
+        let mut names = std::mem::take(&mut self.names);
+        if !self.injected_sources.is_empty() {
+            for (name, data) in
+                injected::build_streams(std::mem::take(&mut self.injected_sources), &mut names)?
+            {
+                self.add_named_stream(name, data);
+            }
+        }
+        self.tpi.hash_adjusters = self.tpi.ambiguous_names(&mut names)?;
+        if !names.is_empty() {
+            let mut strings = vec![];
+            names.build().encode((), &mut strings)?;
+            self.add_named_stream("/names", strings);
+        }
+        for (name, data) in self.named_streams {
+            let mut writer = DefaultMsfStreamWriter::new(&mut sink)?;
+            writer.write_all(&data)?;
+            let index = allocator.allocate(writer.finish()?);
+            metrics.add_stream_bytes(&name, data.len() as u64);
+            self.info.named_streams.push((index, name));
+        }
+        metrics.add_phase("named streams", start);
+
+        // the type streams are encoded in memory and only then laid out into blocks in the usual order, with
+        // the `parallel` feature so are all of the module streams, alongside them
+        let start = Stopwatch::start();
+        let (modules, (tpi, ipi)) = {
+            trace_span!("encode");
+            self.dbi.prepare()?;
+            let (tpi, ipi) = (self.tpi, self.ipi);
+            let dbi = &mut self.dbi;
+            join(|| dbi.encode_modules(), || join(|| tpi.encode(), || ipi.encode()))
+        };
 
+        metrics.add_phase("encode", start);
+
+        trace_span!("write");
+        let start = Stopwatch::start();
         let info_layout = self.info.commit(&mut sink)?;
-        let dbi_layout = self.dbi.commit(&mut sink, &mut allocator)?;
-        let tpi_layout = self.tpi.commit(&mut sink, &mut allocator)?;
-        let ipi_layout = self.ipi.commit(&mut sink, &mut allocator)?;
+        let dbi_layout = self.dbi.commit(modules?, &mut sink, &mut allocator)?;
+        let tpi_layout = tpi?.commit(&mut sink, &mut allocator)?;
+        let ipi_layout = ipi?.commit(&mut sink, &mut allocator)?;
+        trace_event!(
+            num_streams = allocator.streams.len(),
+            size = sink.position(),
+            "wrote the streams"
+        );
+        for (name, layout) in [
+            ("PDB", &info_layout),
+            ("DBI", &dbi_layout),
+            ("TPI", &tpi_layout),
+            ("IPI", &ipi_layout),
+        ] {
+            metrics.add_stream_bytes(name, layout.byte_size.into());
+        }
+        metrics.add_phase("write", start);
+        let start = Stopwatch::start();
         allocator.insert_builtin(BuiltinStream::Pdb, info_layout);
         allocator.insert_builtin(BuiltinStream::Dbi, dbi_layout);
         allocator.insert_builtin(BuiltinStream::Tpi, tpi_layout);
@@ -84,14 +279,13 @@ impl PdbBuilder {
         let dir_bytes = dir_layout.byte_size;
         let block_map_addr = addr_map_layout.blocks.first().copied().unwrap();
 
-        allocator.allocate(dir_layout);
-        allocator.allocate(addr_map_layout);
-
-        // stream blocks plus one superblock
-        let allocated_blocks = allocator.block_count() as u32 + 1;
-        // allocated blocks plus FPM blocks
-        let fpm_gap_size = DEFAULT_BLOCK_SIZE - 2;
-        let num_blocks = allocated_blocks + (1 + allocated_blocks / fpm_gap_size) * 2;
+        // every block up to the end of the file is in use, the FPM blocks of the last interval included,
+        // except for the ones skipped to align streams
+        let num_blocks = (sink.position() / u64::from(DEFAULT_BLOCK_SIZE)) as u32;
+        let mut fpm = FreeBlockMap::all_used(num_blocks);
+        for &block in sink.unused_blocks() {
+            fpm.set_free(block, true);
+        }
 
         let superblock = SuperBlock {
             magic: MsfHeader,
@@ -102,12 +296,15 @@ impl PdbBuilder {
             unknown: 0,
             block_map_addr,
         };
-        FreeBlockMap::write(&superblock, &mut sink)?;
+        fpm.write(&superblock, &mut sink)?;
 
         sink.seek(io::SeekFrom::Start(0))?;
         superblock.encode((), &mut sink)?;
+        sink.finish()?;
+        metrics.add_stream_bytes("directory", dir_bytes.into());
+        metrics.add_phase("directory", start);
 
-        Ok(())
+        Ok(metrics)
     }
 }
 
@@ -115,26 +312,90 @@ impl PdbBuilder {
 pub struct DbiBuilder {
     symbols: SymbolsBuilder,
     modules: Vec<ModuleBuilder>,
-    section_contribs: Vec<SectionContrib>,
+    // the source files of the modules
+    interner: Interner,
+    // along with the section index within the object file, only written with `SectionContribVersion::V2`
+    section_contribs: Vec<(SectionContrib, u32)>,
+    section_contrib_version: Option<SectionContribVersion>,
     section_entries: Vec<SectionMapEntry>,
+    section_headers: Vec<SectionHeader>,
     names: StringsBuilder,
     debug_streams: Vec<StreamIndex>,
+    validate_addresses: bool,
+    validate_registers: bool,
+    // x64 unless told otherwise
+    machine_type: Option<MachineType>,
+    dll_version: u16,
+    rbld: u16,
+    mfc_type_server_index: u32,
+    incrementally_linked: bool,
+    type_server_map: Vec<u8>,
+    omap: Option<Omap>,
+    // debug streams carried over as they are from a parsed PDB, by their slot in the DBI stream
+    debug_data: Vec<(usize, Vec<u8>)>,
+    handles: u32,
+}
+
+// a symbol record referenced before it's added, it's bound to the record by the module or the symbols
+// builder that adds it and the references are patched when the DBI stream is committed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SymbolHandle(u32);
+
+#[derive(Debug)]
+struct Omap {
+    to_src: Vec<OmapEntry>,
+    from_src: Vec<OmapEntry>,
+    original_headers: Vec<SectionHeader>,
+}
+
+// code or data that a new layout of the sections moved from one RVA to another
+#[derive(Debug, Clone, Copy)]
+pub struct MovedRange {
+    pub old_rva: u32,
+    pub new_rva: u32,
+    pub len: u32,
 }
 
 impl DbiBuilder {
+    pub fn reserve_symbol(&mut self) -> SymbolHandle {
+        self.handles += 1;
+        SymbolHandle(self.handles)
+    }
+
     pub fn symbols(&mut self) -> PublicsBuilder {
         PublicsBuilder {
             symbols: &mut self.symbols,
         }
     }
 
-    pub fn add_module(&mut self, module: ModuleBuilder) -> &mut Self {
+    pub fn modules(&self) -> &[ModuleBuilder] {
+        &self.modules
+    }
+
+    pub fn add_module(&mut self, mut module: ModuleBuilder) -> &mut Self {
+        module.intern(&mut self.interner);
         self.modules.push(module);
         self
     }
 
     pub fn add_section_contrib(&mut self, section: SectionContrib) -> &mut Self {
-        self.section_contribs.push(section);
+        self.section_contribs.push((section, 0));
+        self
+    }
+
+    // a contribution along with the index of its section within the object file
+    pub fn add_section_contrib_v2(
+        &mut self,
+        section: SectionContrib,
+        coff_section_index: u32,
+    ) -> &mut Self {
+        self.section_contribs.push((section, coff_section_index));
+        self
+    }
+
+    // Ver60 unless set otherwise, V2 entries carry the section index within the object file
+    pub fn section_contrib_version(&mut self, version: SectionContribVersion) -> &mut Self {
+        self.section_contrib_version = Some(version);
         self
     }
 
@@ -143,11 +404,516 @@ impl DbiBuilder {
         self
     }
 
-    fn commit<S>(self, sink: &mut S, allocator: &mut StreamAllocator) -> Result<MsfStreamLayout>
+    pub fn add_section_header(&mut self, header: SectionHeader) -> &mut Self {
+        self.section_headers.push(header);
+        self
+    }
+
+    pub fn section_headers(&self) -> &[SectionHeader] {
+        &self.section_headers
+    }
+
+    // adds the section headers of an image along with a section map describing them
+    pub fn add_sections(&mut self, headers: Vec<SectionHeader>) -> &mut Self {
+        let entry = |flags, frame, sec_byte_length| SectionMapEntry {
+            flags,
+            logical_overlay: 0,
+            group: 0,
+            frame,
+            sec_name: u16::MAX,
+            class_name: u16::MAX,
+            offset: 0,
+            sec_byte_length,
+        };
+        let count = headers.len() as u16;
+        for (frame, header) in (1..).zip(headers) {
+            let characteristics = header.flags();
+            let flags = DescriptorFlags::new()
+                .with_is_readable(characteristics.mem_read())
+                .with_is_writable(characteristics.mem_write())
+                .with_is_executable(characteristics.mem_execute())
+                .with_is_32bit(true)
+                .with_is_selector(true);
+            self.add_section_entry(entry(flags, frame, header.virtual_size));
+            self.add_section_header(header);
+        }
+        // the last entry covers absolute symbols
+        let flags = DescriptorFlags::new().with_is_32bit(true).with_is_absolute(true);
+        self.add_section_entry(entry(flags, count + 1, u32::MAX));
+        self
+    }
+
+    // the version of the mspdb DLL that wrote the file, e.g. the build of the MSVC toolset
+    pub fn dll_version(&mut self, version: u16) -> &mut Self {
+        self.dll_version = version;
+        self
+    }
+
+    pub fn rbld(&mut self, rbld: u16) -> &mut Self {
+        self.rbld = rbld;
+        self
+    }
+
+    pub fn mfc_type_server_index(&mut self, index: u32) -> &mut Self {
+        self.mfc_type_server_index = index;
+        self
+    }
+
+    pub fn incrementally_linked(&mut self, enabled: bool) -> &mut Self {
+        self.incrementally_linked = enabled;
+        self
+    }
+
+    // the type server map substream, carried over as is from the PDB of an incrementally linked binary
+    pub fn type_server_map(&mut self, data: Vec<u8>) -> &mut Self {
+        self.type_server_map = data;
+        self
+    }
+
+    // sets the contents of an optional debug stream by its slot, e.g. `DbgHeader::Xdata as usize`, the
+    // section header and OMAP slots are overwritten by the section headers and OMAP of the builder
+    pub fn debug_stream(&mut self, slot: usize, data: Vec<u8>) -> &mut Self {
+        self.debug_data.retain(|(s, _)| *s != slot);
+        self.debug_data.push((slot, data));
+        self
+    }
+
+    pub fn debug_stream_data(&self, slot: usize) -> Option<&[u8]> {
+        self.debug_data
+            .iter()
+            .find(|(s, _)| *s == slot)
+            .map(|(_, data)| data.as_slice())
+    }
+
+    // renames the publics, globals and module symbols added so far, e.g. to demangle, prefix or anonymize
+    // them, `None` keeps the name, the records are laid out again and the references between them updated
+    pub fn rename_symbols<F>(&mut self, mut rename: F) -> &mut Self
+    where
+        F: FnMut(&str) -> Option<String>,
+    {
+        let module_offsets: Vec<_> = self
+            .modules
+            .iter_mut()
+            .map(|module| module.rename_symbols(&mut rename))
+            .collect();
+        let offsets = self.symbols.rename_symbols(&mut rename, &module_offsets);
+        for module in &mut self.modules {
+            for offset in &mut module.global_refs {
+                *offset = remap_offset(&offsets, *offset);
+            }
+        }
+        self
+    }
+
+    // replaces the sections added so far with a new layout, like after instrumentation moved code around,
+    // addresses outside of the moved ranges keep their RVA and the section map is rebuilt from the headers,
+    // with `emit_omap` the symbols keep their addresses and OMAP streams map between the two layouts instead
+    pub fn relayout_sections(
+        &mut self,
+        headers: Vec<SectionHeader>,
+        moved: &[MovedRange],
+        emit_omap: bool,
+    ) -> Result<&mut Self> {
+        let old_headers = std::mem::take(&mut self.section_headers);
+        self.section_entries.clear();
+        if emit_omap {
+            self.omap = Some(Omap {
+                to_src: omap_entries(
+                    moved
+                        .iter()
+                        .map(|range| (range.new_rva, range.old_rva, range.len)),
+                ),
+                from_src: omap_entries(
+                    moved
+                        .iter()
+                        .map(|range| (range.old_rva, range.new_rva, range.len)),
+                ),
+                original_headers: old_headers,
+            });
+            return Ok(self.add_sections(headers));
+        }
+
+        let relocate = &mut |addr: &mut DataRegionOffset| relocate(addr, &old_headers, &headers, moved);
+        for public in self.symbols.publics.values_mut() {
+            relocate(&mut public.offset);
+        }
+        self.symbols.encoded_globals.clear();
+        for (&offset, symbol) in &mut self.symbols.globals {
+            symbol.remap_addresses(relocate);
+            let mut encoded = vec![];
+            if symbol.encode((), &mut encoded).is_ok() {
+                self.symbols.encoded_globals.insert(encoded, offset);
+            }
+        }
+        let contribs = self
+            .section_contribs
+            .iter_mut()
+            .map(|(contrib, _)| contrib)
+            .chain(self.modules.iter_mut().flat_map(|module| {
+                std::iter::once(&mut module.section_contrib).chain(&mut module.section_contribs)
+            }));
+        for contrib in contribs {
+            let mut addr = DataRegionOffset::new(contrib.offset as u32, contrib.i_sect);
+            relocate(&mut addr);
+            contrib.i_sect = addr.segment;
+            contrib.offset = addr.offset as i32;
+        }
+        for module in &mut self.modules {
+            for symbol in &mut module.symbols {
+                symbol.remap_addresses(relocate);
+            }
+            // line numbers are relative to the start of a fragment, which is expected to move as a whole
+            for entry in &mut module.debug_entries {
+                if !matches!(
+                    entry.record_type,
+                    DebugSubsectionRecordType::Lines | DebugSubsectionRecordType::ILLines
+                ) {
+                    continue;
+                }
+                let mut record = entry.decoded()?;
+                if let DebugSubsectionRecord::Lines { header, .. }
+                | DebugSubsectionRecord::ILLines { header, .. } = &mut record
+                {
+                    relocate(&mut header.reloc);
+                }
+                *entry = record.to_entry()?;
+            }
+        }
+        Ok(self.add_sections(headers))
+    }
+
+    // makes the commit fail when a public or a procedure lies outside of the declared sections
+    pub fn validate_addresses(&mut self, enabled: bool) -> &mut Self {
+        self.validate_addresses = enabled;
+        self
+    }
+
+    fn check_addresses(&self) -> Result<()> {
+        let check = |name: &str, offset: &DataRegionOffset, size: u32| {
+            let header = usize::from(offset.segment)
+                .checked_sub(1)
+                .and_then(|index| self.section_headers.get(index));
+            match header {
+                Some(header)
+                    if offset.offset < header.virtual_size
+                        && u64::from(offset.offset) + u64::from(size) <= u64::from(header.virtual_size) =>
+                {
+                    Ok(())
+                }
+                _ => Err(Error::AddressOutOfSection(name.to_owned())),
+            }
+        };
+        for public in self.symbols.publics.values() {
+            check(public.name.as_ref(), &public.offset, 0)?;
+        }
+        for symbol in self.modules.iter().flat_map(|module| &module.symbols) {
+            match symbol {
+                SymbolRecord::Proc(proc)
+                | SymbolRecord::GlobalProc(proc)
+                | SymbolRecord::ProcId(proc)
+                | SymbolRecord::GlobalProcId(proc) => {
+                    check(proc.name.as_ref(), &proc.code_offset, proc.code_size)?;
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    pub fn machine_type(&mut self, machine: MachineType) -> &mut Self {
+        self.machine_type = Some(machine);
+        self
+    }
+
+    // makes the commit fail when a symbol of a module uses a register or a frame pointer that
+    // the machine doesn't have, e.g. x64 registers in the def ranges of an ARM64 image
+    pub fn validate_registers(&mut self, enabled: bool) -> &mut Self {
+        self.validate_registers = enabled;
+        self
+    }
+
+    fn load<R>(&mut self, pdb: &mut PdbFile<R>) -> Result<()>
+    where
+        R: io::Read + io::Seek,
+    {
+        let dbi = pdb.get_dbi()?;
+        let header = dbi.header();
+        self.dll_version = header.dll_version;
+        self.rbld = header.rbld;
+        self.mfc_type_server_index = header.mfc_type_server_index;
+        self.incrementally_linked = header.flags.is_incrementally_linked();
+        self.machine_type = Some(header.machine_type);
+        self.type_server_map = dbi.type_server_stream().clone();
+        self.section_contrib_version = *dbi.section_contrib_version();
+        // the contributions of the modules are all listed here, including the main ones
+        let coff_section_indices = dbi
+            .coff_section_indices()
+            .iter()
+            .copied()
+            .chain(std::iter::repeat(0));
+        self.section_contribs = dbi
+            .section_contribs()
+            .iter()
+            .cloned()
+            .zip(coff_section_indices)
+            .collect();
+        self.section_entries = dbi.sec_map().entries.clone();
+
+        // every slot is kept, including the empty and custom ones, so that the header reads the same
+        let slots = dbi.dbg_streams().len();
+        self.debug_streams.resize(slots, StreamIndex(u16::MAX));
+        for (slot, &index) in dbi.dbg_streams().iter().enumerate() {
+            if slot == DbgHeader::SectionHdr as usize {
+                continue;
+            }
+            if let Some(mut stream) = pdb.get_indexed_stream(index) {
+                let mut data = vec![];
+                io::Read::read_to_end(&mut stream, &mut data)?;
+                self.debug_data.push((slot, data));
+            }
+        }
+        match pdb.get_section_headers(&dbi) {
+            Ok(headers) => self.section_headers = headers.headers().clone(),
+            Err(Error::StreamNotFound(_)) => {}
+            Err(err) => return Err(err),
+        }
+
+        let globals = pdb.get_globals(&dbi)?;
+        let mut records = vec![];
+        for (offset, symbol) in pdb.get_symbols(&dbi)?.into_records() {
+            if let Some(count) = globals.ref_count(offset).filter(|&count| count > 1) {
+                self.symbols.ref_counts.insert(offset, count);
+            }
+            records.push((offset, symbol));
+        }
+        let offsets = self.symbols.insert_records(records);
+
+        let mut module_offsets = Vec::with_capacity(dbi.modules().len());
+        for (module, files) in dbi.modules().iter().zip(dbi.module_source_files()) {
+            let mut builder = ModuleBuilder::new(
+                module.module_name.as_ref().to_owned(),
+                module.obj_file_name.as_ref().to_owned(),
+                module.header.section_contrib.clone(),
+            );
+            builder
+                .dirty(module.header.flags.is_dirty())
+                .type_server_index(module.header.type_server_index);
+            if let (Some(src_file_name), Some(pdb_file_path)) =
+                (dbi.src_file_name(module), dbi.pdb_file_path(module))
+            {
+                builder.ec_names(src_file_name.to_owned(), pdb_file_path.to_owned());
+            }
+            for file in files {
+                builder.add_source_file(file.to_owned());
+            }
+            module_offsets.push(builder.load(pdb, module)?);
+            for offset in &mut builder.global_refs {
+                *offset = remap_offset(&offsets, *offset);
+            }
+            builder.intern(&mut self.interner);
+            self.modules.push(builder);
+        }
+        for symbol in self.symbols.globals.values_mut() {
+            remap_referent(symbol, &module_offsets);
+        }
+        Ok(())
+    }
+
+    fn check_registers(&self) -> Result<()> {
+        let machine = self.machine_type.unwrap_or(MachineType::Amd64);
+        let invalid = |register: Register| Error::InvalidRegister {
+            register: register.0,
+            machine,
+        };
+        for symbol in self.modules.iter().flat_map(|module| &module.symbols) {
+            match symbol {
+                SymbolRecord::Register { register, .. }
+                | SymbolRecord::DefRangeRegister { register, .. }
+                | SymbolRecord::DefRangeSubfieldRegister { register, .. }
+                    if !register.is_valid_for(machine) =>
+                {
+                    return Err(invalid(*register));
+                }
+                SymbolRecord::DefRangeRegisterRel { register, .. }
+                | SymbolRecord::RegisterRelative { register, .. }
+                    if !register.is_valid_for(machine) || !register.is_base_for(machine) =>
+                {
+                    return Err(invalid(*register));
+                }
+                SymbolRecord::FrameCookie { register, kind, .. } => {
+                    if !register.is_valid_for(machine) || !register.is_base_for(machine) {
+                        return Err(invalid(*register));
+                    }
+                    if matches!(kind, FrameCookie::XorR13) && !matches!(machine, MachineType::Amd64) {
+                        return Err(invalid(Register::R13));
+                    }
+                }
+                SymbolRecord::FrameProcedure { properties, .. }
+                    if matches!(
+                        machine,
+                        MachineType::X86 | MachineType::Amd64 | MachineType::Arm64
+                    ) =>
+                {
+                    let encodings = [
+                        (
+                            properties.encoded_local_base_pointer(),
+                            properties.local_base_pointer(machine),
+                        ),
+                        (
+                            properties.encoded_param_base_pointer(),
+                            properties.param_base_pointer(machine),
+                        ),
+                    ];
+                    for (encoded, decoded) in encodings {
+                        if encoded != 0 && decoded.is_none() {
+                            return Err(Error::UnsupportedFeature(
+                                "frame pointer encodings the machine doesn't define",
+                            ));
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn resolve_handles(&mut self) -> Result<()> {
+        let mut module_symbols = HashMap::new();
+        for (index, module) in self.modules.iter().enumerate() {
+            for &(handle, offset) in &module.bindings {
+                // module indices are 1-based
+                module_symbols.insert(handle, (index as u16 + 1, offset));
+            }
+        }
+        for (offset, handle) in std::mem::take(&mut self.symbols.ref_fixups) {
+            let &(module, referent) = module_symbols
+                .get(&handle)
+                .ok_or(Error::UnboundSymbol(handle.0))?;
+            if let Some(
+                SymbolRecord::ProcedureRef(proc)
+                | SymbolRecord::LocalProcedureRef(proc)
+                | SymbolRecord::DataRef(proc)
+                | SymbolRecord::AnnotationRef(proc),
+            ) = self.symbols.globals.get_mut(&offset)
+            {
+                proc.module = module;
+                proc.referent = referent;
+            }
+        }
+        for module in &mut self.modules {
+            for handle in std::mem::take(&mut module.pending_global_refs) {
+                let &offset = self
+                    .symbols
+                    .bindings
+                    .get(&handle)
+                    .ok_or(Error::UnboundSymbol(handle.0))?;
+                module.global_refs.push(offset);
+            }
+        }
+        Ok(())
+    }
+
+    // settles everything the module streams depend on, after which they can be encoded independently
+    fn prepare(&mut self) -> Result<()> {
+        self.resolve_handles()?;
+        if self.validate_addresses {
+            self.check_addresses()?;
+        }
+        if self.validate_registers {
+            self.check_registers()?;
+        }
+        if self.symbols.order != SymbolOrder::Insertion {
+            let offsets = self.symbols.reorder();
+            for module in &mut self.modules {
+                for offset in &mut module.global_refs {
+                    *offset = remap_offset(&offsets, *offset);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // encoding the module streams up front takes as much memory as all of them together, which is only
+    // worth it when they are encoded in parallel, otherwise each is encoded as it's laid out
+    #[cfg(feature = "parallel")]
+    fn encode_modules(&mut self) -> Result<Option<Vec<EncodedModule>>> {
+        use rayon::prelude::*;
+
+        self.modules
+            .par_iter_mut()
+            .map(ModuleBuilder::encode)
+            .collect::<Result<_>>()
+            .map(Some)
+    }
+
+    #[cfg(not(feature = "parallel"))]
+    fn encode_modules(&mut self) -> Result<Option<Vec<EncodedModule>>> {
+        Ok(None)
+    }
+
+    // `encoded` are the module streams returned by `encode_modules`, if they were encoded up front
+    fn commit<S>(
+        mut self,
+        encoded: Option<Vec<EncodedModule>>,
+        sink: &mut MsfSink<S>,
+        allocator: &mut StreamAllocator,
+    ) -> Result<MsfStreamLayout>
     where
         S: io::Write + io::Seek,
     {
-        let streams = self.symbols.commit(sink, allocator)?;
+        let streams = self.symbols.commit(&self.section_headers, sink, allocator)?;
+        for (slot, data) in std::mem::take(&mut self.debug_data) {
+            // these are written below
+            let regenerated = match slot {
+                s if s == DbgHeader::SectionHdr as usize => !self.section_headers.is_empty(),
+                s if s == DbgHeader::SectionHdrOrig as usize
+                    || s == DbgHeader::OmapToSrc as usize
+                    || s == DbgHeader::OmapFromSrc as usize =>
+                {
+                    self.omap.is_some()
+                }
+                _ => false,
+            };
+            if regenerated {
+                continue;
+            }
+            let mut stream = DefaultMsfStreamWriter::new(sink)?;
+            stream.write_all(&data)?;
+            let index = allocator.allocate(stream.finish()?);
+            let len = self
+                .debug_streams
+                .len()
+                .max(DbgHeader::Max as usize)
+                .max(slot + 1);
+            self.debug_streams.resize(len, StreamIndex(u16::MAX));
+            self.debug_streams[slot] = index;
+        }
+        if !self.section_headers.is_empty() {
+            let mut stream = DefaultMsfStreamWriter::new(sink)?;
+            self.section_headers.encode(((),), &mut stream)?;
+            let index = allocator.allocate(stream.finish()?);
+            let len = self.debug_streams.len().max(DbgHeader::Max as usize);
+            self.debug_streams.resize(len, StreamIndex(u16::MAX));
+            self.debug_streams[DbgHeader::SectionHdr as usize] = index;
+        }
+        if let Some(omap) = self.omap.take() {
+            let mut stream = DefaultMsfStreamWriter::new(sink)?;
+            omap.original_headers.encode(((),), &mut stream)?;
+            let original_headers = allocator.allocate(stream.finish()?);
+            let mut stream = DefaultMsfStreamWriter::new(sink)?;
+            omap.to_src.encode(((),), &mut stream)?;
+            let to_src = allocator.allocate(stream.finish()?);
+            let mut stream = DefaultMsfStreamWriter::new(sink)?;
+            omap.from_src.encode(((),), &mut stream)?;
+            let from_src = allocator.allocate(stream.finish()?);
+
+            let len = self.debug_streams.len().max(DbgHeader::Max as usize);
+            self.debug_streams.resize(len, StreamIndex(u16::MAX));
+            self.debug_streams[DbgHeader::SectionHdrOrig as usize] = original_headers;
+            self.debug_streams[DbgHeader::OmapToSrc as usize] = to_src;
+            self.debug_streams[DbgHeader::OmapFromSrc as usize] = from_src;
+        }
         let mut modules = Vec::with_capacity(self.modules.len());
         let mut files = Vec::with_capacity(self.modules.len());
 
@@ -159,11 +925,32 @@ impl DbiBuilder {
             + file_count * u32::default_encoded_size(());
         let file_names_size: usize = file_names.map(|s| s.len() + 1).sum();
 
-        for module in self.modules {
-            let (res, names) = module.commit(sink, allocator)?;
+        let mut encoded = encoded.map(Vec::into_iter);
+        for (index, mut module) in (0..).zip(self.modules) {
+            for mut contrib in module.section_contribs.drain(..) {
+                contrib.i_mod = index;
+                self.section_contribs.push((contrib, 0));
+            }
+            let stream = match encoded.as_mut().and_then(Iterator::next) {
+                Some(stream) => stream,
+                None => module.encode()?,
+            };
+            let (res, names) = module.commit(stream, sink, allocator, &mut self.names)?;
             modules.push(res);
             files.push(names);
         }
+        self.section_contribs
+            .sort_by_key(|(contrib, _)| (contrib.i_sect, contrib.offset));
+        let section_contrib_version = self
+            .section_contrib_version
+            .unwrap_or(SectionContribVersion::Ver60);
+        let mut section_contribs = vec![];
+        for (contrib, coff_section_index) in &self.section_contribs {
+            contrib.encode((), &mut section_contribs)?;
+            if section_contrib_version == SectionContribVersion::V2 {
+                coff_section_index.encode(constants::ENDIANESS, &mut section_contribs)?;
+            }
+        }
         let names = self.names.build();
 
         let header = DbiHeader {
@@ -176,21 +963,20 @@ impl DbiBuilder {
                 .with_minor(11)
                 .with_is_new_format(true),
             public_symbol_stream_index: streams.publics,
-            dll_version: 0,
+            dll_version: self.dll_version,
             sym_record_stream_index: streams.symbols,
-            rbld: 0,
-            modi_stream_size: modules.encoded_size(()) as u32,
-            sec_contr_stream_size: u16::default_encoded_size(()) as u32 * 2
-                + self.section_contribs.encoded_size(()) as u32,
+            rbld: self.rbld,
+            modi_stream_size: codecs::padded_rem_list::encoded_size(&modules, ()) as u32,
+            sec_contr_stream_size: u16::default_encoded_size(()) as u32 * 2 + section_contribs.len() as u32,
             section_map_size: u16::default_encoded_size(()) as u32 * 2
                 + self.section_entries.encoded_size(()) as u32,
             file_info_size: (file_info_size + file_names_size) as u32,
-            type_server_size: 0,
-            mfc_type_server_index: 0,
+            type_server_size: self.type_server_map.len() as u32,
+            mfc_type_server_index: self.mfc_type_server_index,
             optional_db_header_size: self.debug_streams.encoded_size(()) as u32,
             ec_stream_size: names.encoded_size(()) as u32,
-            flags: DbiFlags::new(),
-            machine_type: MachineType::Amd64,
+            flags: DbiFlags::new().with_is_incrementally_linked(self.incrementally_linked),
+            machine_type: self.machine_type.unwrap_or(MachineType::Amd64),
             reserved: Default::default(),
         };
 
@@ -199,8 +985,8 @@ impl DbiBuilder {
 
         codecs::padded_rem_list::encode(&modules, constants::ENDIANESS, &mut stream)?;
 
-        SectionContribVersion::Ver60.encode(constants::ENDIANESS, &mut stream)?;
-        self.section_contribs.encode(((),), &mut stream)?;
+        section_contrib_version.encode(constants::ENDIANESS, &mut stream)?;
+        stream.write_all(&section_contribs)?;
 
         let section_map_len = self.section_entries.len() as u16;
         section_map_len.encode(constants::ENDIANESS, &mut stream)?;
@@ -209,9 +995,12 @@ impl DbiBuilder {
 
         let num_modules = modules.len() as u16;
         num_modules.encode(constants::ENDIANESS, &mut stream)?;
+        // the counts overflow in large projects, readers sum up the per-module counts instead
         (file_count as u16).encode(constants::ENDIANESS, &mut stream)?;
-        for index in 0..modules.len() as u16 {
-            index.encode(constants::ENDIANESS, &mut stream)?;
+        let mut first_file: u16 = 0;
+        for module in &modules {
+            first_file.encode(constants::ENDIANESS, &mut stream)?;
+            first_file = first_file.wrapping_add(module.header.num_files);
         }
         for module in &modules {
             module
@@ -228,6 +1017,7 @@ impl DbiBuilder {
             stream.write_all(name.as_bytes())?;
             stream.write_all(b"\0")?;
         }
+        stream.write_all(&self.type_server_map)?;
         names.encode((), &mut stream)?;
         self.debug_streams.encode(((),), &mut stream)?;
 
@@ -259,7 +1049,7 @@ impl InfoBuilder {
         self
     }
 
-    fn commit<S>(self, sink: &mut S) -> Result<MsfStreamLayout>
+    fn commit<S>(self, sink: &mut MsfSink<S>) -> Result<MsfStreamLayout>
     where
         S: io::Write + io::Seek,
     {
@@ -278,8 +1068,8 @@ impl InfoBuilder {
         let mut offsets = Vec::with_capacity(self.named_streams.len());
         let mut offset = 0;
         for (index, name) in self.named_streams {
+            offsets.push((offset, u16::from(index).into()));
             offset += name.len() as u32 + 1;
-            offsets.push((u16::from(index).into(), offset));
 
             StrBuf::new(name).encode((), &mut writer)?;
         }
@@ -295,60 +1085,239 @@ impl InfoBuilder {
 pub type TpiBuilder = TypeStreamBuilder<TypeRecord>;
 pub type IpiBuilder = TypeStreamBuilder<IdRecord>;
 
-#[derive(Debug)]
+// records are encoded as soon as they are added, so that they can be passed by reference and don't
+// stay around in their decoded form until the commit
+#[derive(Debug, Clone)]
 pub struct TypeStreamBuilder<A> {
-    records: Vec<A>,
-    hashes: Vec<u32>,
-    offset: usize,
+    records: Vec<u8>,
+    hashes: Vec<Option<u32>>,
     index: u32,
+    // the first TPI index the IPI records can't refer to, set with `type_limit`
+    type_limit: Option<u32>,
+    // the keys of the user-defined types of the TPI along with their indices
+    udt_keys: Vec<(u32, Arc<str>)>,
+    // the keys repeat for the forward references and definitions of a type
+    interner: Interner,
+    // pairs of a /names offset and the index its name resolves to
+    hash_adjusters: Vec<(u32, u32)>,
+    record: PhantomData<A>,
 }
 
 impl<A> TypeStreamBuilder<A>
 where
     A: Encode + EncodedSize,
 {
-    pub fn add(&mut self, name: &str, record: A) -> TypeIndex {
-        let size = u16::default_encoded_size(()) + record.encoded_size(());
-        self.offset += align_to(size, RECORD_ALIGNMENT);
-        self.records.push(record);
-        self.hashes.push(hash_v1(name.as_bytes()) % HASH_BUCKET_NUMBER);
+    // records hashed by their contents get their hash when they are written
+    fn push(&mut self, record: &A, hash: Option<u32>) -> Result<TypeIndex> {
+        let start = self.records.len();
+        if let Err(err) = encode_prefixed(record, &mut self.records) {
+            self.records.truncate(start);
+            return Err(err.into());
+        }
+        self.hashes.push(hash);
 
         let index = TypeIndex::try_from(self.index).unwrap();
         self.index += 1;
-        index
+        Ok(index)
+    }
+
+    // the index of the next record added
+    pub fn next_index(&self) -> TypeIndex {
+        TypeIndex::try_from(self.index).unwrap()
     }
 
-    fn commit<S>(self, sink: &mut S, allocator: &mut StreamAllocator) -> Result<MsfStreamLayout>
+    // fails on the first index that is neither builtin nor allocated before the record
+    fn check_indices(
+        &self,
+        own: IndexKind,
+        remap: impl FnOnce(&mut dyn FnMut(IndexKind, u32) -> u32),
+    ) -> Result<()> {
+        let mut invalid = None;
+        remap(&mut |kind, index| {
+            let limit = if kind == own {
+                Some(self.index)
+            } else {
+                self.type_limit
+            };
+            if invalid.is_none() && limit.is_some_and(|limit| index >= limit) {
+                invalid = Some((kind, index));
+            }
+            index
+        });
+        match invalid {
+            Some((kind, index)) => Err(Error::InvalidTypeIndex {
+                record: self.index,
+                kind: match kind {
+                    IndexKind::Type => "type",
+                    IndexKind::Id => "id",
+                },
+                index,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    // encodes the records and computes their hashes, which can be done alongside the module streams
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, fields(records = self.hashes.len()))
+    )]
+    fn encode(self) -> Result<EncodedTypeStream> {
+        let last_index = TypeIndex::try_from(self.index).unwrap();
+        let mut hash_values = Vec::with_capacity(self.hashes.len());
+        let mut start = 0;
+        for hash in self.hashes {
+            let len = u16::from_le_bytes([self.records[start], self.records[start + 1]]);
+            let end = start + usize::from(len) + std::mem::size_of::<u16>();
+            let hash = hash.unwrap_or_else(|| contents_hash(&self.records[start..end]));
+            hash_values.push(hash % HASH_BUCKET_NUMBER);
+            start = end;
+        }
+        Ok(EncodedTypeStream {
+            last_index,
+            records: self.records,
+            hash: TypeHash {
+                hash_values,
+                index_offsets: vec![],
+                hash_adjusters: Table::from_sized_iter(self.hash_adjusters.into_iter()),
+            },
+        })
+    }
+}
+
+// the bytes of a module stream and the sizes of its parts
+type EncodedModule = (Vec<u8>, ModuleLayout);
+
+struct EncodedTypeStream {
+    last_index: TypeIndex,
+    records: Vec<u8>,
+    hash: TypeHash,
+}
+
+impl EncodedTypeStream {
+    fn commit<S>(self, sink: &mut MsfSink<S>, allocator: &mut StreamAllocator) -> Result<MsfStreamLayout>
     where
         S: io::Write + io::Seek,
     {
         let mut writer = DefaultMsfStreamWriter::new(sink)?;
-        let hash = TypeHash {
-            hash_values: self.hashes,
-            index_offsets: vec![],
-            hash_adjusters: Table::default(),
-        };
-        let hash_layout = hash.write(&mut writer)?;
+        let hash_layout = self.hash.write(&mut writer)?;
         let hash_stream = allocator.allocate(writer.finish()?);
-        let last_index = TypeIndex::try_from(FIRST_NON_BUILTIN_TYPE + self.records.len() as u32).unwrap();
 
         let mut writer = DefaultMsfStreamWriter::new(sink)?;
-        let header = TypeStreamHeader::new(last_index, self.offset as u32, hash_stream, hash_layout);
+        let header = TypeStreamHeader::new(
+            self.last_index,
+            self.records.len() as u32,
+            hash_stream,
+            hash_layout,
+        );
         header.encode((), &mut writer)?;
-        for typ in self.records {
-            PrefixedRecord(typ).encode((), &mut writer)?;
-        }
+        writer.write_all(&self.records)?;
         Ok(writer.finish()?)
     }
 }
 
+impl TpiBuilder {
+    // user-defined types are hashed the way MSVC does for debuggers to find them by name, other
+    // records are hashed by their contents unless given a `name`, e.g. to find them with `TypeHash`,
+    // panics on records that fail to encode, which `try_add` returns as an error instead
+    pub fn add(&mut self, name: &str, record: impl Borrow<TypeRecord>) -> TypeIndex {
+        self.insert(name, record.borrow())
+            .expect("the type record failed to encode")
+    }
+
+    // names of user-defined types defined more than once, e.g. by local types of different functions,
+    // resolve to the first of the definitions
+    fn ambiguous_names(&self, names: &mut StringsBuilder) -> Result<Vec<(u32, u32)>> {
+        let mut first: HashMap<&str, (u32, bool)> = HashMap::new();
+        for (index, key) in &self.udt_keys {
+            first
+                .entry(key)
+                .and_modify(|(_, dup)| *dup = true)
+                .or_insert((*index, false));
+        }
+        let mut ambiguous: Vec<_> = first
+            .into_iter()
+            .filter(|(_, (_, dup))| *dup)
+            .map(|(key, (index, _))| (index, key))
+            .collect();
+        ambiguous.sort_unstable();
+        ambiguous
+            .into_iter()
+            .map(|(index, key)| Ok((names.add(key)?.0, index)))
+            .collect()
+    }
+
+    // adds a user-defined type with the unique name MSVC would give it, unless it has one already
+    pub fn add_udt(&mut self, mut record: TypeRecord) -> TypeIndex {
+        let has_unique_name = match &record {
+            TypeRecord::Class(class) | TypeRecord::Struct(class) | TypeRecord::Interface(class) => {
+                class.properties.has_unique_name()
+            }
+            TypeRecord::Union(union) => union.properties.has_unique_name(),
+            TypeRecord::Enum(enum_) => enum_.properties.has_unique_name(),
+            _ => true,
+        };
+        if !has_unique_name {
+            record.derive_unique_name();
+        }
+        self.add("", record)
+    }
+
+    // like `add`, but rejects records referencing types that were not added yet
+    pub fn try_add(&mut self, name: &str, mut record: TypeRecord) -> Result<TypeIndex> {
+        self.check_indices(IndexKind::Type, |f| record.remap_indices(f))?;
+        self.insert(name, &record)
+    }
+
+    pub(crate) fn insert(&mut self, name: &str, record: &TypeRecord) -> Result<TypeIndex> {
+        let hash = type_record_key(record, name).map(|key| hash_v1(key.as_bytes()));
+        let index = self.push(record, hash)?;
+        if let Some(key) = type_record_key(record, "") {
+            let key = self.interner.intern(key);
+            self.udt_keys.push((u32::from(index), key));
+        }
+        Ok(index)
+    }
+}
+
+impl IpiBuilder {
+    // panics on records that fail to encode, which `try_add` returns as an error instead
+    pub fn add(&mut self, name: &str, record: impl Borrow<IdRecord>) -> TypeIndex {
+        self.insert(name, record.borrow())
+            .expect("the id record failed to encode")
+    }
+
+    // the types referenced by the records passed to `try_add` are checked against the limit, usually the
+    // `next_index` of the TPI, and aren't checked at all without it
+    pub fn type_limit(&mut self, limit: TypeIndex) -> &mut Self {
+        self.type_limit = Some(u32::from(limit));
+        self
+    }
+
+    // like `add`, but rejects records referencing ids that were not added yet, and types at or past
+    // the `type_limit`
+    pub fn try_add(&mut self, name: &str, mut record: IdRecord) -> Result<TypeIndex> {
+        self.check_indices(IndexKind::Id, |f| record.remap_indices(f))?;
+        self.insert(name, &record)
+    }
+
+    pub(crate) fn insert(&mut self, name: &str, record: &IdRecord) -> Result<TypeIndex> {
+        let hash = id_record_hash(record, name);
+        self.push(record, hash)
+    }
+}
+
 impl<A> Default for TypeStreamBuilder<A> {
     fn default() -> Self {
         Self {
             records: vec![],
             hashes: vec![],
-            offset: 0,
             index: FIRST_NON_BUILTIN_TYPE,
+            type_limit: None,
+            udt_keys: vec![],
+            interner: Interner::default(),
+            hash_adjusters: vec![],
+            record: PhantomData,
         }
     }
 }
@@ -359,54 +1328,293 @@ pub struct PublicsBuilder<'a> {
 }
 
 impl<'a> PublicsBuilder<'a> {
-    pub fn add(&mut self, public: Public) -> SymbolOffset {
+    pub fn add(&mut self, public: Public) -> Result<SymbolOffset> {
+        let Some(&existing) = self.symbols.public_names.get(public.name.as_ref()) else {
+            return Ok(self.insert(public));
+        };
+        match self.symbols.duplicate_publics {
+            DuplicatePublics::Keep => Ok(self.insert(public)),
+            DuplicatePublics::FirstWins => Ok(existing),
+            // the names are the same, so the record keeps its size and offset
+            DuplicatePublics::LastWins => {
+                self.symbols.publics.insert(existing, public);
+                Ok(existing)
+            }
+            DuplicatePublics::Reject => Err(Error::DuplicateSymbol(public.name.as_ref().to_owned())),
+        }
+    }
+
+    // adds every public, reporting the ones named like a public added before instead of failing
+    pub fn add_all<I>(&mut self, publics: I) -> Vec<PublicConflict>
+    where
+        I: IntoIterator<Item = Public>,
+    {
+        let mut conflicts = vec![];
+        for public in publics {
+            if let Some(&existing) = self.symbols.public_names.get(public.name.as_ref()) {
+                conflicts.push(PublicConflict {
+                    name: public.name.as_ref().to_owned(),
+                    existing,
+                });
+            }
+            // rejected publics are only reported
+            let _ = self.add(public);
+        }
+        conflicts
+    }
+
+    pub fn on_duplicate(&mut self, policy: DuplicatePublics) -> &mut Self {
+        self.symbols.duplicate_publics = policy;
+        self
+    }
+
+    pub fn finish_publics(self) -> &'a mut SymbolsBuilder {
+        self.symbols
+    }
+
+    fn insert(&mut self, public: Public) -> SymbolOffset {
         let offset = SymbolOffset(self.symbols.offset);
         let size = u16::default_encoded_size(()) * 2 + public.encoded_size(());
         self.symbols.offset += align_to(size, RECORD_ALIGNMENT) as u32;
+        self.symbols
+            .public_names
+            .entry(public.name.as_ref().to_owned())
+            .or_insert(offset);
         self.symbols.publics.insert(offset, public);
         offset
     }
+}
 
-    pub fn finish_publics(self) -> &'a mut SymbolsBuilder {
-        self.symbols
-    }
+// what `PublicsBuilder::add` does with a public named like one added before
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DuplicatePublics {
+    // both publics are written
+    #[default]
+    Keep,
+    FirstWins,
+    LastWins,
+    // adding fails with `Error::DuplicateSymbol`
+    Reject,
+}
+
+#[derive(Debug)]
+pub struct PublicConflict {
+    pub name: String,
+    // the public added first under the name
+    pub existing: SymbolOffset,
+}
+
+// how the records of the publics and globals are laid out in the symbol record stream
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolOrder {
+    // publics and globals interleaved in the order they were added
+    #[default]
+    Insertion,
+    GlobalsFirst,
+    PublicsFirst,
 }
 
 #[derive(Debug, Default)]
 pub struct SymbolsBuilder {
+    order: SymbolOrder,
     publics: BTreeMap<SymbolOffset, Public>,
     globals: BTreeMap<SymbolOffset, SymbolRecord>,
+    // only the globals referenced more than once
+    ref_counts: BTreeMap<SymbolOffset, u32>,
+    encoded_globals: HashMap<Vec<u8>, SymbolOffset>,
+    public_names: HashMap<String, SymbolOffset>,
+    duplicate_publics: DuplicatePublics,
+    bindings: HashMap<SymbolHandle, SymbolOffset>,
+    // the references to module symbols that are patched at commit
+    ref_fixups: Vec<(SymbolOffset, SymbolHandle)>,
     offset: u32,
 }
 
 impl SymbolsBuilder {
-    pub fn add(&mut self, symbol: SymbolRecord) -> SymbolOffset {
+    pub fn add<'a>(&mut self, symbol: impl Into<Cow<'a, SymbolRecord>>) -> SymbolOffset {
+        let symbol = symbol.into().into_owned();
+        let offset = SymbolOffset(self.offset);
+        self.offset += record_size(&symbol);
+        self.globals.insert(offset, symbol);
+        offset
+    }
+
+    // like `add`, but identical globals, like the UDTs of a header included by many modules, share a
+    // single record with a reference count, so a record passed by reference is only cloned when it's
+    // the first of its kind
+    pub fn add_shared<'a>(&mut self, symbol: impl Into<Cow<'a, SymbolRecord>>) -> SymbolOffset {
+        let symbol = symbol.into();
+        let mut encoded = vec![];
+        if symbol.encode((), &mut encoded).is_ok() {
+            if let Some(&offset) = self.encoded_globals.get(&encoded) {
+                *self.ref_counts.entry(offset).or_insert(1) += 1;
+                return offset;
+            }
+        }
+
         let offset = SymbolOffset(self.offset);
         let size = u16::default_encoded_size(()) + symbol.encoded_size(());
         self.offset += align_to(size, RECORD_ALIGNMENT) as u32;
+        self.globals.insert(offset, symbol.into_owned());
+        self.encoded_globals.insert(encoded, offset);
+        offset
+    }
+
+    // adds a reference record like S_PROCREF whose referent and module are taken from the module symbol
+    // bound to the handle, such records are never shared with identical ones
+    pub fn add_ref(&mut self, symbol: SymbolRecord, referent: SymbolHandle) -> SymbolOffset {
+        let offset = SymbolOffset(self.offset);
+        self.offset += record_size(&symbol);
         self.globals.insert(offset, symbol);
+        self.ref_fixups.push((offset, referent));
         offset
     }
 
-    fn commit<S>(self, sink: &mut S, allocator: &mut StreamAllocator) -> Result<SymbolStreams>
+    pub fn bind(&mut self, handle: SymbolHandle, offset: SymbolOffset) -> &mut Self {
+        self.bindings.insert(handle, offset);
+        self
+    }
+
+    // adds an S_GDATA32 or, for statics, an S_LDATA32 record, and with `with_public` also a public
+    // of the same name and address, so that the variable can be found both ways
+    pub fn add_data(&mut self, data: Data, is_static: bool, with_public: bool) -> SymbolOffset {
+        if with_public {
+            let public = Public {
+                properties: PublicProperties::new(),
+                offset: data.offset.clone(),
+                name: data.name.clone(),
+            };
+            // publics of the same name are kept, like `PublicsBuilder` does by default
+            PublicsBuilder { symbols: self }.insert(public);
+        }
+        if is_static {
+            self.add(SymbolRecord::Data(data))
+        } else {
+            self.add(SymbolRecord::GlobalData(data))
+        }
+    }
+
+    // offsets are handed out in insertion order, with any other order the records are laid out again
+    // at commit and the references the builder knows about, like the global refs of the modules, updated,
+    // offsets kept elsewhere are only final for `SymbolOrder::Insertion`
+    pub fn order(&mut self, order: SymbolOrder) -> &mut Self {
+        self.order = order;
+        self
+    }
+
+    // returns the new offsets of the records keyed by the old ones
+    fn rename_symbols(
+        &mut self,
+        rename: &mut dyn FnMut(&str) -> Option<String>,
+        module_offsets: &[HashMap<u32, u32>],
+    ) -> HashMap<u32, u32> {
+        let mut records = self.take_records();
+        for (_, symbol) in &mut records {
+            rename_symbol(symbol, rename);
+            remap_referent(symbol, module_offsets);
+        }
+        self.insert_records(records)
+    }
+
+    // lays the records out in the configured order, returns the new offsets keyed by the old ones
+    fn reorder(&mut self) -> HashMap<u32, u32> {
+        let records = self.take_records();
+        self.insert_records(records)
+    }
+
+    fn take_records(&mut self) -> Vec<(SymbolOffset, SymbolRecord)> {
+        let publics = std::mem::take(&mut self.publics)
+            .into_iter()
+            .map(|(offset, public)| (offset, SymbolRecord::Public32(public)));
+        let globals = std::mem::take(&mut self.globals);
+        match self.order {
+            SymbolOrder::Insertion => {
+                let mut records: BTreeMap<_, _> = publics.collect();
+                records.extend(globals);
+                records.into_iter().collect()
+            }
+            SymbolOrder::GlobalsFirst => globals.into_iter().chain(publics).collect(),
+            SymbolOrder::PublicsFirst => publics.chain(globals).collect(),
+        }
+    }
+
+    fn insert_records(&mut self, records: Vec<(SymbolOffset, SymbolRecord)>) -> HashMap<u32, u32> {
+        self.public_names.clear();
+        self.encoded_globals.clear();
+
+        let mut offsets = HashMap::with_capacity(records.len());
+        self.offset = 0;
+        for (old, symbol) in records {
+            let offset = SymbolOffset(self.offset);
+            self.offset += record_size(&symbol);
+            offsets.insert(old.0, offset.0);
+            match symbol {
+                SymbolRecord::Public32(public) => {
+                    self.public_names
+                        .entry(public.name.as_ref().to_owned())
+                        .or_insert(offset);
+                    self.publics.insert(offset, public);
+                }
+                symbol => {
+                    let mut encoded = vec![];
+                    if symbol.encode((), &mut encoded).is_ok() {
+                        self.encoded_globals.insert(encoded, offset);
+                    }
+                    self.globals.insert(offset, symbol);
+                }
+            }
+        }
+        self.ref_counts = std::mem::take(&mut self.ref_counts)
+            .into_iter()
+            .map(|(offset, count)| (remap_offset(&offsets, offset), count))
+            .collect();
+        for offset in self.bindings.values_mut() {
+            *offset = remap_offset(&offsets, *offset);
+        }
+        for (offset, _) in &mut self.ref_fixups {
+            *offset = remap_offset(&offsets, *offset);
+        }
+        offsets
+    }
+
+    fn commit<S>(
+        self,
+        sections: &[SectionHeader],
+        sink: &mut MsfSink<S>,
+        allocator: &mut StreamAllocator,
+    ) -> Result<SymbolStreams>
     where
         S: io::Write + io::Seek,
     {
         let mut globals_stream = DefaultMsfStreamWriter::new(sink)?;
-        Globals::from_symbols(&self.globals).write_with_header(&mut globals_stream)?;
+        Globals::from_symbols(&self.globals, &self.ref_counts).write_with_header(&mut globals_stream)?;
         let globals = allocator.allocate(globals_stream.finish()?);
 
         let mut publics_stream = DefaultMsfStreamWriter::new(sink)?;
-        Publics::from_publics(&self.publics).write_with_header(&mut publics_stream)?;
+        Publics::from_publics(&self.publics, sections).write_with_header(&mut publics_stream)?;
         let publics = allocator.allocate(publics_stream.finish()?);
 
-        let mut syms_stream = DefaultMsfStreamWriter::new(sink)?;
-        for (_, sym) in self.publics {
-            PrefixedRecord(SymbolRecord::Public32(sym)).encode((), &mut syms_stream)?;
-        }
-        for (_, sym) in self.globals {
-            PrefixedRecord(sym).encode((), &mut syms_stream)?;
+        // publics and globals can be added in any order, the records are written in the order of their offsets
+        let mut records: BTreeMap<SymbolOffset, SymbolRecord> = self
+            .publics
+            .into_iter()
+            .map(|(offset, public)| (offset, SymbolRecord::Public32(public)))
+            .collect();
+        records.extend(self.globals);
+
+        // the offsets have been handed out already, so every record has to land exactly where it was promised
+        let mut buf = vec![];
+        for (offset, sym) in records {
+            if buf.len() != offset.0 as usize {
+                return Err(Error::SymbolOffsetMismatch {
+                    expected: offset.0,
+                    actual: buf.len() as u32,
+                });
+            }
+            PrefixedRecord(sym).encode((), &mut buf)?;
         }
+        let mut syms_stream = DefaultMsfStreamWriter::new(sink)?;
+        syms_stream.write_all(&buf)?;
         let symbols = allocator.allocate(syms_stream.finish()?);
 
         Ok(SymbolStreams {
@@ -417,14 +1625,33 @@ impl SymbolsBuilder {
     }
 }
 
-#[derive(Debug)]
+// the compiler a module is attributed to by its S_COMPILE3 record
+#[derive(Debug, Clone)]
+pub struct CompilerInfo {
+    pub name: String,
+    pub language: SourceLanguage,
+    // a CodeView CPU type, see `MachineType::cpu_type`
+    pub machine: u16,
+    pub frontend_version: Version,
+    pub backend_version: Version,
+}
+
+#[derive(Debug, Clone)]
 pub struct ModuleBuilder {
     name: String,
     obj_file_name: String,
     section_contrib: SectionContrib,
     symbols: Vec<SymbolRecord>,
     debug_entries: Vec<DebugSubsectionEntry>,
-    source_files: Vec<String>,
+    source_files: Vec<Arc<str>>,
+    ec_names: Option<(String, String)>,
+    is_dirty: bool,
+    type_server_index: u8,
+    // contributions other than the main one, they are moved to the DBI stream
+    section_contribs: Vec<SectionContrib>,
+    global_refs: Vec<SymbolOffset>,
+    pending_global_refs: Vec<SymbolHandle>,
+    bindings: Vec<(SymbolHandle, SymbolOffset)>,
     offset: u32,
 }
 
@@ -437,11 +1664,44 @@ impl ModuleBuilder {
             symbols: vec![],
             debug_entries: vec![],
             source_files: vec![],
-            offset: 0,
+            ec_names: None,
+            is_dirty: false,
+            type_server_index: 0,
+            section_contribs: vec![],
+            global_refs: vec![],
+            pending_global_refs: vec![],
+            bindings: vec![],
+            // the debug section signature precedes the first record
+            offset: 4,
+        }
+    }
+
+    // adds the S_OBJNAME and S_COMPILE3 records that debuggers expect at the start of every module,
+    // so it has to be called before any other symbol is added
+    pub fn compiler(&mut self, info: CompilerInfo) -> Result<&mut Self> {
+        if !self.symbols.is_empty() {
+            return Err(Error::UnsupportedFeature(
+                "compiler info added after other symbols",
+            ));
         }
+        self.add_symbol(SymbolRecord::ObjectName {
+            signature: 0,
+            name: StrBuf::new(self.obj_file_name.clone()),
+        });
+        self.add_symbol(SymbolRecord::Compile3 {
+            properties: CompileProperties::new().with_source_language(info.language),
+            machine: info.machine,
+            frontend_version: info.frontend_version,
+            frontend_qfe: 0,
+            backend_version: info.backend_version,
+            backend_qfe: 0,
+            version: StrBuf::new(info.name),
+        });
+        Ok(self)
     }
 
-    pub fn add_symbol(&mut self, symbol: SymbolRecord) -> SymbolOffset {
+    pub fn add_symbol<'a>(&mut self, symbol: impl Into<Cow<'a, SymbolRecord>>) -> SymbolOffset {
+        let symbol = symbol.into().into_owned();
         let offset = SymbolOffset(self.offset);
         let size = u16::default_encoded_size(()) + symbol.encoded_size(());
         self.offset += align_to(size, RECORD_ALIGNMENT) as u32;
@@ -449,36 +1709,300 @@ impl ModuleBuilder {
         offset
     }
 
+    // adds the S_LOCAL record of the variable followed by its def ranges
+    pub fn add_local(&mut self, local: LocalVarBuilder) -> SymbolOffset {
+        let mut records = local.build().into_iter();
+        let offset = records.next().map(|local| self.add_symbol(local)).unwrap();
+        for record in records {
+            self.add_symbol(record);
+        }
+        offset
+    }
+
+    // adds a procedure along with the record that closes its scope
+    pub fn add_procedure(&mut self, mut proc: Procedure, is_global: bool) -> SymbolOffset {
+        let size = u16::default_encoded_size(()) * 2 + proc.encoded_size(());
+        proc.end = SymbolOffset(self.offset + align_to(size, RECORD_ALIGNMENT) as u32);
+        let offset = if is_global {
+            self.add_symbol(SymbolRecord::GlobalProc(proc))
+        } else {
+            self.add_symbol(SymbolRecord::Proc(proc))
+        };
+        self.add_symbol(SymbolRecord::ScopeEnd);
+        offset
+    }
+
+    // adds a thunk along with the record that closes its scope and a contribution covering its code,
+    // the ordinals that need variant data like `ThisAdjustor` are not supported
+    pub fn add_thunk(
+        &mut self,
+        name: String,
+        offset: DataRegionOffset,
+        size: u16,
+        ordinal: ThunkOrdinal,
+    ) -> SymbolOffset {
+        self.add_code_contrib(&offset, size.into());
+        let mut thunk = SymbolRecord::Thunk32 {
+            parent: None,
+            end: SymbolOffset(0),
+            next: None,
+            offset,
+            size,
+            ordinal,
+            name: StrBuf::new(name),
+        };
+        let record_size = u16::default_encoded_size(()) * 2 + thunk.encoded_size(());
+        set_scope_end(
+            &mut thunk,
+            SymbolOffset(self.offset + align_to(record_size, RECORD_ALIGNMENT) as u32),
+        );
+        let offset = self.add_symbol(thunk);
+        self.add_symbol(SymbolRecord::ScopeEnd);
+        offset
+    }
+
+    // adds an incremental linking thunk or a branch island jumping to `target`, along with
+    // a contribution covering its code
+    pub fn add_trampoline(
+        &mut self,
+        trampoline_type: TrampolineType,
+        thunk: DataRegionOffset,
+        size: u16,
+        target: DataRegionOffset,
+    ) -> SymbolOffset {
+        self.add_code_contrib(&thunk, size.into());
+        self.add_symbol(SymbolRecord::Trampoline {
+            trampoline_type,
+            size,
+            thunk_offset: thunk.offset,
+            target_offset: target.offset,
+            thunk_section: thunk.segment,
+            target_section: target.segment,
+        })
+    }
+
+    // records a reference to a global symbol, like the data symbols the module defines
+    pub fn add_global_ref(&mut self, offset: SymbolOffset) -> &mut Self {
+        self.global_refs.push(offset);
+        self
+    }
+
+    // records a reference to a global symbol bound to the handle by `SymbolsBuilder::bind`
+    pub fn add_global_ref_to(&mut self, handle: SymbolHandle) -> &mut Self {
+        self.pending_global_refs.push(handle);
+        self
+    }
+
+    // makes the references to the handle point at a symbol of this module, once it's added to the DBI
+    pub fn bind(&mut self, handle: SymbolHandle, offset: SymbolOffset) -> &mut Self {
+        self.bindings.push((handle, offset));
+        self
+    }
+
+    // the main contribution followed by the others
+    pub fn section_contribs(&self) -> impl Iterator<Item = &SectionContrib> {
+        std::iter::once(&self.section_contrib).chain(&self.section_contribs)
+    }
+
+    // the module index of the contribution is filled in when the DBI stream is written
+    pub fn add_section_contrib(&mut self, contrib: SectionContrib) -> &mut Self {
+        self.section_contribs.push(contrib);
+        self
+    }
+
+    fn add_code_contrib(&mut self, offset: &DataRegionOffset, size: u32) {
+        let characteristics = IMAGE_SCN_CNT_CODE | IMAGE_SCN_MEM_EXECUTE | IMAGE_SCN_MEM_READ;
+        let contrib = SectionContrib::new(offset.segment, offset.offset as i32, size, characteristics, 0);
+        self.add_section_contrib(contrib);
+    }
+
+    // adds symbols in stream order, filling in the parent and end offsets of nested scopes
+    pub fn add_symbols<I>(&mut self, symbols: I) -> &mut Self
+    where
+        I: IntoIterator<Item = SymbolRecord>,
+    {
+        let mut scopes: Vec<(usize, SymbolOffset)> = vec![];
+        for mut symbol in symbols {
+            let index = self.symbols.len();
+            if matches!(
+                symbol,
+                SymbolRecord::ScopeEnd | SymbolRecord::ProcEnd | SymbolRecord::InlineSiteEnd
+            ) {
+                let offset = self.add_symbol(symbol);
+                if let Some((start, _)) = scopes.pop() {
+                    set_scope_end(&mut self.symbols[start], offset);
+                }
+            } else {
+                let opens_scope = set_scope_parent(&mut symbol, scopes.last().map(|&(_, offset)| offset));
+                let offset = self.add_symbol(symbol);
+                if opens_scope {
+                    scopes.push((index, offset));
+                }
+            }
+        }
+        self
+    }
+
     pub fn add_debug_entry(&mut self, entry: DebugSubsectionEntry) -> &mut Self {
         self.debug_entries.push(entry);
         self
     }
 
+    pub fn add_subsection(&mut self, record: DebugSubsectionRecord) -> Result<&mut Self> {
+        Ok(self.add_debug_entry(record.to_entry()?))
+    }
+
     pub fn add_source_file(&mut self, file: String) -> &mut Self {
-        self.source_files.push(file);
+        self.source_files.push(file.into());
+        self
+    }
+
+    // records the source file and the PDB of the compiler in the EC names of the DBI stream and
+    // marks the module as built for edit and continue
+    pub fn ec_names(&mut self, src_file_name: String, pdb_file_path: String) -> &mut Self {
+        self.ec_names = Some((src_file_name, pdb_file_path));
+        self
+    }
+
+    pub fn source_files(&self) -> impl Iterator<Item = &str> {
+        self.source_files.iter().map(|file| &**file)
+    }
+
+    // shares the storage of the source files with the equal paths of other modules
+    fn intern(&mut self, interner: &mut Interner) {
+        for file in &mut self.source_files {
+            *file = interner.intern(file);
+        }
+    }
+
+    // marks the module as out of date, for the incremental linker to rebuild it
+    pub fn dirty(&mut self, is_dirty: bool) -> &mut Self {
+        self.is_dirty = is_dirty;
+        self
+    }
+
+    // the type server of the module, as an index into the type server map of the DBI stream
+    pub fn type_server_index(&mut self, index: u8) -> &mut Self {
+        self.type_server_index = index;
         self
     }
 
-    fn commit<S>(self, sink: &mut S, allocator: &mut StreamAllocator) -> Result<(DbiModule, Vec<String>)>
+    // adds the symbols, subsections and global refs of a parsed module, returns the new offsets of the
+    // records keyed by the old ones
+    fn load<R>(&mut self, pdb: &mut PdbFile<R>, module: &DbiModule) -> Result<HashMap<u32, u32>>
+    where
+        R: io::Read + io::Seek,
+    {
+        let mut offsets = HashMap::new();
+        let mut old = self.offset;
+        for raw in pdb.get_module_raw_symbols(module)? {
+            match raw.decode_as::<SymbolRecord>() {
+                Ok(symbol) => {
+                    let new = self.add_symbol(symbol);
+                    offsets.insert(old, new.0);
+                }
+                // like when reading the module, lenient parsing reports the record and leaves it out
+                Err(error) if pdb.options.lenient => pdb.diagnostics.push(Diagnostic {
+                    stream: "module symbols",
+                    offset: old.into(),
+                    error,
+                }),
+                Err(error) => {
+                    let index = Some(module.header.debug_info_stream);
+                    return Err(error.in_stream("module symbols", index, old.into()));
+                }
+            }
+            // the length prefix and the kind precede the body
+            old += 4 + raw.data.len() as u32;
+        }
+        for symbol in &mut self.symbols {
+            remap_scope_offsets(symbol, &offsets);
+        }
+        // modules without a stream of their own have nothing more to load
+        if pdb.get_indexed_stream(module.header.debug_info_stream).is_none() {
+            return Ok(offsets);
+        }
+        let contents = pdb.get_module_debug_info(module)?;
+        for entry in contents.c13_records() {
+            self.add_debug_entry(DebugSubsectionEntry {
+                record_type: entry.record_type,
+                data: entry.data.clone(),
+            });
+        }
+        self.global_refs = contents.global_refs();
+        Ok(offsets)
+    }
+
+    // returns the new offsets of the records keyed by the old ones
+    fn rename_symbols(&mut self, rename: &mut dyn FnMut(&str) -> Option<String>) -> HashMap<u32, u32> {
+        let mut offsets = HashMap::with_capacity(self.symbols.len());
+        let (mut old, mut new) = (4, 4);
+        for symbol in &mut self.symbols {
+            let size = record_size(symbol);
+            // the object name is a path rather than a symbol
+            if !matches!(symbol, SymbolRecord::ObjectName { .. }) {
+                rename_symbol(symbol, rename);
+            }
+            offsets.insert(old, new);
+            old += size;
+            new += record_size(symbol);
+        }
+        for symbol in &mut self.symbols {
+            remap_scope_offsets(symbol, &offsets);
+        }
+        for (_, offset) in &mut self.bindings {
+            *offset = remap_offset(&offsets, *offset);
+        }
+        self.offset = new;
+        offsets
+    }
+
+    // the module stream is encoded on its own, ahead of the commit laying it out into blocks
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip_all, fields(module = %self.name)))]
+    fn encode(&mut self) -> Result<EncodedModule> {
+        Module::new(
+            std::mem::take(&mut self.symbols),
+            std::mem::take(&mut self.debug_entries),
+        )
+        .with_global_refs(&self.global_refs)
+        .encode_stream()
+    }
+
+    fn commit<S>(
+        self,
+        (bytes, layout): EncodedModule,
+        sink: &mut MsfSink<S>,
+        allocator: &mut StreamAllocator,
+        ec_names: &mut StringsBuilder,
+    ) -> Result<(DbiModule, Vec<Arc<str>>)>
     where
         S: io::Write + io::Seek,
     {
         let mut dbg_stream = DefaultMsfStreamWriter::new(sink)?;
-        let layout = Module::new(self.symbols, self.debug_entries).write(&mut dbg_stream)?;
+        dbg_stream.write_all(&bytes)?;
         let debug_info_stream = allocator.allocate(dbg_stream.finish()?);
 
+        let (src_file_name_ni, pdb_file_path_ni) = match &self.ec_names {
+            Some((src_file_name, pdb_file_path)) => {
+                (ec_names.add(src_file_name)?, ec_names.add(pdb_file_path)?)
+            }
+            None => (StringOffset(0), StringOffset(0)),
+        };
+
         let header = ModuleInfoHeader {
             module: 0,
             section_contrib: self.section_contrib,
-            flags: ModuleInfoFlags::new(),
-            type_server_index: 0,
+            flags: ModuleInfoFlags::new()
+                .with_is_dirty(self.is_dirty)
+                .with_is_ec_enabled(self.ec_names.is_some()),
+            type_server_index: self.type_server_index,
             debug_info_stream,
             layout,
             num_files: self.source_files.len() as u16,
             pad1: Default::default(),
             file_names_offs: 0,
-            src_file_name_ni: 0,
-            pdb_file_path_ni: 0,
+            src_file_name_ni: src_file_name_ni.0,
+            pdb_file_path_ni: pdb_file_path_ni.0,
         };
 
         let res = DbiModule {
@@ -490,6 +2014,283 @@ impl ModuleBuilder {
     }
 }
 
+// where a local variable lives over some of its live ranges
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LocalLocation {
+    Register(Register),
+    // relative to the frame pointer
+    FrameRel(i32),
+    // relative to a base register like RSP
+    RegisterRel(Register, i32),
+}
+
+// builds an S_LOCAL record followed by the def range records describing where the variable lives
+#[derive(Debug, Clone)]
+pub struct LocalVarBuilder {
+    name: String,
+    local_type: TypeIndex,
+    properties: LocalProperties,
+    ranges: Vec<LiveRange>,
+}
+
+#[derive(Debug, Clone)]
+struct LiveRange {
+    location: LocalLocation,
+    segment: u16,
+    start: u32,
+    end: u32,
+    gaps: Vec<LocalVariableGap>,
+}
+
+impl LocalVarBuilder {
+    // the length of a def range is 16-bit, longer ranges are split
+    const MAX_RANGE: u32 = 0xF000;
+
+    pub fn new(name: String, local_type: TypeIndex) -> Self {
+        Self {
+            name,
+            local_type,
+            properties: LocalProperties::new(),
+            ranges: vec![],
+        }
+    }
+
+    pub fn properties(&mut self, properties: LocalProperties) -> &mut Self {
+        self.properties = properties;
+        self
+    }
+
+    // ranges are expected in address order, subsequent ranges with the same location are folded into
+    // a single record with gaps
+    pub fn add_range(&mut self, location: LocalLocation, start: DataRegionOffset, len: u32) -> &mut Self {
+        let mut start_offset = start.offset;
+        let end = start.offset + len;
+        while start_offset < end {
+            let chunk_end = end.min(start_offset + Self::MAX_RANGE);
+            match self.ranges.last_mut() {
+                Some(last)
+                    if last.location == location
+                        && last.segment == start.segment
+                        && last.end <= start_offset
+                        && chunk_end - last.start <= Self::MAX_RANGE =>
+                {
+                    if last.end < start_offset {
+                        last.gaps.push(LocalVariableGap {
+                            gap_start_offset: (last.end - last.start) as u16,
+                            range: (start_offset - last.end) as u16,
+                        });
+                    }
+                    last.end = chunk_end;
+                }
+                _ => self.ranges.push(LiveRange {
+                    location,
+                    segment: start.segment,
+                    start: start_offset,
+                    end: chunk_end,
+                    gaps: vec![],
+                }),
+            }
+            start_offset = chunk_end;
+        }
+        self
+    }
+
+    pub fn build(self) -> Vec<SymbolRecord> {
+        let mut res = Vec::with_capacity(self.ranges.len() + 1);
+        res.push(SymbolRecord::Local {
+            local_type: self.local_type,
+            properties: self.properties,
+            name: StrBuf::new(self.name),
+        });
+        for live in self.ranges {
+            let range = LocalVariableRange {
+                offset_start: live.start,
+                i_sect_start: live.segment,
+                range: (live.end - live.start) as u16,
+            };
+            let gaps = live.gaps;
+            res.push(match live.location {
+                LocalLocation::Register(register) => SymbolRecord::DefRangeRegister {
+                    register,
+                    may_have_no_name: 0,
+                    range,
+                    gaps,
+                },
+                LocalLocation::FrameRel(offset) => {
+                    SymbolRecord::DefRangeFramePointerRel { offset, range, gaps }
+                }
+                LocalLocation::RegisterRel(register, base_pointer_offset) => {
+                    SymbolRecord::DefRangeRegisterRel {
+                        register,
+                        properties: DefRangeRegisterRelProperties::new(),
+                        base_pointer_offset,
+                        range,
+                        gaps,
+                    }
+                }
+            });
+        }
+        res
+    }
+}
+
+// the start of every moved range maps to its new RVA, the gaps between them to themselves
+fn omap_entries(ranges: impl Iterator<Item = (u32, u32, u32)>) -> Vec<OmapEntry> {
+    let mut ranges: Vec<_> = ranges.collect();
+    ranges.sort_by_key(|&(from, _, _)| from);
+    let mut entries = vec![];
+    let mut cursor = 0;
+    for (from, to, len) in ranges {
+        if cursor < from {
+            entries.push(OmapEntry {
+                rva: cursor,
+                rva_to: cursor,
+            });
+        }
+        entries.push(OmapEntry {
+            rva: from,
+            rva_to: to,
+        });
+        cursor = from + len;
+    }
+    entries.push(OmapEntry {
+        rva: cursor,
+        rva_to: cursor,
+    });
+    entries
+}
+
+fn relocate(
+    addr: &mut DataRegionOffset,
+    old_headers: &[SectionHeader],
+    new_headers: &[SectionHeader],
+    moved: &[MovedRange],
+) {
+    // absolute symbols have no section
+    let Some(old) = usize::from(addr.segment)
+        .checked_sub(1)
+        .and_then(|index| old_headers.get(index))
+    else {
+        return;
+    };
+    let rva = old.virtual_address + addr.offset;
+    let rva = moved
+        .iter()
+        .find(|range| rva >= range.old_rva && rva - range.old_rva < range.len)
+        .map_or(rva, |range| range.new_rva + (rva - range.old_rva));
+    // the end of a section still belongs to it
+    let contains = |header: &&SectionHeader| {
+        rva >= header.virtual_address && rva - header.virtual_address <= header.virtual_size
+    };
+    let same = new_headers
+        .get(usize::from(addr.segment) - 1)
+        .filter(contains)
+        .map(|header| (addr.segment, header));
+    let found = same.or_else(|| (1..).zip(new_headers).find(|(_, header)| contains(header)));
+    if let Some((segment, header)) = found {
+        *addr = DataRegionOffset::new(rva - header.virtual_address, segment);
+    }
+}
+
+fn rename_symbol(symbol: &mut SymbolRecord, rename: &mut dyn FnMut(&str) -> Option<String>) {
+    if let Some(name) = symbol.name_mut() {
+        if let Some(renamed) = rename(name.as_ref()) {
+            *name = StrBuf::new(renamed);
+        }
+    }
+}
+
+pub(crate) fn record_size(symbol: &SymbolRecord) -> u32 {
+    align_to(
+        u16::default_encoded_size(()) + symbol.encoded_size(()),
+        RECORD_ALIGNMENT,
+    ) as u32
+}
+
+// points a reference record like S_PROCREF at the new offset of its referent in the module
+fn remap_referent(symbol: &mut SymbolRecord, module_offsets: &[HashMap<u32, u32>]) {
+    if let SymbolRecord::ProcedureRef(proc)
+    | SymbolRecord::LocalProcedureRef(proc)
+    | SymbolRecord::DataRef(proc)
+    | SymbolRecord::AnnotationRef(proc) = symbol
+    {
+        // module indices are 1-based
+        if let Some(module) = usize::from(proc.module)
+            .checked_sub(1)
+            .and_then(|index| module_offsets.get(index))
+        {
+            proc.referent = remap_offset(module, proc.referent);
+        }
+    }
+}
+
+// offsets without an entry, like the zero of an absent parent, are kept
+fn remap_offset(offsets: &HashMap<u32, u32>, offset: SymbolOffset) -> SymbolOffset {
+    SymbolOffset(offsets.get(&offset.0).copied().unwrap_or(offset.0))
+}
+
+fn remap_scope_offsets(symbol: &mut SymbolRecord, offsets: &HashMap<u32, u32>) {
+    let remap = |offset: &mut SymbolOffset| *offset = remap_offset(offsets, *offset);
+    match symbol {
+        SymbolRecord::Proc(proc)
+        | SymbolRecord::GlobalProc(proc)
+        | SymbolRecord::ProcId(proc)
+        | SymbolRecord::GlobalProcId(proc)
+        | SymbolRecord::DPCProc(proc)
+        | SymbolRecord::DPCProcId(proc) => {
+            proc.parent.iter_mut().for_each(remap);
+            remap(&mut proc.end);
+            proc.next.iter_mut().for_each(remap);
+        }
+        SymbolRecord::Thunk32 {
+            parent, end, next, ..
+        } => {
+            parent.iter_mut().for_each(remap);
+            remap(end);
+            next.iter_mut().for_each(remap);
+        }
+        SymbolRecord::InlineSite { parent, end, .. } => {
+            parent.iter_mut().for_each(remap);
+            remap(end);
+        }
+        SymbolRecord::Block { parent, end, .. } => {
+            remap(parent);
+            remap(end);
+        }
+        _ => {}
+    }
+}
+
+fn set_scope_parent(symbol: &mut SymbolRecord, offset: Option<SymbolOffset>) -> bool {
+    match symbol {
+        SymbolRecord::Proc(proc)
+        | SymbolRecord::GlobalProc(proc)
+        | SymbolRecord::ProcId(proc)
+        | SymbolRecord::GlobalProcId(proc)
+        | SymbolRecord::DPCProc(proc)
+        | SymbolRecord::DPCProcId(proc) => proc.parent = offset,
+        SymbolRecord::Thunk32 { parent, .. } | SymbolRecord::InlineSite { parent, .. } => *parent = offset,
+        SymbolRecord::Block { parent, .. } => *parent = offset.unwrap_or(SymbolOffset(0)),
+        _ => return false,
+    }
+    true
+}
+
+fn set_scope_end(symbol: &mut SymbolRecord, offset: SymbolOffset) {
+    match symbol {
+        SymbolRecord::Proc(proc)
+        | SymbolRecord::GlobalProc(proc)
+        | SymbolRecord::ProcId(proc)
+        | SymbolRecord::GlobalProcId(proc)
+        | SymbolRecord::DPCProc(proc)
+        | SymbolRecord::DPCProcId(proc) => proc.end = offset,
+        SymbolRecord::Thunk32 { end, .. }
+        | SymbolRecord::InlineSite { end, .. }
+        | SymbolRecord::Block { end, .. } => *end = offset,
+        _ => {}
+    }
+}
+
 struct SymbolStreams {
     publics: StreamIndex,
     globals: StreamIndex,
@@ -513,10 +2314,6 @@ impl StreamAllocator {
     fn insert_builtin(&mut self, stream: BuiltinStream, layout: MsfStreamLayout) {
         self.streams[stream as usize] = layout;
     }
-
-    fn block_count(&self) -> usize {
-        self.streams.iter().map(|layout| layout.blocks.len()).sum()
-    }
 }
 
 impl Default for StreamAllocator {
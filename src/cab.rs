@@ -0,0 +1,170 @@
+use std::io::{self, Read};
+
+use declio::ctx::Len;
+use declio::util::Bytes;
+use declio::{magic_bytes, Decode};
+use miniz_oxide::inflate::core::{decompress, inflate_flags, DecompressorOxide};
+use miniz_oxide::inflate::TINFLStatus;
+
+use crate::constants;
+use crate::result::{Error, Result};
+use crate::utils::StrBuf;
+
+magic_bytes! {
+    #[derive(Debug)]
+    CabSignature(b"MSCF");
+    #[derive(Debug)]
+    MsZipSignature(b"CK");
+}
+
+const FLAG_PREV_CABINET: u16 = 0x1;
+const FLAG_NEXT_CABINET: u16 = 0x2;
+const FLAG_RESERVE_PRESENT: u16 = 0x4;
+
+const COMPRESSION_NONE: u16 = 0;
+const COMPRESSION_MSZIP: u16 = 1;
+
+// a file stored in a cabinet, symbol servers serve compressed PDBs as single file cabinets
+#[derive(Debug)]
+pub struct CabFile {
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+pub fn is_cab(bytes: &[u8]) -> bool {
+    bytes.starts_with(b"MSCF")
+}
+
+pub fn extract<R: io::Read + io::Seek>(mut reader: R) -> Result<Vec<CabFile>> {
+    let header = CabHeader::decode((), &mut reader)?;
+    if header.flags & (FLAG_PREV_CABINET | FLAG_NEXT_CABINET) != 0 {
+        return Err(Error::UnsupportedFeature("multi-volume cabinet"));
+    }
+    let reserve = if header.flags & FLAG_RESERVE_PRESENT != 0 {
+        let reserve = CabReserve::decode((), &mut reader)?;
+        reader.seek(io::SeekFrom::Current(reserve.header_bytes.into()))?;
+        reserve
+    } else {
+        CabReserve::default()
+    };
+
+    let mut folders = Vec::with_capacity(header.num_folders.into());
+    for _ in 0..header.num_folders {
+        let folder = CabFolder::decode((), &mut reader)?;
+        reader.seek(io::SeekFrom::Current(reserve.folder_bytes.into()))?;
+        folders.push(folder);
+    }
+
+    reader.seek(io::SeekFrom::Start(header.files_offset.into()))?;
+    let entries: Vec<CabFileEntry> = Decode::decode((Len(header.num_files.into()), ()), &mut reader)?;
+
+    let mut contents = Vec::with_capacity(folders.len());
+    for folder in &folders {
+        reader.seek(io::SeekFrom::Start(folder.data_offset.into()))?;
+        contents.push(read_folder(&mut reader, folder, reserve.data_bytes)?);
+    }
+
+    let mut files = Vec::with_capacity(entries.len());
+    for entry in entries {
+        let content = contents
+            .get(usize::from(entry.folder))
+            .ok_or(Error::UnsupportedFeature("cabinet file in a continued folder"))?;
+        let start = entry.folder_offset as usize;
+        let data = content
+            .get(start..start.saturating_add(entry.size as usize))
+            .ok_or(Error::MalformedInput("cabinet file out of folder bounds"))?;
+        files.push(CabFile {
+            name: entry.name.as_ref().to_owned(),
+            data: data.to_vec(),
+        });
+    }
+    Ok(files)
+}
+
+fn read_folder<R: io::Read>(reader: &mut R, folder: &CabFolder, reserve: u8) -> Result<Vec<u8>> {
+    let mut out = vec![];
+    for _ in 0..folder.num_blocks {
+        let block = CabDataHeader::decode((), reader)?;
+        io::copy(&mut reader.by_ref().take(reserve.into()), &mut io::sink())?;
+        let data = <Bytes>::decode(Len(block.compressed_bytes.into()), reader)?.into_vec();
+
+        match folder.compression & 0xF {
+            COMPRESSION_NONE => out.extend(data),
+            COMPRESSION_MSZIP => inflate_block(&data, &mut out, block.uncompressed_bytes.into())?,
+            _ => return Err(Error::UnsupportedFeature("cabinet compression other than MSZIP")),
+        }
+    }
+    Ok(out)
+}
+
+// every MSZIP block is a separate deflate stream that can refer back to the previous blocks
+fn inflate_block(mut data: &[u8], out: &mut Vec<u8>, size: usize) -> Result<()> {
+    MsZipSignature::decode((), &mut data)?;
+    let start = out.len();
+    out.resize(start + size, 0);
+
+    let mut decompressor = DecompressorOxide::new();
+    let flags = inflate_flags::TINFL_FLAG_USING_NON_WRAPPING_OUTPUT_BUF;
+    let (status, _, written) = decompress(&mut decompressor, data, out, start, flags);
+    if status != TINFLStatus::Done || written != size {
+        return Err(Error::MalformedInput("corrupted MSZIP block"));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Decode)]
+#[declio(ctx_is = "constants::ENDIANESS")]
+#[allow(unused)]
+struct CabHeader {
+    signature: CabSignature,
+    reserved1: u32,
+    size: u32,
+    reserved2: u32,
+    files_offset: u32,
+    reserved3: u32,
+    version_minor: u8,
+    version_major: u8,
+    num_folders: u16,
+    num_files: u16,
+    flags: u16,
+    set_id: u16,
+    cabinet_index: u16,
+}
+
+#[derive(Debug, Default, Decode)]
+#[declio(ctx_is = "constants::ENDIANESS")]
+struct CabReserve {
+    header_bytes: u16,
+    folder_bytes: u8,
+    data_bytes: u8,
+}
+
+#[derive(Debug, Decode)]
+#[declio(ctx_is = "constants::ENDIANESS")]
+struct CabFolder {
+    data_offset: u32,
+    num_blocks: u16,
+    compression: u16,
+}
+
+#[derive(Debug, Decode)]
+#[declio(ctx_is = "constants::ENDIANESS")]
+#[allow(unused)]
+struct CabFileEntry {
+    size: u32,
+    folder_offset: u32,
+    folder: u16,
+    date: u16,
+    time: u16,
+    attributes: u16,
+    name: StrBuf,
+}
+
+#[derive(Debug, Decode)]
+#[declio(ctx_is = "constants::ENDIANESS")]
+#[allow(unused)]
+struct CabDataHeader {
+    checksum: u32,
+    compressed_bytes: u16,
+    uncompressed_bytes: u16,
+}
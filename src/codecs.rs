@@ -33,6 +33,65 @@ pub mod optional_index {
     }
 }
 
+// a stream index that is nil when set to 0xFFFF
+pub mod optional_stream {
+    use declio::{Decode, Encode, Error};
+
+    use crate::{constants, StreamIndex};
+
+    const NIL: u16 = u16::MAX;
+
+    #[inline]
+    pub fn encode<Ctx, W>(val: &Option<StreamIndex>, _ctx: Ctx, writer: &mut W) -> Result<(), Error>
+    where
+        W: std::io::Write,
+    {
+        let val = val.map_or(NIL, u16::from);
+        u16::encode(&val, constants::ENDIANESS, writer)
+    }
+
+    #[inline]
+    pub fn decode<Ctx, R>(_ctx: Ctx, reader: &mut R) -> Result<Option<StreamIndex>, Error>
+    where
+        R: std::io::Read,
+    {
+        let val = u16::decode(constants::ENDIANESS, reader)?;
+        Ok((val != NIL).then(|| val.into()))
+    }
+}
+
+// a 16-bit count followed by that many null-terminated strings, as in S_ANNOTATION
+pub mod counted_strings {
+    use declio::{Decode, Encode, EncodedSize, Error};
+
+    use crate::constants;
+    use crate::utils::StrBuf;
+
+    pub fn encode<Ctx, W>(strings: &[StrBuf], _ctx: Ctx, writer: &mut W) -> Result<(), Error>
+    where
+        W: std::io::Write,
+    {
+        let count = u16::try_from(strings.len()).map_err(Error::wrap)?;
+        count.encode(constants::ENDIANESS, writer)?;
+        for str in strings {
+            str.encode((), writer)?;
+        }
+        Ok(())
+    }
+
+    pub fn decode<Ctx, R>(_ctx: Ctx, reader: &mut R) -> Result<Vec<StrBuf>, Error>
+    where
+        R: std::io::Read,
+    {
+        let count = u16::decode(constants::ENDIANESS, reader)?;
+        (0..count).map(|_| StrBuf::decode((), reader)).collect()
+    }
+
+    pub fn encoded_size<Ctx>(strings: &[StrBuf], _ctx: Ctx) -> usize {
+        std::mem::size_of::<u16>() + strings.iter().map(|str| str.encoded_size(())).sum::<usize>()
+    }
+}
+
 pub mod padded_rem_list {
     use declio::{Decode, Encode, EncodedSize};
 
@@ -44,6 +103,21 @@ pub mod padded_rem_list {
         A: Decode<Ctx>,
         R: std::io::Read,
         Ctx: Copy,
+    {
+        decode_checked(ctx, reader, |_, _| {})
+    }
+
+    // like `decode`, but passes the offset of every unexpected padding byte to a callback
+    pub fn decode_checked<A, Ctx, R, F>(
+        ctx: Ctx,
+        reader: &mut R,
+        mut on_padding: F,
+    ) -> Result<Vec<A>, declio::Error>
+    where
+        A: Decode<Ctx>,
+        R: std::io::Read,
+        Ctx: Copy,
+        F: FnMut(usize, u8),
     {
         let mut buf = vec![];
         reader.read_to_end(&mut buf)?;
@@ -57,6 +131,16 @@ pub mod padded_rem_list {
             let read = rem - slice.len();
             if read % RECORD_ALIGNMENT != 0 {
                 let padding = RECORD_ALIGNMENT - (read % RECORD_ALIGNMENT);
+                let offset = buf.len() - slice.len();
+                let bytes = slice
+                    .get(..padding)
+                    .ok_or_else(|| declio::Error::new("Record padding out of bounds"))?;
+                // either zeroes or the length of the padding ORed with 0xF0 followed by zeroes
+                for (i, &byte) in bytes.iter().enumerate() {
+                    if byte != 0 && (i != 0 || byte != padding as u8 | 0xF0) {
+                        on_padding(offset + i, byte);
+                    }
+                }
                 slice = &slice[padding..];
             }
             rem = slice.len();
@@ -166,3 +250,58 @@ macro_rules! impl_bitfield_specifier_codecs {
         }
     };
 }
+
+// defines an enum stored as a whole u32 along with its codecs, `BitfieldSpecifier` can't be used for
+// these because its discriminant checks overflow on targets with a 32-bit usize like wasm32
+#[macro_export]
+macro_rules! u32_enum {
+    (
+        $(#[$meta:meta])*
+        $vis:vis enum $name:ident {
+            $($(#[$variant_meta:meta])* $variant:ident = $value:expr),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        #[repr(u32)]
+        $vis enum $name {
+            $($(#[$variant_meta])* $variant = $value),*
+        }
+
+        impl $name {
+            pub(crate) fn from_u32(value: u32) -> Option<Self> {
+                $(if value == $value {
+                    return Some(Self::$variant);
+                })*
+                None
+            }
+        }
+
+        impl<Ctx> ::declio::Decode<Ctx> for $name {
+            fn decode<R>(_ctx: Ctx, reader: &mut R) -> Result<Self, ::declio::Error>
+            where
+                R: ::std::io::Read,
+            {
+                let value = ::declio::Decode::decode($crate::constants::ENDIANESS, reader)?;
+                Self::from_u32(value).ok_or_else(|| {
+                    ::declio::Error::new(format!("invalid {} value {value:#x}", stringify!($name)))
+                })
+            }
+        }
+
+        impl<Ctx> ::declio::Encode<Ctx> for $name {
+            fn encode<W>(&self, _ctx: Ctx, writer: &mut W) -> Result<(), ::declio::Error>
+            where
+                W: ::std::io::Write,
+            {
+                ::declio::Encode::encode(&(*self as u32), $crate::constants::ENDIANESS, writer)
+            }
+        }
+
+        impl<Ctx> ::declio::EncodedSize<Ctx> for $name {
+            #[inline]
+            fn encoded_size(&self, _ctx: Ctx) -> usize {
+                ::std::mem::size_of::<u32>()
+            }
+        }
+    };
+}
@@ -1,11 +1,16 @@
 use std::cmp::Ordering;
 use std::io::{self, Read};
+use std::marker::PhantomData;
 
 use declio::{Decode, Encode, EncodedSize};
 use symbols::{Public, SymbolRecord};
 
-use crate::constants;
+use crate::dbi::MachineType;
+use crate::diagnostics::ParseLimits;
+use crate::result::{Error, Result};
+use crate::types::FIRST_NON_BUILTIN_TYPE;
 use crate::utils::align_to;
+use crate::{constants, IdIndex, StreamIndex, TypeIndex};
 
 pub mod symbols;
 pub mod types;
@@ -22,14 +27,44 @@ impl<A> PrefixedRecord<A> {
 }
 
 impl<A> PrefixedRecord<A> {
-    pub fn decode<R>(reader: &mut R) -> Result<Self, declio::Error>
+    pub fn decode<R>(reader: &mut R) -> Result<Self>
     where
         A: Decode,
         R: io::Read,
+    {
+        Self::decode_with_limits(reader, &ParseLimits::default())
+    }
+
+    pub fn decode_with_limits<R>(reader: &mut R, limits: &ParseLimits) -> Result<Self>
+    where
+        A: Decode,
+        R: io::Read,
+    {
+        Self::decode_with(reader, limits, |_| {})
+    }
+
+    // like `decode_with_limits`, but passes the kind of the record to a callback ahead of decoding it
+    pub fn decode_with<R, F>(reader: &mut R, limits: &ParseLimits, on_kind: F) -> Result<Self>
+    where
+        A: Decode,
+        R: io::Read,
+        F: FnOnce(u16),
     {
         let len = u16::decode(constants::ENDIANESS, reader)?;
+        limits.check_record_size(len.into())?;
         let mut slice = reader.take(len.into());
-        let res = A::decode((), &mut slice)?;
+        let mut kind = [0; 2];
+        slice.read_exact(&mut kind)?;
+        on_kind(u16::from_le_bytes(kind));
+        let res = match A::decode((), &mut (&kind[..]).chain(slice.by_ref())) {
+            Ok(res) => res,
+            Err(source) => {
+                // skip the rest of the record so that the reader can resume after it
+                io::copy(&mut slice, &mut io::sink())?;
+                let kind = u16::from_le_bytes(kind);
+                return Err(Error::RecordFailed { kind, source });
+            }
+        };
 
         let mut padding_buffer = [0; 16];
         while slice.limit() != 0 {
@@ -38,13 +73,72 @@ impl<A> PrefixedRecord<A> {
                 let padding = (byte & 0x0F) - 1;
                 slice.read_exact(&mut padding_buffer[..padding as usize])?;
             } else if byte != 0 {
-                return Err(declio::Error::new(format!("invalid pading byte {}", byte)));
+                io::copy(&mut slice, &mut io::sink())?;
+                return Err(Error::InvalidPadding(byte));
             }
         }
         Ok(Self(res))
     }
 }
 
+// decodes length-prefixed records one at a time, a record with a body that fails to decode is skipped
+// using its length and reported in its place, the iteration stops at the first error that leaves the
+// reader inside of a record, like a length past the end of the stream
+pub(crate) struct PrefixedRecords<R, A> {
+    reader: io::Take<R>,
+    len: u64,
+    // the offset of the first record
+    start: u32,
+    stream: &'static str,
+    index: Option<StreamIndex>,
+    limits: ParseLimits,
+    stopped: bool,
+    record: PhantomData<A>,
+}
+
+impl<R, A> PrefixedRecords<R, A> {
+    pub fn new(
+        reader: io::Take<R>,
+        start: u32,
+        stream: &'static str,
+        index: Option<StreamIndex>,
+        limits: ParseLimits,
+    ) -> Self {
+        Self {
+            len: reader.limit(),
+            reader,
+            start,
+            stream,
+            index,
+            limits,
+            stopped: false,
+            record: PhantomData,
+        }
+    }
+}
+
+impl<R, A> Iterator for PrefixedRecords<R, A>
+where
+    R: io::Read,
+    A: Decode,
+{
+    type Item = Result<(u32, A)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.stopped || self.reader.limit() == 0 {
+            return None;
+        }
+        let offset = self.start + (self.len - self.reader.limit()) as u32;
+        match PrefixedRecord::decode_with_limits(&mut self.reader, &self.limits) {
+            Ok(record) => Some(Ok((offset, record.into_inner()))),
+            Err(err) => {
+                self.stopped = !matches!(err, Error::RecordFailed { .. } | Error::InvalidPadding(_));
+                Some(Err(err.in_stream(self.stream, self.index, offset.into())))
+            }
+        }
+    }
+}
+
 impl<A> Encode for PrefixedRecord<A>
 where
     A: Encode + EncodedSize,
@@ -53,25 +147,113 @@ where
     where
         W: io::Write,
     {
-        const PREFIX_SIZE: usize = std::mem::size_of::<u16>();
-        let padding_bytes = [0u8; RECORD_ALIGNMENT];
-
-        let size = self.0.encoded_size(());
-        let full_size = align_to(size + PREFIX_SIZE, RECORD_ALIGNMENT) - PREFIX_SIZE;
-        (full_size as u16).encode(constants::ENDIANESS, writer)?;
-        self.0.encode((), writer)?;
-
-        let padding = full_size - size;
-        if padding != 0 {
-            let pad_byte = padding as u8 | 0xF0;
-            writer.write_all(&[pad_byte])?;
-            writer.write_all(&padding_bytes[0..padding - 1])?;
+        encode_prefixed(&self.0, writer)
+    }
+}
+
+// encodes a record with its length prefix and padding, like `PrefixedRecord` does, but by reference
+pub(crate) fn encode_prefixed<A, W>(record: &A, writer: &mut W) -> Result<(), declio::Error>
+where
+    A: Encode + EncodedSize,
+    W: io::Write,
+{
+    const PREFIX_SIZE: usize = std::mem::size_of::<u16>();
+    let padding_bytes = [0u8; RECORD_ALIGNMENT];
+
+    let size = record.encoded_size(());
+    let full_size = align_to(size + PREFIX_SIZE, RECORD_ALIGNMENT) - PREFIX_SIZE;
+    (full_size as u16).encode(constants::ENDIANESS, writer)?;
+    record.encode((), writer)?;
+
+    let padding = full_size - size;
+    if padding != 0 {
+        let pad_byte = padding as u8 | 0xF0;
+        writer.write_all(&[pad_byte])?;
+        writer.write_all(&padding_bytes[0..padding - 1])?;
+    }
+    Ok(())
+}
+
+// the stream that a record index points into
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexKind {
+    Type,
+    Id,
+}
+
+// rewrites the record indices stored in a field, non-builtin indices are passed to the callback
+pub(crate) trait RemapIndex {
+    fn remap(&mut self, kind: IndexKind, f: &mut dyn FnMut(IndexKind, u32) -> u32);
+}
+
+impl RemapIndex for u32 {
+    fn remap(&mut self, kind: IndexKind, f: &mut dyn FnMut(IndexKind, u32) -> u32) {
+        if *self >= FIRST_NON_BUILTIN_TYPE {
+            *self = f(kind, *self);
+        }
+    }
+}
+
+impl RemapIndex for TypeIndex {
+    fn remap(&mut self, kind: IndexKind, f: &mut dyn FnMut(IndexKind, u32) -> u32) {
+        let mut index = u32::from(*self);
+        index.remap(kind, f);
+        *self = TypeIndex::try_from(index).unwrap_or(*self);
+    }
+}
+
+impl RemapIndex for IdIndex {
+    fn remap(&mut self, kind: IndexKind, f: &mut dyn FnMut(IndexKind, u32) -> u32) {
+        let mut index = u32::from(*self);
+        index.remap(kind, f);
+        *self = IdIndex::try_from(index).unwrap_or(*self);
+    }
+}
+
+impl<A: RemapIndex> RemapIndex for Option<A> {
+    fn remap(&mut self, kind: IndexKind, f: &mut dyn FnMut(IndexKind, u32) -> u32) {
+        if let Some(index) = self {
+            index.remap(kind, f);
         }
-        Ok(())
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Encode, Decode, EncodedSize)]
+impl<A: RemapIndex> RemapIndex for Vec<A> {
+    fn remap(&mut self, kind: IndexKind, f: &mut dyn FnMut(IndexKind, u32) -> u32) {
+        for index in self {
+            index.remap(kind, f);
+        }
+    }
+}
+
+// a record with an undecoded body, used for formats predating the supported leaf kinds
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawRecord {
+    pub kind: u16,
+    pub data: Vec<u8>,
+}
+
+impl RawRecord {
+    // decodes the body as a typed record, e.g. to skip over records that fail to parse
+    pub fn decode_as<A: Decode>(&self) -> Result<A> {
+        let kind = self.kind.to_le_bytes();
+        Ok(A::decode((), &mut kind.chain(&self.data[..]))?)
+    }
+}
+
+impl Decode for RawRecord {
+    fn decode<R>(_ctx: (), reader: &mut R) -> Result<Self, declio::Error>
+    where
+        R: io::Read,
+    {
+        let kind = u16::decode(constants::ENDIANESS, reader)?;
+        let mut data = vec![];
+        reader.read_to_end(&mut data)?;
+        Ok(Self { kind, data })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, EncodedSize)]
 #[declio(ctx_is = "constants::ENDIANESS")]
 pub struct DataRegionOffset {
     pub offset: u32,
@@ -99,10 +281,74 @@ impl Ord for DataRegionOffset {
     }
 }
 
-#[derive(Debug, Encode, Decode, EncodedSize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode, EncodedSize)]
 #[declio(ctx_is = "constants::ENDIANESS")]
 pub struct Register(pub u16);
 
+impl Register {
+    pub const EBX: Register = Register(20);
+    pub const EBP: Register = Register(22);
+    // the virtual frame pointer of x86 frames described by FPO data
+    pub const VFRAME: Register = Register(30006);
+    pub const RBP: Register = Register(334);
+    pub const RSP: Register = Register(335);
+    pub const R13: Register = Register(341);
+    pub const RAX: Register = Register(328);
+    pub const R15: Register = Register(343);
+    pub const ARM64_W0: Register = Register(10);
+    pub const ARM64_X0: Register = Register(50);
+    pub const ARM64_FP: Register = Register(79);
+    pub const ARM64_LR: Register = Register(80);
+    pub const ARM64_SP: Register = Register(81);
+    pub const ARM64_ZR: Register = Register(82);
+    pub const ARM64_PC: Register = Register(83);
+    pub const ARM64_NZCV: Register = Register(90);
+    pub const ARM64_S0: Register = Register(100);
+    pub const ARM64_D0: Register = Register(140);
+    pub const ARM64_Q0: Register = Register(180);
+    pub const ARM64_FPSR: Register = Register(220);
+    pub const ARM64_V0: Register = Register(310);
+
+    // x29 and x30 are also known as FP and LR
+    pub const fn arm64_x(n: u16) -> Register {
+        Register(Self::ARM64_X0.0 + n)
+    }
+
+    pub const fn arm64_w(n: u16) -> Register {
+        Register(Self::ARM64_W0.0 + n)
+    }
+
+    pub const fn arm64_d(n: u16) -> Register {
+        Register(Self::ARM64_D0.0 + n)
+    }
+
+    pub const fn arm64_q(n: u16) -> Register {
+        Register(Self::ARM64_Q0.0 + n)
+    }
+
+    // whether the register exists on the machine, the numbers of x64 registers overlap with
+    // the vector registers of ARM64, so only ARM64 is checked here
+    pub fn is_valid_for(self, machine: MachineType) -> bool {
+        match machine {
+            MachineType::Arm64 => matches!(
+                self.0,
+                0 | 10..=41 | 50..=83 | 90 | 100..=131 | 140..=171 | 180..=211 | 220 | 221 | 310..=341
+            ),
+            _ => true,
+        }
+    }
+
+    // whether memory can be addressed relative to the register, machines other than x64 and ARM64
+    // are not checked
+    pub fn is_base_for(self, machine: MachineType) -> bool {
+        match machine {
+            MachineType::Amd64 => (Self::RAX.0..=Self::R15.0).contains(&self.0),
+            MachineType::Arm64 => (Self::ARM64_X0.0..=Self::ARM64_SP.0).contains(&self.0),
+            _ => true,
+        }
+    }
+}
+
 pub(crate) trait NamedSymbol {
     fn name(&self) -> Option<&str>;
 }
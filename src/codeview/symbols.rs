@@ -1,16 +1,18 @@
+use std::borrow::Cow;
 use std::fmt::Debug;
 
 use declio::util::LittleEndian;
 use declio::{Decode, Encode, EncodedSize};
 use modular_bitfield::prelude::*;
 
-use super::{DataRegionOffset, Register};
+use super::{DataRegionOffset, IndexKind, Register, RemapIndex};
+use crate::dbi::{MachineType, SectionCharacteristics};
 use crate::utils::StrBuf;
 use crate::{
     codecs, constants, impl_bitfield_codecs, impl_bitfield_specifier_codecs, IdIndex, Integer, SymbolOffset, TypeIndex
 };
 
-#[derive(Debug, Encode, Decode, EncodedSize)]
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, EncodedSize)]
 #[declio(ctx_is = "constants::ENDIANESS", id_type = "LittleEndian<u16>")]
 pub enum SymbolRecord {
     #[declio(id = "constants::S_INLINEES.into()")]
@@ -88,6 +90,10 @@ pub enum SymbolRecord {
     ProcedureRef(ProcedureRef),
     #[declio(id = "constants::S_LPROCREF.into()")]
     LocalProcedureRef(ProcedureRef),
+    #[declio(id = "constants::S_DATAREF.into()")]
+    DataRef(ProcedureRef),
+    #[declio(id = "constants::S_ANNOTATIONREF.into()")]
+    AnnotationRef(ProcedureRef),
     #[declio(id = "constants::S_ENVBLOCK.into()")]
     EnvBlock {
         #[declio(with = "codecs::padded_rem_list")]
@@ -275,12 +281,21 @@ pub enum SymbolRecord {
     #[declio(id = "constants::S_ANNOTATION.into()")]
     Annotation {
         code_offset: DataRegionOffset,
-        #[declio(with = "codecs::padded_rem_list")]
+        #[declio(with = "codecs::counted_strings")]
         strings: Vec<StrBuf>,
     },
 }
 
 impl SymbolRecord {
+    // the characteristics of S_SECTION and S_COFFGROUP records
+    pub fn section_flags(&self) -> Option<SectionCharacteristics> {
+        match self {
+            SymbolRecord::Section { characteristics, .. }
+            | SymbolRecord::CoffGroup { characteristics, .. } => Some((*characteristics).into()),
+            _ => None,
+        }
+    }
+
     pub fn name(&self) -> Option<&str> {
         match self {
             SymbolRecord::Thunk32 { name, .. } => Some(name.as_ref()),
@@ -297,6 +312,8 @@ impl SymbolRecord {
             SymbolRecord::Public32(public) => Some(public.name.as_ref()),
             SymbolRecord::ProcedureRef(proc) => Some(proc.name.as_ref()),
             SymbolRecord::LocalProcedureRef(proc) => Some(proc.name.as_ref()),
+            SymbolRecord::DataRef(data) => Some(data.name.as_ref()),
+            SymbolRecord::AnnotationRef(annotation) => Some(annotation.name.as_ref()),
             SymbolRecord::Local { name, .. } => Some(name.as_ref()),
             SymbolRecord::Block { name, .. } => Some(name.as_ref()),
             SymbolRecord::Label { name, .. } => Some(name.as_ref()),
@@ -318,9 +335,204 @@ impl SymbolRecord {
             _ => None,
         }
     }
+
+    pub fn name_mut(&mut self) -> Option<&mut StrBuf> {
+        match self {
+            SymbolRecord::Thunk32 { name, .. } => Some(name),
+            SymbolRecord::Section { name, .. } => Some(name),
+            SymbolRecord::CoffGroup { name, .. } => Some(name),
+            SymbolRecord::Export { name, .. } => Some(name),
+            SymbolRecord::Proc(proc) => Some(&mut proc.name),
+            SymbolRecord::GlobalProc(proc) => Some(&mut proc.name),
+            SymbolRecord::ProcId(proc) => Some(&mut proc.name),
+            SymbolRecord::GlobalProcId(proc) => Some(&mut proc.name),
+            SymbolRecord::DPCProc(proc) => Some(&mut proc.name),
+            SymbolRecord::DPCProcId(proc) => Some(&mut proc.name),
+            SymbolRecord::Register { name, .. } => Some(name),
+            SymbolRecord::Public32(public) => Some(&mut public.name),
+            SymbolRecord::ProcedureRef(proc) => Some(&mut proc.name),
+            SymbolRecord::LocalProcedureRef(proc) => Some(&mut proc.name),
+            SymbolRecord::DataRef(data) => Some(&mut data.name),
+            SymbolRecord::AnnotationRef(annotation) => Some(&mut annotation.name),
+            SymbolRecord::Local { name, .. } => Some(name),
+            SymbolRecord::Block { name, .. } => Some(name),
+            SymbolRecord::Label { name, .. } => Some(name),
+            SymbolRecord::ObjectName { name, .. } => Some(name),
+            SymbolRecord::FileStatic { name, .. } => Some(name),
+            SymbolRecord::Udt(udt) => Some(&mut udt.name),
+            SymbolRecord::CobolUdt(udt) => Some(&mut udt.name),
+            SymbolRecord::BasePointerRelative { name, .. } => Some(name),
+            SymbolRecord::RegisterRelative { name, .. } => Some(name),
+            SymbolRecord::Constant(constant) => Some(&mut constant.name),
+            SymbolRecord::ManagedConstant(constant) => Some(&mut constant.name),
+            SymbolRecord::Data(data) => Some(&mut data.name),
+            SymbolRecord::GlobalData(data) => Some(&mut data.name),
+            SymbolRecord::ManagedLocalData(data) => Some(&mut data.name),
+            SymbolRecord::ManagedGlobalData(data) => Some(&mut data.name),
+            SymbolRecord::ThreadLocalStorage(tls) => Some(&mut tls.name),
+            SymbolRecord::GlobalThreadLocalStorage(tls) => Some(&mut tls.name),
+            SymbolRecord::UsingNamespace { name } => Some(name),
+            _ => None,
+        }
+    }
+
+    // rewrites the type and IPI indices referenced by the symbol
+    pub fn remap_indices(&mut self, f: &mut dyn FnMut(IndexKind, u32) -> u32) {
+        use IndexKind::{Id, Type};
+        match self {
+            SymbolRecord::Proc(proc) | SymbolRecord::GlobalProc(proc) | SymbolRecord::DPCProc(proc) => {
+                proc.function_type.remap(Type, f)
+            }
+            SymbolRecord::ProcId(proc)
+            | SymbolRecord::GlobalProcId(proc)
+            | SymbolRecord::DPCProcId(proc) => proc.function_type.remap(Id, f),
+            SymbolRecord::Register { contained_type, .. } => contained_type.remap(Type, f),
+            SymbolRecord::Local { local_type, .. } => local_type.remap(Type, f),
+            SymbolRecord::CallSiteInfo { call_type, .. } => call_type.remap(Type, f),
+            SymbolRecord::FileStatic { index, .. } => index.remap(Type, f),
+            SymbolRecord::HeapAllocationSite { call_type, .. } => call_type.remap(Type, f),
+            SymbolRecord::Caller { types } => types.remap(Type, f),
+            SymbolRecord::Udt(udt) | SymbolRecord::CobolUdt(udt) => udt.udt_type.remap(Type, f),
+            SymbolRecord::BasePointerRelative { value_type, .. } => value_type.remap(Type, f),
+            SymbolRecord::RegisterRelative { value_type, .. } => value_type.remap(Type, f),
+            SymbolRecord::Constant(constant) | SymbolRecord::ManagedConstant(constant) => {
+                constant.constant_type.remap(Type, f)
+            }
+            SymbolRecord::Data(data)
+            | SymbolRecord::GlobalData(data)
+            | SymbolRecord::ManagedLocalData(data)
+            | SymbolRecord::ManagedGlobalData(data) => data.data_type.remap(Type, f),
+            SymbolRecord::ThreadLocalStorage(tls) | SymbolRecord::GlobalThreadLocalStorage(tls) => {
+                tls.data_type.remap(Type, f)
+            }
+            SymbolRecord::InlineSite { inlinee, .. } => inlinee.remap(Id, f),
+            SymbolRecord::BuildInfo { build_record } => build_record.remap(Id, f),
+            _ => {}
+        }
+    }
+
+    // rewrites the section and offset pairs addressing the code and data the symbol describes
+    pub fn remap_addresses(&mut self, f: &mut dyn FnMut(&mut DataRegionOffset)) {
+        match self {
+            SymbolRecord::Thunk32 { offset, .. }
+            | SymbolRecord::CoffGroup { offset, .. }
+            | SymbolRecord::Block {
+                code_offset: offset, ..
+            }
+            | SymbolRecord::Label {
+                code_offset: offset, ..
+            }
+            | SymbolRecord::CallSiteInfo {
+                code_offset: offset, ..
+            }
+            | SymbolRecord::HeapAllocationSite {
+                code_offset: offset, ..
+            }
+            | SymbolRecord::Annotation {
+                code_offset: offset, ..
+            } => f(offset),
+            SymbolRecord::Proc(proc)
+            | SymbolRecord::GlobalProc(proc)
+            | SymbolRecord::ProcId(proc)
+            | SymbolRecord::GlobalProcId(proc)
+            | SymbolRecord::DPCProc(proc)
+            | SymbolRecord::DPCProcId(proc) => f(&mut proc.code_offset),
+            SymbolRecord::Public32(public) => f(&mut public.offset),
+            SymbolRecord::Data(data)
+            | SymbolRecord::GlobalData(data)
+            | SymbolRecord::ManagedLocalData(data)
+            | SymbolRecord::ManagedGlobalData(data) => f(&mut data.offset),
+            SymbolRecord::ThreadLocalStorage(tls) | SymbolRecord::GlobalThreadLocalStorage(tls) => {
+                f(&mut tls.offset)
+            }
+            SymbolRecord::Trampoline {
+                thunk_offset,
+                target_offset,
+                thunk_section,
+                target_section,
+                ..
+            } => {
+                remap_pair(thunk_offset, thunk_section, f);
+                remap_pair(target_offset, target_section, f);
+            }
+            SymbolRecord::DefRange { range, .. }
+            | SymbolRecord::DefRangeSubfield { range, .. }
+            | SymbolRecord::DefRangeRegister { range, .. }
+            | SymbolRecord::DefRangeFramePointerRel { range, .. }
+            | SymbolRecord::DefRangeSubfieldRegister { range, .. }
+            | SymbolRecord::DefRangeRegisterRel { range, .. } => {
+                remap_pair(&mut range.offset_start, &mut range.i_sect_start, f)
+            }
+            _ => {}
+        }
+    }
+
+    pub fn referenced_types(&self) -> Vec<TypeIndex> {
+        match self {
+            SymbolRecord::Proc(proc) | SymbolRecord::GlobalProc(proc) | SymbolRecord::DPCProc(proc) => {
+                vec![proc.function_type]
+            }
+            SymbolRecord::Register { contained_type, .. } => vec![*contained_type],
+            SymbolRecord::Local { local_type, .. } => vec![*local_type],
+            SymbolRecord::CallSiteInfo { call_type, .. } => vec![*call_type],
+            SymbolRecord::FileStatic { index, .. } => vec![*index],
+            SymbolRecord::HeapAllocationSite { call_type, .. } => vec![*call_type],
+            SymbolRecord::Caller { types } => types.clone(),
+            SymbolRecord::Udt(udt) | SymbolRecord::CobolUdt(udt) => vec![udt.udt_type],
+            SymbolRecord::BasePointerRelative { value_type, .. } => vec![*value_type],
+            SymbolRecord::RegisterRelative { value_type, .. } => vec![*value_type],
+            SymbolRecord::Constant(constant) | SymbolRecord::ManagedConstant(constant) => {
+                vec![constant.constant_type]
+            }
+            SymbolRecord::Data(data)
+            | SymbolRecord::GlobalData(data)
+            | SymbolRecord::ManagedLocalData(data)
+            | SymbolRecord::ManagedGlobalData(data) => vec![data.data_type],
+            SymbolRecord::ThreadLocalStorage(tls) | SymbolRecord::GlobalThreadLocalStorage(tls) => {
+                vec![tls.data_type]
+            }
+            _ => vec![],
+        }
+    }
+
+    // references into the IPI stream
+    pub fn referenced_ids(&self) -> Vec<IdIndex> {
+        match self {
+            // the function type of these procedures is an ID
+            SymbolRecord::ProcId(proc)
+            | SymbolRecord::GlobalProcId(proc)
+            | SymbolRecord::DPCProcId(proc) => IdIndex::try_from(u32::from(proc.function_type))
+                .into_iter()
+                .collect(),
+            SymbolRecord::InlineSite { inlinee, .. } => vec![*inlinee],
+            SymbolRecord::BuildInfo { build_record } => vec![*build_record],
+            _ => vec![],
+        }
+    }
+}
+
+// lets builders take records either by value or by reference, cloning the borrowed ones only when
+// they have to keep them
+impl From<SymbolRecord> for Cow<'_, SymbolRecord> {
+    fn from(symbol: SymbolRecord) -> Self {
+        Cow::Owned(symbol)
+    }
+}
+
+impl<'a> From<&'a SymbolRecord> for Cow<'a, SymbolRecord> {
+    fn from(symbol: &'a SymbolRecord) -> Self {
+        Cow::Borrowed(symbol)
+    }
 }
 
-#[derive(Debug, Encode, Decode, EncodedSize)]
+fn remap_pair(offset: &mut u32, segment: &mut u16, f: &mut dyn FnMut(&mut DataRegionOffset)) {
+    let mut pair = DataRegionOffset::new(*offset, *segment);
+    f(&mut pair);
+    *offset = pair.offset;
+    *segment = pair.segment;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, EncodedSize)]
 #[declio(ctx_is = "constants::ENDIANESS")]
 pub struct LocalVariableRange {
     pub offset_start: u32,
@@ -328,14 +540,14 @@ pub struct LocalVariableRange {
     pub range: u16,
 }
 
-#[derive(Debug, Encode, Decode, EncodedSize)]
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, EncodedSize)]
 #[declio(ctx_is = "constants::ENDIANESS")]
 pub struct LocalVariableGap {
     pub gap_start_offset: u16,
     pub range: u16,
 }
 
-#[derive(Debug, Encode, Decode, EncodedSize)]
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, EncodedSize)]
 #[declio(ctx_is = "constants::ENDIANESS")]
 pub struct Version {
     pub major: u16,
@@ -343,7 +555,7 @@ pub struct Version {
     pub build: u16,
 }
 
-#[derive(Debug, Encode, Decode, EncodedSize)]
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, EncodedSize)]
 #[declio(ctx_is = "constants::ENDIANESS")]
 pub struct Public {
     pub properties: PublicProperties,
@@ -351,14 +563,14 @@ pub struct Public {
     pub name: StrBuf,
 }
 
-#[derive(Debug, Encode, Decode, EncodedSize)]
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, EncodedSize)]
 #[declio(ctx_is = "constants::ENDIANESS")]
 pub struct UserDefinedType {
     pub udt_type: TypeIndex,
     pub name: StrBuf,
 }
 
-#[derive(Debug, Encode, Decode, EncodedSize)]
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, EncodedSize)]
 #[declio(ctx_is = "constants::ENDIANESS")]
 pub struct Constant {
     pub constant_type: TypeIndex,
@@ -366,7 +578,7 @@ pub struct Constant {
     pub name: StrBuf,
 }
 
-#[derive(Debug, Encode, Decode, EncodedSize)]
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, EncodedSize)]
 #[declio(ctx_is = "constants::ENDIANESS")]
 pub struct Data {
     pub data_type: TypeIndex,
@@ -374,7 +586,7 @@ pub struct Data {
     pub name: StrBuf,
 }
 
-#[derive(Debug, Encode, Decode, EncodedSize)]
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, EncodedSize)]
 #[declio(ctx_is = "constants::ENDIANESS")]
 pub struct ThreadLocalStorage {
     pub data_type: TypeIndex,
@@ -382,7 +594,7 @@ pub struct ThreadLocalStorage {
     pub name: StrBuf,
 }
 
-#[derive(Debug, Encode, Decode, EncodedSize)]
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, EncodedSize)]
 #[declio(ctx_is = "constants::ENDIANESS")]
 pub struct ProcedureRef {
     pub sum_name: u32,
@@ -391,7 +603,7 @@ pub struct ProcedureRef {
     pub name: StrBuf,
 }
 
-#[derive(Debug, Encode, Decode, EncodedSize)]
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, EncodedSize)]
 #[declio(ctx_is = "constants::ENDIANESS")]
 pub struct Procedure {
     #[declio(with = "codecs::optional_index")]
@@ -408,7 +620,7 @@ pub struct Procedure {
     pub name: StrBuf,
 }
 
-#[derive(Debug, Clone, Copy, BitfieldSpecifier)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, BitfieldSpecifier)]
 #[bits = 8]
 pub enum ThunkOrdinal {
     Standard,
@@ -422,7 +634,7 @@ pub enum ThunkOrdinal {
 
 impl_bitfield_specifier_codecs!(ThunkOrdinal);
 
-#[derive(Debug, Clone, Copy, BitfieldSpecifier)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, BitfieldSpecifier)]
 #[bits = 16]
 pub enum TrampolineType {
     TrampIncremental,
@@ -431,7 +643,7 @@ pub enum TrampolineType {
 
 impl_bitfield_specifier_codecs!(TrampolineType);
 
-#[derive(Debug, Clone, Copy, BitfieldSpecifier)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, BitfieldSpecifier)]
 #[bits = 8]
 pub enum FrameCookie {
     Copy,
@@ -442,7 +654,7 @@ pub enum FrameCookie {
 
 impl_bitfield_specifier_codecs!(FrameCookie);
 
-#[derive(Debug, Clone, Copy, BitfieldSpecifier)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, BitfieldSpecifier)]
 #[bits = 8]
 pub enum SourceLanguage {
     C = 0x00,
@@ -470,7 +682,7 @@ pub enum SourceLanguage {
 impl_bitfield_specifier_codecs!(SourceLanguage);
 
 #[bitfield(bits = 16)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct LocalProperties {
     pub is_parameter: bool,
     pub is_address_taken: bool,
@@ -490,7 +702,7 @@ pub struct LocalProperties {
 impl_bitfield_codecs!(LocalProperties);
 
 #[bitfield(bits = 32)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct PublicProperties {
     pub is_code: bool,
     pub is_function: bool,
@@ -503,7 +715,7 @@ pub struct PublicProperties {
 impl_bitfield_codecs!(PublicProperties);
 
 #[bitfield(bits = 8)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct ProcedureProperties {
     pub has_fp: bool,
     pub has_iret: bool,
@@ -518,7 +730,7 @@ pub struct ProcedureProperties {
 impl_bitfield_codecs!(ProcedureProperties);
 
 #[bitfield(bits = 32)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct CompileProperties {
     pub source_language: SourceLanguage,
     pub is_ec: bool,
@@ -540,7 +752,7 @@ pub struct CompileProperties {
 impl_bitfield_codecs!(CompileProperties);
 
 #[bitfield(bits = 16)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct ExportProperties {
     pub is_constant: bool,
     pub is_data: bool,
@@ -555,7 +767,7 @@ pub struct ExportProperties {
 impl_bitfield_codecs!(ExportProperties);
 
 #[bitfield(bits = 16)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct DefRangeRegisterRelProperties {
     pub is_subfield: bool,
     #[skip]
@@ -566,7 +778,7 @@ pub struct DefRangeRegisterRelProperties {
 impl_bitfield_codecs!(DefRangeRegisterRelProperties);
 
 #[bitfield(bits = 32)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct FrameProcedureProperties {
     pub has_alloca: bool,
     pub has_set_jmp: bool,
@@ -594,3 +806,29 @@ pub struct FrameProcedureProperties {
 }
 
 impl_bitfield_codecs!(FrameProcedureProperties);
+
+impl FrameProcedureProperties {
+    // the register that locals are addressed relative to
+    pub fn local_base_pointer(&self, machine: MachineType) -> Option<Register> {
+        decode_frame_pointer(self.encoded_local_base_pointer(), machine)
+    }
+
+    // the register that parameters are addressed relative to
+    pub fn param_base_pointer(&self, machine: MachineType) -> Option<Register> {
+        decode_frame_pointer(self.encoded_param_base_pointer(), machine)
+    }
+}
+
+fn decode_frame_pointer(encoded: u8, machine: MachineType) -> Option<Register> {
+    match (machine, encoded) {
+        (MachineType::X86, 1) => Some(Register::VFRAME),
+        (MachineType::X86, 2) => Some(Register::EBP),
+        (MachineType::X86, 3) => Some(Register::EBX),
+        (MachineType::Amd64, 1) => Some(Register::RSP),
+        (MachineType::Amd64, 2) => Some(Register::RBP),
+        (MachineType::Amd64, 3) => Some(Register::R13),
+        (MachineType::Arm64, 1) => Some(Register::ARM64_SP),
+        (MachineType::Arm64, 2) => Some(Register::ARM64_FP),
+        _ => None,
+    }
+}
@@ -5,12 +5,13 @@ use declio::util::LittleEndian;
 use declio::{Decode, Encode, EncodedSize};
 use modular_bitfield::prelude::*;
 
+use crate::codeview::{IndexKind, RemapIndex};
 use crate::utils::StrBuf;
 use crate::{
-    codecs, constants, div_ceil, impl_bitfield_codecs, impl_bitfield_specifier_codecs, Guid, Integer, TypeIndex
+    codecs, constants, div_ceil, impl_bitfield_codecs, impl_bitfield_specifier_codecs, u32_enum, Guid, IdIndex, Integer, TypeIndex
 };
 
-#[derive(Debug, Encode, Decode, EncodedSize)]
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, EncodedSize)]
 #[declio(ctx_is = "constants::ENDIANESS", id_type = "LittleEndian<u16>")]
 pub enum TypeRecord {
     #[declio(id = "constants::LF_POINTER.into()")]
@@ -158,7 +159,227 @@ pub enum TypeRecord {
     },
 }
 
-#[derive(Debug, Encode, Decode, EncodedSize)]
+impl TypeRecord {
+    pub fn name(&self) -> Option<&str> {
+        match self {
+            TypeRecord::Class(class) => Some(class.name.as_ref()),
+            TypeRecord::Struct(class) => Some(class.name.as_ref()),
+            TypeRecord::Interface(class) => Some(class.name.as_ref()),
+            TypeRecord::Union(union) => Some(union.name.as_ref()),
+            TypeRecord::Enum(enum_) => Some(enum_.name.as_ref()),
+            TypeRecord::TypeServer2 { name, .. } => Some(name.as_ref()),
+            TypeRecord::StaticDataMember { name, .. } => Some(name.as_ref()),
+            TypeRecord::OverloadedMethod { name, .. } => Some(name.as_ref()),
+            TypeRecord::DataMember { name, .. } => Some(name.as_ref()),
+            TypeRecord::NestedType { name, .. } => Some(name.as_ref()),
+            TypeRecord::OneMethod { name, .. } => Some(name.as_ref()),
+            TypeRecord::Enumerator { name, .. } => Some(name.as_ref()),
+            _ => None,
+        }
+    }
+
+    // rewrites the type indices of the record, including those of nested field list members
+    pub fn remap_indices(&mut self, f: &mut dyn FnMut(IndexKind, u32) -> u32) {
+        use IndexKind::Type;
+        match self {
+            TypeRecord::Pointer {
+                referent,
+                containing_class,
+                ..
+            } => {
+                referent.remap(Type, f);
+                containing_class.remap(Type, f);
+            }
+            TypeRecord::Modifier { modified_type, .. } => modified_type.remap(Type, f),
+            TypeRecord::Procedure {
+                return_type,
+                arg_list,
+                ..
+            } => {
+                return_type.remap(Type, f);
+                arg_list.remap(Type, f);
+            }
+            TypeRecord::MemberFunction {
+                return_type,
+                class_type,
+                this_type,
+                arg_list,
+                ..
+            } => {
+                return_type.remap(Type, f);
+                class_type.remap(Type, f);
+                this_type.remap(Type, f);
+                arg_list.remap(Type, f);
+            }
+            TypeRecord::ArgList { arg_list, .. } => arg_list.remap(Type, f),
+            TypeRecord::FieldList { fields } => {
+                for field in fields {
+                    field.remap_indices(f);
+                }
+            }
+            TypeRecord::Array {
+                element_type,
+                index_type,
+                ..
+            } => {
+                element_type.remap(Type, f);
+                index_type.remap(Type, f);
+            }
+            TypeRecord::Class(class) | TypeRecord::Struct(class) | TypeRecord::Interface(class) => {
+                class.field_list.remap(Type, f);
+                class.derivation_list.remap(Type, f);
+                class.vtable_shape.remap(Type, f);
+            }
+            TypeRecord::Union(union) => union.field_list.remap(Type, f),
+            TypeRecord::Enum(enum_) => {
+                enum_.underlying_type.remap(Type, f);
+                enum_.field_list.remap(Type, f);
+            }
+            TypeRecord::VFTable {
+                complete_class,
+                overriden_vftable,
+                ..
+            } => {
+                complete_class.remap(Type, f);
+                overriden_vftable.remap(Type, f);
+            }
+            TypeRecord::BitField { field_type, .. } => field_type.remap(Type, f),
+            TypeRecord::BaseClass(base) | TypeRecord::BaseInterface(base) => base.base_type.remap(Type, f),
+            TypeRecord::VirtualBaseClass(base) | TypeRecord::IndirectVirtualBaseClass(base) => {
+                base.base_type.remap(Type, f);
+                base.vbptr_type.remap(Type, f);
+            }
+            TypeRecord::VFPtr { table_type, .. } => table_type.remap(Type, f),
+            TypeRecord::StaticDataMember { field_type, .. } => field_type.remap(Type, f),
+            TypeRecord::OverloadedMethod { method_list, .. } => method_list.remap(Type, f),
+            TypeRecord::DataMember { field_type, .. } => field_type.remap(Type, f),
+            TypeRecord::NestedType { nested_type, .. } => nested_type.remap(Type, f),
+            TypeRecord::OneMethod { method_type, .. } => method_type.remap(Type, f),
+            TypeRecord::ListContinuation(next) => next.remap(Type, f),
+            TypeRecord::MethodList { methods } => {
+                for method in methods {
+                    method.method_type.remap(Type, f);
+                }
+            }
+            TypeRecord::Label(_)
+            | TypeRecord::TypeServer2 { .. }
+            | TypeRecord::VfTableShape(_)
+            | TypeRecord::Enumerator { .. } => {}
+        }
+    }
+
+    // fills in the unique name of a class, struct, interface, union or enum the way MSVC decorates it,
+    // e.g. `.?AUInner@Outer@ns@@` for `ns::Outer::Inner`, fails for other records and for names with
+    // template arguments or anonymous namespaces, which cannot be decorated from the name alone
+    pub fn derive_unique_name(&mut self) -> bool {
+        let (prefix, properties, name, unique_name) = match self {
+            TypeRecord::Class(class) => {
+                (".?AV", &mut class.properties, &class.name, &mut class.unique_name)
+            }
+            TypeRecord::Struct(class) | TypeRecord::Interface(class) => {
+                (".?AU", &mut class.properties, &class.name, &mut class.unique_name)
+            }
+            TypeRecord::Union(union) => {
+                (".?AT", &mut union.properties, &union.name, &mut union.unique_name)
+            }
+            // recent compilers use the int variant whatever the underlying type is
+            TypeRecord::Enum(enum_) => (
+                ".?AW4",
+                &mut enum_.properties,
+                &enum_.name,
+                &mut enum_.unique_name,
+            ),
+            _ => return false,
+        };
+        let Some(decorated) = decorate_name(prefix, name.as_ref()) else {
+            return false;
+        };
+        *unique_name = StrBuf::new(decorated);
+        properties.set_has_unique_name(true);
+        true
+    }
+
+    // type indices referenced by the record, including those of nested field list members
+    pub fn referenced_types(&self) -> Vec<TypeIndex> {
+        let mut res = vec![];
+        self.collect_referenced_types(&mut res);
+        res
+    }
+
+    fn collect_referenced_types(&self, out: &mut Vec<TypeIndex>) {
+        match self {
+            TypeRecord::Pointer {
+                referent,
+                containing_class,
+                ..
+            } => out.extend(Some(*referent).into_iter().chain(*containing_class)),
+            TypeRecord::Modifier { modified_type, .. } => out.push(*modified_type),
+            TypeRecord::Procedure {
+                return_type,
+                arg_list,
+                ..
+            } => out.extend(return_type.iter().copied().chain(Some(*arg_list))),
+            TypeRecord::MemberFunction {
+                return_type,
+                class_type,
+                this_type,
+                arg_list,
+                ..
+            } => out.extend(
+                [*return_type, *class_type, *this_type, Some(*arg_list)]
+                    .into_iter()
+                    .flatten(),
+            ),
+            TypeRecord::ArgList { arg_list, .. } => {
+                out.extend(arg_list.iter().filter_map(|&arg| TypeIndex::try_from(arg).ok()))
+            }
+            TypeRecord::FieldList { fields } => {
+                for field in fields {
+                    field.collect_referenced_types(out);
+                }
+            }
+            TypeRecord::Array {
+                element_type,
+                index_type,
+                ..
+            } => out.extend([*element_type, *index_type]),
+            TypeRecord::Class(class) | TypeRecord::Struct(class) | TypeRecord::Interface(class) => out
+                .extend(
+                    [class.field_list, class.derivation_list, class.vtable_shape]
+                        .into_iter()
+                        .flatten(),
+                ),
+            TypeRecord::Union(union) => out.extend(union.field_list),
+            TypeRecord::Enum(enum_) => out.extend([enum_.underlying_type, enum_.field_list]),
+            TypeRecord::VFTable {
+                complete_class,
+                overriden_vftable,
+                ..
+            } => out.extend([*complete_class, *overriden_vftable]),
+            TypeRecord::BitField { field_type, .. } => out.push(*field_type),
+            TypeRecord::BaseClass(base) | TypeRecord::BaseInterface(base) => out.push(base.base_type),
+            TypeRecord::VirtualBaseClass(base) | TypeRecord::IndirectVirtualBaseClass(base) => {
+                out.extend([base.base_type, base.vbptr_type])
+            }
+            TypeRecord::VFPtr { table_type, .. } => out.push(*table_type),
+            TypeRecord::StaticDataMember { field_type, .. } => out.push(*field_type),
+            TypeRecord::OverloadedMethod { method_list, .. } => out.push(*method_list),
+            TypeRecord::DataMember { field_type, .. } => out.extend(*field_type),
+            TypeRecord::NestedType { nested_type, .. } => out.push(*nested_type),
+            TypeRecord::OneMethod { method_type, .. } => out.push(*method_type),
+            TypeRecord::ListContinuation(next) => out.push(*next),
+            TypeRecord::MethodList { methods } => {
+                out.extend(methods.iter().map(|method| method.method_type))
+            }
+            TypeRecord::Label(_)
+            | TypeRecord::TypeServer2 { .. }
+            | TypeRecord::VfTableShape(_)
+            | TypeRecord::Enumerator { .. } => {}
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, EncodedSize)]
 #[declio(ctx_is = "constants::ENDIANESS", id_type = "LittleEndian<u16>")]
 pub enum IdRecord {
     #[declio(id = "constants::LF_FUNC_ID.into()")]
@@ -207,7 +428,99 @@ pub enum IdRecord {
     },
 }
 
-#[derive(Debug, Encode, Decode, EncodedSize)]
+impl IdRecord {
+    pub fn referenced_types(&self) -> Vec<TypeIndex> {
+        match self {
+            IdRecord::FuncId { function_type, .. } => vec![*function_type],
+            IdRecord::MemberFuncId {
+                class_type,
+                function_type,
+                ..
+            } => vec![*class_type, *function_type],
+            IdRecord::UdtSourceLine { udt, .. } | IdRecord::UdtModSourceLine { udt, .. } => vec![*udt],
+            IdRecord::BuildInfo { .. } | IdRecord::StringList { .. } | IdRecord::StringId { .. } => vec![],
+        }
+    }
+
+    // rewrites both the type indices and the indices of other IPI records
+    pub fn remap_indices(&mut self, f: &mut dyn FnMut(IndexKind, u32) -> u32) {
+        use IndexKind::{Id, Type};
+        match self {
+            IdRecord::FuncId {
+                parent_scope,
+                function_type,
+                ..
+            } => {
+                parent_scope.remap(Id, f);
+                function_type.remap(Type, f);
+            }
+            IdRecord::MemberFuncId {
+                class_type,
+                function_type,
+                ..
+            } => {
+                class_type.remap(Type, f);
+                function_type.remap(Type, f);
+            }
+            IdRecord::BuildInfo { arguments, .. } => arguments.remap(Id, f),
+            IdRecord::StringList { strings, .. } => strings.remap(Id, f),
+            IdRecord::StringId { id, .. } => id.remap(Id, f),
+            IdRecord::UdtSourceLine { udt, source_file, .. } => {
+                udt.remap(Type, f);
+                source_file.remap(Id, f);
+            }
+            // the source file of this record is an offset into the string table
+            IdRecord::UdtModSourceLine { udt, .. } => udt.remap(Type, f),
+        }
+    }
+
+    // indices of other records in the IPI stream
+    pub fn referenced_ids(&self) -> Vec<IdIndex> {
+        let id = |idx: TypeIndex| IdIndex::try_from(u32::from(idx)).ok();
+        match self {
+            IdRecord::FuncId { parent_scope, .. } => parent_scope.and_then(id).into_iter().collect(),
+            IdRecord::BuildInfo { arguments, .. } => arguments
+                .iter()
+                .filter_map(|&arg| IdIndex::try_from(arg).ok())
+                .collect(),
+            IdRecord::StringList { strings, .. } => strings.iter().copied().filter_map(id).collect(),
+            IdRecord::StringId { id: parent, .. } => parent.and_then(id).into_iter().collect(),
+            IdRecord::UdtSourceLine { source_file, .. } => id(*source_file).into_iter().collect(),
+            // the source file of this record is an offset into the string table
+            IdRecord::MemberFuncId { .. } | IdRecord::UdtModSourceLine { .. } => vec![],
+        }
+    }
+}
+
+// the components of a qualified name are written innermost first, with repeated ones
+// replaced by the position of their first occurrence
+fn decorate_name(prefix: &str, name: &str) -> Option<String> {
+    if name.is_empty() || name.contains(['<', '>', '`', '\'', ' ']) {
+        return None;
+    }
+    let mut res = prefix.to_owned();
+    let mut seen: Vec<&str> = vec![];
+    for component in name.rsplit("::") {
+        if component.is_empty() {
+            return None;
+        }
+        match seen.iter().position(|&prev| prev == component) {
+            Some(pos) => res.push_str(&pos.to_string()),
+            None => {
+                res.push_str(component);
+                res.push('@');
+                // only the first ten names can be referred back to
+                if seen.len() < 10 {
+                    seen.push(component);
+                }
+            }
+        }
+    }
+    res.push('@');
+    Some(res)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, EncodedSize)]
 #[declio(ctx_is = "constants::ENDIANESS")]
 pub struct StructRecord {
     pub member_count: u16,
@@ -224,7 +537,7 @@ pub struct StructRecord {
     pub unique_name: StrBuf,
 }
 
-#[derive(Debug, Encode, Decode, EncodedSize)]
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, EncodedSize)]
 #[declio(ctx_is = "constants::ENDIANESS")]
 pub struct UnionRecord {
     pub member_count: u16,
@@ -237,20 +550,19 @@ pub struct UnionRecord {
     pub unique_name: StrBuf,
 }
 
-#[derive(Debug, Encode, Decode, EncodedSize)]
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, EncodedSize)]
 #[declio(ctx_is = "constants::ENDIANESS")]
 pub struct EnumRecord {
     pub member_count: u16,
     pub properties: ClassProperties,
     pub underlying_type: TypeIndex,
     pub field_list: TypeIndex,
-    pub size: Integer,
     pub name: StrBuf,
     #[declio(skip_if = "!properties.has_unique_name()")]
     pub unique_name: StrBuf,
 }
 
-#[derive(Debug, Encode, Decode, EncodedSize)]
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, EncodedSize)]
 #[declio(ctx_is = "constants::ENDIANESS")]
 pub struct BaseClasRecord {
     pub properties: MemberProperties,
@@ -258,7 +570,7 @@ pub struct BaseClasRecord {
     pub offset: Integer,
 }
 
-#[derive(Debug, Encode, Decode, EncodedSize)]
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, EncodedSize)]
 #[declio(ctx_is = "constants::ENDIANESS")]
 pub struct VirtualBaseClasRecord {
     pub properties: MemberProperties,
@@ -268,7 +580,7 @@ pub struct VirtualBaseClasRecord {
     pub vtable_index: Integer,
 }
 
-#[derive(Debug, Encode, Decode, EncodedSize)]
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, EncodedSize)]
 #[declio(ctx_is = "constants::ENDIANESS")]
 pub struct MethodListEntry {
     pub properties: MemberProperties,
@@ -278,7 +590,7 @@ pub struct MethodListEntry {
     pub vtable_offset: Option<u32>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct VftShape {
     pub slots: Vec<VFTableSlotKind>,
 }
@@ -294,7 +606,7 @@ impl<Ctx: Copy> Decode<Ctx> for VftShape {
             let byte = u8::decode(ctx, reader)?;
             let high = byte >> 4;
             slots.push(VFTableSlotKind::from_bytes(high).map_err(declio::Error::new)?);
-            if count % 2 != 0 {
+            if slots.len() < count.into() {
                 let low = byte & 0x0F;
                 slots.push(VFTableSlotKind::from_bytes(low).map_err(declio::Error::new)?);
             }
@@ -304,11 +616,17 @@ impl<Ctx: Copy> Decode<Ctx> for VftShape {
 }
 
 impl<Ctx> Encode<Ctx> for VftShape {
-    fn encode<W>(&self, _ctx: Ctx, _writer: &mut W) -> Result<(), declio::Error>
+    fn encode<W>(&self, _ctx: Ctx, writer: &mut W) -> Result<(), declio::Error>
     where
         W: std::io::Write,
     {
-        todo!()
+        (self.slots.len() as u16).encode(constants::ENDIANESS, writer)?;
+        // two slots to a byte, the first one in the high nibble
+        for pair in self.slots.chunks(2) {
+            let low = pair.get(1).map_or(0, |&slot| slot as u8);
+            ((pair[0] as u8) << 4 | low).encode((), writer)?;
+        }
+        Ok(())
     }
 }
 
@@ -319,7 +637,7 @@ impl<Ctx> EncodedSize<Ctx> for VftShape {
 }
 
 #[bitfield(bits = 32)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct PointerProperties {
     pub kind: PointerKind,
     pub mode: PointerMode,
@@ -338,7 +656,7 @@ pub struct PointerProperties {
 
 impl_bitfield_codecs!(PointerProperties);
 
-#[derive(Debug, Clone, Copy, BitfieldSpecifier)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, BitfieldSpecifier)]
 #[bits = 5]
 pub enum PointerKind {
     Near16 = 0x00,
@@ -358,7 +676,7 @@ pub enum PointerKind {
 
 impl_bitfield_specifier_codecs!(PointerKind);
 
-#[derive(Debug, Clone, Copy, BitfieldSpecifier)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, BitfieldSpecifier)]
 #[bits = 3]
 pub enum PointerMode {
     Vanilla = 0x00,
@@ -377,7 +695,7 @@ impl PointerMode {
 impl_bitfield_specifier_codecs!(PointerMode);
 
 #[bitfield(bits = 16)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct ModifierProperties {
     pub is_const: bool,
     pub is_volatile: bool,
@@ -389,7 +707,7 @@ pub struct ModifierProperties {
 impl_bitfield_codecs!(ModifierProperties);
 
 #[bitfield(bits = 8)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct FunctionProperties {
     pub is_cxx_return_udt: bool,
     pub is_constructor: bool,
@@ -401,7 +719,7 @@ pub struct FunctionProperties {
 impl_bitfield_codecs!(FunctionProperties);
 
 #[bitfield(bits = 16)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct ClassProperties {
     pub is_packed: bool,
     pub has_constructor_or_deconstructor: bool,
@@ -424,7 +742,7 @@ pub struct ClassProperties {
 impl_bitfield_codecs!(ClassProperties);
 
 #[bitfield(bits = 16)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct MemberProperties {
     pub access: MemberAccess,
     pub method_kind: MethodKind,
@@ -439,7 +757,7 @@ pub struct MemberProperties {
 
 impl_bitfield_codecs!(MemberProperties);
 
-#[derive(Debug, Clone, Copy, BitfieldSpecifier)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, BitfieldSpecifier)]
 #[bits = 8]
 pub enum CallingConvention {
     NearC = 0x00,
@@ -470,7 +788,7 @@ pub enum CallingConvention {
 
 impl_bitfield_specifier_codecs!(CallingConvention);
 
-#[derive(Debug, Clone, Copy, BitfieldSpecifier)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, BitfieldSpecifier)]
 #[bits = 16]
 pub enum LabelType {
     Near = 0x0,
@@ -479,7 +797,7 @@ pub enum LabelType {
 
 impl_bitfield_specifier_codecs!(LabelType);
 
-#[derive(Debug, Clone, Copy, BitfieldSpecifier)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, BitfieldSpecifier)]
 #[bits = 4]
 pub enum VFTableSlotKind {
     Near16 = 0x00,
@@ -493,7 +811,7 @@ pub enum VFTableSlotKind {
 
 impl_bitfield_specifier_codecs!(VFTableSlotKind);
 
-#[derive(Debug, Clone, Copy, BitfieldSpecifier)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, BitfieldSpecifier)]
 #[bits = 2]
 pub enum MemberAccess {
     None = 0,
@@ -504,7 +822,7 @@ pub enum MemberAccess {
 
 impl_bitfield_specifier_codecs!(MemberAccess);
 
-#[derive(Debug, Clone, Copy, BitfieldSpecifier)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, BitfieldSpecifier)]
 #[bits = 3]
 pub enum MethodKind {
     Vanilla = 0x00,
@@ -524,63 +842,84 @@ impl MethodKind {
 
 impl_bitfield_specifier_codecs!(MethodKind);
 
-#[derive(Debug, Clone, Copy, BitfieldSpecifier)]
-#[bits = 32]
-pub enum BuiltinType {
-    Void = 0x0003,
-    NotTranslated = 0x0007,
-    HResult = 0x0008,
-    SignedChar = 0x0010,
-    UnsignedChar = 0x0020,
-    NarrowChar = 0x0070,
-    WideChar = 0x0071,
-    Char16 = 0x007a,
-    Char32 = 0x007b,
-    Char8 = 0x007c,
-
-    I8 = 0x0068,
-    U8 = 0x0069,
-    I16Short = 0x0011,
-    U16Short = 0x0021,
-    I16 = 0x0072,
-    U16 = 0x0073,
-    I32Long = 0x0012,
-    U32Long = 0x0022,
-    I32 = 0x0074,
-    U32 = 0x0075,
-    I64Quad = 0x0013,
-    U64Quad = 0x0023,
-    I64 = 0x0076,
-    U64 = 0x0077,
-    I128Oct = 0x0014,
-    U128Oct = 0x0024,
-    I128 = 0x0078,
-    U128 = 0x0079,
-
-    F16 = 0x0046,
-    F32 = 0x0040,
-    F32PartialPrecision = 0x0045,
-    F48 = 0x0044,
-    F64 = 0x0041,
-    F80 = 0x0042,
-    F128 = 0x0043,
-
-    Complex16 = 0x0056,
-    Complex32 = 0x0050,
-    Complex32PartialPrecision = 0x0055,
-    Complex48 = 0x0054,
-    Complex64 = 0x0051,
-    Complex80 = 0x0052,
-    Complex128 = 0x0053,
-
-    Bool8 = 0x0030,
-    Bool16 = 0x0031,
-    Bool32 = 0x0032,
-    Bool64 = 0x0033,
-    Bool128 = 0x0034,
-}
-
-impl_bitfield_specifier_codecs!(BuiltinType);
+u32_enum! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum BuiltinType {
+        Void = 0x0003,
+        NotTranslated = 0x0007,
+        HResult = 0x0008,
+        SignedChar = 0x0010,
+        UnsignedChar = 0x0020,
+        NarrowChar = 0x0070,
+        WideChar = 0x0071,
+        Char16 = 0x007a,
+        Char32 = 0x007b,
+        Char8 = 0x007c,
+
+        I8 = 0x0068,
+        U8 = 0x0069,
+        I16Short = 0x0011,
+        U16Short = 0x0021,
+        I16 = 0x0072,
+        U16 = 0x0073,
+        I32Long = 0x0012,
+        U32Long = 0x0022,
+        I32 = 0x0074,
+        U32 = 0x0075,
+        I64Quad = 0x0013,
+        U64Quad = 0x0023,
+        I64 = 0x0076,
+        U64 = 0x0077,
+        I128Oct = 0x0014,
+        U128Oct = 0x0024,
+        I128 = 0x0078,
+        U128 = 0x0079,
+
+        F16 = 0x0046,
+        F32 = 0x0040,
+        F32PartialPrecision = 0x0045,
+        F48 = 0x0044,
+        F64 = 0x0041,
+        F80 = 0x0042,
+        F128 = 0x0043,
+
+        Complex16 = 0x0056,
+        Complex32 = 0x0050,
+        Complex32PartialPrecision = 0x0055,
+        Complex48 = 0x0054,
+        Complex64 = 0x0051,
+        Complex80 = 0x0052,
+        Complex128 = 0x0053,
+
+        Bool8 = 0x0030,
+        Bool16 = 0x0031,
+        Bool32 = 0x0032,
+        Bool64 = 0x0033,
+        Bool128 = 0x0034,
+    }
+}
+
+impl BuiltinType {
+    // the size of a value of the type in bytes, none for the ones without values
+    pub fn size(self) -> Option<u64> {
+        use BuiltinType::*;
+        let size = match self {
+            Void | NotTranslated => return None,
+            SignedChar | UnsignedChar | NarrowChar | Char8 | I8 | U8 | Bool8 => 1,
+            WideChar | Char16 | I16Short | U16Short | I16 | U16 | F16 | Bool16 => 2,
+            HResult | Char32 | I32Long | U32Long | I32 | U32 | F32 | F32PartialPrecision | Complex16
+            | Bool32 => 4,
+            F48 => 6,
+            I64Quad | U64Quad | I64 | U64 | F64 | Complex32 | Complex32PartialPrecision | Bool64 => 8,
+            F80 => 10,
+            Complex48 => 12,
+            I128Oct | U128Oct | I128 | U128 | F128 | Complex64 | Bool128 => 16,
+            Complex80 => 20,
+            Complex128 => 32,
+        };
+        Some(size)
+    }
+}
 
 impl From<BuiltinType> for TypeIndex {
     fn from(tp: BuiltinType) -> Self {
@@ -595,6 +934,6 @@ impl TryFrom<TypeIndex> for BuiltinType {
     type Error = NonBuiltinType;
 
     fn try_from(value: TypeIndex) -> Result<Self, Self::Error> {
-        BuiltinType::from_bytes(u32::from(value)).map_err(|_| NonBuiltinType)
+        BuiltinType::from_u32(u32::from(value)).ok_or(NonBuiltinType)
     }
 }
@@ -0,0 +1,237 @@
+use std::io;
+
+use declio::Decode;
+
+use crate::builders::{ModuleBuilder, PdbBuilder};
+use crate::codeview::symbols::SymbolRecord;
+use crate::codeview::types::TypeRecord;
+use crate::codeview::{IndexKind, PrefixedRecord, RawRecord, RECORD_ALIGNMENT};
+use crate::merge::{MergedIndices, TypeMerger};
+use crate::module::DebugSubsectionRecordType;
+use crate::result::{Error, Result};
+use crate::utils::align_to;
+use crate::{constants, Guid, PdbFile};
+
+const CV_SIGNATURE_C13: u32 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugRelocationKind {
+    // IMAGE_REL_AMD64_SECREL and equivalents, adds the offset within the section
+    SectionRelative,
+    // IMAGE_REL_AMD64_SECTION and equivalents, stores the section number
+    SectionIndex,
+}
+
+// a relocation of the `.debug$S` section, resolved to the final location of its target
+#[derive(Debug, Clone, Copy)]
+pub struct DebugRelocation {
+    // the offset of the relocated field within `.debug$S`
+    pub offset: u32,
+    pub kind: DebugRelocationKind,
+    pub segment: u16,
+    pub target_offset: u32,
+}
+
+// merges the CodeView sections of a COFF object, the records of `.debug$T` are merged into the TPI and IPI
+// of the builder and the symbols of `.debug$S` added to the module with their indices rewritten accordingly,
+// other subsections like line information are not carried over
+pub fn add_object(
+    builder: &mut PdbBuilder,
+    merger: &mut TypeMerger,
+    module: &mut ModuleBuilder,
+    debug_s: &[u8],
+    debug_t: Option<&[u8]>,
+    relocations: &[DebugRelocation],
+) -> Result<()> {
+    let indices = match debug_t {
+        Some(debug_t) => merger.merge_object(builder, &read_types(debug_t)?)?,
+        None => MergedIndices::default(),
+    };
+    add_symbols(module, debug_s, relocations, &mut |kind, index| {
+        indices.remap(kind, index)
+    })
+}
+
+// the PDB that an object compiled with `/Zi` keeps its types in
+#[derive(Debug)]
+pub struct TypeServerRef {
+    pub guid: Guid,
+    pub age: u32,
+    pub path: String,
+}
+
+// ingests objects that may reference a type server, the types of every server are spliced into
+// the builder once and shared by all of the objects that use it
+pub struct TypeServers<F> {
+    resolve: F,
+    spliced: Vec<SplicedServer>,
+}
+
+#[derive(Debug)]
+struct SplicedServer {
+    guid: Guid,
+    indices: MergedIndices,
+}
+
+impl<F, R> TypeServers<F>
+where
+    F: FnMut(&TypeServerRef) -> Result<PdbFile<R>>,
+    R: io::Read + io::Seek,
+{
+    // the resolver opens the PDB at the path recorded by the compiler, which is often stale
+    pub fn new(resolve: F) -> Self {
+        Self {
+            resolve,
+            spliced: vec![],
+        }
+    }
+
+    // like `add_object`, but objects with a `LF_TYPESERVER2` record get their indices mapped into
+    // the types of the referenced PDB
+    pub fn add_object(
+        &mut self,
+        builder: &mut PdbBuilder,
+        merger: &mut TypeMerger,
+        module: &mut ModuleBuilder,
+        debug_s: &[u8],
+        debug_t: Option<&[u8]>,
+        relocations: &[DebugRelocation],
+    ) -> Result<()> {
+        let Some(server) = debug_t.map(read_type_server).transpose()?.flatten() else {
+            return add_object(builder, merger, module, debug_s, debug_t, relocations);
+        };
+        let spliced = match self
+            .spliced
+            .iter()
+            .position(|spliced| spliced.guid == server.guid)
+        {
+            Some(index) => &self.spliced[index],
+            None => {
+                let pdb = (self.resolve)(&server)?;
+                let spliced = splice(builder, merger, pdb, &server)?;
+                self.spliced.push(spliced);
+                &self.spliced[self.spliced.len() - 1]
+            }
+        };
+        add_symbols(module, debug_s, relocations, &mut |kind, index| {
+            spliced.indices.remap(kind, index)
+        })
+    }
+}
+
+fn add_symbols(
+    module: &mut ModuleBuilder,
+    debug_s: &[u8],
+    relocations: &[DebugRelocation],
+    remap: &mut dyn FnMut(IndexKind, u32) -> u32,
+) -> Result<()> {
+    let mut debug_s = debug_s.to_vec();
+    for reloc in relocations {
+        apply_relocation(&mut debug_s, reloc)?;
+    }
+
+    let mut symbols = vec![];
+    let mut data = read_signature(&debug_s)?;
+    while !data.is_empty() {
+        let record_type = u32::decode(constants::ENDIANESS, &mut data)?;
+        let len = u32::decode(constants::ENDIANESS, &mut data)? as usize;
+        let subsection = data
+            .get(..len)
+            .ok_or(Error::MalformedInput("truncated .debug$S subsection"))?;
+        data = data.get(align_to(len, RECORD_ALIGNMENT)..).unwrap_or_default();
+
+        if record_type != DebugSubsectionRecordType::Symbols as u32 {
+            continue;
+        }
+        let mut records = subsection;
+        while !records.is_empty() {
+            let mut symbol: SymbolRecord = PrefixedRecord::<RawRecord>::decode(&mut records)?
+                .into_inner()
+                .decode_as()?;
+            symbol.remap_indices(remap);
+            symbols.push(symbol);
+        }
+    }
+    module.add_symbols(symbols);
+    Ok(())
+}
+
+// a `.debug$T` section referencing a type server holds nothing but the reference
+fn read_type_server(debug_t: &[u8]) -> Result<Option<TypeServerRef>> {
+    let mut data = read_signature(debug_t)?;
+    if data.is_empty() {
+        return Ok(None);
+    }
+    let raw = PrefixedRecord::<RawRecord>::decode(&mut data)?.into_inner();
+    if raw.kind != constants::LF_TYPESERVER2 {
+        return Ok(None);
+    }
+    match raw.decode_as()? {
+        TypeRecord::TypeServer2 { guid, age, name } => Ok(Some(TypeServerRef {
+            guid,
+            age,
+            path: name.as_ref().to_owned(),
+        })),
+        _ => Ok(None),
+    }
+}
+
+// merges the TPI and IPI of the server, PDBs keep separate index spaces for the two
+fn splice<R>(
+    builder: &mut PdbBuilder,
+    merger: &mut TypeMerger,
+    mut pdb: PdbFile<R>,
+    server: &TypeServerRef,
+) -> Result<SplicedServer>
+where
+    R: io::Read + io::Seek,
+{
+    let info = pdb.get_info()?;
+    if info.header().guid != server.guid {
+        return Err(Error::TypeServerMismatch(server.path.clone()));
+    }
+    Ok(SplicedServer {
+        guid: server.guid.clone(),
+        indices: merger.merge_pdb(builder, &mut pdb)?,
+    })
+}
+
+fn read_types(debug_t: &[u8]) -> Result<Vec<RawRecord>> {
+    let mut records = vec![];
+    let mut data = read_signature(debug_t)?;
+    while !data.is_empty() {
+        let raw = PrefixedRecord::<RawRecord>::decode(&mut data)?.into_inner();
+        if raw.kind == constants::LF_TYPESERVER2 {
+            return Err(Error::UnsupportedFeature(
+                "objects using a type server, see `TypeServers`",
+            ));
+        }
+        records.push(raw);
+    }
+    Ok(records)
+}
+
+fn read_signature(section: &[u8]) -> Result<&[u8]> {
+    let mut data = section;
+    if u32::decode(constants::ENDIANESS, &mut data)? != CV_SIGNATURE_C13 {
+        return Err(Error::UnsupportedFeature("CodeView signature other than C13"));
+    }
+    Ok(data)
+}
+
+fn apply_relocation(section: &mut [u8], reloc: &DebugRelocation) -> Result<()> {
+    let start = reloc.offset as usize;
+    let out_of_bounds = Error::MalformedInput("relocation out of .debug$S bounds");
+    match reloc.kind {
+        DebugRelocationKind::SectionRelative => {
+            let field = section.get_mut(start..start + 4).ok_or(out_of_bounds)?;
+            let addend = u32::from_le_bytes([field[0], field[1], field[2], field[3]]);
+            field.copy_from_slice(&addend.wrapping_add(reloc.target_offset).to_le_bytes());
+        }
+        DebugRelocationKind::SectionIndex => {
+            let field = section.get_mut(start..start + 2).ok_or(out_of_bounds)?;
+            field.copy_from_slice(&reloc.segment.to_le_bytes());
+        }
+    }
+    Ok(())
+}
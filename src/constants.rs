@@ -72,6 +72,8 @@ pub const S_REGISTER: u16 = 0x1106;
 pub const S_PUB32: u16 = 0x110e;
 pub const S_PROCREF: u16 = 0x1125;
 pub const S_LPROCREF: u16 = 0x1127;
+pub const S_DATAREF: u16 = 0x1126;
+pub const S_ANNOTATIONREF: u16 = 0x1128;
 pub const S_ENVBLOCK: u16 = 0x113d;
 pub const S_INLINESITE: u16 = 0x114d;
 pub const S_LOCAL: u16 = 0x113e;
@@ -7,12 +7,14 @@ use derive_getters::Getters;
 use modular_bitfield::bitfield;
 use modular_bitfield::prelude::*;
 
+use crate::codeview::RECORD_ALIGNMENT;
+use crate::diagnostics::{ParseOptions, ReadContext, WarningKind};
 use crate::module::ModuleLayout;
 use crate::result::{Error, Result};
-use crate::strings::Strings;
-use crate::utils::StrBuf;
+use crate::strings::{Strings, StringsBuilder};
+use crate::utils::{align_to, StrBuf};
 use crate::{
-    codecs, constants, impl_bitfield_codecs, impl_bitfield_specifier_codecs, BufMsfStream, StreamIndex
+    codecs, constants, impl_bitfield_codecs, impl_bitfield_specifier_codecs, u32_enum, BufMsfStream, StreamIndex, StringOffset
 };
 
 magic_bytes! {
@@ -25,6 +27,10 @@ pub struct DbiStream {
     header: DbiHeader,
     modules: Vec<DbiModule>,
     section_contribs: Vec<SectionContrib>,
+    // `None` for the SC40 entries of pre-V60 substreams and for empty ones
+    section_contrib_version: Option<SectionContribVersion>,
+    // the section index within the object file of every V2 contribution, empty for other versions
+    coff_section_indices: Vec<u32>,
     sec_map: SectionMap,
     file_info: FileInfo,
     file_names: Vec<u8>,
@@ -34,41 +40,97 @@ pub struct DbiStream {
 }
 
 impl DbiStream {
-    pub fn read<R: io::Read>(mut reader: R) -> Result<Self> {
+    pub fn read<R: io::Read>(reader: R) -> Result<Self> {
+        let (mut diagnostics, mut warnings) = (vec![], vec![]);
+        let options = ParseOptions::default();
+        Self::read_with_context(
+            reader,
+            &mut ReadContext::new(&options, &mut diagnostics, &mut warnings, None, None),
+        )
+    }
+
+    pub(crate) fn read_with_context<R: io::Read>(mut reader: R, ctx: &mut ReadContext) -> Result<Self> {
         let header = DbiHeader::decode((), &mut reader)?;
-        if !matches!(header.version, DbiVersion::V70 | DbiVersion::V110) {
-            return Err(Error::UnsupportedFeature("DBI version older than V70"));
+        if header.version == DbiVersion::Vc41 {
+            return Err(Error::UnsupportedFeature("DBI version older than V50"));
+        }
+        // the substreams are read into memory as a whole or decoded into lists sized by them
+        let budget = ctx.budget();
+        for size in [
+            header.modi_stream_size,
+            header.sec_contr_stream_size,
+            header.section_map_size,
+            header.file_info_size,
+            header.type_server_size,
+            header.optional_db_header_size,
+            header.ec_stream_size,
+        ] {
+            budget.allocate(size.into())?;
         }
 
         let mut modi_stream = reader.by_ref().take(header.modi_stream_size.into());
-        let modules = codecs::padded_rem_list::decode((), &mut modi_stream)?;
+        let on_padding = |offset: usize, byte| {
+            let offset = DbiHeader::SIZE + offset as u64;
+            ctx.warn("DBI", offset, WarningKind::UnexpectedPadding(byte));
+        };
+        let modules = if header.version.is_legacy() {
+            let modules: Vec<LegacyDbiModule> =
+                codecs::padded_rem_list::decode_checked(header.version, &mut modi_stream, on_padding)?;
+            modules.into_iter().map(|m| m.0).collect()
+        } else {
+            codecs::padded_rem_list::decode_checked((), &mut modi_stream, on_padding)?
+        };
 
         let mut sect_contr_stream = reader.by_ref().take(header.sec_contr_stream_size.into());
         let mut section_contribs = vec![];
-        let version = SectionContribVersion::decode(constants::ENDIANESS, &mut sect_contr_stream)?;
-
-        while sect_contr_stream.limit() > 0 {
-            section_contribs.push(SectionContrib::decode((), &mut sect_contr_stream)?);
-            if version == SectionContribVersion::V2 {
-                // isect coff
-                u32::decode(constants::ENDIANESS, &mut sect_contr_stream)?;
+        let mut coff_section_indices = vec![];
+        let mut sect_contr_bytes = vec![];
+        sect_contr_stream.read_to_end(&mut sect_contr_bytes)?;
+        let mut slice = &sect_contr_bytes[..];
+
+        // pre-V60 substreams have no version prefix and store the shorter SC40 entries
+        let section_contrib_version = if matches!(header.version, DbiVersion::Vc41 | DbiVersion::V50) {
+            while !slice.is_empty() {
+                section_contribs.push(SectionContrib40::decode((), &mut slice)?.into());
             }
-        }
+            None
+        } else if slice.is_empty() {
+            None
+        } else {
+            let version = SectionContribVersion::decode(constants::ENDIANESS, &mut slice)?;
+            while !slice.is_empty() {
+                section_contribs.push(SectionContrib::decode((), &mut slice)?);
+                if version == SectionContribVersion::V2 {
+                    coff_section_indices.push(u32::decode(constants::ENDIANESS, &mut slice)?);
+                }
+            }
+            Some(version)
+        };
 
         let mut sec_map_stream = reader.by_ref().take(header.section_map_size.into());
         let sec_map = SectionMap::decode((), &mut sec_map_stream)?;
-        debug_assert_eq!(sec_map_stream.limit(), 0);
+        let trailing = io::copy(&mut sec_map_stream, &mut io::sink())?;
+        if trailing != 0 {
+            let end = DbiHeader::SIZE
+                + u64::from(header.modi_stream_size)
+                + u64::from(header.sec_contr_stream_size)
+                + u64::from(header.section_map_size);
+            ctx.warn("DBI", end - trailing, WarningKind::TrailingBytes(trailing));
+        }
 
         let mut file_info_stream = reader.by_ref().take(header.file_info_size.into());
         let file_info = FileInfo::decode((), &mut file_info_stream)?;
 
         let mut file_names = vec![];
         file_info_stream.read_to_end(&mut file_names)?;
-        debug_assert_eq!(file_info_stream.limit(), 0);
 
         let type_server_stream: Bytes = Decode::decode(Len(header.type_server_size as usize), &mut reader)?;
 
-        let ec_stream: Strings = Strings::decode((), &mut reader)?;
+        let ec_stream = if header.ec_stream_size == 0 {
+            StringsBuilder::default().build()
+        } else {
+            Strings::decode((), &mut reader)?
+        };
 
         let dbg_stream_count = header.optional_db_header_size as usize / 2;
         let dbg_streams: Vec<StreamIndex> = Decode::decode(Len(dbg_stream_count), &mut reader)?;
@@ -77,6 +139,8 @@ impl DbiStream {
             header,
             modules,
             section_contribs,
+            section_contrib_version,
+            coff_section_indices,
             sec_map,
             file_info,
             file_names,
@@ -87,9 +151,148 @@ impl DbiStream {
 
         Ok(dbi)
     }
+
+    // the stream indices stored in the stream along with their offsets, leaving out the nil ones
+    pub(crate) fn stream_indices(&self) -> impl Iterator<Item = (u64, StreamIndex)> + '_ {
+        let header = &self.header;
+        let dbg_start = DbiHeader::SIZE
+            + [
+                header.modi_stream_size,
+                header.sec_contr_stream_size,
+                header.section_map_size,
+                header.file_info_size,
+                header.type_server_size,
+                header.ec_stream_size,
+            ]
+            .iter()
+            .map(|&size| u64::from(size))
+            .sum::<u64>();
+        let header_indices = [
+            (12, header.global_symbol_stream_index),
+            (16, header.public_symbol_stream_index),
+            (20, header.sym_record_stream_index),
+        ];
+        let dbg_indices = (dbg_start..).step_by(2).zip(self.dbg_streams.iter().copied());
+        header_indices
+            .into_iter()
+            .chain(dbg_indices)
+            .filter(|(_, index)| index.0 != u16::MAX)
+    }
+
+    // encodes the stream back with the sizes in the header updated, so that a parsed stream can be modified
+    // and saved, the module list of versions older than V70 is not supported
+    pub fn write<W: io::Write>(&self, mut writer: W) -> Result<()> {
+        if self.header.version.is_legacy() {
+            return Err(Error::UnsupportedFeature("writing DBI modules older than V70"));
+        }
+        // linkers pad the module list with zeroes rather than the usual 0xF_ bytes
+        let mut modules = vec![];
+        for module in &self.modules {
+            module.encode(constants::ENDIANESS, &mut modules)?;
+            modules.resize(align_to(modules.len(), RECORD_ALIGNMENT), 0);
+        }
+
+        let mut section_contribs = vec![];
+        match self.section_contrib_version {
+            Some(version) => {
+                version.encode(constants::ENDIANESS, &mut section_contribs)?;
+                for (i, contrib) in self.section_contribs.iter().enumerate() {
+                    contrib.encode((), &mut section_contribs)?;
+                    if version == SectionContribVersion::V2 {
+                        let index = self.coff_section_indices.get(i).copied().unwrap_or_default();
+                        index.encode(constants::ENDIANESS, &mut section_contribs)?;
+                    }
+                }
+            }
+            None => {
+                for contrib in &self.section_contribs {
+                    SectionContrib40::from(contrib).encode((), &mut section_contribs)?;
+                }
+            }
+        }
+
+        let mut file_info = vec![];
+        self.file_info.encode((), &mut file_info)?;
+        file_info.extend_from_slice(&self.file_names);
+
+        // a stream without EC names has no string table at all
+        let mut ec_stream = vec![];
+        if self.header.ec_stream_size != 0 {
+            self.ec_stream.encode((), &mut ec_stream)?;
+        }
+
+        let header = DbiHeader {
+            modi_stream_size: modules.len() as u32,
+            sec_contr_stream_size: section_contribs.len() as u32,
+            section_map_size: self.sec_map.encoded_size(()) as u32,
+            file_info_size: file_info.len() as u32,
+            type_server_size: self.type_server_stream.len() as u32,
+            optional_db_header_size: self.dbg_streams.encoded_size(()) as u32,
+            ec_stream_size: ec_stream.len() as u32,
+            ..self.header
+        };
+        header.encode((), &mut writer)?;
+        writer.write_all(&modules)?;
+        writer.write_all(&section_contribs)?;
+        self.sec_map.encode((), &mut writer)?;
+        writer.write_all(&file_info)?;
+        writer.write_all(&self.type_server_stream)?;
+        writer.write_all(&ec_stream)?;
+        self.dbg_streams.encode(((),), &mut writer)?;
+        Ok(())
+    }
+
+    pub fn header_mut(&mut self) -> &mut DbiHeader {
+        &mut self.header
+    }
+
+    pub fn modules_mut(&mut self) -> &mut [DbiModule] {
+        &mut self.modules
+    }
+
+    // the source file a module was compiled from, recorded for edit and continue
+    pub fn src_file_name(&self, module: &DbiModule) -> Option<&str> {
+        self.ec_name(module.header.src_file_name_ni)
+    }
+
+    // the PDB the compiler wrote the types of a module into, recorded for edit and continue
+    pub fn pdb_file_path(&self, module: &DbiModule) -> Option<&str> {
+        self.ec_name(module.header.pdb_file_path_ni)
+    }
+
+    // the source files of every module in the order of the modules, as listed by the file info substream
+    pub fn module_source_files(&self) -> Vec<Vec<&str>> {
+        let mut offsets = self.file_info.file_name_offsets.iter();
+        self.file_info
+            .module_file_counts
+            .iter()
+            .map(|&count| {
+                offsets
+                    .by_ref()
+                    .take(count.into())
+                    .filter_map(|&offset| {
+                        let name = self
+                            .file_names
+                            .get(offset as usize..)?
+                            .split(|&b| b == 0)
+                            .next()?;
+                        std::str::from_utf8(name).ok()
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn ec_name(&self, offset: u32) -> Option<&str> {
+        // a zero index means that there is no name
+        if offset == 0 {
+            return None;
+        }
+        self.ec_stream.get(StringOffset(offset))
+    }
 }
 
-#[derive(Debug, Encode, Decode)]
+#[derive(Debug, PartialEq, Eq, Encode, Decode)]
 #[declio(ctx_is = "constants::ENDIANESS")]
 pub struct DbiHeader {
     pub signature: DbiSignature,
@@ -115,30 +318,38 @@ pub struct DbiHeader {
     pub reserved: [u8; 4],
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, BitfieldSpecifier)]
-#[bits = 32]
-pub enum DbiVersion {
-    Vc41 = 930803,
-    V50 = 19960307,
-    V60 = 19970606,
-    V70 = 19990903,
-    V110 = 20091201,
+impl DbiHeader {
+    const SIZE: u64 = 64;
 }
 
-impl_bitfield_specifier_codecs!(DbiVersion);
+u32_enum! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum DbiVersion {
+        Vc41 = 930803,
+        V50 = 19960307,
+        V60 = 19970606,
+        V70 = 19990903,
+        V110 = 20091201,
+    }
+}
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, BitfieldSpecifier)]
-#[bits = 32]
-#[repr(u32)]
-pub enum SectionContribVersion {
-    Ver60 = 0xeffe0000 + 19970605,
-    V2 = 0xeffe0000 + 20140516,
+impl DbiVersion {
+    // versions preceding the EC info in module headers
+    pub fn is_legacy(self) -> bool {
+        matches!(self, DbiVersion::Vc41 | DbiVersion::V50 | DbiVersion::V60)
+    }
 }
 
-impl_bitfield_specifier_codecs!(SectionContribVersion);
+u32_enum! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum SectionContribVersion {
+        Ver60 = 0xeffe0000 + 19970605,
+        V2 = 0xeffe0000 + 20140516,
+    }
+}
 
 #[bitfield(bits = 16)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct BuildNumber {
     pub minor: B8,
     pub major: B7,
@@ -148,7 +359,7 @@ pub struct BuildNumber {
 impl_bitfield_codecs!(BuildNumber);
 
 #[bitfield(bits = 16)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct DbiFlags {
     pub is_incrementally_linked: bool,
     pub is_stripped: bool,
@@ -159,7 +370,7 @@ pub struct DbiFlags {
 
 impl_bitfield_codecs!(DbiFlags);
 
-#[derive(Debug, Encode, Decode, EncodedSize)]
+#[derive(Debug, PartialEq, Eq, Encode, Decode, EncodedSize)]
 #[declio(ctx_is = "constants::ENDIANESS")]
 pub struct DbiModule {
     pub header: ModuleInfoHeader,
@@ -167,7 +378,42 @@ pub struct DbiModule {
     pub obj_file_name: StrBuf,
 }
 
-#[derive(Debug, Encode, Decode, EncodedSize)]
+struct LegacyDbiModule(DbiModule);
+
+impl Decode<DbiVersion> for LegacyDbiModule {
+    fn decode<R>(version: DbiVersion, reader: &mut R) -> Result<Self, declio::Error>
+    where
+        R: io::Read,
+    {
+        let module = u32::decode(constants::ENDIANESS, reader)?;
+        let section_contrib = if version == DbiVersion::V60 {
+            SectionContrib::decode((), reader)?
+        } else {
+            SectionContrib40::decode((), reader)?.into()
+        };
+        let header = ModuleInfoHeader {
+            module,
+            section_contrib,
+            flags: Decode::decode((), reader)?,
+            type_server_index: Decode::decode((), reader)?,
+            debug_info_stream: Decode::decode((), reader)?,
+            layout: Decode::decode((), reader)?,
+            num_files: Decode::decode(constants::ENDIANESS, reader)?,
+            pad1: Decode::decode((), reader)?,
+            file_names_offs: Decode::decode(constants::ENDIANESS, reader)?,
+            src_file_name_ni: 0,
+            pdb_file_path_ni: 0,
+        };
+        let res = DbiModule {
+            header,
+            module_name: Decode::decode((), reader)?,
+            obj_file_name: Decode::decode((), reader)?,
+        };
+        Ok(Self(res))
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Encode, Decode, EncodedSize)]
 #[declio(ctx_is = "constants::ENDIANESS")]
 pub struct ModuleInfoHeader {
     pub module: u32,
@@ -184,7 +430,7 @@ pub struct ModuleInfoHeader {
 }
 
 #[bitfield(bits = 8)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct ModuleInfoFlags {
     pub is_dirty: bool,
     pub is_ec_enabled: bool,
@@ -194,7 +440,7 @@ pub struct ModuleInfoFlags {
 
 impl_bitfield_codecs!(ModuleInfoFlags);
 
-#[derive(Debug, Encode, Decode, EncodedSize)]
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, EncodedSize)]
 #[declio(ctx_is = "constants::ENDIANESS")]
 pub struct SectionContrib {
     pub i_sect: u16,
@@ -208,7 +454,69 @@ pub struct SectionContrib {
     pub reloc_crc: u32,
 }
 
-#[derive(Debug, Encode, Decode, EncodedSize)]
+impl SectionContrib {
+    pub fn new(i_sect: u16, offset: i32, size: u32, characteristics: u32, i_mod: u16) -> Self {
+        Self {
+            i_sect,
+            pad1: Default::default(),
+            offset,
+            size,
+            characteristics,
+            i_mod,
+            pad2: Default::default(),
+            data_crc: 0,
+            reloc_crc: 0,
+        }
+    }
+
+    pub fn flags(&self) -> SectionCharacteristics {
+        self.characteristics.into()
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Encode, Decode)]
+#[declio(ctx_is = "constants::ENDIANESS")]
+struct SectionContrib40 {
+    i_sect: u16,
+    pad1: [u8; 2],
+    offset: i32,
+    size: u32,
+    characteristics: u32,
+    i_mod: u16,
+    pad2: [u8; 2],
+}
+
+impl From<&SectionContrib> for SectionContrib40 {
+    fn from(sc: &SectionContrib) -> Self {
+        Self {
+            i_sect: sc.i_sect,
+            pad1: sc.pad1,
+            offset: sc.offset,
+            size: sc.size,
+            characteristics: sc.characteristics,
+            i_mod: sc.i_mod,
+            pad2: sc.pad2,
+        }
+    }
+}
+
+impl From<SectionContrib40> for SectionContrib {
+    fn from(sc: SectionContrib40) -> Self {
+        Self {
+            i_sect: sc.i_sect,
+            pad1: sc.pad1,
+            offset: sc.offset,
+            size: sc.size,
+            characteristics: sc.characteristics,
+            i_mod: sc.i_mod,
+            pad2: sc.pad2,
+            data_crc: 0,
+            reloc_crc: 0,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Encode, Decode, EncodedSize)]
 #[declio(ctx_is = "constants::ENDIANESS")]
 pub struct SectionMap {
     pub sec_count: u16,
@@ -217,7 +525,7 @@ pub struct SectionMap {
     pub entries: Vec<SectionMapEntry>,
 }
 
-#[derive(Debug, Encode, Decode, EncodedSize)]
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, EncodedSize)]
 #[declio(ctx_is = "constants::ENDIANESS")]
 pub struct SectionMapEntry {
     pub flags: DescriptorFlags,
@@ -230,10 +538,11 @@ pub struct SectionMapEntry {
     pub sec_byte_length: u32,
 }
 
-#[derive(Debug, Encode, Decode, EncodedSize)]
+#[derive(Debug, PartialEq, Eq, Encode, EncodedSize)]
 #[declio(ctx_is = "constants::ENDIANESS")]
 pub struct FileInfo {
     pub num_modules: u16,
+    // truncated to 16 bits, the actual count is the sum of `module_file_counts`
     pub num_source_files: u16,
     #[declio(ctx = "(Len(*num_modules as usize), constants::ENDIANESS)")]
     pub module_indicies: Vec<u16>,
@@ -243,8 +552,30 @@ pub struct FileInfo {
     pub file_name_offsets: Vec<u32>,
 }
 
+impl Decode for FileInfo {
+    fn decode<R>(_: (), reader: &mut R) -> Result<Self, declio::Error>
+    where
+        R: io::Read,
+    {
+        let num_modules = u16::decode(constants::ENDIANESS, reader)?;
+        let num_source_files = u16::decode(constants::ENDIANESS, reader)?;
+        let module_indicies = Decode::decode((Len(num_modules.into()), constants::ENDIANESS), reader)?;
+        let module_file_counts: Vec<u16> =
+            Decode::decode((Len(num_modules.into()), constants::ENDIANESS), reader)?;
+        let file_count = module_file_counts.iter().map(|&count| usize::from(count)).sum();
+        let file_name_offsets = Decode::decode((Len(file_count), constants::ENDIANESS), reader)?;
+        Ok(Self {
+            num_modules,
+            num_source_files,
+            module_indicies,
+            module_file_counts,
+            file_name_offsets,
+        })
+    }
+}
+
 #[bitfield(bits = 16)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct DescriptorFlags {
     pub is_readable: bool,
     pub is_writable: bool,
@@ -261,7 +592,76 @@ pub struct DescriptorFlags {
 
 impl_bitfield_codecs!(DescriptorFlags);
 
-#[derive(Debug, Encode, Decode, EncodedSize)]
+pub const IMAGE_SCN_CNT_CODE: u32 = 0x20;
+pub const IMAGE_SCN_CNT_INITIALIZED_DATA: u32 = 0x40;
+pub const IMAGE_SCN_CNT_UNINITIALIZED_DATA: u32 = 0x80;
+pub const IMAGE_SCN_MEM_EXECUTE: u32 = 0x2000_0000;
+pub const IMAGE_SCN_MEM_READ: u32 = 0x4000_0000;
+pub const IMAGE_SCN_MEM_WRITE: u32 = 0x8000_0000;
+
+// the IMAGE_SCN_* flags of a section, the raw value converts both ways without losing the reserved bits
+#[bitfield(bits = 32)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SectionCharacteristics {
+    #[skip]
+    reserved1: B3,
+    pub type_no_pad: bool,
+    #[skip]
+    reserved2: B1,
+    pub cnt_code: bool,
+    pub cnt_initialized_data: bool,
+    pub cnt_uninitialized_data: bool,
+    pub lnk_other: bool,
+    pub lnk_info: bool,
+    #[skip]
+    reserved3: B1,
+    pub lnk_remove: bool,
+    pub lnk_comdat: bool,
+    #[skip]
+    reserved4: B2,
+    pub gprel: bool,
+    pub mem_purgeable: bool,
+    #[skip]
+    reserved5: B1,
+    pub mem_locked: bool,
+    pub mem_preload: bool,
+    // the log2 of the alignment plus one, zero when unset
+    pub encoded_alignment: B4,
+    pub lnk_nreloc_ovfl: bool,
+    pub mem_discardable: bool,
+    pub mem_not_cached: bool,
+    pub mem_not_paged: bool,
+    pub mem_shared: bool,
+    pub mem_execute: bool,
+    pub mem_read: bool,
+    pub mem_write: bool,
+}
+
+impl_bitfield_codecs!(SectionCharacteristics);
+
+impl SectionCharacteristics {
+    // only meaningful in object files
+    pub fn alignment(&self) -> Option<u32> {
+        match self.encoded_alignment() {
+            0 => None,
+            n => Some(1 << (n - 1)),
+        }
+    }
+}
+
+impl From<u32> for SectionCharacteristics {
+    fn from(raw: u32) -> Self {
+        Self::from_bytes(raw.to_le_bytes())
+    }
+}
+
+impl From<SectionCharacteristics> for u32 {
+    fn from(flags: SectionCharacteristics) -> Self {
+        u32::from_le_bytes(flags.into_bytes())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, EncodedSize)]
 #[declio(ctx_is = "constants::ENDIANESS")]
 pub struct SectionHeader {
     #[declio(with = "codecs::byte_array")]
@@ -277,6 +677,12 @@ pub struct SectionHeader {
     pub characteristics: u32,
 }
 
+impl SectionHeader {
+    pub fn flags(&self) -> SectionCharacteristics {
+        self.characteristics.into()
+    }
+}
+
 #[derive(Debug, Getters)]
 pub struct SectionHeaderStream {
     headers: Vec<SectionHeader>,
@@ -285,15 +691,43 @@ pub struct SectionHeaderStream {
 impl SectionHeaderStream {
     const ENTRY_SIZE: u32 = 40;
 
-    pub(crate) fn read<R: io::Read + io::Seek>(mut reader: BufMsfStream<R>) -> Result<Self> {
+    pub(crate) fn read<R: io::Read + io::Seek>(reader: &mut BufMsfStream<R>) -> Result<Self> {
         let count = reader.get_ref().length() / Self::ENTRY_SIZE;
-        let records = Decode::decode(Len(count as usize), &mut reader)?;
-        debug_assert!(reader.get_ref().is_eof());
+        let records = Decode::decode(Len(count as usize), reader)?;
         Ok(Self { headers: records })
     }
 }
 
-#[derive(Debug, Encode, Decode, EncodedSize)]
+// maps the start of a range of RVAs to where the range moved, zero when it was removed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode, EncodedSize)]
+#[declio(ctx_is = "constants::ENDIANESS")]
+pub struct OmapEntry {
+    pub rva: u32,
+    pub rva_to: u32,
+}
+
+#[derive(Debug, Getters)]
+pub struct OmapStream {
+    entries: Vec<OmapEntry>,
+}
+
+impl OmapStream {
+    const ENTRY_SIZE: u32 = 8;
+
+    pub(crate) fn read<R: io::Read + io::Seek>(reader: &mut BufMsfStream<R>) -> Result<Self> {
+        let count = reader.get_ref().length() / Self::ENTRY_SIZE;
+        let entries = Decode::decode(Len(count as usize), reader)?;
+        Ok(Self { entries })
+    }
+
+    pub fn translate(&self, rva: u32) -> Option<u32> {
+        let end = self.entries.partition_point(|entry| entry.rva <= rva);
+        let entry = self.entries[..end].last()?;
+        (entry.rva_to != 0).then(|| entry.rva_to + (rva - entry.rva))
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Encode, Decode, EncodedSize)]
 #[declio(ctx_is = "constants::ENDIANESS")]
 pub struct FpoData {
     pub offset: u32,
@@ -311,15 +745,14 @@ pub struct FpoStream {
 impl FpoStream {
     const ENTRY_SIZE: u32 = 16;
 
-    pub(crate) fn read<R: io::Read + io::Seek>(mut reader: BufMsfStream<R>) -> Result<Self> {
+    pub(crate) fn read<R: io::Read + io::Seek>(reader: &mut BufMsfStream<R>) -> Result<Self> {
         let count = reader.get_ref().length() / Self::ENTRY_SIZE;
-        let records = Decode::decode(Len(count as usize), &mut reader)?;
-        debug_assert!(reader.get_ref().is_eof());
+        let records = Decode::decode(Len(count as usize), reader)?;
         Ok(Self { records })
     }
 }
 
-#[derive(Debug, Encode, Decode, EncodedSize)]
+#[derive(Debug, PartialEq, Eq, Encode, Decode, EncodedSize)]
 #[declio(ctx_is = "constants::ENDIANESS")]
 pub struct FrameData {
     pub rva_start: u32,
@@ -341,19 +774,18 @@ pub struct FrameDataStream {
 impl FrameDataStream {
     const ENTRY_SIZE: u32 = 32;
 
-    pub(crate) fn read<R: io::Read + io::Seek>(mut reader: BufMsfStream<R>) -> Result<Self> {
+    pub(crate) fn read<R: io::Read + io::Seek>(reader: &mut BufMsfStream<R>) -> Result<Self> {
         if reader.get_ref().length() % Self::ENTRY_SIZE != 0 {
             // reloc_ptr
-            u32::decode(constants::ENDIANESS, &mut reader)?;
+            u32::decode(constants::ENDIANESS, reader)?;
         }
         let count = reader.get_ref().length() / Self::ENTRY_SIZE;
-        let frames = Decode::decode(Len(count as usize), &mut reader)?;
-        debug_assert!(reader.get_ref().is_eof());
+        let frames = Decode::decode(Len(count as usize), reader)?;
         Ok(Self { frames })
     }
 }
 
-#[derive(Debug, Clone, Copy, BitfieldSpecifier)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, BitfieldSpecifier)]
 #[bits = 16]
 pub enum MachineType {
     Invalid = 0xffff,
@@ -381,4 +813,29 @@ pub enum MachineType {
     WceMipsV2 = 0x169,
 }
 
+impl MachineType {
+    // the CodeView CPU type used by compile symbols for the machine
+    pub fn cpu_type(self) -> Option<u16> {
+        match self {
+            MachineType::X86 => Some(0x07),
+            MachineType::Ia64 => Some(0x80),
+            MachineType::Arm => Some(0x64),
+            MachineType::Thumb => Some(0x66),
+            MachineType::ArmNT => Some(0xF4),
+            MachineType::Amd64 => Some(0xD0),
+            MachineType::Arm64 => Some(0xF6),
+            MachineType::Ebc => Some(0xE0),
+            _ => None,
+        }
+    }
+
+    pub fn pointer_size(self) -> Option<u64> {
+        match self {
+            MachineType::Amd64 | MachineType::Arm64 | MachineType::Ia64 => Some(8),
+            MachineType::Invalid | MachineType::Unknown => None,
+            _ => Some(4),
+        }
+    }
+}
+
 impl_bitfield_specifier_codecs!(MachineType);
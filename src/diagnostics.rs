@@ -0,0 +1,230 @@
+use std::cell::{Cell, RefCell};
+use std::io;
+use std::rc::Rc;
+
+use crate::metrics::{Metrics, RecordDecoder};
+use crate::result::{Error, Result};
+use crate::utils::trace_span;
+use crate::StreamIndex;
+
+#[derive(Debug, Clone, Default)]
+pub struct ParseOptions {
+    // collect record errors as diagnostics instead of failing the whole stream
+    pub lenient: bool,
+    pub limits: ParseLimits,
+    // count the records and time the streams read, see `PdbFile::metrics`
+    pub metrics: bool,
+}
+
+impl ParseOptions {
+    pub fn lenient() -> Self {
+        Self {
+            lenient: true,
+            ..Self::default()
+        }
+    }
+}
+
+// bounds on the sizes read from the file, intended for untrusted input
+#[derive(Debug, Clone, Copy)]
+pub struct ParseLimits {
+    pub max_streams: u32,
+    // the size of a single length-prefixed entry, like a C13 subsection, CodeView records can't exceed
+    // 64 KiB anyway
+    pub max_record_size: u32,
+    // the bytes that the sizes and counts read from a stream can claim in total while the stream is
+    // read, and that the streams of the file can add up to
+    pub max_total_memory: u64,
+}
+
+impl ParseLimits {
+    pub(crate) fn check_streams(&self, count: u32) -> Result<()> {
+        if count > self.max_streams {
+            return Err(Error::LimitExceeded("too many streams"));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn check_record_size(&self, size: u32) -> Result<()> {
+        if size > self.max_record_size {
+            return Err(Error::LimitExceeded("record too large"));
+        }
+        Ok(())
+    }
+
+    // for a single allocation, readers of streams with a `ReadContext` add theirs up in a `MemoryBudget`
+    pub(crate) fn check_memory(&self, bytes: u64) -> Result<()> {
+        if bytes > self.max_total_memory {
+            return Err(Error::LimitExceeded("memory budget exhausted"));
+        }
+        Ok(())
+    }
+}
+
+// a single stream can't exceed 4 GiB, and no subsection written by MSVC or LLVM comes close to 16 MiB
+impl Default for ParseLimits {
+    fn default() -> Self {
+        Self {
+            max_streams: u16::MAX as u32 + 1,
+            max_record_size: 16 << 20,
+            max_total_memory: 4 << 30,
+        }
+    }
+}
+
+// the bytes claimed by the sizes and counts read from a stream so far, clones share the count so
+// that it can be charged from within the decoders of records
+#[derive(Debug, Clone)]
+pub(crate) struct MemoryBudget {
+    limit: u64,
+    used: Rc<Cell<u64>>,
+}
+
+impl MemoryBudget {
+    pub(crate) fn new(limits: &ParseLimits) -> Self {
+        Self {
+            limit: limits.max_total_memory,
+            used: Rc::default(),
+        }
+    }
+
+    pub(crate) fn allocate(&self, bytes: u64) -> Result<()> {
+        let used = self.used.get().saturating_add(bytes);
+        if used > self.limit {
+            return Err(Error::LimitExceeded("memory budget exhausted"));
+        }
+        self.used.set(used);
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct Diagnostic {
+    pub stream: &'static str,
+    pub offset: u64,
+    pub error: Error,
+}
+
+// a non-fatal oddity found while parsing, unlike diagnostics these don't leave anything out
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Warning {
+    pub stream: &'static str,
+    pub offset: u64,
+    pub kind: WarningKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WarningKind {
+    // bytes left over past the entries of a stream or substream
+    TrailingBytes(u64),
+    // a byte in the padding between entries that isn't written by any known tool
+    UnexpectedPadding(u8),
+    // a stream index past the end of the stream directory
+    StreamOutOfRange(u16),
+}
+
+pub(crate) struct ReadContext<'a> {
+    options: &'a ParseOptions,
+    diagnostics: &'a mut Vec<Diagnostic>,
+    warnings: &'a mut Vec<Warning>,
+    metrics: Option<&'a RefCell<Metrics>>,
+    budget: MemoryBudget,
+    index: Option<StreamIndex>,
+}
+
+impl<'a> ReadContext<'a> {
+    pub(crate) fn new(
+        options: &'a ParseOptions,
+        diagnostics: &'a mut Vec<Diagnostic>,
+        warnings: &'a mut Vec<Warning>,
+        metrics: Option<&'a RefCell<Metrics>>,
+        index: Option<StreamIndex>,
+    ) -> Self {
+        Self {
+            options,
+            diagnostics,
+            warnings,
+            metrics,
+            budget: MemoryBudget::new(&options.limits),
+            index,
+        }
+    }
+
+    pub(crate) fn limits(&self) -> ParseLimits {
+        self.options.limits
+    }
+
+    // the budget of the stream being read
+    pub(crate) fn budget(&self) -> MemoryBudget {
+        self.budget.clone()
+    }
+
+    pub(crate) fn records(&self) -> RecordDecoder<'a> {
+        RecordDecoder::new(self.options.limits, self.metrics)
+    }
+
+    pub(crate) fn warn(&mut self, stream: &'static str, offset: u64, kind: WarningKind) {
+        self.warnings.push(Warning { stream, offset, kind });
+    }
+
+    // `start` is the offset of the records in the stream, which the reported offsets are relative to
+    pub(crate) fn read_records<A, R, F>(
+        &mut self,
+        stream: &'static str,
+        start: u64,
+        reader: &mut io::Take<R>,
+        decode: F,
+    ) -> Result<Vec<A>>
+    where
+        R: io::Read,
+        F: FnMut(&mut io::Take<R>) -> Result<A>,
+    {
+        let mut records = vec![];
+        self.visit_records(stream, start, reader, decode, |record| {
+            records.extend(record);
+            Ok(())
+        })?;
+        Ok(records)
+    }
+
+    // like `read_records`, but hands every record over to `visit` instead of collecting them, records
+    // skipped in lenient mode are passed as `None`, so that readers of indexed records can keep count
+    pub(crate) fn visit_records<A, R, F, V>(
+        &mut self,
+        stream: &'static str,
+        start: u64,
+        reader: &mut io::Take<R>,
+        mut decode: F,
+        mut visit: V,
+    ) -> Result<()>
+    where
+        R: io::Read,
+        F: FnMut(&mut io::Take<R>) -> Result<A>,
+        V: FnMut(Option<A>) -> Result<()>,
+    {
+        trace_span!("records", stream);
+        let total = reader.limit();
+        while reader.limit() > 0 {
+            let consumed = total - reader.limit();
+            let offset = start + consumed;
+            match decode(reader) {
+                Ok(record) => visit(Some(record))?,
+                Err(error) if self.options.lenient => {
+                    let stalled = total - reader.limit() == consumed;
+                    self.diagnostics.push(Diagnostic {
+                        stream,
+                        offset,
+                        error,
+                    });
+                    // nothing was consumed, the rest of the stream is unreadable
+                    if stalled {
+                        break;
+                    }
+                    visit(None)?;
+                }
+                Err(error) => return Err(error.in_stream(stream, self.index, offset)),
+            }
+        }
+        Ok(())
+    }
+}
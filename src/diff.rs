@@ -0,0 +1,247 @@
+use std::collections::BTreeMap;
+use std::io;
+
+use declio::Encode;
+
+use crate::codeview::types::TypeRecord;
+use crate::result::Result;
+use crate::stats::SizeStats;
+use crate::PdbFile;
+
+#[derive(Debug)]
+pub struct PdbDiff {
+    pub types: ItemDiff<TypeSummary>,
+    pub symbols: ItemDiff<SymbolSummary>,
+    pub modules: ItemDiff<ModuleSummary>,
+    // only the streams whose size differs
+    pub streams: Vec<SizeDelta>,
+}
+
+#[derive(Debug)]
+pub struct ItemDiff<A> {
+    pub added: Vec<A>,
+    pub removed: Vec<A>,
+    pub changed: Vec<Change<A>>,
+}
+
+impl<A> ItemDiff<A> {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+#[derive(Debug)]
+pub struct Change<A> {
+    pub old: A,
+    pub new: A,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum UdtKind {
+    Class,
+    Struct,
+    Interface,
+    Union,
+    Enum,
+}
+
+// type indices differ between files, so user-defined types are matched by name
+#[derive(Debug, PartialEq, Eq)]
+pub struct TypeSummary {
+    pub name: String,
+    pub kind: UdtKind,
+    pub size: Option<u64>,
+    pub member_count: u16,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct SymbolSummary {
+    pub name: String,
+    pub kind: u16,
+    // the encoded record without the length prefix
+    pub record: Vec<u8>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct ModuleSummary {
+    pub name: String,
+    pub stream_bytes: u32,
+    pub symbol_bytes: u32,
+    pub c11_bytes: u32,
+    pub c13_bytes: u32,
+}
+
+#[derive(Debug)]
+pub struct SizeDelta {
+    pub name: String,
+    pub old_bytes: u64,
+    pub new_bytes: u64,
+}
+
+impl SizeDelta {
+    pub fn delta(&self) -> i64 {
+        self.new_bytes as i64 - self.old_bytes as i64
+    }
+}
+
+pub fn diff<R1, R2>(old: &mut PdbFile<R1>, new: &mut PdbFile<R2>) -> Result<PdbDiff>
+where
+    R1: io::Read + io::Seek,
+    R2: io::Read + io::Seek,
+{
+    let types = diff_items(type_summaries(old)?, type_summaries(new)?);
+    let symbols = diff_items(symbol_summaries(old)?, symbol_summaries(new)?);
+
+    let old_stats = old.get_size_stats(0)?;
+    let new_stats = new.get_size_stats(0)?;
+    let module_summaries = |stats: SizeStats| {
+        stats
+            .modules
+            .into_iter()
+            .map(|module| {
+                let summary = ModuleSummary {
+                    name: module.name,
+                    stream_bytes: module.stream_bytes,
+                    symbol_bytes: module.symbol_bytes,
+                    c11_bytes: module.c11_bytes,
+                    c13_bytes: module.c13_bytes,
+                };
+                (summary.name.clone(), summary)
+            })
+            // keeps the first module when names repeat
+            .rev()
+            .collect::<BTreeMap<_, _>>()
+    };
+    let stream_sizes = |stats: &SizeStats| {
+        stats
+            .streams
+            .iter()
+            .filter_map(|stream| Some((stream.name.clone()?, u64::from(stream.bytes?))))
+            .collect::<BTreeMap<_, _>>()
+    };
+    let old_streams = stream_sizes(&old_stats);
+    let mut new_streams = stream_sizes(&new_stats);
+
+    let mut streams = vec![];
+    for (name, old_bytes) in old_streams {
+        let new_bytes = new_streams.remove(&name).unwrap_or(0);
+        if old_bytes != new_bytes {
+            streams.push(SizeDelta {
+                name,
+                old_bytes,
+                new_bytes,
+            });
+        }
+    }
+    streams.extend(new_streams.into_iter().map(|(name, new_bytes)| SizeDelta {
+        name,
+        old_bytes: 0,
+        new_bytes,
+    }));
+
+    let res = PdbDiff {
+        types,
+        symbols,
+        modules: diff_items(module_summaries(old_stats), module_summaries(new_stats)),
+        streams,
+    };
+    Ok(res)
+}
+
+fn type_summaries<R>(pdb: &mut PdbFile<R>) -> Result<BTreeMap<(String, UdtKind), TypeSummary>>
+where
+    R: io::Read + io::Seek,
+{
+    let tpi = pdb.get_tpi()?;
+    let mut res = BTreeMap::new();
+    for record in tpi.records() {
+        let (kind, name, properties, size, member_count) = match record {
+            TypeRecord::Class(class) => (
+                UdtKind::Class,
+                &class.name,
+                class.properties,
+                class.size.as_u64(),
+                class.member_count,
+            ),
+            TypeRecord::Struct(class) => (
+                UdtKind::Struct,
+                &class.name,
+                class.properties,
+                class.size.as_u64(),
+                class.member_count,
+            ),
+            TypeRecord::Interface(class) => (
+                UdtKind::Interface,
+                &class.name,
+                class.properties,
+                class.size.as_u64(),
+                class.member_count,
+            ),
+            TypeRecord::Union(union) => (
+                UdtKind::Union,
+                &union.name,
+                union.properties,
+                union.size.as_u64(),
+                union.member_count,
+            ),
+            TypeRecord::Enum(enum_) => (
+                UdtKind::Enum,
+                &enum_.name,
+                enum_.properties,
+                // the size of an enum is the one of its underlying type
+                None,
+                enum_.member_count,
+            ),
+            _ => continue,
+        };
+        if properties.is_forward_ref() {
+            continue;
+        }
+        let name = name.as_ref().to_owned();
+        res.entry((name.clone(), kind)).or_insert(TypeSummary {
+            name,
+            kind,
+            size,
+            member_count,
+        });
+    }
+    Ok(res)
+}
+
+fn symbol_summaries<R>(pdb: &mut PdbFile<R>) -> Result<BTreeMap<(String, u16), SymbolSummary>>
+where
+    R: io::Read + io::Seek,
+{
+    let dbi = pdb.get_dbi()?;
+    let symbols = pdb.get_symbols(&dbi)?;
+    let mut res = BTreeMap::new();
+    for symbol in symbols.records() {
+        let name = match symbol.name() {
+            Some(name) => name.to_owned(),
+            None => continue,
+        };
+        let mut record = vec![];
+        symbol.encode((), &mut record)?;
+        let kind = u16::from_le_bytes([record[0], record[1]]);
+        res.entry((name.clone(), kind))
+            .or_insert(SymbolSummary { name, kind, record });
+    }
+    Ok(res)
+}
+
+fn diff_items<K: Ord, A: PartialEq>(old: BTreeMap<K, A>, mut new: BTreeMap<K, A>) -> ItemDiff<A> {
+    let mut res = ItemDiff {
+        added: vec![],
+        removed: vec![],
+        changed: vec![],
+    };
+    for (key, old) in old {
+        match new.remove(&key) {
+            Some(new) if new != old => res.changed.push(Change { old, new }),
+            Some(_) => {}
+            None => res.removed.push(old),
+        }
+    }
+    res.added.extend(new.into_values());
+    res
+}
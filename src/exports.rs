@@ -0,0 +1,72 @@
+use object::pe::ImageSectionHeader;
+use object::read::pe::{ExportTarget, ImageNtHeaders, PeFile, PeFile32, PeFile64};
+use object::{FileKind, LittleEndian as LE};
+
+use crate::builders::PdbBuilder;
+use crate::codeview::symbols::{Public, PublicProperties};
+use crate::codeview::DataRegionOffset;
+use crate::dbi::SectionHeader;
+use crate::result::{Error, Result};
+use crate::utils::StrBuf;
+
+// adds the sections of a PE image and a public for every function or variable it exports, the least
+// a debugger needs to symbolize a DLL that comes without a PDB, the sections are only added when the
+// builder has none yet, returns the number of publics added
+pub fn add_exports(builder: &mut PdbBuilder, image: &[u8]) -> Result<usize> {
+    match FileKind::parse(image).map_err(Error::invalid_image)? {
+        FileKind::Pe32 => add_pe_exports(builder, &PeFile32::parse(image).map_err(Error::invalid_image)?),
+        FileKind::Pe64 => add_pe_exports(builder, &PeFile64::parse(image).map_err(Error::invalid_image)?),
+        _ => Err(Error::UnsupportedFeature("images other than PE")),
+    }
+}
+
+fn add_pe_exports<Pe: ImageNtHeaders>(builder: &mut PdbBuilder, pe: &PeFile<'_, Pe>) -> Result<usize> {
+    let sections: Vec<SectionHeader> = pe.section_table().iter().map(section_header).collect();
+    let mut publics = vec![];
+    if let Some(table) = pe.export_table().map_err(Error::invalid_image)? {
+        for export in table.exports().map_err(Error::invalid_image)? {
+            // forwarders live in another image and exports by ordinal have no name to give
+            let (Some(name), ExportTarget::Address(rva)) = (export.name, export.target) else {
+                continue;
+            };
+            let Some((segment, header)) = (1..).zip(&sections).find(|(_, header)| {
+                (header.virtual_address..header.virtual_address + header.virtual_size).contains(&rva)
+            }) else {
+                continue;
+            };
+            let is_code = header.flags().cnt_code();
+            publics.push(Public {
+                properties: PublicProperties::new()
+                    .with_is_code(is_code)
+                    .with_is_function(is_code),
+                offset: DataRegionOffset::new(rva - header.virtual_address, segment),
+                name: StrBuf::new(String::from_utf8_lossy(name).into_owned()),
+            });
+        }
+    }
+
+    if builder.dbi().section_headers().is_empty() {
+        builder.dbi().add_sections(sections);
+    }
+    let count = publics.len();
+    let mut symbols = builder.dbi().symbols();
+    for public in publics {
+        symbols.add(public)?;
+    }
+    Ok(count)
+}
+
+pub(crate) fn section_header(header: &ImageSectionHeader) -> SectionHeader {
+    SectionHeader {
+        name: header.name,
+        virtual_size: header.virtual_size.get(LE),
+        virtual_address: header.virtual_address.get(LE),
+        size_of_raw_data: header.size_of_raw_data.get(LE),
+        pointer_to_raw_data: header.pointer_to_raw_data.get(LE),
+        pointer_to_relocations: header.pointer_to_relocations.get(LE),
+        pointer_to_line_numbers: header.pointer_to_linenumbers.get(LE),
+        number_of_relocations: header.number_of_relocations.get(LE),
+        number_of_line_numbers: header.number_of_linenumbers.get(LE),
+        characteristics: header.characteristics.get(LE),
+    }
+}
@@ -0,0 +1,266 @@
+// a C ABI over `Pdb` for tools written in other languages, see `include/pdb_sdk.h`, to get a shared
+// library build the crate with `cargo rustc --release --features ffi --crate-type cdylib`
+//
+// handles must come from `pdb_open` or `pdb_open_bytes` and can't be used once passed to `pdb_close`
+// or from more than one thread at a time, strings are passed in as NUL-terminated UTF-8 and handed out
+// as pointers with a length that stay valid for as long as the handle they come from
+#![allow(clippy::missing_safety_doc)]
+
+use std::cell::RefCell;
+use std::ffi::{c_char, c_void, CStr, CString};
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+
+use crate::address_index::AddressKind;
+use crate::codeview::symbols::SymbolRecord;
+use crate::diagnostics::ParseOptions;
+use crate::functions::Function;
+use crate::pdb::Pdb;
+use crate::result::{Error, Result};
+
+pub struct PdbHandle {
+    pdb: Pdb<io::Cursor<Vec<u8>>>,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PdbStatus {
+    Found = 1,
+    NotFound = 0,
+    Failed = -1,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct PdbSymbol {
+    pub name: *const u8,
+    pub name_len: usize,
+    pub rva: u32,
+    // zero when unknown, like for publics
+    pub size: u32,
+    // the index of the module in the DBI stream, `u32::MAX` for publics
+    pub module: u32,
+}
+
+pub type PdbModuleCallback =
+    extern "C" fn(ctx: *mut c_void, index: u32, name: *const u8, name_len: usize) -> bool;
+pub type PdbSymbolCallback = extern "C" fn(ctx: *mut c_void, symbol: *const PdbSymbol) -> bool;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+// the message of the last error on the calling thread, or null when there was none
+#[no_mangle]
+pub extern "C" fn pdb_last_error() -> *const c_char {
+    LAST_ERROR.with(|error| {
+        error
+            .borrow()
+            .as_ref()
+            .map_or(std::ptr::null(), |error| error.as_ptr())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn pdb_open(path: *const c_char) -> *mut PdbHandle {
+    open(|| {
+        let path = str_arg(path)?;
+        Ok(std::fs::read(path)?)
+    })
+}
+
+// the bytes are copied, so the buffer can be released once the call returns
+#[no_mangle]
+pub unsafe extern "C" fn pdb_open_bytes(data: *const u8, len: usize) -> *mut PdbHandle {
+    open(|| {
+        if data.is_null() {
+            return Err(Error::InvalidArgument("null data"));
+        }
+        Ok(std::slice::from_raw_parts(data, len).to_vec())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn pdb_close(handle: *mut PdbHandle) {
+    if !handle.is_null() {
+        drop(Box::from_raw(handle));
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn pdb_guid(handle: *const PdbHandle, guid: *mut u8, age: *mut u32) -> PdbStatus {
+    call(handle, |pdb| {
+        let header = pdb.info()?.header();
+        if !guid.is_null() {
+            std::ptr::copy_nonoverlapping(header.guid.bytes().as_ptr(), guid, 16);
+        }
+        if !age.is_null() {
+            *age = header.age;
+        }
+        Ok(PdbStatus::Found)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn pdb_function_at(
+    handle: *const PdbHandle,
+    rva: u32,
+    out: *mut PdbSymbol,
+) -> PdbStatus {
+    call(handle, |pdb| write_function(pdb.function_at(rva)?, out))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn pdb_function_by_name(
+    handle: *const PdbHandle,
+    name: *const c_char,
+    out: *mut PdbSymbol,
+) -> PdbStatus {
+    call(handle, |pdb| {
+        write_function(pdb.function_by_name(str_arg(name)?)?, out)
+    })
+}
+
+// the public whose address is the closest one at or before an RVA within its section
+#[no_mangle]
+pub unsafe extern "C" fn pdb_public_at(
+    handle: *const PdbHandle,
+    rva: u32,
+    out: *mut PdbSymbol,
+) -> PdbStatus {
+    call(handle, |pdb| {
+        let Some(range) = pdb.address_index()?.public_at(rva) else {
+            return Ok(PdbStatus::NotFound);
+        };
+        let AddressKind::Public { offset } = range.kind else {
+            return Ok(PdbStatus::NotFound);
+        };
+        match pdb.symbols()?.get(offset) {
+            Some(SymbolRecord::Public32(public)) => {
+                write_symbol(public.name.as_ref(), range.start, 0, u32::MAX, out);
+                Ok(PdbStatus::Found)
+            }
+            _ => Ok(PdbStatus::NotFound),
+        }
+    })
+}
+
+// calls back with every module until the callback returns false
+#[no_mangle]
+pub unsafe extern "C" fn pdb_enumerate_modules(
+    handle: *const PdbHandle,
+    callback: PdbModuleCallback,
+    ctx: *mut c_void,
+) -> PdbStatus {
+    call(handle, |pdb| {
+        for (index, module) in (0..).zip(pdb.dbi()?.modules()) {
+            let name: &str = module.module_name.as_ref();
+            if !callback(ctx, index, name.as_ptr(), name.len()) {
+                break;
+            }
+        }
+        Ok(PdbStatus::Found)
+    })
+}
+
+// calls back with every public that has an address until the callback returns false
+#[no_mangle]
+pub unsafe extern "C" fn pdb_enumerate_publics(
+    handle: *const PdbHandle,
+    callback: PdbSymbolCallback,
+    ctx: *mut c_void,
+) -> PdbStatus {
+    call(handle, |pdb| {
+        for record in pdb.symbols()?.records() {
+            let SymbolRecord::Public32(public) = record else {
+                continue;
+            };
+            let Some(rva) = pdb.rva(public.offset.clone())? else {
+                continue;
+            };
+            let mut symbol = std::mem::MaybeUninit::uninit();
+            write_symbol(public.name.as_ref(), rva, 0, u32::MAX, symbol.as_mut_ptr());
+            if !callback(ctx, symbol.as_ptr()) {
+                break;
+            }
+        }
+        Ok(PdbStatus::Found)
+    })
+}
+
+fn open(read: impl FnOnce() -> Result<Vec<u8>>) -> *mut PdbHandle {
+    let res = catch(|| {
+        let pdb = Pdb::load_with_options(io::Cursor::new(read()?), ParseOptions::lenient())?;
+        Ok(Box::into_raw(Box::new(PdbHandle { pdb })))
+    });
+    res.unwrap_or(std::ptr::null_mut())
+}
+
+unsafe fn call<F>(handle: *const PdbHandle, f: F) -> PdbStatus
+where
+    F: FnOnce(&Pdb<io::Cursor<Vec<u8>>>) -> Result<PdbStatus>,
+{
+    let res = catch(|| match handle.as_ref() {
+        Some(handle) => f(&handle.pdb),
+        None => Err(Error::InvalidArgument("null handle")),
+    });
+    res.unwrap_or(PdbStatus::Failed)
+}
+
+// runs an operation and keeps its error for `pdb_last_error`, panics are turned into errors so that
+// they don't unwind into foreign frames
+fn catch<A>(f: impl FnOnce() -> Result<A>) -> Option<A> {
+    let res = match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(res) => res.map_err(|err| err.to_string()),
+        Err(payload) => {
+            let message = payload
+                .downcast_ref::<&str>()
+                .map(|message| message.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "panic".to_owned());
+            Err(format!("panicked: {message}"))
+        }
+    };
+    let (value, error) = match res {
+        Ok(value) => (Some(value), None),
+        Err(err) => (None, CString::new(err.replace('\0', " ")).ok()),
+    };
+    LAST_ERROR.with(|last| *last.borrow_mut() = error);
+    value
+}
+
+unsafe fn str_arg<'a>(str: *const c_char) -> Result<&'a str> {
+    if str.is_null() {
+        return Err(Error::InvalidArgument("null string"));
+    }
+    CStr::from_ptr(str)
+        .to_str()
+        .map_err(|_| Error::InvalidArgument("string is not UTF-8"))
+}
+
+unsafe fn write_function(function: Option<Function<'_>>, out: *mut PdbSymbol) -> Result<PdbStatus> {
+    let Some(function) = function else {
+        return Ok(PdbStatus::NotFound);
+    };
+    let proc = function.procedure;
+    write_symbol(
+        proc.name.as_ref(),
+        function.rva,
+        proc.code_size,
+        function.module as u32,
+        out,
+    );
+    Ok(PdbStatus::Found)
+}
+
+unsafe fn write_symbol(name: &str, rva: u32, size: u32, module: u32, out: *mut PdbSymbol) {
+    if !out.is_null() {
+        out.write(PdbSymbol {
+            name: name.as_ptr(),
+            name_len: name.len(),
+            rva,
+            size,
+            module,
+        });
+    }
+}
@@ -0,0 +1,131 @@
+use crate::codeview::symbols::{Procedure, SymbolRecord};
+use crate::codeview::types::{IdRecord, TypeRecord};
+use crate::locals::LocalVariable;
+use crate::module::SourceLine;
+use crate::types::{IpiStream, TpiStream};
+use crate::IdIndex;
+
+// a procedure joined with the records describing it across the streams, the unit of information
+// a debugger shows for a function
+#[derive(Debug)]
+pub struct Function<'a> {
+    // the index of the module in the DBI stream
+    pub module: usize,
+    pub procedure: &'a Procedure,
+    pub rva: u32,
+    // the S_FRAMEPROC record of the procedure
+    pub frame: Option<&'a SymbolRecord>,
+    // the locals of the procedure and its blocks, those of inlined calls are left out
+    pub locals: Vec<LocalVariable<'a>>,
+    pub lines: Vec<SourceLine<'a>>,
+    // the procedure or member function type, looked up through the IPI for procedures with IDs
+    pub signature: Option<&'a TypeRecord>,
+}
+
+impl<'a> Function<'a> {
+    // `symbols` are the records of the module starting with the procedure, `lines` are those of the module
+    pub(crate) fn new(
+        module: usize,
+        rva: u32,
+        symbols: &'a [SymbolRecord],
+        lines: Vec<SourceLine<'a>>,
+        tpi: &'a TpiStream,
+        ipi: Option<&'a IpiStream>,
+    ) -> Option<Self> {
+        let (symbol, scope) = symbols.split_first()?;
+        let procedure = as_procedure(symbol)?;
+
+        // the runs of records outside of inline sites, up to the end of the procedure
+        let mut runs = vec![];
+        let mut run_start = Some(0);
+        let mut inline_depth = None;
+        let mut depth = 0;
+        let mut end = scope.len();
+        let mut frame = None;
+        for (i, symbol) in scope.iter().enumerate() {
+            match symbol {
+                SymbolRecord::ScopeEnd | SymbolRecord::ProcEnd | SymbolRecord::InlineSiteEnd => {
+                    if depth == 0 {
+                        end = i;
+                        break;
+                    }
+                    depth -= 1;
+                    if inline_depth == Some(depth) {
+                        inline_depth = None;
+                        run_start = Some(i + 1);
+                    }
+                }
+                SymbolRecord::InlineSite { .. } => {
+                    if inline_depth.is_none() {
+                        inline_depth = Some(depth);
+                        runs.extend(run_start.take().map(|start| start..i));
+                    }
+                    depth += 1;
+                }
+                SymbolRecord::Block { .. } | SymbolRecord::Thunk32 { .. } => depth += 1,
+                SymbolRecord::FrameProcedure { .. } if depth == 0 && frame.is_none() => {
+                    frame = Some(symbol)
+                }
+                symbol if as_procedure(symbol).is_some() => depth += 1,
+                _ => {}
+            }
+        }
+        runs.extend(run_start.map(|start| start..end));
+
+        let start = procedure.code_offset.offset;
+        let lines = lines
+            .into_iter()
+            .filter(|line| {
+                line.offset.segment == procedure.code_offset.segment
+                    && (start..start + procedure.code_size).contains(&line.offset.offset)
+            })
+            .collect();
+
+        Some(Self {
+            module,
+            procedure,
+            rva,
+            frame,
+            locals: runs
+                .into_iter()
+                .flat_map(|run| LocalVariable::collect(&scope[run]))
+                .collect(),
+            lines,
+            signature: signature(symbol, procedure, tpi, ipi),
+        })
+    }
+}
+
+pub(crate) fn as_procedure(symbol: &SymbolRecord) -> Option<&Procedure> {
+    match symbol {
+        SymbolRecord::Proc(proc)
+        | SymbolRecord::GlobalProc(proc)
+        | SymbolRecord::ProcId(proc)
+        | SymbolRecord::GlobalProcId(proc)
+        | SymbolRecord::DPCProc(proc)
+        | SymbolRecord::DPCProcId(proc) => Some(proc),
+        _ => None,
+    }
+}
+
+fn signature<'a>(
+    symbol: &SymbolRecord,
+    procedure: &Procedure,
+    tpi: &'a TpiStream,
+    ipi: Option<&'a IpiStream>,
+) -> Option<&'a TypeRecord> {
+    let function_type = match symbol {
+        // the function type of these procedures is an ID
+        SymbolRecord::ProcId(_) | SymbolRecord::GlobalProcId(_) | SymbolRecord::DPCProcId(_) => {
+            let id = IdIndex::try_from(u32::from(procedure.function_type)).ok()?;
+            match ipi?.record(id)? {
+                IdRecord::FuncId { function_type, .. } | IdRecord::MemberFuncId { function_type, .. } => {
+                    *function_type
+                }
+                _ => return None,
+            }
+        }
+        _ => procedure.function_type,
+    };
+    tpi.record(function_type)
+}
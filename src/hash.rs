@@ -3,7 +3,7 @@ use declio::{Decode, Encode};
 
 use crate::{constants, div_ceil};
 
-#[derive(Debug, Encode, Decode)]
+#[derive(Debug, PartialEq, Eq, Encode, Decode)]
 #[declio(ctx_is = "constants::ENDIANESS")]
 pub(crate) struct Table {
     size: u32,
@@ -44,7 +44,7 @@ impl Default for Table {
     }
 }
 
-#[derive(Debug, Encode, Decode)]
+#[derive(Debug, PartialEq, Eq, Encode, Decode)]
 #[declio(ctx_is = "constants::ENDIANESS")]
 pub struct KeyVal {
     pub key: u32,
@@ -57,7 +57,7 @@ impl KeyVal {
     }
 }
 
-#[derive(Debug, Default, Encode, Decode)]
+#[derive(Debug, Default, PartialEq, Eq, Encode, Decode)]
 #[declio(ctx_is = "constants::ENDIANESS")]
 pub struct BitVector {
     words: u32,
@@ -96,6 +96,24 @@ impl BitVector {
     }
 }
 
+// a case-sensitive hash used by newer string tables
+pub(crate) fn hash_v2(bytes: &[u8]) -> u32 {
+    let mut hash: u32 = 0xb170a1bf;
+    let mut mix = |item: u32| {
+        hash = hash.wrapping_add(item);
+        hash = hash.wrapping_add(hash << 10);
+        hash ^= hash >> 6;
+    };
+    let mut chunks = bytes.chunks_exact(4);
+    for chunk in chunks.by_ref() {
+        mix(u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]));
+    }
+    for &byte in chunks.remainder() {
+        mix(byte.into());
+    }
+    hash.wrapping_mul(1664525).wrapping_add(1013904223)
+}
+
 pub(crate) fn hash_v1(bytes: &[u8]) -> u32 {
     let mut hash = 0;
     let mut slice = bytes;
@@ -1,20 +1,15 @@
 use std::io;
 
 use declio::util::Bytes;
-use declio::{Decode, Encode, EncodedSize};
+use declio::{Decode, Encode};
 use derive_getters::Getters;
-use modular_bitfield::BitfieldSpecifier;
 
 use crate::hash::Table;
 use crate::result::{Error, Result};
-use crate::{codecs, constants, impl_bitfield_specifier_codecs, Guid, StreamIndex};
+use crate::utils::StrBuf;
+use crate::{codecs, constants, u32_enum, Guid, StreamIndex};
 
-const SUPPORTED_VERSIOMS: &[PdbVersion] = &[
-    PdbVersion::Vc70,
-    PdbVersion::Vc80,
-    PdbVersion::Vc110,
-    PdbVersion::Vc140,
-];
+pub(crate) const SRCSRV_STREAM: &str = "srcsrv";
 
 #[derive(Debug, Getters)]
 pub struct PdbInfo {
@@ -26,11 +21,16 @@ pub struct PdbInfo {
 impl PdbInfo {
     pub fn read<R: io::Read>(mut reader: R) -> Result<Self> {
         let header = PdbInfoHeader::decode((), &mut reader)?;
-        if !SUPPORTED_VERSIOMS.contains(&header.version) {
-            return Err(Error::UnsupportedFeature("Invalid PDB stream version"));
-        }
-        let named_streams = NamedStreams::decode((), &mut reader)?;
-        let features = codecs::padded_rem_list::decode(constants::ENDIANESS, &mut reader)?;
+        // the name map was introduced in VC 4.1
+        let named_streams = match header.version {
+            PdbVersion::Vc2 | PdbVersion::Vc4 => NamedStreams::default(),
+            _ => NamedStreams::decode((), &mut reader)?,
+        };
+        let features = if header.version.is_legacy() {
+            vec![]
+        } else {
+            codecs::padded_rem_list::decode(constants::ENDIANESS, &mut reader)?
+        };
 
         Ok(Self {
             header,
@@ -40,16 +40,39 @@ impl PdbInfo {
     }
 }
 
-#[derive(Debug, Encode, Decode)]
+#[derive(Debug, PartialEq, Eq, Encode, Decode)]
 #[declio(ctx_is = "constants::ENDIANESS")]
 pub struct PdbInfoHeader {
     pub version: PdbVersion,
     pub signature: u32,
     pub age: u32,
+    #[declio(skip_if = "version.is_legacy()")]
     pub guid: Guid,
 }
 
-#[derive(Debug, Encode, Decode)]
+// the fields of the header to replace when patching a file in place, the rest is kept
+#[derive(Debug, Default)]
+pub struct InfoPatch {
+    pub signature: Option<u32>,
+    pub age: Option<u32>,
+    pub guid: Option<Guid>,
+}
+
+impl InfoPatch {
+    pub(crate) fn apply(self, header: &mut PdbInfoHeader) -> Result<()> {
+        if self.guid.is_some() && header.version.is_legacy() {
+            return Err(Error::UnsupportedFeature("GUID in a legacy info stream"));
+        }
+        header.signature = self.signature.unwrap_or(header.signature);
+        header.age = self.age.unwrap_or(header.age);
+        if let Some(guid) = self.guid {
+            header.guid = guid;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Encode, Decode)]
 #[declio(ctx_is = "constants::ENDIANESS")]
 pub struct NamedStreams {
     #[declio(via = "Bytes<u32>")]
@@ -71,31 +94,145 @@ impl NamedStreams {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, BitfieldSpecifier)]
-#[bits = 32]
-pub enum PdbVersion {
-    Vc2 = 19941610,
-    Vc4 = 19950623,
-    Vc41 = 19950814,
-    Vc50 = 19960307,
-    Vc98 = 19970604,
-    Vc70Dep = 19990604,
-    Vc70 = 20000404,
-    Vc80 = 20030901,
-    Vc110 = 20091201,
-    Vc140 = 20140508,
+#[derive(Debug, Default, Getters)]
+pub struct LinkInfo {
+    cwd: StrBuf,
+    command: StrBuf,
+    output_file: StrBuf,
+    libs: Vec<StrBuf>,
+}
+
+impl LinkInfo {
+    pub(crate) fn read<R: io::Read>(mut reader: R) -> Result<Self> {
+        let mut buf = vec![];
+        reader.read_to_end(&mut buf)?;
+        // linkers other than MSVC leave the stream empty
+        if buf.is_empty() {
+            return Ok(Self::default());
+        }
+
+        let header = LinkInfoHeader::decode((), &mut &buf[..])?;
+        let string_at = |offset: u32| -> Result<StrBuf> {
+            let mut slice = buf
+                .get(offset as usize..)
+                .ok_or(Error::MalformedInput("link info offset out of bounds"))?;
+            Ok(StrBuf::decode((), &mut slice)?)
+        };
+
+        let mut libs = vec![];
+        let mut offset = header.libs_offset;
+        while header.libs_offset != 0 && (offset as usize) < buf.len() {
+            let lib = string_at(offset)?;
+            if lib.as_ref().is_empty() {
+                break;
+            }
+            offset = offset
+                .checked_add(lib.as_ref().len() as u32 + 1)
+                .ok_or(Error::MalformedInput("link info offset out of bounds"))?;
+            libs.push(lib);
+        }
+
+        // the output file is stored within the command line
+        let output_file_offset = header
+            .command_offset
+            .checked_add(header.output_file_index)
+            .ok_or(Error::MalformedInput("link info offset out of bounds"))?;
+        let res = Self {
+            cwd: string_at(header.cwd_offset)?,
+            command: string_at(header.command_offset)?,
+            output_file: string_at(output_file_offset)?,
+            libs,
+        };
+        Ok(res)
+    }
+}
+
+// the type merge cache of MSVC, its layout is undocumented so it's exposed as a version and raw words
+#[derive(Debug, Default)]
+pub struct TmCache(Vec<u8>);
+
+impl TmCache {
+    pub(crate) fn read<R: io::Read>(mut reader: R) -> Result<Self> {
+        let mut buf = vec![];
+        reader.read_to_end(&mut buf)?;
+        Ok(Self(buf))
+    }
+
+    pub fn version(&self) -> Option<u32> {
+        self.words().next()
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = u32> + '_ {
+        self.words().skip(1)
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.0
+    }
+
+    fn words(&self) -> impl Iterator<Item = u32> + '_ {
+        self.0
+            .chunks_exact(4)
+            .map(|chunk| u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Decode)]
+#[declio(ctx_is = "constants::ENDIANESS")]
+struct LinkInfoHeader {
+    #[allow(unused)]
+    size: u32,
+    #[allow(unused)]
+    version: u32,
+    cwd_offset: u32,
+    command_offset: u32,
+    output_file_index: u32,
+    libs_offset: u32,
 }
 
-impl_bitfield_specifier_codecs!(PdbVersion);
+u32_enum! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum PdbVersion {
+        Vc2 = 19941610,
+        Vc4 = 19950623,
+        Vc41 = 19950814,
+        Vc50 = 19960307,
+        Vc98 = 19970604,
+        Vc70Dep = 19990604,
+        Vc70 = 20000404,
+        Vc80 = 20030901,
+        Vc110 = 20091201,
+        Vc140 = 20140508,
+    }
+}
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, BitfieldSpecifier)]
-#[bits = 32]
-pub enum PdbFeature {
-    None = 0,
-    Vc110 = 20091201,
-    Vc140 = 20140508,
-    NoTypeMerge = 0x4D544F4E,
-    MinimalDebugInfo = 0x494E494D,
+impl PdbVersion {
+    // versions preceding the GUID and the feature list, the GUID came with the final VC 7.0 format, the
+    // deprecated one of its betas still identifies the PDB by the signature alone
+    pub fn is_legacy(self) -> bool {
+        matches!(
+            self,
+            PdbVersion::Vc2
+                | PdbVersion::Vc4
+                | PdbVersion::Vc41
+                | PdbVersion::Vc50
+                | PdbVersion::Vc98
+                | PdbVersion::Vc70Dep
+        )
+    }
 }
 
-impl_bitfield_specifier_codecs!(PdbFeature);
+u32_enum! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum PdbFeature {
+        None = 0,
+        Vc110 = 20091201,
+        Vc140 = 20140508,
+        NoTypeMerge = 0x4D544F4E,
+        MinimalDebugInfo = 0x494E494D,
+    }
+}
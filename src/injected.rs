@@ -0,0 +1,165 @@
+use std::io;
+
+use declio::ctx::Len;
+use declio::{magic_bytes, Decode, Encode};
+
+use crate::hash::BitVector;
+use crate::result::Result;
+use crate::strings::{Strings, StringsBuilder};
+use crate::utils::jam_crc32;
+use crate::{constants, StringOffset};
+
+pub(crate) const HEADER_BLOCK_STREAM: &str = "/src/headerblock";
+pub(crate) const FILES_PREFIX: &str = "/src/files/";
+const SRC_HEADER_BLOCK_VERSION: u32 = 19980827;
+
+magic_bytes! {
+    #[derive(Debug)]
+    SrcHeaderBlockVersion(&SRC_HEADER_BLOCK_VERSION.to_le_bytes());
+}
+
+// a file injected into the PDB, e.g. an embedded source or a natvis visualizer
+#[derive(Debug, Clone)]
+pub struct InjectedSource {
+    pub name: String,
+    pub virtual_name: String,
+    pub crc: u32,
+    pub file_size: u32,
+    pub compression: u8,
+}
+
+impl InjectedSource {
+    pub fn stream_name(&self) -> String {
+        format!("{}{}", FILES_PREFIX, self.virtual_name)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Encode, Decode)]
+#[declio(ctx_is = "constants::ENDIANESS")]
+struct SrcHeaderBlockHeader {
+    version: SrcHeaderBlockVersion,
+    size: u32,
+    file_time: u64,
+    age: u32,
+    padding: [u8; 44],
+}
+
+#[derive(Debug, PartialEq, Eq, Encode, Decode)]
+#[declio(ctx_is = "constants::ENDIANESS")]
+struct SrcHeaderBlockEntry {
+    size: u32,
+    version: u32,
+    crc: u32,
+    file_size: u32,
+    file_ni: u32,
+    obj_ni: u32,
+    vfile_ni: u32,
+    compression: u8,
+    is_virtual: u8,
+    padding: [u8; 2],
+    reserved: [u8; 8],
+}
+
+impl SrcHeaderBlockEntry {
+    const BYTE_SIZE: u32 = 40;
+}
+
+// the same layout as the name map, but with entries in place of stream indices
+#[derive(Debug, PartialEq, Eq, Encode, Decode)]
+#[declio(ctx_is = "constants::ENDIANESS")]
+struct SrcHeaderBlockTable {
+    size: u32,
+    cap: u32,
+    present: BitVector,
+    deleted: BitVector,
+    #[declio(ctx = "Len(*size as usize)")]
+    entries: Vec<SrcHeaderBlockKeyVal>,
+}
+
+#[derive(Debug, PartialEq, Eq, Encode, Decode)]
+#[declio(ctx_is = "constants::ENDIANESS")]
+struct SrcHeaderBlockKeyVal {
+    key: u32,
+    entry: SrcHeaderBlockEntry,
+}
+
+pub(crate) fn read_header_block<R: io::Read>(
+    mut reader: R,
+    strings: &Strings,
+) -> Result<Vec<InjectedSource>> {
+    let _header = SrcHeaderBlockHeader::decode((), &mut reader)?;
+    let table = SrcHeaderBlockTable::decode((), &mut reader)?;
+    let string = |offset| strings.get(StringOffset(offset)).unwrap_or_default().to_owned();
+
+    let res = table
+        .entries
+        .into_iter()
+        .map(|SrcHeaderBlockKeyVal { entry, .. }| InjectedSource {
+            name: string(entry.file_ni),
+            virtual_name: string(entry.vfile_ni),
+            crc: entry.crc,
+            file_size: entry.file_size,
+            compression: entry.compression,
+        })
+        .collect();
+    Ok(res)
+}
+
+// produces the header block for the given files with their names added to the /names stream,
+// returns the streams to write
+pub(crate) fn build_streams(
+    files: Vec<(String, Vec<u8>)>,
+    names: &mut StringsBuilder,
+) -> Result<Vec<(String, Vec<u8>)>> {
+    let mut streams = vec![];
+    let mut entries = vec![];
+
+    for (name, data) in files {
+        let virtual_name = name.to_lowercase().replace('/', "\\");
+        let file_ni = names.add(&name)?;
+        let vfile_ni = names.add(&virtual_name)?;
+        let entry = SrcHeaderBlockEntry {
+            size: SrcHeaderBlockEntry::BYTE_SIZE,
+            version: SRC_HEADER_BLOCK_VERSION,
+            crc: jam_crc32(&data),
+            file_size: data.len() as u32,
+            file_ni: file_ni.0,
+            obj_ni: 0,
+            vfile_ni: vfile_ni.0,
+            compression: 0,
+            is_virtual: 0,
+            padding: [0; 2],
+            reserved: [0; 8],
+        };
+        entries.push(SrcHeaderBlockKeyVal {
+            key: vfile_ni.0,
+            entry,
+        });
+        streams.push((format!("{}{}", FILES_PREFIX, virtual_name), data));
+    }
+
+    let size = entries.len() as u32;
+    let table = SrcHeaderBlockTable {
+        size,
+        cap: size.max(8),
+        present: BitVector::new_filled(size),
+        deleted: BitVector::default(),
+        entries,
+    };
+    let mut table_bytes = vec![];
+    table.encode((), &mut table_bytes)?;
+
+    let header = SrcHeaderBlockHeader {
+        version: SrcHeaderBlockVersion,
+        size: 64 + table_bytes.len() as u32,
+        file_time: 0,
+        age: 0,
+        padding: [0; 44],
+    };
+    let mut header_block = vec![];
+    header.encode((), &mut header_block)?;
+    header_block.extend(table_bytes);
+
+    streams.push((HEADER_BLOCK_STREAM.to_owned(), header_block));
+    Ok(streams)
+}
@@ -0,0 +1,196 @@
+use std::fmt::Write;
+use std::iter::Peekable;
+use std::str::Chars;
+
+use crate::result::{Error, Result};
+
+// a minimal JSON reader covering the documents embedded in or imported into PDBs
+#[derive(Debug)]
+pub(crate) enum JsonValue {
+    String(String),
+    // kept as written so that the caller can pick the numeric type
+    Number(String),
+    // none of the documents read so far has a flag whose value matters
+    Bool,
+    Null,
+    Array(Vec<JsonValue>),
+    Object(Vec<(String, JsonValue)>),
+}
+
+impl JsonValue {
+    pub fn get(&self, key: &str) -> Option<&JsonValue> {
+        match self {
+            JsonValue::Object(entries) => entries.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            JsonValue::String(str) => Some(str),
+            _ => None,
+        }
+    }
+}
+
+pub(crate) fn parse(json: &str) -> Result<JsonValue> {
+    let mut parser = JsonParser(json.chars().peekable());
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    match parser.0.next() {
+        None => Ok(value),
+        Some(_) => Err(malformed()),
+    }
+}
+
+pub(crate) fn write_string(out: &mut String, str: &str) {
+    out.push('"');
+    for char in str.chars() {
+        match char {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+struct JsonParser<'a>(Peekable<Chars<'a>>);
+
+impl<'a> JsonParser<'a> {
+    fn skip_whitespace(&mut self) {
+        while self.0.next_if(|c| c.is_whitespace()).is_some() {}
+    }
+
+    fn expect(&mut self, expected: char) -> Result<()> {
+        self.skip_whitespace();
+        match self.0.next() {
+            Some(c) if c == expected => Ok(()),
+            _ => Err(malformed()),
+        }
+    }
+
+    fn object(&mut self) -> Result<Vec<(String, JsonValue)>> {
+        self.expect('{')?;
+        let mut entries = vec![];
+        self.skip_whitespace();
+        if self.0.next_if_eq(&'}').is_some() {
+            return Ok(entries);
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.string()?;
+            self.expect(':')?;
+            entries.push((key, self.value()?));
+            self.skip_whitespace();
+            match self.0.next() {
+                Some(',') => continue,
+                Some('}') => return Ok(entries),
+                _ => return Err(malformed()),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Vec<JsonValue>> {
+        self.expect('[')?;
+        let mut elems = vec![];
+        self.skip_whitespace();
+        if self.0.next_if_eq(&']').is_some() {
+            return Ok(elems);
+        }
+        loop {
+            elems.push(self.value()?);
+            self.skip_whitespace();
+            match self.0.next() {
+                Some(',') => continue,
+                Some(']') => return Ok(elems),
+                _ => return Err(malformed()),
+            }
+        }
+    }
+
+    fn value(&mut self) -> Result<JsonValue> {
+        self.skip_whitespace();
+        match self.0.peek() {
+            Some('"') => Ok(JsonValue::String(self.string()?)),
+            Some('{') => Ok(JsonValue::Object(self.object()?)),
+            Some('[') => Ok(JsonValue::Array(self.array()?)),
+            Some(_) => {
+                let mut word = String::new();
+                while let Some(c) = self
+                    .0
+                    .next_if(|c| c.is_alphanumeric() || matches!(c, '-' | '+' | '.'))
+                {
+                    word.push(c);
+                }
+                match word.as_str() {
+                    "true" | "false" => Ok(JsonValue::Bool),
+                    "null" => Ok(JsonValue::Null),
+                    _ if word.starts_with(|c: char| c == '-' || c.is_ascii_digit()) => {
+                        Ok(JsonValue::Number(word))
+                    }
+                    _ => Err(malformed()),
+                }
+            }
+            None => Err(malformed()),
+        }
+    }
+
+    fn string(&mut self) -> Result<String> {
+        self.expect('"')?;
+        let mut str = String::new();
+        loop {
+            match self.0.next() {
+                Some('"') => return Ok(str),
+                Some('\\') => match self.0.next() {
+                    Some('n') => str.push('\n'),
+                    Some('r') => str.push('\r'),
+                    Some('t') => str.push('\t'),
+                    Some('b') => str.push('\u{8}'),
+                    Some('f') => str.push('\u{c}'),
+                    Some('u') => str.push(self.unicode_escape()?),
+                    Some(c) => str.push(c),
+                    None => return Err(malformed()),
+                },
+                Some(c) => str.push(c),
+                None => return Err(malformed()),
+            }
+        }
+    }
+
+    // characters outside of the BMP are escaped as a pair of surrogates, the ones left unpaired
+    // become replacement characters
+    fn unicode_escape(&mut self) -> Result<char> {
+        let mut code = self.code_unit()?;
+        if (0xD800..0xDC00).contains(&code) {
+            let mut ahead = JsonParser(self.0.clone());
+            if ahead.0.next() == Some('\\') && ahead.0.next() == Some('u') {
+                if let Ok(low @ 0xDC00..=0xDFFF) = ahead.code_unit() {
+                    code = 0x10000 + ((code - 0xD800) << 10) + (low - 0xDC00);
+                    *self = ahead;
+                }
+            }
+        }
+        Ok(char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER))
+    }
+
+    // the four hex digits of a `\u` escape
+    fn code_unit(&mut self) -> Result<u32> {
+        let mut code = 0;
+        for _ in 0..4 {
+            let digit = self.0.next().and_then(|c| c.to_digit(16)).ok_or_else(malformed)?;
+            code = code << 4 | digit;
+        }
+        Ok(code)
+    }
+}
+
+fn malformed() -> Error {
+    Error::MalformedInput("JSON")
+}
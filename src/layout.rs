@@ -0,0 +1,207 @@
+use std::mem;
+
+use crate::codeview::types::{BuiltinType, ClassProperties, PointerKind, PointerProperties, TypeRecord};
+use crate::dbi::MachineType;
+use crate::types::{TpiStream, FIRST_NON_BUILTIN_TYPE};
+use crate::utils::StrBuf;
+use crate::TypeIndex;
+
+// a storage unit of a user-defined type shared by adjacent bit fields, like the `u32` holding both
+// `a : 3` and `b : 5`
+#[derive(Debug)]
+pub struct BitFieldUnit<'a> {
+    pub byte_offset: u64,
+    pub underlying_type: TypeIndex,
+    pub fields: Vec<BitFieldMember<'a>>,
+}
+
+#[derive(Debug)]
+pub struct BitFieldMember<'a> {
+    pub name: &'a str,
+    // from the least significant bit of the storage unit
+    pub bit_offset: u8,
+    pub bit_width: u8,
+}
+
+impl TpiStream {
+    // the bit fields of a class, struct or union grouped by their storage units in field order,
+    // forward references have no fields, so `udt` has to be the definition
+    pub fn bit_field_units(&self, udt: TypeIndex) -> Vec<BitFieldUnit<'_>> {
+        let field_list = match self.record(udt) {
+            Some(TypeRecord::Class(class) | TypeRecord::Struct(class) | TypeRecord::Interface(class)) => {
+                class.field_list
+            }
+            Some(TypeRecord::Union(union)) => union.field_list,
+            _ => None,
+        };
+        let mut units: Vec<BitFieldUnit> = vec![];
+        for field in field_list.map(|list| self.fields(list)).unwrap_or_default() {
+            let TypeRecord::DataMember {
+                field_type: Some(field_type),
+                offset,
+                name,
+                ..
+            } = field
+            else {
+                continue;
+            };
+            let Some(&TypeRecord::BitField {
+                field_type: underlying_type,
+                bit_size,
+                bit_offset,
+            }) = self.record(*field_type)
+            else {
+                continue;
+            };
+            let Some(byte_offset) = offset.as_u64() else {
+                continue;
+            };
+            let member = BitFieldMember {
+                name: name.as_ref(),
+                bit_offset,
+                bit_width: bit_size,
+            };
+            match units.last_mut() {
+                Some(unit)
+                    if unit.byte_offset == byte_offset
+                        && u32::from(unit.underlying_type) == u32::from(underlying_type) =>
+                {
+                    unit.fields.push(member)
+                }
+                _ => units.push(BitFieldUnit {
+                    byte_offset,
+                    underlying_type,
+                    fields: vec![member],
+                }),
+            }
+        }
+        units
+    }
+
+    // the size of a value of the type in bytes, forward references are resolved to their definitions,
+    // the machine is only consulted for pointers that don't specify their size
+    pub fn type_size(&self, index: TypeIndex, machine: MachineType) -> Option<u64> {
+        let mut index = index;
+        // modifiers, bit fields and enums refer to earlier records, so a valid chain can't be longer
+        for _ in 0..=self.records().len() {
+            if u32::from(index) < FIRST_NON_BUILTIN_TYPE {
+                return builtin_size(index);
+            }
+            let record = self.record(index)?;
+            match record {
+                TypeRecord::Modifier { modified_type, .. } => index = *modified_type,
+                TypeRecord::BitField { field_type, .. } => index = *field_type,
+                TypeRecord::Enum(enum_) => index = enum_.underlying_type,
+                TypeRecord::Pointer { properties, .. } => return pointer_size(properties, machine),
+                TypeRecord::Array { dimensions, .. } => return dimensions.first()?.as_u64(),
+                _ => {
+                    return match self.record(self.resolve_forward_ref(index))? {
+                        TypeRecord::Class(class)
+                        | TypeRecord::Struct(class)
+                        | TypeRecord::Interface(class)
+                            if !class.properties.is_forward_ref() =>
+                        {
+                            class.size.as_u64()
+                        }
+                        TypeRecord::Union(union) if !union.properties.is_forward_ref() => {
+                            union.size.as_u64()
+                        }
+                        _ => None,
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    // the definition with the same name as a forward reference to a class, struct, union or enum,
+    // the index itself when it's a definition already or the definition is missing
+    pub fn resolve_forward_ref(&self, index: TypeIndex) -> TypeIndex {
+        let Some((record, key)) = self
+            .record(index)
+            .and_then(|record| Some((record, udt_key(record)?)))
+        else {
+            return index;
+        };
+        if !key.0.is_forward_ref() {
+            return index;
+        }
+        self.iter()
+            .find(|(_, other)| {
+                mem::discriminant(*other) == mem::discriminant(record)
+                    && udt_key(other).is_some_and(|def| !def.0.is_forward_ref() && same_name(&key, &def))
+            })
+            .and_then(|(index, _)| TypeIndex::try_from(index).ok())
+            .unwrap_or(index)
+    }
+
+    // the members of a field list along with those of its continuations
+    pub fn fields(&self, field_list: TypeIndex) -> Vec<&TypeRecord> {
+        let mut res = vec![];
+        let mut next = Some(field_list);
+        // every continuation is a record of its own, so a list can't be longer than the stream
+        for _ in 0..self.records().len() {
+            let Some(TypeRecord::FieldList { fields }) = next.and_then(|index| self.record(index)) else {
+                break;
+            };
+            next = None;
+            for field in fields {
+                match field {
+                    TypeRecord::ListContinuation(index) => next = Some(*index),
+                    field => res.push(field),
+                }
+            }
+        }
+        res
+    }
+}
+
+type UdtKey<'a> = (ClassProperties, &'a StrBuf, &'a StrBuf);
+
+fn udt_key(record: &TypeRecord) -> Option<UdtKey<'_>> {
+    match record {
+        TypeRecord::Class(class) | TypeRecord::Struct(class) | TypeRecord::Interface(class) => {
+            Some((class.properties, &class.name, &class.unique_name))
+        }
+        TypeRecord::Union(union) => Some((union.properties, &union.name, &union.unique_name)),
+        TypeRecord::Enum(enum_) => Some((enum_.properties, &enum_.name, &enum_.unique_name)),
+        _ => None,
+    }
+}
+
+fn same_name((lhs, lhs_name, lhs_unique): &UdtKey, (rhs, rhs_name, rhs_unique): &UdtKey) -> bool {
+    if lhs.has_unique_name() && rhs.has_unique_name() {
+        lhs_unique.as_ref() == rhs_unique.as_ref()
+    } else {
+        lhs_name.as_ref() == rhs_name.as_ref()
+    }
+}
+
+pub(crate) fn builtin_size(index: TypeIndex) -> Option<u64> {
+    let index = u32::from(index);
+    // the second byte is the pointer mode of builtins referred to through a pointer
+    match (index >> 8) & 0xF {
+        0 => BuiltinType::try_from(TypeIndex::try_from(index & 0xFF).ok()?)
+            .ok()?
+            .size(),
+        1 => Some(2),
+        2..=4 => Some(4),
+        5 => Some(6),
+        6 => Some(8),
+        7 => Some(16),
+        _ => None,
+    }
+}
+
+fn pointer_size(properties: &PointerProperties, machine: MachineType) -> Option<u64> {
+    if properties.size() != 0 {
+        return Some(properties.size().into());
+    }
+    match properties.kind() {
+        PointerKind::Near16 => Some(2),
+        PointerKind::Far16 | PointerKind::Huge16 | PointerKind::Near32 => Some(4),
+        PointerKind::Far32 => Some(6),
+        PointerKind::Near64 => Some(8),
+        _ => machine.pointer_size(),
+    }
+}
@@ -1,83 +1,551 @@
+use std::cell::RefCell;
 use std::io;
 use std::num::NonZeroU32;
 
-use dbi::{DbiModule, DbiStream, FpoStream, FrameDataStream, SectionHeaderStream};
+use codeview::symbols::SymbolRecord;
+use codeview::types::{IdRecord, TypeRecord};
+use codeview::{PrefixedRecords, RawRecord};
+use dbi::{DbiModule, DbiStream, FpoStream, FrameDataStream, OmapStream, SectionHeaderStream};
 use declio::ctx::Len;
 use declio::{Decode, Encode, EncodedSize};
-use info::PdbInfo;
-use module::Module;
-use msf::{MsfStream, MsfStreamLayout, StreamIndex, SuperBlock};
+use diagnostics::{Diagnostic, ParseOptions, ReadContext, Warning, WarningKind};
+use digest::{Digest, Sha256, StreamDigest};
+use info::{InfoPatch, LinkInfo, PdbInfo, PdbInfoHeader, TmCache};
+use injected::InjectedSource;
+use metrics::{Metrics, Stopwatch};
+use module::{LazyModule, Module};
+use msf::{
+    BlockAllocation, BlockIndex, FreeBlockMap, MsfHeader, MsfInfo, MsfStream, MsfStreamLayout, StreamIndex, SuperBlock
+};
 use publics::Publics;
 use result::{Error, Result};
+use sourcelink::SourceLink;
+use stats::{ModuleSize, ModuleTypeCosts, RecordRefs, SizeStats, StreamSize};
 use strings::Strings;
 use symbol_map::SymbolMap;
-use symbols::Symbols;
-use types::{IpiStream, TpiStream, TypeHash, TypeStream};
-use utils::div_ceil;
+use symbols::{SymbolStreamBytes, Symbols};
+use types::{IpiStream, RawTypeStream, TpiStream, TypeHash, TypeStream, TypeStreamHeader};
+use utils::{div_ceil, trace_event};
+use verify::{Divergence, RoundtripStream};
 
+pub mod address_index;
+pub mod bindings;
 pub mod builders;
+#[cfg(feature = "cab")]
+pub mod cab;
 mod codecs;
 pub mod codeview;
+pub mod coff;
 mod constants;
 pub mod dbi;
+pub mod diagnostics;
+pub mod diff;
+pub mod digest;
+#[cfg(feature = "object")]
+pub mod exports;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod functions;
 mod hash;
 pub mod info;
+pub mod injected;
+mod json;
+pub mod layout;
+pub mod lines;
+pub mod locals;
+pub mod map;
+pub mod merge;
+pub mod metrics;
 pub mod module;
-mod msf;
+pub mod msf;
+#[cfg(feature = "object")]
+pub mod pdata;
+pub mod pdb;
 mod publics;
 pub mod result;
-mod strings;
-mod symbol_map;
+pub mod search;
+pub mod sourcelink;
+pub mod stats;
+pub mod strings;
+pub mod symbol_map;
 pub mod symbols;
+pub mod symlist;
+#[cfg(feature = "symsrv")]
+pub mod symsrv;
+pub mod symstore;
+pub mod typegraph;
 pub mod types;
+pub mod unwind;
 pub mod utils;
+pub mod variables;
+pub mod verify;
 
 #[derive(Debug)]
 pub struct PdbFile<R> {
     inner: R,
-    layouts: Vec<MsfStreamLayout>,
-    block_size: u32,
+    super_block: SuperBlock,
+    dir_layout: MsfStreamLayout,
+    layouts: Vec<Option<MsfStreamLayout>>,
+    options: ParseOptions,
+    diagnostics: Vec<Diagnostic>,
+    warnings: Vec<Warning>,
+    metrics: Option<RefCell<Metrics>>,
+    cache: StreamCache,
+}
+
+// stream indices remembered by the getters that look them up on their own
+#[derive(Debug, Default)]
+struct StreamCache {
+    names: Option<StreamIndex>,
+    symbols: Option<SymbolStreams>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct SymbolStreams {
+    publics: StreamIndex,
+    globals: StreamIndex,
+    symbols: StreamIndex,
 }
 
 impl<R> PdbFile<R>
 where
     R: io::Read + io::Seek,
 {
-    pub fn open(mut reader: R) -> Result<Self> {
+    pub fn open(reader: R) -> Result<Self> {
+        Self::open_with_options(reader, ParseOptions::default())
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    pub fn open_with_options(mut reader: R, options: ParseOptions) -> Result<Self> {
         let super_block = SuperBlock::decode((), &mut reader)?;
         let dir_layout = Self::get_dir_layout(&mut reader, &super_block)?;
         let mut dir_reader = MsfStream::<&mut R>::new(&mut reader, &dir_layout, super_block.block_size);
         let num_streams = u32::decode(constants::ENDIANESS, &mut dir_reader)?;
+        options.limits.check_streams(num_streams)?;
         let stream_sizes: Vec<u32> =
             Decode::decode((Len(num_streams as usize), constants::ENDIANESS), &mut dir_reader)?;
+        let total_bytes = stream_sizes
+            .iter()
+            .filter(|&&size| size != u32::MAX)
+            .map(|&size| u64::from(size))
+            .sum();
+        options.limits.check_memory(total_bytes)?;
+
         let mut layouts = Vec::with_capacity(stream_sizes.len());
         for byte_size in stream_sizes {
+            // nil streams keep their slot so that the indices stay intact
             if byte_size == u32::MAX {
+                layouts.push(None);
                 continue;
             }
             let block_count = div_ceil(byte_size, super_block.block_size);
             let blocks = Decode::decode(Len(block_count as usize), &mut reader)?;
-            layouts.push(MsfStreamLayout::new(blocks, byte_size));
+            layouts.push(Some(MsfStreamLayout::new(blocks, byte_size)));
         }
+        trace_event!(
+            block_size = super_block.block_size,
+            num_blocks = super_block.num_blocks,
+            num_streams,
+            "read the stream directory"
+        );
 
         let res = Self {
+            metrics: options.metrics.then(RefCell::default),
             inner: reader,
             layouts,
-            block_size: super_block.block_size,
+            super_block,
+            dir_layout,
+            options,
+            diagnostics: vec![],
+            warnings: vec![],
+            cache: StreamCache::default(),
         };
         Ok(res)
     }
 
+    pub fn msf_info(&self) -> MsfInfo {
+        MsfInfo {
+            block_size: self.super_block.block_size,
+            num_blocks: self.super_block.num_blocks,
+            num_dir_bytes: self.super_block.num_dir_bytes,
+            free_block_map_block: self.super_block.free_block_map_block,
+            block_map_addr: self.super_block.block_map_addr.0,
+            num_streams: self.layouts.len() as u32,
+        }
+    }
+
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    pub fn take_diagnostics(&mut self) -> Vec<Diagnostic> {
+        std::mem::take(&mut self.diagnostics)
+    }
+
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    pub fn take_warnings(&mut self) -> Vec<Warning> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    // the metrics collected so far with `ParseOptions::metrics`
+    pub fn metrics(&self) -> Option<Metrics> {
+        self.metrics.as_ref().map(|metrics| metrics.borrow().clone())
+    }
+
+    pub fn take_metrics(&mut self) -> Option<Metrics> {
+        self.metrics
+            .as_mut()
+            .map(|metrics| std::mem::take(metrics.get_mut()))
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
     fn get_indexed_stream(&mut self, index: StreamIndex) -> Option<BufMsfStream<&mut R>> {
-        let layout = self.layouts.get(index.0 as usize)?;
-        let msf = MsfStream::new(&mut self.inner, layout, self.block_size);
+        let layout = self.layouts.get(index.0 as usize)?.as_ref()?;
+        trace_event!(index = index.0, size = layout.byte_size, "opened a stream");
+        let msf = MsfStream::new(&mut self.inner, layout, self.super_block.block_size)
+            .with_index(index)
+            .with_metrics(self.metrics.as_ref());
         Some(io::BufReader::new(msf))
     }
 
+    fn get_indexed_stream_with_context(
+        &mut self,
+        index: StreamIndex,
+    ) -> Option<(BufMsfStream<'_, &mut R>, ReadContext<'_>)> {
+        let layout = self.layouts.get(index.0 as usize)?.as_ref()?;
+        trace_event!(index = index.0, size = layout.byte_size, "opened a stream");
+        let msf = MsfStream::new(&mut self.inner, layout, self.super_block.block_size)
+            .with_index(index)
+            .with_metrics(self.metrics.as_ref());
+        let ctx = ReadContext::new(
+            &self.options,
+            &mut self.diagnostics,
+            &mut self.warnings,
+            self.metrics.as_ref(),
+            Some(index),
+        );
+        Some((io::BufReader::new(msf), ctx))
+    }
+
     fn get_stream(&mut self, stream: BuiltinStream) -> Option<BufMsfStream<&mut R>> {
         self.get_indexed_stream(StreamIndex(stream as u16))
     }
 
+    pub fn get_free_block_map(&mut self) -> Result<FreeBlockMap> {
+        let layout = FreeBlockMap::layout(&self.super_block);
+        let msf = MsfStream::new(&mut self.inner, &layout, self.super_block.block_size);
+        FreeBlockMap::read(io::BufReader::new(msf))
+    }
+
+    // compares the free block map against the blocks referenced by the MSF structures
+    pub fn get_block_allocation(&mut self) -> Result<BlockAllocation> {
+        let fpm = self.get_free_block_map()?;
+        let block_size = self.super_block.block_size;
+        let num_blocks = self.super_block.num_blocks;
+
+        let mut referenced = vec![false; num_blocks as usize];
+        let mut mark = |block: u32| {
+            if let Some(slot) = referenced.get_mut(block as usize) {
+                *slot = true;
+            }
+        };
+        mark(0);
+        // both FPM copies are reserved in every interval
+        for interval in (0..num_blocks).step_by(block_size as usize) {
+            mark(interval + 1);
+            mark(interval + 2);
+        }
+        let block_map_bytes = self.dir_layout.blocks.len() as u32 * 4;
+        for i in 0..div_ceil(block_map_bytes, block_size) {
+            mark(self.super_block.block_map_addr.0 + i);
+        }
+        let layouts = std::iter::once(&self.dir_layout).chain(self.layouts.iter().flatten());
+        for block in layouts.flat_map(|layout| &layout.blocks) {
+            mark(block.0);
+        }
+
+        Ok(fpm.allocation(&referenced))
+    }
+
+    // a digest of the contents of every stream that is not nil
+    pub fn get_stream_digests(&mut self) -> Result<Vec<StreamDigest>> {
+        let mut res = vec![];
+        for index in 0..self.layouts.len() {
+            let index = StreamIndex(index as u16);
+            if let Some((size, digest)) = self.digest_stream(index)? {
+                let digest = digest.finish();
+                res.push(StreamDigest { index, size, digest });
+            }
+        }
+        Ok(res)
+    }
+
+    // a digest of the contents of all streams in order, independent of the block layout of the file
+    // and of whatever is left in padding and unused blocks
+    pub fn get_content_digest(&mut self) -> Result<Digest> {
+        let mut digest = Sha256::new();
+        digest.update(&(self.layouts.len() as u32).to_le_bytes());
+        for index in 0..self.layouts.len() {
+            match self.digest_stream(StreamIndex(index as u16))? {
+                Some((size, stream)) => {
+                    digest.update(&size.to_le_bytes());
+                    digest.update(stream.finish().bytes());
+                }
+                None => digest.update(&u32::MAX.to_le_bytes()),
+            }
+        }
+        Ok(digest.finish())
+    }
+
+    fn digest_stream(&mut self, index: StreamIndex) -> Result<Option<(u32, Sha256)>> {
+        let Some(mut stream) = self.get_indexed_stream(index) else {
+            return Ok(None);
+        };
+        let size = stream.get_ref().length();
+        let mut digest = Sha256::new();
+        io::copy(&mut stream, &mut digest)?;
+        Ok(Some((size, digest)))
+    }
+
+    // summarizes where the bytes of the file go, reporting the `top_n` largest types and symbols
+    pub fn get_size_stats(&mut self, top_n: usize) -> Result<SizeStats> {
+        let info = self.get_info()?;
+        let dbi = self.get_dbi()?;
+
+        let header = dbi.header();
+        let streams = (0..)
+            .zip(&self.layouts)
+            .map(|(index, layout)| {
+                let name = match index {
+                    1 => Some("PDB"),
+                    2 => Some("TPI"),
+                    3 => Some("DBI"),
+                    4 => Some("IPI"),
+                    _ if index == header.global_symbol_stream_index.0 => Some("globals"),
+                    _ if index == header.public_symbol_stream_index.0 => Some("publics"),
+                    _ if index == header.sym_record_stream_index.0 => Some("symbols"),
+                    _ => info
+                        .named_streams()
+                        .iter()
+                        .find(|(_, idx)| idx.0 == index)
+                        .map(|(name, _)| name),
+                };
+                StreamSize {
+                    index,
+                    name: name.map(str::to_owned),
+                    bytes: layout.as_ref().map(|layout| layout.byte_size),
+                }
+            })
+            .collect();
+
+        let mut modules = vec![];
+        let mut subsections = vec![];
+        for module in dbi.modules() {
+            let layout = &module.header.layout;
+            let stream_bytes = self
+                .layouts
+                .get(module.header.debug_info_stream.0 as usize)
+                .and_then(Option::as_ref)
+                .map_or(0, |layout| layout.byte_size);
+            modules.push(ModuleSize {
+                name: module.module_name.as_ref().to_owned(),
+                stream_bytes,
+                symbol_bytes: layout.sym_bytes,
+                c11_bytes: layout.c11_bytes,
+                c13_bytes: layout.c13_bytes,
+            });
+            let Some((mut stream, mut ctx)) =
+                self.get_indexed_stream_with_context(module.header.debug_info_stream)
+            else {
+                continue;
+            };
+            let skipped = u64::from(layout.sym_bytes) + u64::from(layout.c11_bytes);
+            io::copy(&mut io::Read::take(&mut stream, skipped), &mut io::sink())?;
+            for entry in Module::read_subsections(&mut stream, layout, &mut ctx)? {
+                // subsection kind and length precede the data
+                let bytes = entry.data.len() as u64 + 8;
+                stats::add_subsection(&mut subsections, entry.record_type, bytes);
+            }
+        }
+
+        let tpi = self.get_raw_tpi()?;
+        let largest_types = stats::largest_records(tpi.iter(), top_n);
+
+        let (stream, mut ctx) = self
+            .get_indexed_stream_with_context(dbi.header().sym_record_stream_index)
+            .ok_or(Error::StreamNotFound("symbols"))?;
+        let decoder = ctx.records();
+        let len = stream.get_ref().length();
+        let mut records = io::Read::take(stream, len.into());
+        let symbols = ctx.read_records("symbols", 0, &mut records, |stream| {
+            decoder.decode::<RawRecord, _>(stream)
+        })?;
+        let largest_symbols = stats::largest_records((0..).zip(&symbols), top_n);
+
+        let res = SizeStats {
+            streams,
+            modules,
+            subsections,
+            largest_types,
+            largest_symbols,
+        };
+        Ok(res)
+    }
+
+    // attributes the bytes of TPI and IPI records to the modules whose symbols reference them
+    pub fn get_module_type_costs(&mut self) -> Result<ModuleTypeCosts> {
+        let dbi = self.get_dbi()?;
+        let record_bytes = |record: &RawRecord| record.data.len() as u32 + 4;
+        let raw_indices = |indices: Vec<TypeIndex>| indices.into_iter().map(u32::from).collect();
+
+        // records left out of the decoded streams in lenient mode keep their size, but reference nothing
+        let tpi = self.get_tpi()?;
+        let raw_tpi = self.get_raw_tpi()?;
+        let mut types: Vec<(u32, RecordRefs)> =
+            (0..raw_tpi.record_count()).map(|_| Default::default()).collect();
+        for (index, raw) in raw_tpi.iter() {
+            let refs = RecordRefs {
+                types: tpi
+                    .get(index)
+                    .map_or(vec![], |record| raw_indices(record.referenced_types())),
+                ids: vec![],
+            };
+            types[(index - types::FIRST_NON_BUILTIN_TYPE) as usize] = (record_bytes(raw), refs);
+        }
+
+        let mut modules: Vec<_> = dbi
+            .modules()
+            .iter()
+            .map(|module| (module.module_name.as_ref().to_owned(), RecordRefs::default()))
+            .collect();
+
+        let ids: Vec<_> = match self.get_ipi() {
+            Ok(ipi) => {
+                let raw_ipi = self.get_raw_ipi()?;
+                let mut ids: Vec<(u32, RecordRefs)> =
+                    (0..raw_ipi.record_count()).map(|_| Default::default()).collect();
+                for (index, raw) in raw_ipi.iter() {
+                    let mut refs = RecordRefs::default();
+                    if let Some(record) = ipi.get(index) {
+                        // the module of these records is one-based
+                        if let IdRecord::UdtModSourceLine { module, .. } = record {
+                            if let Some((_, refs)) = modules.get_mut(usize::from(*module).wrapping_sub(1)) {
+                                refs.ids.push(index);
+                            }
+                        }
+                        refs.types = raw_indices(record.referenced_types());
+                        refs.ids = record.referenced_ids().into_iter().map(u32::from).collect();
+                    }
+                    ids[(index - types::FIRST_NON_BUILTIN_TYPE) as usize] = (record_bytes(raw), refs);
+                }
+                ids
+            }
+            Err(Error::StreamNotFound(_)) => vec![],
+            Err(err) => return Err(err),
+        };
+
+        for (module, (_, refs)) in dbi.modules().iter().zip(&mut modules) {
+            // records that fail to parse are skipped rather than failing the whole report
+            for record in self.get_module_raw_symbols(module)? {
+                if let Ok(symbol) = record.decode_as::<SymbolRecord>() {
+                    refs.types
+                        .extend(symbol.referenced_types().into_iter().map(u32::from));
+                    refs.ids
+                        .extend(symbol.referenced_ids().into_iter().map(u32::from));
+                }
+            }
+        }
+
+        Ok(stats::module_type_costs(modules, &types, &ids))
+    }
+
+    // re-encodes every record of the stream and reports the first one that differs from the original
+    pub fn verify_roundtrip(&mut self, stream: RoundtripStream) -> Result<Option<Divergence>> {
+        match stream {
+            RoundtripStream::Tpi => {
+                let mut stream = self
+                    .get_stream(BuiltinStream::Tpi)
+                    .ok_or(Error::StreamNotFound("TPI"))?;
+                let header = TypeStreamHeader::read(&mut stream)?;
+                let (index, start) = (stream.get_ref().index(), header.byte_size());
+                let mut records = io::Read::take(stream, header.type_record_bytes.into());
+                verify::verify_records::<TypeRecord, _>("type records", index, start, &mut records)
+            }
+            RoundtripStream::Ipi => {
+                let mut stream = self
+                    .get_stream(BuiltinStream::Ipi)
+                    .ok_or(Error::StreamNotFound("IPI"))?;
+                let header = TypeStreamHeader::read(&mut stream)?;
+                let (index, start) = (stream.get_ref().index(), header.byte_size());
+                let mut records = io::Read::take(stream, header.type_record_bytes.into());
+                verify::verify_records::<IdRecord, _>("id records", index, start, &mut records)
+            }
+            RoundtripStream::Symbols(dbi) => {
+                let stream = self
+                    .get_indexed_stream(dbi.header().sym_record_stream_index)
+                    .ok_or(Error::StreamNotFound("symbols"))?;
+                let (index, len) = (stream.get_ref().index(), stream.get_ref().length());
+                let mut records = io::Read::take(stream, len.into());
+                verify::verify_records::<SymbolRecord, _>("symbols", index, 0, &mut records)
+            }
+            RoundtripStream::Module(module) => {
+                let mut stream = self
+                    .get_indexed_stream(module.header.debug_info_stream)
+                    .ok_or(Error::StreamNotFound("module debug info"))?;
+                // skip the debug section signature
+                u32::decode(constants::ENDIANESS, &mut stream)?;
+                let index = stream.get_ref().index();
+                let len = module.header.layout.sym_bytes.saturating_sub(4);
+                let mut records = io::Read::take(stream, len.into());
+                verify::verify_records::<SymbolRecord, _>("module symbols", index, 4, &mut records)
+            }
+        }
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(stream = name)))]
+    fn read_stream<A, F>(name: &'static str, mut stream: BufMsfStream<&mut R>, read: F) -> Result<A>
+    where
+        F: FnOnce(&mut BufMsfStream<&mut R>) -> Result<A>,
+    {
+        let start = Stopwatch::start();
+        let res = read(&mut stream);
+        let offset = stream.get_ref().position() - stream.buffer().len() as u32;
+        let index = stream.get_ref().index();
+        if let Some(metrics) = stream.get_ref().metrics() {
+            let mut metrics = metrics.borrow_mut();
+            metrics.add_stream_bytes(name, offset.into());
+            metrics.add_phase(name, start);
+        }
+        res.map_err(|err| err.in_stream(name, index, offset.into()))
+    }
+
+    // like `read_stream`, but reports the bytes left over past what was read
+    fn read_stream_to_end<A, F>(
+        name: &'static str,
+        stream: BufMsfStream<&mut R>,
+        ctx: &mut ReadContext,
+        read: F,
+    ) -> Result<A>
+    where
+        F: FnOnce(&mut BufMsfStream<&mut R>, &mut ReadContext) -> Result<A>,
+    {
+        Self::read_stream(name, stream, |s| {
+            let res = read(s, ctx)?;
+            let consumed = s.get_ref().position() - s.buffer().len() as u32;
+            let trailing = s.get_ref().length().saturating_sub(consumed);
+            if trailing != 0 {
+                ctx.warn(name, consumed.into(), WarningKind::TrailingBytes(trailing.into()));
+            }
+            Ok(res)
+        })
+    }
+
     fn get_dir_layout(reader: &mut R, super_block: &SuperBlock) -> Result<MsfStreamLayout> {
         reader.seek(io::SeekFrom::Start(super_block.block_map_offset().into()))?;
         let blocks = Decode::decode(Len(super_block.block_map_blocks() as usize), reader)?;
@@ -88,7 +556,128 @@ where
         let stream = self
             .get_stream(BuiltinStream::Pdb)
             .ok_or(Error::StreamNotFound("PDB"))?;
-        PdbInfo::read(stream)
+        Self::read_stream("PDB", stream, |s| PdbInfo::read(s))
+    }
+
+    pub fn get_link_info(&mut self, info: &PdbInfo) -> Result<LinkInfo> {
+        let index = info
+            .named_streams()
+            .get("/LinkInfo")
+            .ok_or(Error::StreamNotFound("link info"))?;
+        let stream = self
+            .get_indexed_stream(index)
+            .ok_or(Error::StreamNotFound("link info"))?;
+        Self::read_stream("link info", stream, |s| LinkInfo::read(s))
+    }
+
+    pub fn get_named_stream(&mut self, info: &PdbInfo, name: &str) -> Result<Vec<u8>> {
+        let index = info
+            .named_streams()
+            .get(name)
+            .ok_or(Error::StreamNotFound("named stream"))?;
+        let mut stream = self
+            .get_indexed_stream(index)
+            .ok_or(Error::StreamNotFound("named stream"))?;
+        let mut buf = vec![];
+        io::Read::read_to_end(&mut stream, &mut buf)?;
+        Ok(buf)
+    }
+
+    // the source indexing script, equivalent to `pdbstr -r -s:srcsrv`
+    pub fn get_srcsrv(&mut self, info: &PdbInfo) -> Result<String> {
+        let bytes = self.get_named_stream(info, info::SRCSRV_STREAM)?;
+        Ok(String::from_utf8_lossy(&bytes).into_owned())
+    }
+
+    // natvis visualizers embedded with `/NATVIS`, as pairs of file names and XML documents
+    pub fn get_natvis(&mut self, info: &PdbInfo) -> Result<Vec<(String, String)>> {
+        let mut res = vec![];
+        for source in self.get_injected_sources(info)? {
+            if source.name.to_ascii_lowercase().ends_with(".natvis") {
+                let bytes = self.get_injected_source_data(info, &source)?;
+                res.push((source.name, String::from_utf8_lossy(&bytes).into_owned()));
+            }
+        }
+        Ok(res)
+    }
+
+    // files embedded in the PDB, listed by the `/src/headerblock` stream
+    pub fn get_injected_sources(&mut self, info: &PdbInfo) -> Result<Vec<InjectedSource>> {
+        let index = match info.named_streams().get(injected::HEADER_BLOCK_STREAM) {
+            Some(index) => index,
+            None => return Ok(vec![]),
+        };
+        let strings = self.get_strings(info)?;
+        let stream = self
+            .get_indexed_stream(index)
+            .ok_or(Error::StreamNotFound("source header block"))?;
+        Self::read_stream("source header block", stream, |s| {
+            injected::read_header_block(s, &strings)
+        })
+    }
+
+    pub fn get_injected_source_data(&mut self, info: &PdbInfo, source: &InjectedSource) -> Result<Vec<u8>> {
+        if source.compression != 0 {
+            return Err(Error::UnsupportedFeature("compressed injected source"));
+        }
+        self.get_named_stream(info, &source.stream_name())
+    }
+
+    // pairs the source files of a module with their embedded copies, if there are any
+    pub fn get_module_sources(
+        &mut self,
+        info: &PdbInfo,
+        module: &Module,
+    ) -> Result<Vec<(String, Option<InjectedSource>)>> {
+        let strings = self.get_strings(info)?;
+        let sources = self.get_injected_sources(info)?;
+        let res = module
+            .source_files(&strings)?
+            .into_iter()
+            .map(|path| {
+                let source = sources
+                    .iter()
+                    .find(|source| source.name.eq_ignore_ascii_case(path));
+                (path.to_owned(), source.cloned())
+            })
+            .collect();
+        Ok(res)
+    }
+
+    // merges the `sourcelink` stream with any additional `sourcelink$n` ones
+    pub fn get_source_link(&mut self, info: &PdbInfo) -> Result<SourceLink> {
+        let mut names: Vec<(u32, &str)> = info
+            .named_streams()
+            .iter()
+            .filter_map(
+                |(name, _)| match name.strip_prefix(sourcelink::SOURCE_LINK_STREAM)? {
+                    "" => Some((0, name)),
+                    suffix => Some((suffix.strip_prefix('$')?.parse().ok()?, name)),
+                },
+            )
+            .collect();
+        if names.is_empty() {
+            return Err(Error::StreamNotFound("source link"));
+        }
+        names.sort_unstable();
+
+        let mut res = SourceLink::default();
+        for (_, name) in names {
+            let bytes = self.get_named_stream(info, name)?;
+            res.merge(SourceLink::parse(&String::from_utf8_lossy(&bytes))?);
+        }
+        Ok(res)
+    }
+
+    pub fn get_tm_cache(&mut self, info: &PdbInfo) -> Result<TmCache> {
+        let index = info
+            .named_streams()
+            .get("/TMCache")
+            .ok_or(Error::StreamNotFound("TM cache"))?;
+        let stream = self
+            .get_indexed_stream(index)
+            .ok_or(Error::StreamNotFound("TM cache"))?;
+        Self::read_stream("TM cache", stream, |s| TmCache::read(s))
     }
 
     pub fn get_strings(&mut self, info: &PdbInfo) -> Result<Strings> {
@@ -96,59 +685,202 @@ where
             .named_streams()
             .get("/names")
             .ok_or(Error::StreamNotFound("names"))?;
-        let mut stream = self
+        self.read_strings(index)
+    }
+
+    // like `get_strings`, but looks up the stream in the PDB info stream on its own
+    pub fn strings(&mut self) -> Result<Strings> {
+        let index = match self.cache.names {
+            Some(index) => index,
+            None => {
+                let index = self
+                    .get_info()?
+                    .named_streams()
+                    .get("/names")
+                    .ok_or(Error::StreamNotFound("names"))?;
+                *self.cache.names.insert(index)
+            }
+        };
+        self.read_strings(index)
+    }
+
+    fn read_strings(&mut self, index: StreamIndex) -> Result<Strings> {
+        let stream = self
             .get_indexed_stream(index)
             .ok_or(Error::StreamNotFound("names"))?;
-        Ok(Strings::decode((), &mut stream)?)
+        Self::read_stream("names", stream, |s| Ok(Strings::decode((), s)?))
     }
 
     pub fn get_dbi(&mut self) -> Result<DbiStream> {
-        let stream = self
-            .get_stream(BuiltinStream::Dbi)
+        let (stream, mut ctx) = self
+            .get_indexed_stream_with_context(StreamIndex(BuiltinStream::Dbi as u16))
             .ok_or(Error::StreamNotFound("DBI"))?;
-        DbiStream::read(stream)
+        let dbi = Self::read_stream_to_end("DBI", stream, &mut ctx, |s, ctx| {
+            DbiStream::read_with_context(s, ctx)
+        })?;
+        for (offset, index) in dbi.stream_indices() {
+            if usize::from(index.0) >= self.layouts.len() {
+                self.warnings.push(Warning {
+                    stream: "DBI",
+                    offset,
+                    kind: WarningKind::StreamOutOfRange(index.0),
+                });
+            }
+        }
+        Ok(dbi)
     }
 
     pub fn get_tpi(&mut self) -> Result<TpiStream> {
-        let stream = self
-            .get_stream(BuiltinStream::Tpi)
+        let (stream, mut ctx) = self
+            .get_indexed_stream_with_context(StreamIndex(BuiltinStream::Tpi as u16))
+            .ok_or(Error::StreamNotFound("TPI"))?;
+        Self::read_stream("TPI", stream, |s| TypeStream::read(s, &mut ctx))
+    }
+
+    // walks the TPI records in chunks of up to `chunk_len`, for type streams too large to keep in memory,
+    // a chunk is dropped once `visit` returns, the header is returned at the end
+    pub fn visit_tpi_chunks<F>(&mut self, chunk_len: usize, mut visit: F) -> Result<TypeStreamHeader>
+    where
+        F: FnMut(TypeIndex, &[TypeRecord]) -> Result<()>,
+    {
+        let (stream, mut ctx) = self
+            .get_indexed_stream_with_context(StreamIndex(BuiltinStream::Tpi as u16))
+            .ok_or(Error::StreamNotFound("TPI"))?;
+        Self::read_stream("TPI", stream, |s| {
+            TypeStream::visit_chunks(s, &mut ctx, chunk_len, |first, chunk| {
+                visit(TypeIndex::try_from(first).unwrap(), chunk)
+            })
+        })
+    }
+
+    pub fn visit_ipi_chunks<F>(&mut self, chunk_len: usize, mut visit: F) -> Result<TypeStreamHeader>
+    where
+        F: FnMut(IdIndex, &[IdRecord]) -> Result<()>,
+    {
+        let (stream, mut ctx) = self
+            .get_indexed_stream_with_context(StreamIndex(BuiltinStream::Ipi as u16))
+            .ok_or(Error::StreamNotFound("IPI"))?;
+        Self::read_stream("IPI", stream, |s| {
+            TypeStream::visit_chunks(s, &mut ctx, chunk_len, |first, chunk| {
+                visit(IdIndex::try_from(first).unwrap(), chunk)
+            })
+        })
+    }
+
+    // exposes records of any TPI version without decoding their leaves
+    pub fn get_raw_tpi(&mut self) -> Result<RawTypeStream> {
+        let (stream, mut ctx) = self
+            .get_indexed_stream_with_context(StreamIndex(BuiltinStream::Tpi as u16))
             .ok_or(Error::StreamNotFound("TPI"))?;
-        TypeStream::read(stream)
+        Self::read_stream("TPI", stream, |s| TypeStream::read(s, &mut ctx))
+    }
+
+    pub fn get_raw_ipi(&mut self) -> Result<RawTypeStream> {
+        let (stream, mut ctx) = self
+            .get_indexed_stream_with_context(StreamIndex(BuiltinStream::Ipi as u16))
+            .ok_or(Error::StreamNotFound("IPI"))?;
+        Self::read_stream("IPI", stream, |s| TypeStream::read(s, &mut ctx))
     }
 
     pub fn get_tpi_hash<A>(&mut self, tpi: &TypeStream<A>) -> Result<TypeHash> {
-        let hash_stream = self
-            .get_indexed_stream(tpi.header().hash_stream_index)
+        let limits = self.options.limits;
+        let hash_stream = tpi
+            .header()
+            .hash_stream_index
+            .and_then(|index| self.get_indexed_stream(index))
             .ok_or(Error::StreamNotFound("TPI hash stream"))?;
-        TypeHash::read(hash_stream, &tpi.header().hash_layout)
+        Self::read_stream("TPI hash stream", hash_stream, |s| {
+            TypeHash::read(s, &tpi.header().hash_layout, &limits)
+        })
     }
 
     pub fn get_ipi(&mut self) -> Result<IpiStream> {
-        let stream = self
-            .get_stream(BuiltinStream::Ipi)
+        let (stream, mut ctx) = self
+            .get_indexed_stream_with_context(StreamIndex(BuiltinStream::Ipi as u16))
             .ok_or(Error::StreamNotFound("IPI"))?;
-        TypeStream::read(stream)
+        Self::read_stream("IPI", stream, |s| TypeStream::read(s, &mut ctx))
     }
 
     pub fn get_publics(&mut self, dbi: &DbiStream) -> Result<Publics> {
-        let stream = self
-            .get_indexed_stream(dbi.header().public_symbol_stream_index)
-            .ok_or(Error::StreamNotFound("publics"))?;
-        Publics::read_with_header(stream)
+        self.read_publics(dbi.header().public_symbol_stream_index)
     }
 
     pub fn get_globals(&mut self, dbi: &DbiStream) -> Result<SymbolMap> {
+        self.read_globals(dbi.header().global_symbol_stream_index)
+    }
+
+    pub fn get_symbols(&mut self, dbi: &DbiStream) -> Result<Symbols> {
+        self.read_symbols(dbi.header().sym_record_stream_index)
+    }
+
+    // like `get_publics`, but looks up the stream in the DBI header on its own
+    pub fn publics(&mut self) -> Result<Publics> {
+        let streams = self.symbol_streams()?;
+        self.read_publics(streams.publics)
+    }
+
+    // like `get_globals`, but looks up the stream in the DBI header on its own
+    pub fn globals(&mut self) -> Result<SymbolMap> {
+        let streams = self.symbol_streams()?;
+        self.read_globals(streams.globals)
+    }
+
+    // like `get_symbols`, but looks up the stream in the DBI header on its own
+    pub fn symbols(&mut self) -> Result<Symbols> {
+        let streams = self.symbol_streams()?;
+        self.read_symbols(streams.symbols)
+    }
+
+    fn symbol_streams(&mut self) -> Result<SymbolStreams> {
+        if let Some(streams) = self.cache.symbols {
+            return Ok(streams);
+        }
+        let dbi = self.get_dbi()?;
+        let header = dbi.header();
+        let streams = SymbolStreams {
+            publics: header.public_symbol_stream_index,
+            globals: header.global_symbol_stream_index,
+            symbols: header.sym_record_stream_index,
+        };
+        Ok(*self.cache.symbols.insert(streams))
+    }
+
+    fn read_publics(&mut self, index: StreamIndex) -> Result<Publics> {
+        let limits = self.options.limits;
         let stream = self
-            .get_indexed_stream(dbi.header().global_symbol_stream_index)
+            .get_indexed_stream(index)
             .ok_or(Error::StreamNotFound("publics"))?;
-        SymbolMap::read_with_header(stream)
+        Self::read_stream("publics", stream, |s| Publics::read_with_header(s, &limits))
     }
 
-    pub fn get_symbols(&mut self, dbi: &DbiStream) -> Result<Symbols> {
+    fn read_globals(&mut self, index: StreamIndex) -> Result<SymbolMap> {
+        let limits = self.options.limits;
         let stream = self
-            .get_indexed_stream(dbi.header().sym_record_stream_index)
+            .get_indexed_stream(index)
+            .ok_or(Error::StreamNotFound("globals"))?;
+        Self::read_stream("globals", stream, |s| SymbolMap::read_with_header(s, &limits))
+    }
+
+    // the records of the symbol record stream decoded one at a time, a record that fails to decode is
+    // reported in its place and the iteration resumes with the next one
+    pub fn iter_symbols(
+        &mut self,
+        dbi: &DbiStream,
+    ) -> Result<impl Iterator<Item = Result<(SymbolOffset, SymbolRecord)>> + '_> {
+        let (stream, ctx) = self
+            .get_indexed_stream_with_context(dbi.header().sym_record_stream_index)
+            .ok_or(Error::StreamNotFound("symbols"))?;
+        let limits = ctx.limits();
+        let (index, len) = (stream.get_ref().index(), stream.get_ref().length());
+        let records = PrefixedRecords::new(io::Read::take(stream, len.into()), 0, "symbols", index, limits);
+        Ok(records.map(|res| res.map(|(offset, symbol)| (SymbolOffset(offset), symbol))))
+    }
+
+    fn read_symbols(&mut self, index: StreamIndex) -> Result<Symbols> {
+        let (stream, mut ctx) = self
+            .get_indexed_stream_with_context(index)
             .ok_or(Error::StreamNotFound("symbols"))?;
-        Symbols::read(stream)
+        Self::read_stream("symbols", stream, |s| Symbols::read(s, &mut ctx))
     }
 
     pub fn get_section_headers(&mut self, dbi: &DbiStream) -> Result<SectionHeaderStream> {
@@ -156,10 +888,12 @@ where
             .dbg_streams()
             .get(DbgHeader::SectionHdr as usize)
             .ok_or(Error::StreamNotFound("section HDR"))?;
-        let stream = self
-            .get_indexed_stream(*index)
+        let (stream, mut ctx) = self
+            .get_indexed_stream_with_context(*index)
             .ok_or(Error::StreamNotFound("section HDR"))?;
-        SectionHeaderStream::read(stream)
+        Self::read_stream_to_end("section HDR", stream, &mut ctx, |s, _| {
+            SectionHeaderStream::read(s)
+        })
     }
 
     pub fn get_frame_data(&mut self, dbi: &DbiStream) -> Result<FrameDataStream> {
@@ -167,10 +901,47 @@ where
             .dbg_streams()
             .get(DbgHeader::NewFPO as usize)
             .ok_or(Error::StreamNotFound("frame data"))?;
-        let stream = self
-            .get_indexed_stream(*index)
+        let (stream, mut ctx) = self
+            .get_indexed_stream_with_context(*index)
             .ok_or(Error::StreamNotFound("frame data"))?;
-        FrameDataStream::read(stream)
+        Self::read_stream_to_end("frame data", stream, &mut ctx, |s, _| FrameDataStream::read(s))
+    }
+
+    // maps the RVAs of a rewritten image to those of the original one the symbols refer to
+    pub fn get_omap_to_src(&mut self, dbi: &DbiStream) -> Result<OmapStream> {
+        let index = dbi
+            .dbg_streams()
+            .get(DbgHeader::OmapToSrc as usize)
+            .ok_or(Error::StreamNotFound("omap to src"))?;
+        let (stream, mut ctx) = self
+            .get_indexed_stream_with_context(*index)
+            .ok_or(Error::StreamNotFound("omap to src"))?;
+        Self::read_stream_to_end("omap to src", stream, &mut ctx, |s, _| OmapStream::read(s))
+    }
+
+    pub fn get_omap_from_src(&mut self, dbi: &DbiStream) -> Result<OmapStream> {
+        let index = dbi
+            .dbg_streams()
+            .get(DbgHeader::OmapFromSrc as usize)
+            .ok_or(Error::StreamNotFound("omap from src"))?;
+        let (stream, mut ctx) = self
+            .get_indexed_stream_with_context(*index)
+            .ok_or(Error::StreamNotFound("omap from src"))?;
+        Self::read_stream_to_end("omap from src", stream, &mut ctx, |s, _| OmapStream::read(s))
+    }
+
+    // the section headers of the original image when the file has OMAP streams
+    pub fn get_original_section_headers(&mut self, dbi: &DbiStream) -> Result<SectionHeaderStream> {
+        let index = dbi
+            .dbg_streams()
+            .get(DbgHeader::SectionHdrOrig as usize)
+            .ok_or(Error::StreamNotFound("original section HDR"))?;
+        let (stream, mut ctx) = self
+            .get_indexed_stream_with_context(*index)
+            .ok_or(Error::StreamNotFound("original section HDR"))?;
+        Self::read_stream_to_end("original section HDR", stream, &mut ctx, |s, _| {
+            SectionHeaderStream::read(s)
+        })
     }
 
     pub fn get_fpo(&mut self, dbi: &DbiStream) -> Result<FpoStream> {
@@ -178,17 +949,310 @@ where
             .dbg_streams()
             .get(DbgHeader::Fpo as usize)
             .ok_or(Error::StreamNotFound("fpo"))?;
-        let stream = self
-            .get_indexed_stream(*index)
+        let (stream, mut ctx) = self
+            .get_indexed_stream_with_context(*index)
             .ok_or(Error::StreamNotFound("fpo"))?;
-        FpoStream::read(stream)
+        Self::read_stream_to_end("fpo", stream, &mut ctx, |s, _| FpoStream::read(s))
+    }
+
+    // the raw contents of a stream by its index in the stream directory
+    pub fn get_stream_data(&mut self, index: StreamIndex) -> Result<Vec<u8>> {
+        let (stream, ctx) = self
+            .get_indexed_stream_with_context(index)
+            .ok_or(Error::StreamNotFound("indexed"))?;
+        let budget = ctx.budget();
+        Self::read_stream("indexed", stream, |s| {
+            budget.allocate(s.get_ref().length().into())?;
+            let mut data = vec![];
+            io::Read::read_to_end(s, &mut data)?;
+            Ok(data)
+        })
+    }
+
+    // the raw contents of a debug stream by its slot, including the ones without a typed reader
+    pub fn get_debug_stream(&mut self, dbi: &DbiStream, slot: usize) -> Result<Vec<u8>> {
+        let index = dbi
+            .dbg_streams()
+            .get(slot)
+            .ok_or(Error::StreamNotFound("debug"))?;
+        let (stream, ctx) = self
+            .get_indexed_stream_with_context(*index)
+            .ok_or(Error::StreamNotFound("debug"))?;
+        let budget = ctx.budget();
+        Self::read_stream("debug", stream, |s| {
+            budget.allocate(s.get_ref().length().into())?;
+            let mut data = vec![];
+            io::Read::read_to_end(s, &mut data)?;
+            Ok(data)
+        })
+    }
+
+    // the symbol record stream as bytes, for walking its records without decoding them
+    pub fn get_symbol_bytes(&mut self, dbi: &DbiStream) -> Result<SymbolStreamBytes> {
+        let (stream, ctx) = self
+            .get_indexed_stream_with_context(dbi.header().sym_record_stream_index)
+            .ok_or(Error::StreamNotFound("symbols"))?;
+        let budget = ctx.budget();
+        Self::read_stream("symbols", stream, |s| {
+            let len = s.get_ref().length();
+            budget.allocate(len.into())?;
+            let mut bytes = Vec::with_capacity(len as usize);
+            io::Read::read_to_end(&mut io::Read::take(s, len.into()), &mut bytes)?;
+            Ok(SymbolStreamBytes::new(bytes, 0))
+        })
+    }
+
+    // like `get_symbol_bytes`, but for the symbols of a module, which start past a signature
+    pub fn get_module_symbol_bytes(&mut self, module: &DbiModule) -> Result<SymbolStreamBytes> {
+        let layout = &module.header.layout;
+        let Some((stream, ctx)) = self.get_indexed_stream_with_context(module.header.debug_info_stream)
+        else {
+            return Ok(SymbolStreamBytes::new(vec![], 0));
+        };
+        let budget = ctx.budget();
+        Self::read_stream("module symbols", stream, |s| {
+            budget.allocate(layout.sym_bytes.into())?;
+            let mut bytes = Vec::with_capacity(layout.sym_bytes as usize);
+            io::Read::read_to_end(&mut io::Read::take(s, layout.sym_bytes.into()), &mut bytes)?;
+            Ok(SymbolStreamBytes::new(bytes, 4))
+        })
+    }
+
+    // the symbols of a module without decoding their bodies
+    pub fn get_module_raw_symbols(&mut self, module: &DbiModule) -> Result<Vec<RawRecord>> {
+        let layout = &module.header.layout;
+        let Some((stream, mut ctx)) = self.get_indexed_stream_with_context(module.header.debug_info_stream)
+        else {
+            return Ok(vec![]);
+        };
+        if layout.sym_bytes < 4 {
+            return Ok(vec![]);
+        }
+        let decoder = ctx.records();
+        Self::read_stream("module symbols", stream, |s| {
+            let mut sym_stream = io::Read::take(s, layout.sym_bytes.into());
+            // skip the debug section signature
+            u32::decode(constants::ENDIANESS, &mut sym_stream)?;
+            ctx.read_records("module symbols", 4, &mut sym_stream, |stream| {
+                decoder.decode::<RawRecord, _>(stream)
+            })
+        })
+    }
+
+    // like `iter_symbols`, but for the symbols of a module, the offsets are those in the module stream
+    pub fn iter_module_symbols(
+        &mut self,
+        module: &DbiModule,
+    ) -> Result<impl Iterator<Item = Result<(SymbolOffset, SymbolRecord)>> + '_> {
+        let layout = &module.header.layout;
+        let (stream, ctx) = self
+            .get_indexed_stream_with_context(module.header.debug_info_stream)
+            .ok_or(Error::StreamNotFound("module debug info"))?;
+        let (limits, index) = (ctx.limits(), stream.get_ref().index());
+        let mut sym_stream = io::Read::take(stream, layout.sym_bytes.into());
+        if layout.sym_bytes < 4 {
+            sym_stream.set_limit(0);
+        } else {
+            // skip the debug section signature
+            u32::decode(constants::ENDIANESS, &mut sym_stream)?;
+        }
+        let records = PrefixedRecords::new(sym_stream, 4, "module symbols", index, limits);
+        Ok(records.map(|res| res.map(|(offset, symbol)| (SymbolOffset(offset), symbol))))
     }
 
     pub fn get_module(&mut self, module: &DbiModule) -> Result<Module> {
-        let stream = self
-            .get_indexed_stream(module.header.debug_info_stream)
+        let (stream, mut ctx) = self
+            .get_indexed_stream_with_context(module.header.debug_info_stream)
+            .ok_or(Error::StreamNotFound("module debug info"))?;
+        Self::read_stream("module debug info", stream, |s| {
+            Module::read(s, &module.header.layout, &mut ctx)
+        })
+    }
+
+    // reads the debug info of a module without decoding it, see `LazyModule`
+    pub fn get_lazy_module(&mut self, module: &DbiModule) -> Result<LazyModule> {
+        let (stream, mut ctx) = self
+            .get_indexed_stream_with_context(module.header.debug_info_stream)
+            .ok_or(Error::StreamNotFound("module debug info"))?;
+        Self::read_stream("module debug info", stream, |s| {
+            LazyModule::read(s, &module.header.layout, &mut ctx)
+        })
+    }
+
+    // like `get_module`, but leaves out the symbols, which `get_module_raw_symbols` reads on their own
+    fn get_module_debug_info(&mut self, module: &DbiModule) -> Result<Module> {
+        let (stream, mut ctx) = self
+            .get_indexed_stream_with_context(module.header.debug_info_stream)
             .ok_or(Error::StreamNotFound("module debug info"))?;
-        Module::read(stream, &module.header.layout)
+        Self::read_stream("module debug info", stream, |s| {
+            Module::read_without_symbols(s, &module.header.layout, &mut ctx)
+        })
+    }
+}
+
+// a PDB held in memory, for targets without a file system like wasm32-unknown-unknown
+impl<'a> PdbFile<io::Cursor<&'a [u8]>> {
+    pub fn from_bytes(bytes: &'a [u8]) -> Result<Self> {
+        Self::from_bytes_with_options(bytes, ParseOptions::default())
+    }
+
+    pub fn from_bytes_with_options(bytes: &'a [u8], options: ParseOptions) -> Result<Self> {
+        Self::open_with_options(io::Cursor::new(bytes), options)
+    }
+}
+
+impl<R> PdbFile<R>
+where
+    R: io::Read + io::Write + io::Seek,
+{
+    // rewrites the header of the info stream in place without touching any other block,
+    // e.g. to pair the PDB with a modified image
+    pub fn patch_info(&mut self, patch: InfoPatch) -> Result<PdbInfoHeader> {
+        let layout = self
+            .layouts
+            .get(BuiltinStream::Pdb as usize)
+            .and_then(Option::as_ref)
+            .ok_or(Error::StreamNotFound("PDB"))?;
+        let mut stream = MsfStream::new(&mut self.inner, layout, self.super_block.block_size);
+        let mut header = PdbInfoHeader::decode((), &mut stream)?;
+        patch.apply(&mut header)?;
+        io::Seek::seek(&mut stream, io::SeekFrom::Start(0))?;
+        header.encode((), &mut stream)?;
+        io::Write::flush(&mut stream)?;
+        Ok(header)
+    }
+
+    // writes a modified DBI stream back in place, which only works when it keeps its size,
+    // like after changing the header of a module
+    pub fn patch_dbi(&mut self, dbi: &DbiStream) -> Result<()> {
+        let layout = self
+            .layouts
+            .get(BuiltinStream::Dbi as usize)
+            .and_then(Option::as_ref)
+            .ok_or(Error::StreamNotFound("DBI"))?;
+        let mut bytes = vec![];
+        dbi.write(&mut bytes)?;
+        let mut stream = MsfStream::new(&mut self.inner, layout, self.super_block.block_size);
+        if bytes.len() != stream.length() as usize {
+            return Err(Error::UnsupportedFeature(
+                "patching the DBI stream with one of a different size",
+            ));
+        }
+        io::Write::write_all(&mut stream, &bytes)?;
+        io::Write::flush(&mut stream)?;
+        Ok(())
+    }
+
+    // replaces the contents of a stream without rewriting the rest of the file, the data and a new
+    // directory go to blocks that were free, or past the end of the file when there are not enough of them,
+    // and the free block map goes to the inactive FPM that the superblock switches to with the very last
+    // write, the blocks of the old contents are freed but not reused until the next change
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(index = index.0)))]
+    pub fn replace_stream(&mut self, index: StreamIndex, data: &[u8]) -> Result<()> {
+        let slot = usize::from(index.0);
+        if slot >= self.layouts.len() {
+            return Err(Error::StreamNotFound("replaced stream"));
+        }
+        let block_size = self.super_block.block_size;
+        let mut fpm = self.get_free_block_map()?;
+        let mut num_blocks = self.super_block.num_blocks;
+        let mut freed = vec![];
+
+        let blocks = fpm.allocate(
+            div_ceil(data.len() as u32, block_size),
+            &mut num_blocks,
+            block_size,
+        );
+        let layout = MsfStreamLayout::new(blocks, data.len() as u32);
+        io::Write::write_all(&mut MsfStream::new(&mut self.inner, &layout, block_size), data)?;
+        if let Some(old) = self.layouts[slot].replace(layout) {
+            freed.extend(old.blocks);
+        }
+
+        let mut directory = vec![];
+        (self.layouts.len() as u32).encode(constants::ENDIANESS, &mut directory)?;
+        for layout in &self.layouts {
+            let size = layout.as_ref().map_or(u32::MAX, |layout| layout.byte_size);
+            size.encode(constants::ENDIANESS, &mut directory)?;
+        }
+        for layout in self.layouts.iter().flatten() {
+            layout.blocks.encode(((),), &mut directory)?;
+        }
+        let blocks = fpm.allocate(
+            div_ceil(directory.len() as u32, block_size),
+            &mut num_blocks,
+            block_size,
+        );
+        let dir_layout = MsfStreamLayout::new(blocks, directory.len() as u32);
+        io::Write::write_all(
+            &mut MsfStream::new(&mut self.inner, &dir_layout, block_size),
+            &directory,
+        )?;
+
+        let mut block_map = vec![];
+        dir_layout.blocks.encode(((),), &mut block_map)?;
+        let map_blocks = fpm.allocate_contiguous(
+            div_ceil(block_map.len() as u32, block_size),
+            &mut num_blocks,
+            block_size,
+        );
+        let map_addr = map_blocks[0];
+        self.inner
+            .seek(io::SeekFrom::Start(u64::from(map_addr.0) * u64::from(block_size)))?;
+        self.inner.write_all(&block_map)?;
+
+        freed.extend(std::mem::replace(&mut self.dir_layout, dir_layout).blocks);
+        let old_map = self.super_block.block_map_addr.0;
+        let old_map_blocks = div_ceil(self.super_block.block_map_blocks() * 4, block_size);
+        freed.extend((old_map..old_map + old_map_blocks).map(BlockIndex));
+        for block in freed {
+            fpm.set_free(block.0, true);
+        }
+
+        let super_block = SuperBlock {
+            magic: MsfHeader,
+            block_size,
+            free_block_map_block: 3 - self.super_block.free_block_map_block,
+            num_blocks,
+            num_dir_bytes: self.dir_layout.byte_size,
+            unknown: self.super_block.unknown,
+            block_map_addr: map_addr,
+        };
+        fpm.write(&super_block, &mut self.inner)?;
+        // the last block may have been left short of its end
+        let file_size = u64::from(num_blocks) * u64::from(block_size);
+        if self.inner.seek(io::SeekFrom::End(0))? < file_size {
+            self.inner.seek(io::SeekFrom::Start(file_size - 1))?;
+            self.inner.write_all(&[0])?;
+        }
+        self.inner.seek(io::SeekFrom::Start(0))?;
+        super_block.encode((), &mut self.inner)?;
+        self.inner.flush()?;
+        self.super_block = super_block;
+        Ok(())
+    }
+
+    // adds a stream at the end of the directory, see `replace_stream`
+    pub fn add_stream(&mut self, data: &[u8]) -> Result<StreamIndex> {
+        let index = StreamIndex(self.layouts.len() as u16);
+        self.layouts.push(None);
+        if let Err(err) = self.replace_stream(index, data) {
+            self.layouts.pop();
+            return Err(err);
+        }
+        Ok(index)
+    }
+}
+
+#[cfg(feature = "cab")]
+impl PdbFile<io::Cursor<Vec<u8>>> {
+    // opens a PDB compressed into a cabinet, like the `.pd_` files served by symbol servers
+    pub fn open_cab<R: io::Read + io::Seek>(reader: R) -> Result<Self> {
+        let file = cab::extract(reader)?
+            .into_iter()
+            .next()
+            .ok_or(Error::UnsupportedFeature("empty cabinet"))?;
+        Self::open(io::Cursor::new(file.data))
     }
 }
 
@@ -204,9 +1268,9 @@ enum BuiltinStream {
     Ipi = 4,
 }
 
-#[allow(unused)]
-#[derive(Debug)]
-enum DbgHeader {
+// the slots of the optional debug streams in the DBI stream, linkers may write custom ones past `Max`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DbgHeader {
     Fpo,
     Exception,
     Fixup,
@@ -221,7 +1285,7 @@ enum DbgHeader {
     Max,
 }
 
-#[derive(Debug, Clone, Copy, Encode, Decode)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode)]
 #[declio(ctx_is = "constants::ENDIANESS")]
 pub struct StringOffset(u32);
 
@@ -230,7 +1294,7 @@ pub struct IndexIsZero;
 
 macro_rules! record_index {
     ($name:ident) => {
-        #[derive(Debug, Clone, Copy)]
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
         pub struct $name(NonZeroU32);
 
         impl TryFrom<u32> for $name {
@@ -294,13 +1358,29 @@ impl From<SymbolOffset> for u32 {
     }
 }
 
+impl From<StringOffset> for u32 {
+    fn from(val: StringOffset) -> Self {
+        val.0
+    }
+}
+
 record_index!(IdIndex);
 record_index!(TypeIndex);
 
-#[derive(Debug, Default, Encode, Decode, EncodedSize)]
+#[derive(Debug, Default, Clone, PartialEq, Eq, Encode, Decode, EncodedSize)]
 pub struct Guid(#[declio(with = "codecs::byte_array")] [u8; 16]);
 
-#[derive(Debug)]
+impl Guid {
+    pub fn new(bytes: [u8; 16]) -> Self {
+        Self(bytes)
+    }
+
+    pub fn bytes(&self) -> &[u8; 16] {
+        &self.0
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Integer {
     I16(i16),
     I32(i32),
@@ -311,6 +1391,20 @@ pub enum Integer {
     U64(u64),
 }
 
+impl Integer {
+    pub fn as_u64(&self) -> Option<u64> {
+        match *self {
+            Integer::I16(i) => u64::try_from(i).ok(),
+            Integer::I32(i) => u64::try_from(i).ok(),
+            Integer::I64(i) => u64::try_from(i).ok(),
+            Integer::U8(i) => Some(i.into()),
+            Integer::U16(i) => Some(i.into()),
+            Integer::U32(i) => Some(i.into()),
+            Integer::U64(i) => Some(i),
+        }
+    }
+}
+
 impl<Ctx: Copy> Decode<Ctx> for Integer {
     fn decode<R>(_ctx: Ctx, reader: &mut R) -> Result<Self, declio::Error>
     where
@@ -325,7 +1419,7 @@ impl<Ctx: Copy> Decode<Ctx> for Integer {
             constants::LF_ULONG => Ok(Integer::U32(u32::decode(constants::ENDIANESS, reader)?)),
             constants::LF_QUADWORD => Ok(Integer::I64(i64::decode(constants::ENDIANESS, reader)?)),
             constants::LF_UQUADWORD => Ok(Integer::U64(u64::decode(constants::ENDIANESS, reader)?)),
-            val => todo!("{}", val),
+            val => Err(declio::Error::new(format!("unsupported numeric leaf {val:#x}"))),
         }
     }
 }
@@ -0,0 +1,242 @@
+use std::collections::HashMap;
+
+use crate::builders::{ModuleBuilder, PdbBuilder};
+use crate::codeview::{DataRegionOffset, RECORD_ALIGNMENT};
+use crate::dbi::SectionHeader;
+use crate::module::{
+    ChecksumType, ColumnNumberEntry, DebugSubsectionRecord, FileChecksumEntry, LineColumnEntry, LineFlags, LineFragmentHeader, LineNumberEntry
+};
+use crate::result::{Error, Result};
+use crate::utils::align_to;
+
+const LINE_MASK: u32 = 0xFFFFFF;
+const IS_STATEMENT: u32 = 1 << 31;
+
+// builds the line tables of a module from samples in no particular order, like the ones converted from
+// DWARF or collected by a profiler, samples are grouped into a fragment per function or, without functions,
+// per contribution of the module and fragments never cross a section
+#[derive(Debug, Default)]
+pub struct LineProgramBuilder {
+    files: Vec<SourceFile>,
+    file_indices: HashMap<String, usize>,
+    functions: Vec<(u32, u32)>,
+    samples: Vec<Sample>,
+}
+
+#[derive(Debug)]
+struct SourceFile {
+    name: String,
+    checksum_type: ChecksumType,
+    checksum: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    rva: u32,
+    file: usize,
+    line: u32,
+    columns: Option<(u16, u16)>,
+}
+
+#[derive(Debug)]
+struct Fragment {
+    segment: u16,
+    start: u32,
+    end: u32,
+    // not covered by a function or a contribution
+    is_loose: bool,
+    samples: Vec<(u32, Sample)>,
+}
+
+impl LineProgramBuilder {
+    // the columns are a start and an end column, fragments with columns on any of their lines have them
+    // on every line, zeroed where missing
+    pub fn add_line(&mut self, rva: u32, file: &str, line: u32, columns: Option<(u16, u16)>) -> &mut Self {
+        let file = self.file_index(file);
+        self.samples.push(Sample {
+            rva,
+            file,
+            line,
+            columns,
+        });
+        self
+    }
+
+    pub fn add_function(&mut self, rva: u32, size: u32) -> &mut Self {
+        self.functions.push((rva, size));
+        self
+    }
+
+    // files without a checksum are written with `ChecksumType::None`
+    pub fn file_checksum(
+        &mut self,
+        file: &str,
+        checksum_type: ChecksumType,
+        checksum: Vec<u8>,
+    ) -> &mut Self {
+        let file = self.file_index(file);
+        self.files[file].checksum_type = checksum_type;
+        self.files[file].checksum = checksum;
+        self
+    }
+
+    // adds the file checksums and the line fragments to the module, the file names go to the /names stream
+    // and addresses are resolved against the sections of the DBI builder, which have to be added first
+    pub fn commit(mut self, builder: &mut PdbBuilder, module: &mut ModuleBuilder) -> Result<()> {
+        let sections = builder.dbi().section_headers();
+        let contribs: Vec<_> = module
+            .section_contribs()
+            .map(|contrib| {
+                let start = contrib.offset as u32;
+                (contrib.i_sect, start, start + contrib.size)
+            })
+            .collect();
+        self.functions.sort_unstable();
+        self.samples.sort_by_key(|sample| sample.rva);
+
+        let mut fragments: Vec<Fragment> = vec![];
+        for sample in self.samples {
+            let (segment, header) = (1..)
+                .zip(sections)
+                .find(|(_, header)| {
+                    (header.virtual_address..header.virtual_address + header.virtual_size)
+                        .contains(&sample.rva)
+                })
+                .ok_or_else(|| Error::AddressOutOfSection(format!("line at {:#x}", sample.rva)))?;
+            let offset = sample.rva - header.virtual_address;
+            let range = range_of(&self.functions, &contribs, header, segment, sample.rva, offset);
+            match fragments.last_mut() {
+                Some(fragment)
+                    if fragment.segment == segment
+                        && range.map_or(fragment.is_loose, |(start, _)| {
+                            !fragment.is_loose && fragment.start == start
+                        }) =>
+                {
+                    fragment.end = fragment.end.max(offset + 1);
+                    fragment.samples.push((offset, sample));
+                }
+                _ => {
+                    let (start, end) = range.unwrap_or((offset, offset + 1));
+                    fragments.push(Fragment {
+                        segment,
+                        start,
+                        end,
+                        is_loose: range.is_none(),
+                        samples: vec![(offset, sample)],
+                    });
+                }
+            }
+        }
+
+        // lines refer to files by the offset of their entry in the checksums subsection
+        let mut checksums = Vec::with_capacity(self.files.len());
+        let mut checksum_offsets = Vec::with_capacity(self.files.len());
+        let mut checksum_offset = 0;
+        for file in self.files {
+            module.add_source_file(file.name.clone());
+            checksum_offsets.push(checksum_offset);
+            checksum_offset += align_to(6 + file.checksum.len(), RECORD_ALIGNMENT) as u32;
+            checksums.push(FileChecksumEntry {
+                file_name_offset: builder.add_name(&file.name)?.0,
+                checksum_size: file.checksum.len() as u8,
+                checksum_type: file.checksum_type,
+                bytes: file.checksum,
+            });
+        }
+        if !checksums.is_empty() {
+            module.add_subsection(DebugSubsectionRecord::FileChecksums { entries: checksums })?;
+        }
+        for fragment in fragments {
+            module.add_subsection(fragment.into_record(&checksum_offsets))?;
+        }
+        Ok(())
+    }
+
+    fn file_index(&mut self, name: &str) -> usize {
+        if let Some(&index) = self.file_indices.get(name) {
+            return index;
+        }
+        self.files.push(SourceFile {
+            name: name.to_owned(),
+            checksum_type: ChecksumType::None,
+            checksum: vec![],
+        });
+        self.file_indices.insert(name.to_owned(), self.files.len() - 1);
+        self.files.len() - 1
+    }
+}
+
+impl Fragment {
+    fn into_record(self, checksum_offsets: &[u32]) -> DebugSubsectionRecord {
+        let has_columns = self.samples.iter().any(|(_, sample)| sample.columns.is_some());
+        // consecutive lines of the same file make up a block
+        let mut entries: Vec<LineColumnEntry> = vec![];
+        let mut block_starts = vec![];
+        for (offset, sample) in self.samples {
+            let name_index = checksum_offsets[sample.file];
+            let line = LineNumberEntry {
+                offset: offset - self.start,
+                flags: (sample.line & LINE_MASK) | IS_STATEMENT,
+            };
+            let (start_col, end_col) = sample.columns.unwrap_or_default();
+            let column = ColumnNumberEntry { start_col, end_col };
+            match entries.last_mut() {
+                Some(entry) if entry.name_index == name_index => {
+                    entry.num_lines += 1;
+                    entry.line_numbers.push(line);
+                    if has_columns {
+                        entry.columns.push(column);
+                    }
+                }
+                _ => {
+                    block_starts.push(offset);
+                    entries.push(LineColumnEntry {
+                        name_index,
+                        num_lines: 1,
+                        code_size: 0,
+                        line_numbers: vec![line],
+                        columns: if has_columns { vec![column] } else { vec![] },
+                    });
+                }
+            }
+        }
+        let block_ends = block_starts.iter().skip(1).copied().chain([self.end]);
+        for ((entry, start), end) in entries.iter_mut().zip(&block_starts).zip(block_ends) {
+            entry.code_size = end - start;
+        }
+
+        DebugSubsectionRecord::Lines {
+            header: LineFragmentHeader {
+                reloc: DataRegionOffset::new(self.start, self.segment),
+                flags: LineFlags::new().with_has_columns(has_columns),
+                code_size: self.end - self.start,
+            },
+            entries,
+        }
+    }
+}
+
+// the section relative range of the function or the contribution a sample belongs to
+fn range_of(
+    functions: &[(u32, u32)],
+    contribs: &[(u16, u32, u32)],
+    header: &SectionHeader,
+    segment: u16,
+    rva: u32,
+    offset: u32,
+) -> Option<(u32, u32)> {
+    let end = functions.partition_point(|&(start, _)| start <= rva);
+    let function = functions[..end]
+        .iter()
+        .rev()
+        .find(|&&(start, size)| rva - start < size);
+    if let Some(&(start, size)) = function {
+        let start = start - header.virtual_address;
+        // a function running past its section is cut at the boundary
+        return Some((start, (start + size).min(header.virtual_size)));
+    }
+    contribs
+        .iter()
+        .find(|&&(section, start, end)| section == segment && (start..end).contains(&offset))
+        .map(|&(_, start, end)| (start, end))
+}
@@ -0,0 +1,135 @@
+use crate::builders::LocalLocation;
+use crate::codeview::symbols::{LocalProperties, LocalVariableGap, LocalVariableRange, SymbolRecord};
+use crate::codeview::DataRegionOffset;
+use crate::TypeIndex;
+
+// a local variable with the def range records that follow its S_LOCAL folded into a location list
+#[derive(Debug)]
+pub struct LocalVariable<'a> {
+    pub name: &'a str,
+    pub local_type: TypeIndex,
+    pub properties: LocalProperties,
+    pub locations: Vec<LocationRange>,
+}
+
+#[derive(Debug)]
+pub struct LocationRange {
+    pub location: LocalLocation,
+    // none when the location is valid for the whole enclosing scope
+    pub range: Option<CodeRange>,
+}
+
+#[derive(Debug)]
+pub struct CodeRange {
+    pub segment: u16,
+    pub start: u32,
+    pub end: u32,
+    // subranges as (start, end) where the variable is not available
+    pub gaps: Vec<(u32, u32)>,
+}
+
+impl CodeRange {
+    fn new(range: &LocalVariableRange, gaps: &[LocalVariableGap]) -> Self {
+        let start = range.offset_start;
+        Self {
+            segment: range.i_sect_start,
+            start,
+            end: start + u32::from(range.range),
+            gaps: gaps
+                .iter()
+                .map(|gap| {
+                    let gap_start = start + u32::from(gap.gap_start_offset);
+                    (gap_start, gap_start + u32::from(gap.range))
+                })
+                .collect(),
+        }
+    }
+
+    pub fn contains(&self, offset: &DataRegionOffset) -> bool {
+        offset.segment == self.segment
+            && (self.start..self.end).contains(&offset.offset)
+            && !self
+                .gaps
+                .iter()
+                .any(|&(start, end)| (start..end).contains(&offset.offset))
+    }
+}
+
+impl<'a> LocalVariable<'a> {
+    // the location of the variable at a code offset, ranges describing parts of it are not considered
+    pub fn location_at(&self, offset: &DataRegionOffset) -> Option<LocalLocation> {
+        let ranged = self
+            .locations
+            .iter()
+            .find(|loc| loc.range.as_ref().is_some_and(|range| range.contains(offset)));
+        let full_scope = || self.locations.iter().find(|loc| loc.range.is_none());
+        ranged.or_else(full_scope).map(|loc| loc.location)
+    }
+
+    // folds every S_LOCAL of a symbol stream with the def ranges that follow it
+    pub fn collect(symbols: &'a [SymbolRecord]) -> Vec<Self> {
+        let mut res: Vec<Self> = vec![];
+        // def ranges only follow an S_LOCAL immediately
+        let mut is_open = false;
+        for symbol in symbols {
+            if let SymbolRecord::Local {
+                local_type,
+                properties,
+                name,
+            } = symbol
+            {
+                res.push(LocalVariable {
+                    name: name.as_ref(),
+                    local_type: *local_type,
+                    properties: *properties,
+                    locations: vec![],
+                });
+                is_open = true;
+                continue;
+            }
+            let Some(var) = res.last_mut().filter(|_| is_open) else {
+                continue;
+            };
+            let location = match symbol {
+                SymbolRecord::DefRangeRegister {
+                    register,
+                    range,
+                    gaps,
+                    ..
+                } => LocationRange {
+                    location: LocalLocation::Register(*register),
+                    range: Some(CodeRange::new(range, gaps)),
+                },
+                SymbolRecord::DefRangeFramePointerRel { offset, range, gaps } => LocationRange {
+                    location: LocalLocation::FrameRel(*offset),
+                    range: Some(CodeRange::new(range, gaps)),
+                },
+                SymbolRecord::DefRangeRegisterRel {
+                    register,
+                    properties,
+                    base_pointer_offset,
+                    range,
+                    gaps,
+                } if !properties.is_subfield() => LocationRange {
+                    location: LocalLocation::RegisterRel(*register, *base_pointer_offset),
+                    range: Some(CodeRange::new(range, gaps)),
+                },
+                SymbolRecord::DefRangeFramePointerRelFullScope { offset } => LocationRange {
+                    location: LocalLocation::FrameRel(*offset),
+                    range: None,
+                },
+                // subfields and programs belong to the variable, but do not describe a whole location
+                SymbolRecord::DefRange { .. }
+                | SymbolRecord::DefRangeSubfield { .. }
+                | SymbolRecord::DefRangeSubfieldRegister { .. }
+                | SymbolRecord::DefRangeRegisterRel { .. } => continue,
+                _ => {
+                    is_open = false;
+                    continue;
+                }
+            };
+            var.locations.push(location);
+        }
+        res
+    }
+}
@@ -0,0 +1,312 @@
+use std::collections::BTreeMap;
+
+use crate::builders::{ModuleBuilder, PdbBuilder};
+use crate::codeview::symbols::{Procedure, ProcedureProperties, Public, PublicProperties};
+use crate::codeview::types::BuiltinType;
+use crate::codeview::DataRegionOffset;
+use crate::dbi::{
+    SectionContrib, SectionHeader, IMAGE_SCN_CNT_CODE, IMAGE_SCN_CNT_INITIALIZED_DATA, IMAGE_SCN_CNT_UNINITIALIZED_DATA, IMAGE_SCN_MEM_EXECUTE, IMAGE_SCN_MEM_READ, IMAGE_SCN_MEM_WRITE
+};
+use crate::result::{Error, Result};
+use crate::utils::StrBuf;
+use crate::{div_ceil, SymbolOffset};
+
+const SECTION_ALIGNMENT: u32 = 0x1000;
+
+// a map file as produced by `link.exe /MAP` or `lld-link /MAP`
+#[derive(Debug, Default)]
+pub struct MapFile {
+    pub module_name: String,
+    pub timestamp: u32,
+    pub load_address: u64,
+    pub contributions: Vec<MapContribution>,
+    pub symbols: Vec<MapSymbol>,
+}
+
+// an entry of the section table, e.g. `0001:00000000 00001234H .text$mn CODE`
+#[derive(Debug)]
+pub struct MapContribution {
+    pub segment: u16,
+    pub offset: u32,
+    pub length: u32,
+    pub name: String,
+    pub class: String,
+}
+
+#[derive(Debug)]
+pub struct MapSymbol {
+    pub segment: u16,
+    pub offset: u32,
+    pub name: String,
+    // the virtual address including the preferred load address
+    pub address: u64,
+    pub is_function: bool,
+    // symbols listed under `Static symbols`
+    pub is_static: bool,
+    pub object: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum MapSection {
+    Header,
+    Contributions,
+    Publics,
+    Statics,
+    Other,
+}
+
+impl MapFile {
+    pub fn parse(map: &str) -> Result<Self> {
+        let mut res = MapFile::default();
+        let mut section = MapSection::Header;
+
+        for line in map.lines() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(rest) = line.strip_prefix("Timestamp is ") {
+                let hex = rest.split_whitespace().next().unwrap_or_default();
+                res.timestamp = u32::from_str_radix(hex, 16).map_err(|_| malformed())?;
+            } else if let Some(rest) = line.strip_prefix("Preferred load address is ") {
+                res.load_address = u64::from_str_radix(rest.trim(), 16).map_err(|_| malformed())?;
+            } else if line.starts_with("Start") && line.contains("Class") {
+                section = MapSection::Contributions;
+            } else if line.contains("Publics by Value") {
+                section = MapSection::Publics;
+            } else if line == "Static symbols" {
+                section = MapSection::Statics;
+            } else if line.starts_with("entry point at")
+                || line.starts_with("Exports")
+                || line.starts_with("FixUps")
+            {
+                section = MapSection::Other;
+            } else {
+                match section {
+                    MapSection::Header if res.module_name.is_empty() => res.module_name = line.to_owned(),
+                    MapSection::Contributions => res.contributions.push(parse_contribution(line)?),
+                    MapSection::Publics => res.symbols.push(parse_symbol(line, false)?),
+                    MapSection::Statics => res.symbols.push(parse_symbol(line, true)?),
+                    _ => {}
+                }
+            }
+        }
+        Ok(res)
+    }
+
+    // adds the sections and publics of the map, and with `procs` also a module per object file
+    // with a procedure for every function
+    pub fn populate(&self, builder: &mut PdbBuilder, procs: bool) -> Result<()> {
+        builder.info().signature(self.timestamp);
+
+        let sections = self.sections();
+        builder
+            .dbi()
+            .add_sections(sections.iter().map(Section::header).collect());
+
+        let code_segments: Vec<u16> = (1..)
+            .zip(&sections)
+            .filter(|(_, section)| section.characteristics & IMAGE_SCN_CNT_CODE != 0)
+            .map(|(index, _)| index)
+            .collect();
+        let mut publics = builder.dbi().symbols();
+        for symbol in self
+            .symbols
+            .iter()
+            .filter(|sym| !sym.is_static && sym.segment != 0)
+        {
+            publics.add(Public {
+                properties: PublicProperties::new()
+                    .with_is_code(code_segments.contains(&symbol.segment))
+                    .with_is_function(symbol.is_function),
+                offset: DataRegionOffset::new(symbol.offset, symbol.segment),
+                name: StrBuf::new(symbol.name.clone()),
+            })?;
+        }
+
+        if procs {
+            for module in self.modules(&sections) {
+                builder.dbi().add_module(module);
+            }
+        }
+        Ok(())
+    }
+
+    fn sections(&self) -> Vec<Section> {
+        let mut sections: BTreeMap<u16, Section> = BTreeMap::new();
+        for contrib in &self.contributions {
+            let section = sections.entry(contrib.segment).or_insert_with(|| Section {
+                name: contrib.name.split('$').next().unwrap_or_default().to_owned(),
+                virtual_address: None,
+                virtual_size: 0,
+                characteristics: 0,
+            });
+            section.virtual_size = section.virtual_size.max(contrib.offset + contrib.length);
+            section.characteristics |= match contrib.class.as_str() {
+                "CODE" => IMAGE_SCN_CNT_CODE | IMAGE_SCN_MEM_EXECUTE | IMAGE_SCN_MEM_READ,
+                _ if contrib.name.starts_with(".bss") => {
+                    IMAGE_SCN_CNT_UNINITIALIZED_DATA | IMAGE_SCN_MEM_READ | IMAGE_SCN_MEM_WRITE
+                }
+                _ if contrib.name.starts_with(".data") => {
+                    IMAGE_SCN_CNT_INITIALIZED_DATA | IMAGE_SCN_MEM_READ | IMAGE_SCN_MEM_WRITE
+                }
+                _ => IMAGE_SCN_CNT_INITIALIZED_DATA | IMAGE_SCN_MEM_READ,
+            };
+        }
+        // the addresses of symbols reveal where the sections were placed
+        for symbol in &self.symbols {
+            if let Some(section) = sections.get_mut(&symbol.segment) {
+                let rva = symbol.address.wrapping_sub(self.load_address);
+                if section.virtual_address.is_none() && symbol.address != 0 && rva >= symbol.offset.into() {
+                    section.virtual_address = u32::try_from(rva - u64::from(symbol.offset)).ok();
+                }
+            }
+        }
+
+        let count = sections.keys().next_back().copied().unwrap_or(0);
+        let mut res = Vec::with_capacity(count.into());
+        let mut next_address = SECTION_ALIGNMENT;
+        for index in 1..=count {
+            // sections missing from the map are left empty to keep the numbering intact
+            let mut section = sections.remove(&index).unwrap_or_default();
+            let address = *section.virtual_address.get_or_insert(next_address);
+            next_address =
+                div_ceil(address + section.virtual_size.max(1), SECTION_ALIGNMENT) * SECTION_ALIGNMENT;
+            res.push(section);
+        }
+        res
+    }
+
+    fn modules(&self, sections: &[Section]) -> Vec<ModuleBuilder> {
+        let mut by_offset: Vec<&MapSymbol> = self.symbols.iter().filter(|sym| sym.segment != 0).collect();
+        by_offset.sort_by_key(|sym| (sym.segment, sym.offset));
+
+        let mut functions: BTreeMap<&str, Vec<(&MapSymbol, u32)>> = BTreeMap::new();
+        for (i, symbol) in by_offset.iter().enumerate() {
+            if !symbol.is_function {
+                continue;
+            }
+            // functions are assumed to span until the next symbol of the section
+            let end = by_offset[i + 1..]
+                .iter()
+                .find(|next| next.segment != symbol.segment || next.offset > symbol.offset)
+                .filter(|next| next.segment == symbol.segment)
+                .map(|next| next.offset)
+                .or_else(|| Some(sections.get(usize::from(symbol.segment) - 1)?.virtual_size))
+                .unwrap_or(symbol.offset);
+            let code_size = end.saturating_sub(symbol.offset);
+            functions
+                .entry(&symbol.object)
+                .or_default()
+                .push((symbol, code_size));
+        }
+
+        (0..)
+            .zip(functions)
+            .map(|(index, (object, functions))| {
+                let (first, size) = functions[0];
+                let characteristics = IMAGE_SCN_CNT_CODE | IMAGE_SCN_MEM_EXECUTE | IMAGE_SCN_MEM_READ;
+                let contrib =
+                    SectionContrib::new(first.segment, first.offset as i32, size, characteristics, index);
+                let mut module = ModuleBuilder::new(object.to_owned(), object.to_owned(), contrib);
+                for (symbol, code_size) in functions {
+                    let proc = Procedure {
+                        parent: None,
+                        end: SymbolOffset(0),
+                        next: None,
+                        code_size,
+                        dbg_start_offset: 0,
+                        dbg_end_offset: code_size,
+                        // there is no type information in map files
+                        function_type: BuiltinType::NotTranslated.into(),
+                        code_offset: DataRegionOffset::new(symbol.offset, symbol.segment),
+                        properties: ProcedureProperties::new(),
+                        name: StrBuf::new(symbol.name.clone()),
+                    };
+                    module.add_procedure(proc, !symbol.is_static);
+                }
+                module
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Default)]
+struct Section {
+    name: String,
+    virtual_address: Option<u32>,
+    virtual_size: u32,
+    characteristics: u32,
+}
+
+impl Section {
+    fn header(&self) -> SectionHeader {
+        let mut name = [0; 8];
+        let len = self.name.len().min(name.len());
+        name[..len].copy_from_slice(&self.name.as_bytes()[..len]);
+        SectionHeader {
+            name,
+            virtual_size: self.virtual_size,
+            virtual_address: self.virtual_address.unwrap_or_default(),
+            size_of_raw_data: 0,
+            pointer_to_raw_data: 0,
+            pointer_to_relocations: 0,
+            pointer_to_line_numbers: 0,
+            number_of_relocations: 0,
+            number_of_line_numbers: 0,
+            characteristics: self.characteristics,
+        }
+    }
+}
+
+fn parse_address(str: &str) -> Result<(u16, u32)> {
+    let (segment, offset) = str.split_once(':').ok_or_else(malformed)?;
+    let segment = u16::from_str_radix(segment, 16).map_err(|_| malformed())?;
+    let offset = u32::from_str_radix(offset, 16).map_err(|_| malformed())?;
+    Ok((segment, offset))
+}
+
+fn parse_contribution(line: &str) -> Result<MapContribution> {
+    let mut parts = line.split_whitespace();
+    let (segment, offset) = parse_address(parts.next().ok_or_else(malformed)?)?;
+    let length = parts
+        .next()
+        .and_then(|len| len.strip_suffix('H'))
+        .and_then(|len| u32::from_str_radix(len, 16).ok())
+        .ok_or_else(malformed)?;
+    let name = parts.next().ok_or_else(malformed)?.to_owned();
+    let class = parts.next().unwrap_or_default().to_owned();
+    Ok(MapContribution {
+        segment,
+        offset,
+        length,
+        name,
+        class,
+    })
+}
+
+// e.g. `0001:00000010       main       0000000140001010 f   main.obj`
+fn parse_symbol(line: &str, is_static: bool) -> Result<MapSymbol> {
+    let mut parts = line.split_whitespace();
+    let (segment, offset) = parse_address(parts.next().ok_or_else(malformed)?)?;
+    let name = parts.next().ok_or_else(malformed)?.to_owned();
+    let address = parts
+        .next()
+        .and_then(|addr| u64::from_str_radix(addr, 16).ok())
+        .ok_or_else(malformed)?;
+    let rest: Vec<&str> = parts.collect();
+    let (object, flags) = rest.split_last().ok_or_else(malformed)?;
+    Ok(MapSymbol {
+        segment,
+        offset,
+        name,
+        address,
+        is_function: flags.contains(&"f"),
+        is_static,
+        object: (*object).to_owned(),
+    })
+}
+
+fn malformed() -> Error {
+    Error::MalformedInput("map file")
+}
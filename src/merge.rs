@@ -0,0 +1,144 @@
+use std::collections::HashMap;
+use std::io;
+
+use declio::Encode;
+
+use crate::builders::PdbBuilder;
+use crate::codeview::types::{IdRecord, TypeRecord};
+use crate::codeview::{IndexKind, RawRecord};
+use crate::result::Result;
+use crate::types::FIRST_NON_BUILTIN_TYPE;
+use crate::{constants, PdbFile, TypeIndex};
+
+// folds the type streams of many objects or PDBs into the TPI and IPI of a single builder,
+// records are rewritten to the merged indices and only added when an identical one is not there yet
+#[derive(Debug, Default)]
+pub struct TypeMerger {
+    types: HashMap<Vec<u8>, TypeIndex>,
+    // definitions of the same user-defined type can differ between objects, the first one wins
+    unique_names: HashMap<(u16, bool, String), TypeIndex>,
+    ids: HashMap<Vec<u8>, TypeIndex>,
+}
+
+// the merged index of every record of a source stream, in stream order
+#[derive(Debug, Default)]
+pub struct MergedIndices {
+    pub types: Vec<u32>,
+    pub ids: Vec<u32>,
+}
+
+impl MergedIndices {
+    // indices that are not defined by the source are left unchanged
+    pub fn remap(&self, kind: IndexKind, index: u32) -> u32 {
+        match kind {
+            IndexKind::Type => lookup(&self.types, index),
+            IndexKind::Id => lookup(&self.ids, index),
+        }
+    }
+}
+
+impl TypeMerger {
+    // the indices of the record are expected to be merged already
+    pub fn add_type(&mut self, builder: &mut PdbBuilder, record: TypeRecord) -> Result<TypeIndex> {
+        let unique_name = unique_name_key(&record);
+        if let Some(index) = unique_name.as_ref().and_then(|key| self.unique_names.get(key)) {
+            return Ok(*index);
+        }
+        let mut bytes = vec![];
+        record.encode((), &mut bytes)?;
+        if let Some(index) = self.types.get(&bytes) {
+            return Ok(*index);
+        }
+
+        let index = builder.tpi().insert("", &record)?;
+        self.types.insert(bytes, index);
+        if let Some(key) = unique_name {
+            self.unique_names.insert(key, index);
+        }
+        Ok(index)
+    }
+
+    // the indices of the record are expected to be merged already
+    pub fn add_id(&mut self, builder: &mut PdbBuilder, record: IdRecord) -> Result<TypeIndex> {
+        let mut bytes = vec![];
+        record.encode((), &mut bytes)?;
+        if let Some(index) = self.ids.get(&bytes) {
+            return Ok(*index);
+        }
+        let index = builder.ipi().insert("", &record)?;
+        self.ids.insert(bytes, index);
+        Ok(index)
+    }
+
+    // merges the records of an object file, which share a single index space between types and IDs
+    pub fn merge_object(
+        &mut self,
+        builder: &mut PdbBuilder,
+        records: &[RawRecord],
+    ) -> Result<MergedIndices> {
+        let mut indices: Vec<u32> = Vec::with_capacity(records.len());
+        for raw in records {
+            let mut remap = |_: IndexKind, index| lookup(&indices, index);
+            let index = match raw.kind {
+                constants::LF_FUNC_ID..=constants::LF_UDT_MOD_SRC_LINE => {
+                    let mut record: IdRecord = raw.decode_as()?;
+                    record.remap_indices(&mut remap);
+                    self.add_id(builder, record)?
+                }
+                _ => {
+                    let mut record: TypeRecord = raw.decode_as()?;
+                    record.remap_indices(&mut remap);
+                    self.add_type(builder, record)?
+                }
+            };
+            indices.push(index.into());
+        }
+        Ok(MergedIndices {
+            types: indices.clone(),
+            ids: indices,
+        })
+    }
+
+    // merges the TPI and IPI of another PDB
+    pub fn merge_pdb<R>(&mut self, builder: &mut PdbBuilder, pdb: &mut PdbFile<R>) -> Result<MergedIndices>
+    where
+        R: io::Read + io::Seek,
+    {
+        let mut res = MergedIndices::default();
+        for raw in pdb.get_raw_tpi()?.records() {
+            let mut record: TypeRecord = raw.decode_as()?;
+            record.remap_indices(&mut |kind, index| res.remap(kind, index));
+            let index = self.add_type(builder, record)?;
+            res.types.push(index.into());
+        }
+        for raw in pdb.get_raw_ipi()?.records() {
+            let mut record: IdRecord = raw.decode_as()?;
+            record.remap_indices(&mut |kind, index| res.remap(kind, index));
+            let index = self.add_id(builder, record)?;
+            res.ids.push(index.into());
+        }
+        Ok(res)
+    }
+}
+
+fn unique_name_key(record: &TypeRecord) -> Option<(u16, bool, String)> {
+    let (kind, properties, unique_name) = match record {
+        TypeRecord::Class(class) => (constants::LF_CLASS, class.properties, &class.unique_name),
+        TypeRecord::Struct(class) => (constants::LF_STRUCTURE, class.properties, &class.unique_name),
+        TypeRecord::Interface(class) => (constants::LF_INTERFACE, class.properties, &class.unique_name),
+        TypeRecord::Union(union) => (constants::LF_UNION, union.properties, &union.unique_name),
+        TypeRecord::Enum(enum_) => (constants::LF_ENUM, enum_.properties, &enum_.unique_name),
+        _ => return None,
+    };
+    properties
+        .has_unique_name()
+        .then(|| (kind, properties.is_forward_ref(), unique_name.as_ref().to_owned()))
+}
+
+fn lookup(indices: &[u32], index: u32) -> u32 {
+    index
+        .checked_sub(FIRST_NON_BUILTIN_TYPE)
+        .and_then(|slot| indices.get(slot as usize))
+        .copied()
+        .unwrap_or(index)
+}
@@ -0,0 +1,91 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::io;
+use std::time::{Duration, Instant};
+
+use declio::Decode;
+
+use crate::codeview::PrefixedRecord;
+use crate::diagnostics::ParseLimits;
+use crate::result::Result;
+
+// counters gathered while reading a PDB with `ParseOptions::metrics` or while committing one, e.g. to
+// catch performance regressions, the streams read are phases named after them
+#[derive(Debug, Default, Clone)]
+pub struct Metrics {
+    // the number of CodeView records decoded by their kind
+    pub records: BTreeMap<u16, u64>,
+    // the bytes read from or written to the streams by their names
+    pub stream_bytes: BTreeMap<String, u64>,
+    pub phases: BTreeMap<&'static str, Duration>,
+}
+
+impl Metrics {
+    pub fn total_records(&self) -> u64 {
+        self.records.values().sum()
+    }
+
+    pub fn total_time(&self) -> Duration {
+        self.phases.values().sum()
+    }
+
+    pub(crate) fn add_record(&mut self, kind: u16) {
+        *self.records.entry(kind).or_default() += 1;
+    }
+
+    pub(crate) fn add_stream_bytes(&mut self, stream: &str, bytes: u64) {
+        match self.stream_bytes.get_mut(stream) {
+            Some(total) => *total += bytes,
+            None => {
+                self.stream_bytes.insert(stream.to_owned(), bytes);
+            }
+        }
+    }
+
+    pub(crate) fn add_phase(&mut self, phase: &'static str, start: Stopwatch) {
+        *self.phases.entry(phase).or_default() += start.elapsed();
+    }
+}
+
+// the start of a phase, there's no clock on wasm32-unknown-unknown where `Instant::now` panics, so
+// phases take no time there
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Stopwatch(Option<Instant>);
+
+impl Stopwatch {
+    const HAS_CLOCK: bool = !cfg!(all(target_arch = "wasm32", target_os = "unknown"));
+
+    pub fn start() -> Self {
+        Self(Self::HAS_CLOCK.then(Instant::now))
+    }
+
+    fn elapsed(&self) -> Duration {
+        self.0.map_or(Duration::ZERO, |start| start.elapsed())
+    }
+}
+
+// decodes length-prefixed records and counts them when metrics are collected
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct RecordDecoder<'a> {
+    limits: ParseLimits,
+    metrics: Option<&'a RefCell<Metrics>>,
+}
+
+impl<'a> RecordDecoder<'a> {
+    pub fn new(limits: ParseLimits, metrics: Option<&'a RefCell<Metrics>>) -> Self {
+        Self { limits, metrics }
+    }
+
+    pub fn decode<A, R>(&self, reader: &mut R) -> Result<A>
+    where
+        A: Decode,
+        R: io::Read,
+    {
+        let record = PrefixedRecord::decode_with(reader, &self.limits, |kind| {
+            if let Some(metrics) = self.metrics {
+                metrics.borrow_mut().add_record(kind);
+            }
+        })?;
+        Ok(record.into_inner())
+    }
+}
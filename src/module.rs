@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::io::{self, Read, Write};
 
 use declio::ctx::{Endian, Len};
@@ -6,11 +7,17 @@ use declio::{magic_bytes, Decode, Encode, EncodedSize};
 use derive_getters::Getters;
 use modular_bitfield::prelude::*;
 
+use crate::builders::record_size;
 use crate::codeview::symbols::SymbolRecord;
-use crate::codeview::{DataRegionOffset, PrefixedRecord};
-use crate::msf::MsfStreamWriter;
-use crate::result::Result;
-use crate::{codecs, constants, impl_bitfield_codecs, impl_bitfield_specifier_codecs};
+use crate::codeview::{DataRegionOffset, PrefixedRecord, RECORD_ALIGNMENT};
+use crate::diagnostics::ReadContext;
+use crate::result::{Error, Result};
+use crate::strings::Strings;
+use crate::symbols::RawSymbols;
+use crate::utils::{align_to, StrBuf};
+use crate::{
+    codecs, constants, impl_bitfield_codecs, impl_bitfield_specifier_codecs, u32_enum, StringOffset, SymbolOffset
+};
 
 magic_bytes! {
     #[derive(Debug)]
@@ -35,25 +42,75 @@ impl Module {
         }
     }
 
-    pub(crate) fn read<R>(mut source: R, layout: &ModuleLayout) -> Result<Self>
+    pub fn with_global_refs(mut self, refs: &[SymbolOffset]) -> Self {
+        self.global_ref_bytes = refs
+            .iter()
+            .flat_map(|&offset| u32::from(offset).to_le_bytes())
+            .collect();
+        self
+    }
+
+    // the offsets of the records in the global symbol stream referenced by the module
+    pub fn global_refs(&self) -> Vec<SymbolOffset> {
+        self.global_ref_bytes
+            .chunks_exact(4)
+            .map(|bytes| SymbolOffset::from(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])))
+            .collect()
+    }
+
+    // the records along with their offsets in the module stream, like the ones S_PROCREF records point at
+    pub fn symbols_with_offsets(&self) -> impl Iterator<Item = (SymbolOffset, &SymbolRecord)> {
+        // past the signature
+        let mut offset = 4;
+        self.symbols.iter().map(move |symbol| {
+            let res = (SymbolOffset(offset), symbol);
+            offset += record_size(symbol);
+            res
+        })
+    }
+
+    pub(crate) fn read<R>(mut source: R, layout: &ModuleLayout, ctx: &mut ReadContext) -> Result<Self>
     where
         R: io::Read,
     {
         let mut sym_stream = source.by_ref().take(layout.sym_bytes.into());
         DebugSectionSignature::decode((), &mut sym_stream)?;
 
-        let mut symbols = vec![];
-        while sym_stream.limit() > 0 {
-            symbols.push(PrefixedRecord::decode(&mut sym_stream)?.into_inner());
-        }
+        let decoder = ctx.records();
+        let symbols = ctx.read_records("module symbols", 4, &mut sym_stream, |stream| {
+            decoder.decode(stream)
+        })?;
+        Self::read_debug_info(symbols, source, layout, ctx)
+    }
+
+    // like `read`, but skips over the symbols, e.g. for callers reading them as raw records
+    pub(crate) fn read_without_symbols<R>(
+        mut source: R,
+        layout: &ModuleLayout,
+        ctx: &mut ReadContext,
+    ) -> Result<Self>
+    where
+        R: io::Read,
+    {
+        io::copy(
+            &mut source.by_ref().take(layout.sym_bytes.into()),
+            &mut io::sink(),
+        )?;
+        Self::read_debug_info(vec![], source, layout, ctx)
+    }
 
+    fn read_debug_info<R>(
+        symbols: Vec<SymbolRecord>,
+        mut source: R,
+        layout: &ModuleLayout,
+        ctx: &mut ReadContext,
+    ) -> Result<Self>
+    where
+        R: io::Read,
+    {
         let c11_bytes = <Bytes>::decode(Len(layout.c11_bytes as usize), &mut source)?.into_vec();
 
-        let mut c13_records = vec![];
-        let mut c13_stream = source.by_ref().take(layout.c13_bytes.into());
-        while c13_stream.limit() > 0 {
-            c13_records.push(DebugSubsectionEntry::decode((), &mut c13_stream)?);
-        }
+        let c13_records = Self::read_subsections(&mut source, layout, ctx)?;
 
         let global_ref_bytes = <Bytes<u32>>::decode(constants::ENDIANESS, &mut source)?.into_vec();
 
@@ -66,42 +123,317 @@ impl Module {
         Ok(res)
     }
 
-    pub(crate) fn write<S, const N: u32>(self, sink: &mut MsfStreamWriter<S, N>) -> Result<ModuleLayout>
+    // the source file paths referenced by the checksums subsection
+    pub fn source_files<'a>(&self, strings: &'a Strings) -> Result<Vec<&'a str>> {
+        let mut res = vec![];
+        for entry in &self.c13_records {
+            if let DebugSubsectionRecord::FileChecksums { entries } = entry.decoded()? {
+                for checksum in entries {
+                    let path = strings
+                        .get(StringOffset(checksum.file_name_offset))
+                        .ok_or(Error::UnsupportedFeature("file name missing from string table"))?;
+                    res.push(path);
+                }
+            }
+        }
+        Ok(res)
+    }
+
+    // the entries of the line tables resolved to their source files
+    pub fn lines<'a>(&self, strings: &'a Strings) -> Result<Vec<SourceLine<'a>>> {
+        let entries = self
+            .c13_records
+            .iter()
+            .map(|entry| (entry.record_type, &entry.data[..]));
+        resolve_lines(entries, strings, DebugSubsectionRecordType::Lines, |_| true)
+    }
+
+    // the line tables of managed code, the offsets are IL offsets relative to the start of the method
+    pub fn il_lines<'a>(&self, strings: &'a Strings) -> Result<Vec<SourceLine<'a>>> {
+        let entries = self
+            .c13_records
+            .iter()
+            .map(|entry| (entry.record_type, &entry.data[..]));
+        resolve_lines(entries, strings, DebugSubsectionRecordType::ILLines, |_| true)
+    }
+
+    // the S_ANNOTATION records of the module along with the procedures that contain them
+    pub fn annotations(&self) -> Vec<Annotation<'_>> {
+        let procs: Vec<_> = self
+            .symbols
+            .iter()
+            .filter_map(|symbol| match symbol {
+                SymbolRecord::Proc(proc)
+                | SymbolRecord::GlobalProc(proc)
+                | SymbolRecord::ProcId(proc)
+                | SymbolRecord::GlobalProcId(proc)
+                | SymbolRecord::DPCProc(proc)
+                | SymbolRecord::DPCProcId(proc) => Some(proc),
+                _ => None,
+            })
+            .collect();
+
+        let mut res = vec![];
+        for symbol in &self.symbols {
+            if let SymbolRecord::Annotation { code_offset, strings } = symbol {
+                let function = procs.iter().find(|proc| {
+                    proc.code_offset.segment == code_offset.segment
+                        && (proc.code_offset.offset..proc.code_offset.offset + proc.code_size)
+                            .contains(&code_offset.offset)
+                });
+                res.push(Annotation {
+                    function: function.map(|proc| proc.name.as_ref()),
+                    offset: code_offset,
+                    strings: strings.iter().map(StrBuf::as_ref).collect(),
+                });
+            }
+        }
+        res
+    }
+
+    pub(crate) fn read_subsections<R>(
+        source: &mut R,
+        layout: &ModuleLayout,
+        ctx: &mut ReadContext,
+    ) -> Result<Vec<DebugSubsectionEntry>>
     where
-        S: io::Write + io::Seek,
+        R: io::Read,
     {
-        DebugSectionSignature.encode((), sink)?;
+        let limits = ctx.limits();
+        let budget = ctx.budget();
+        let mut c13_stream = source.take(layout.c13_bytes.into());
+        let start = u64::from(layout.sym_bytes) + u64::from(layout.c11_bytes);
+        ctx.read_records("module C13 lines", start, &mut c13_stream, |stream| {
+            let record_type = DebugSubsectionRecordType::decode((), stream)?;
+            let len = u32::decode(constants::ENDIANESS, stream)?;
+            limits.check_record_size(len)?;
+            budget.allocate(len.into())?;
+            let data = <Bytes>::decode(Len(len as usize), stream)?.into_vec();
+            Ok(DebugSubsectionEntry { record_type, data })
+        })
+    }
+
+    // encodes the module stream on its own, for modules to be encoded in parallel before being laid out
+    pub(crate) fn encode_stream(self) -> Result<(Vec<u8>, ModuleLayout)> {
+        let mut sink = vec![];
+        DebugSectionSignature.encode((), &mut sink)?;
         for symbol in self.symbols {
-            PrefixedRecord(symbol).encode((), sink)?;
+            PrefixedRecord(symbol).encode((), &mut sink)?;
         }
-        let sym_bytes = sink.position();
+        let sym_bytes = sink.len() as u32;
         sink.write_all(&self.c11_bytes)?;
-        let start = sink.position();
+        let start = sink.len() as u32;
         for rec in self.c13_records {
-            rec.encode((), sink)?;
+            rec.encode((), &mut sink)?;
         }
 
-        let c13_bytes = sink.position() - start;
-        Bytes::<u32>::from(&self.global_ref_bytes).encode(constants::ENDIANESS, sink)?;
+        let c13_bytes = sink.len() as u32 - start;
+        Bytes::<u32>::from(&self.global_ref_bytes).encode(constants::ENDIANESS, &mut sink)?;
 
         let layout = ModuleLayout {
             sym_bytes,
             c11_bytes: self.c11_bytes.len() as u32,
             c13_bytes,
         };
-        Ok(layout)
+        Ok((sink, layout))
     }
 }
 
-#[derive(Debug, Encode, Decode, EncodedSize)]
+// the debug info of a module kept as bytes, with the extents of its subsections recorded up front so
+// that the symbols or a single subsection are decoded only when asked for, e.g. to get the lines of one
+// function without decoding those of the whole module
+#[derive(Debug)]
+pub struct LazyModule {
+    bytes: Vec<u8>,
+    sym_bytes: u32,
+    subsections: Vec<SubsectionExtent>,
+}
+
+// the position of a C13 subsection in the module stream, past its kind and length
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SubsectionExtent {
+    pub record_type: DebugSubsectionRecordType,
+    pub offset: u32,
+    pub size: u32,
+}
+
+impl LazyModule {
+    pub(crate) fn read<R>(source: R, layout: &ModuleLayout, ctx: &mut ReadContext) -> Result<Self>
+    where
+        R: io::Read,
+    {
+        let c13_start = layout.sym_bytes + layout.c11_bytes;
+        let size = u64::from(c13_start) + u64::from(layout.c13_bytes);
+        let limits = ctx.limits();
+        ctx.budget().allocate(size)?;
+        let mut bytes = Vec::with_capacity(size as usize);
+        source.take(size).read_to_end(&mut bytes)?;
+        if bytes.len() as u64 != size {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+
+        let mut c13_stream = (&bytes[c13_start as usize..]).take(layout.c13_bytes.into());
+        let subsections =
+            ctx.read_records("module C13 lines", c13_start.into(), &mut c13_stream, |stream| {
+                let header_offset = c13_start + layout.c13_bytes - stream.limit() as u32;
+                let record_type = DebugSubsectionRecordType::decode((), stream)?;
+                let size = u32::decode(constants::ENDIANESS, stream)?;
+                limits.check_record_size(size)?;
+                if io::copy(&mut stream.take(size.into()), &mut io::sink())? != u64::from(size) {
+                    return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+                }
+                Ok(SubsectionExtent {
+                    record_type,
+                    offset: header_offset + 8,
+                    size,
+                })
+            })?;
+
+        Ok(Self {
+            bytes,
+            sym_bytes: layout.sym_bytes,
+            subsections,
+        })
+    }
+
+    pub fn subsections(&self) -> &[SubsectionExtent] {
+        &self.subsections
+    }
+
+    // the records of the symbol substream, walked without decoding them
+    pub fn raw_symbols(&self) -> RawSymbols<'_> {
+        RawSymbols::new(&self.bytes[..self.sym_bytes as usize], 4)
+    }
+
+    pub fn symbols(&self) -> Result<Vec<SymbolRecord>> {
+        self.raw_symbols()
+            .map(|res| res.and_then(|(_, symbol)| symbol.decode()))
+            .collect()
+    }
+
+    pub fn subsection_data(&self, extent: &SubsectionExtent) -> &[u8] {
+        &self.bytes[extent.offset as usize..(extent.offset + extent.size) as usize]
+    }
+
+    pub fn decode_subsection(&self, extent: &SubsectionExtent) -> Result<DebugSubsectionRecord> {
+        let data = self.subsection_data(extent);
+        Ok(DebugSubsectionRecord::decode(extent.record_type, &mut &data[..])?)
+    }
+
+    pub fn lines<'a>(&self, strings: &'a Strings) -> Result<Vec<SourceLine<'a>>> {
+        resolve_lines(self.entries(), strings, DebugSubsectionRecordType::Lines, |_| {
+            true
+        })
+    }
+
+    // the lines of the fragments covering a location, like the start of a procedure, the other fragments
+    // are left undecoded
+    pub fn lines_at<'a>(
+        &self,
+        strings: &'a Strings,
+        offset: DataRegionOffset,
+    ) -> Result<Vec<SourceLine<'a>>> {
+        resolve_lines(
+            self.entries(),
+            strings,
+            DebugSubsectionRecordType::Lines,
+            |header| {
+                header.reloc.segment == offset.segment
+                    && (header.reloc.offset..header.reloc.offset + header.code_size)
+                        .contains(&offset.offset)
+            },
+        )
+    }
+
+    fn entries(&self) -> impl Iterator<Item = (DebugSubsectionRecordType, &[u8])> {
+        self.subsections
+            .iter()
+            .map(|extent| (extent.record_type, self.subsection_data(extent)))
+    }
+}
+
+// resolves the line fragments of a kind to their source files, fragments rejected by `covers` are
+// skipped before decoding their entries
+fn resolve_lines<'a, 'b>(
+    entries: impl Iterator<Item = (DebugSubsectionRecordType, &'b [u8])>,
+    strings: &'a Strings,
+    kind: DebugSubsectionRecordType,
+    covers: impl Fn(&LineFragmentHeader) -> bool,
+) -> Result<Vec<SourceLine<'a>>> {
+    // lines refer to files by the offset of their entry in the checksums subsection
+    let mut files = HashMap::new();
+    let mut fragments = vec![];
+    for (record_type, data) in entries {
+        if record_type == kind {
+            if !covers(&LineFragmentHeader::decode((), &mut &data[..])?) {
+                continue;
+            }
+        } else if record_type != DebugSubsectionRecordType::FileChecksums {
+            continue;
+        }
+        match DebugSubsectionRecord::decode(record_type, &mut &data[..])? {
+            DebugSubsectionRecord::FileChecksums { entries } => {
+                let mut offset = 0;
+                for checksum in entries {
+                    let path = strings
+                        .get(StringOffset(checksum.file_name_offset))
+                        .ok_or(Error::UnsupportedFeature("file name missing from string table"))?;
+                    files.insert(offset, path);
+                    offset += align_to(6 + checksum.bytes.len(), RECORD_ALIGNMENT) as u32;
+                }
+            }
+            DebugSubsectionRecord::Lines { header, entries }
+            | DebugSubsectionRecord::ILLines { header, entries } => fragments.push((header, entries)),
+        }
+    }
+
+    let mut res = vec![];
+    for (header, entries) in fragments {
+        for entry in entries {
+            let file = files.get(&entry.name_index).ok_or(Error::UnsupportedFeature(
+                "line entry referencing an unknown file",
+            ))?;
+            // columns are only there when the fragment has them
+            let mut columns = entry.columns.iter();
+            for line in entry.line_numbers {
+                res.push(SourceLine {
+                    file,
+                    line: line.flags & 0xFFFFFF,
+                    columns: columns.next().map(|col| (col.start_col, col.end_col)),
+                    offset: DataRegionOffset::new(header.reloc.offset + line.offset, header.reloc.segment),
+                });
+            }
+        }
+    }
+    Ok(res)
+}
+
+#[derive(Debug)]
+pub struct Annotation<'a> {
+    // the name of the procedure containing the annotated code
+    pub function: Option<&'a str>,
+    pub offset: &'a DataRegionOffset,
+    pub strings: Vec<&'a str>,
+}
+
+#[derive(Debug)]
+pub struct SourceLine<'a> {
+    pub file: &'a str,
+    pub line: u32,
+    // the start and end column, the end is zero when unknown
+    pub columns: Option<(u16, u16)>,
+    pub offset: DataRegionOffset,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, EncodedSize)]
 #[declio(ctx_is = "constants::ENDIANESS")]
 pub struct ModuleLayout {
-    sym_bytes: u32,
-    c11_bytes: u32,
-    c13_bytes: u32,
+    pub(crate) sym_bytes: u32,
+    pub(crate) c11_bytes: u32,
+    pub(crate) c13_bytes: u32,
 }
 
-#[derive(Debug, Encode, Decode, EncodedSize)]
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, EncodedSize)]
 #[declio(ctx_is = "constants::ENDIANESS")]
 pub struct DebugSubsectionEntry {
     pub record_type: DebugSubsectionRecordType,
@@ -116,27 +448,26 @@ impl DebugSubsectionEntry {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, BitfieldSpecifier)]
-#[bits = 32]
-pub enum DebugSubsectionRecordType {
-    Symbols = 0xf1,
-    Lines = 0xf2,
-    StringTable = 0xf3,
-    FileChecksums = 0xf4,
-    FrameData = 0xf5,
-    InlineeLines = 0xf6,
-    CrossScopeImports = 0xf7,
-    CrossScopeExports = 0xf8,
-    ILLines = 0xf9,
-    FuncMDTokenMap = 0xfa,
-    TypeMDTokenMap = 0xfb,
-    MergedAssemblyInput = 0xfc,
-    CoffSymbolRVA = 0xfd,
-}
-
-impl_bitfield_specifier_codecs!(DebugSubsectionRecordType);
-
-#[derive(Debug, Encode, Decode, EncodedSize)]
+u32_enum! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum DebugSubsectionRecordType {
+        Symbols = 0xf1,
+        Lines = 0xf2,
+        StringTable = 0xf3,
+        FileChecksums = 0xf4,
+        FrameData = 0xf5,
+        InlineeLines = 0xf6,
+        CrossScopeImports = 0xf7,
+        CrossScopeExports = 0xf8,
+        ILLines = 0xf9,
+        FuncMDTokenMap = 0xfa,
+        TypeMDTokenMap = 0xfb,
+        MergedAssemblyInput = 0xfc,
+        CoffSymbolRVA = 0xfd,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, EncodedSize)]
 #[declio(ctx = "record_type: DebugSubsectionRecordType", id_expr = "record_type")]
 pub enum DebugSubsectionRecord {
     #[declio(id = "DebugSubsectionRecordType::Lines")]
@@ -153,9 +484,38 @@ pub enum DebugSubsectionRecord {
         #[declio(with = "codecs::padded_rem_list")]
         entries: Vec<FileChecksumEntry>,
     },
+    // laid out like native lines, but with IL offsets
+    #[declio(id = "DebugSubsectionRecordType::ILLines")]
+    ILLines {
+        header: LineFragmentHeader,
+        #[declio(
+            with = "codecs::padded_rem_list",
+            ctx = "(header.flags, constants::ENDIANESS)"
+        )]
+        entries: Vec<LineColumnEntry>,
+    },
 }
 
-#[derive(Debug, Encode, Decode, EncodedSize)]
+impl DebugSubsectionRecord {
+    pub fn record_type(&self) -> DebugSubsectionRecordType {
+        match self {
+            DebugSubsectionRecord::Lines { .. } => DebugSubsectionRecordType::Lines,
+            DebugSubsectionRecord::FileChecksums { .. } => DebugSubsectionRecordType::FileChecksums,
+            DebugSubsectionRecord::ILLines { .. } => DebugSubsectionRecordType::ILLines,
+        }
+    }
+
+    // encodes the record into an entry, padded to the record alignment like LLVM does
+    pub fn to_entry(&self) -> Result<DebugSubsectionEntry> {
+        let record_type = self.record_type();
+        let mut data = vec![];
+        self.encode(record_type, &mut data)?;
+        data.resize(align_to(data.len(), RECORD_ALIGNMENT), 0);
+        Ok(DebugSubsectionEntry { record_type, data })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, EncodedSize)]
 #[declio(ctx_is = "constants::ENDIANESS")]
 pub struct LineFragmentHeader {
     pub reloc: DataRegionOffset,
@@ -163,7 +523,7 @@ pub struct LineFragmentHeader {
     pub code_size: u32,
 }
 
-#[derive(Debug, Encode, Decode, EncodedSize)]
+#[derive(Debug, Clone, PartialEq, Eq, Encode, EncodedSize)]
 #[declio(ctx = "flags: LineFlags, endian: Endian")]
 pub struct LineColumnEntry {
     pub name_index: u32,
@@ -175,8 +535,45 @@ pub struct LineColumnEntry {
     pub columns: Vec<ColumnNumberEntry>,
 }
 
+// the line count comes from the file, so the lists grow with the entries actually read instead of
+// being allocated for the count up front, the subsection they are read from is bounded already
+impl Decode<(LineFlags, Endian)> for LineColumnEntry {
+    fn decode<R>((flags, endian): (LineFlags, Endian), reader: &mut R) -> Result<Self, declio::Error>
+    where
+        R: io::Read,
+    {
+        fn decode_list<A: Decode, R: io::Read>(
+            count: u32,
+            reader: &mut R,
+        ) -> Result<Vec<A>, declio::Error> {
+            let mut list = vec![];
+            for _ in 0..count {
+                list.push(A::decode((), reader)?);
+            }
+            Ok(list)
+        }
+
+        let name_index = u32::decode(endian, reader)?;
+        let num_lines = u32::decode(endian, reader)?;
+        let code_size = u32::decode(endian, reader)?;
+        let line_numbers = decode_list(num_lines, reader)?;
+        let columns = if flags.has_columns() {
+            decode_list(num_lines, reader)?
+        } else {
+            vec![]
+        };
+        Ok(Self {
+            name_index,
+            num_lines,
+            code_size,
+            line_numbers,
+            columns,
+        })
+    }
+}
+
 #[bitfield(bits = 16)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct LineFlags {
     pub has_columns: bool,
     #[skip]
@@ -185,21 +582,21 @@ pub struct LineFlags {
 
 impl_bitfield_codecs!(LineFlags);
 
-#[derive(Debug, Encode, Decode, EncodedSize)]
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, EncodedSize)]
 #[declio(ctx_is = "constants::ENDIANESS")]
 pub struct LineNumberEntry {
     pub offset: u32,
     pub flags: u32,
 }
 
-#[derive(Debug, Encode, Decode, EncodedSize)]
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, EncodedSize)]
 #[declio(ctx_is = "constants::ENDIANESS")]
 pub struct ColumnNumberEntry {
     pub start_col: u16,
     pub end_col: u16,
 }
 
-#[derive(Debug, Encode, Decode, EncodedSize)]
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, EncodedSize)]
 #[declio(ctx_is = "constants::ENDIANESS")]
 pub struct FileChecksumEntry {
     pub file_name_offset: u32,
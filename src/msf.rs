@@ -1,20 +1,21 @@
-use std::io::{self, Read};
+use std::cell::RefCell;
+use std::io::{self, Read, Seek, Write};
 
 use declio::{magic_bytes, Decode, Encode, EncodedSize};
 
+use crate::metrics::Metrics;
 use crate::result::Result;
 use crate::utils::div_ceil;
 use crate::{constants, BufMsfStream};
 
 pub(crate) const DEFAULT_BLOCK_SIZE: u32 = 4096;
-pub(crate) const EMPTY_BLOCK: &[u8] = &[0; DEFAULT_BLOCK_SIZE as usize];
 
 magic_bytes! {
     #[derive(Debug)]
     pub(crate) MsfHeader(b"Microsoft C/C++ MSF 7.00\r\n\x1aDS\0\0\0");
 }
 
-#[derive(Debug, Encode, Decode)]
+#[derive(Debug, PartialEq, Eq, Encode, Decode)]
 #[declio(ctx_is = "constants::ENDIANESS")]
 pub(crate) struct SuperBlock {
     pub magic: MsfHeader,
@@ -54,6 +55,8 @@ pub(crate) struct MsfStream<'a, R> {
     inner: R,
     position: u32,
     block_size: u32,
+    metrics: Option<&'a RefCell<Metrics>>,
+    index: Option<StreamIndex>,
 }
 
 impl<'a, R> MsfStream<'a, R> {
@@ -63,18 +66,45 @@ impl<'a, R> MsfStream<'a, R> {
             layout,
             position: 0,
             block_size,
+            metrics: None,
+            index: None,
         }
     }
 
+    // the index of the stream in the directory, for the errors of its readers
+    pub fn with_index(mut self, index: StreamIndex) -> Self {
+        self.index = Some(index);
+        self
+    }
+
+    pub fn index(&self) -> Option<StreamIndex> {
+        self.index
+    }
+
+    // the metrics that the reads of the stream are counted in
+    pub fn with_metrics(mut self, metrics: Option<&'a RefCell<Metrics>>) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    pub fn metrics(&self) -> Option<&'a RefCell<Metrics>> {
+        self.metrics
+    }
+
     pub fn length(&self) -> u32 {
         self.layout.byte_size
     }
 
-    pub fn is_eof(&self) -> bool {
-        self.layout.byte_size == self.position
+    pub fn position(&self) -> u32 {
+        self.position
     }
 }
 
+// every interval of `block_size` blocks starts with a data block followed by the two copies of the FPM
+pub(crate) fn is_fpm_block(block: u32, block_size: u32) -> bool {
+    matches!(block % block_size, 1 | 2)
+}
+
 impl<'a, R> io::Read for MsfStream<'a, R>
 where
     R: io::Read + io::Seek,
@@ -98,6 +128,33 @@ where
     }
 }
 
+// overwrites the bytes of the stream in place, it can't grow past its size
+impl<'a, R> io::Write for MsfStream<'a, R>
+where
+    R: io::Write + io::Seek,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let rem_block = self.block_size - self.position % self.block_size;
+        let rem_stream = self.layout.byte_size - self.position;
+        if rem_stream == 0 {
+            return Ok(0);
+        }
+        let file_pos = self.layout.blocks[(self.position / self.block_size) as usize];
+        let offset: u64 = (self.position % self.block_size).into();
+        self.inner.seek(io::SeekFrom::Start(
+            file_pos.to_file_pos(self.block_size) + offset,
+        ))?;
+        let len = rem_stream.min(rem_block).min(buf.len() as u32);
+        let written = self.inner.write(&buf[..len as usize])?;
+        self.position += written as u32;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
 impl<'a, R> io::Seek for MsfStream<'a, R>
 where
     R: io::Seek,
@@ -124,16 +181,138 @@ where
     }
 }
 
+// how the unused bytes of the blocks of a written PDB are filled, that is the ends of the last blocks of
+// streams, the FPM blocks and the blocks skipped to align streams
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StreamPadding {
+    #[default]
+    Zeros,
+    // like the 0xDD of debug heaps, to tell padding apart from data
+    Fill(u8),
+    // skipped with a seek instead of written out, which leaves holes in files on file systems
+    // supporting them and spares the I/O of the fill
+    Sparse,
+}
+
+// the file a PDB is written to, keeps track of its position to spare seeks for it
+pub(crate) struct MsfSink<S> {
+    inner: S,
+    padding: StreamPadding,
+    // the number of blocks the start of every stream is aligned to
+    alignment: u32,
+    position: u64,
+    // the end of the bytes actually written, the file has to be extended past it when it ends with a hole
+    written_end: u64,
+    padded_end: u64,
+    // the blocks skipped to align streams, which are left free
+    unused_blocks: Vec<u32>,
+}
+
+impl<S> MsfSink<S>
+where
+    S: io::Write + io::Seek,
+{
+    pub fn new(mut inner: S, padding: StreamPadding, alignment: u32) -> io::Result<Self> {
+        let position = inner.stream_position()?;
+        Ok(Self {
+            inner,
+            padding,
+            alignment: alignment.max(1),
+            position,
+            written_end: position,
+            padded_end: position,
+            unused_blocks: vec![],
+        })
+    }
+
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    pub fn unused_blocks(&self) -> &[u32] {
+        &self.unused_blocks
+    }
+
+    pub fn pad(&mut self, len: u64) -> io::Result<()> {
+        let fill = match self.padding {
+            StreamPadding::Zeros => 0,
+            StreamPadding::Fill(byte) => byte,
+            StreamPadding::Sparse => {
+                self.seek(io::SeekFrom::Current(len as i64))?;
+                self.padded_end = self.padded_end.max(self.position);
+                return Ok(());
+            }
+        };
+        let block = [fill; DEFAULT_BLOCK_SIZE as usize];
+        let mut rem = len;
+        while rem > 0 {
+            let chunk = rem.min(block.len() as u64);
+            self.write_all(&block[..chunk as usize])?;
+            rem -= chunk;
+        }
+        Ok(())
+    }
+
+    // skips blocks until the first block of a stream about to be written is aligned
+    fn align_stream(&mut self, block_size: u32) -> io::Result<()> {
+        loop {
+            let block = (self.position / u64::from(block_size)) as u32;
+            if block % self.alignment == 0 && !is_fpm_block(block, block_size) {
+                return Ok(());
+            }
+            if !is_fpm_block(block, block_size) {
+                self.unused_blocks.push(block);
+            }
+            self.pad(block_size.into())?;
+        }
+    }
+
+    // writes the last byte of a trailing hole, which otherwise would not be part of the file
+    pub fn finish(mut self) -> io::Result<S> {
+        if self.padded_end > self.written_end {
+            self.inner.seek(io::SeekFrom::Start(self.padded_end - 1))?;
+            self.inner.write_all(&[0])?;
+        }
+        Ok(self.inner)
+    }
+}
+
+impl<S> io::Write for MsfSink<S>
+where
+    S: io::Write,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.position += written as u64;
+        self.written_end = self.written_end.max(self.position);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<S> io::Seek for MsfSink<S>
+where
+    S: io::Seek,
+{
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        self.position = self.inner.seek(pos)?;
+        Ok(self.position)
+    }
+}
+
 pub(crate) type DefaultMsfStreamWriter<'a, S> = MsfStreamWriter<'a, S, DEFAULT_BLOCK_SIZE>;
 
 pub(crate) struct MsfStreamWriter<'a, S, const BLOCK_SIZE: u32> {
-    sink: &'a mut S,
+    sink: &'a mut MsfSink<S>,
     blocks: Vec<BlockIndex>,
     position: u32,
 }
 
 impl<'a, S, const BLOCK_SIZE: u32> MsfStreamWriter<'a, S, BLOCK_SIZE> {
-    pub fn new(sink: &'a mut S) -> io::Result<Self> {
+    pub fn new(sink: &'a mut MsfSink<S>) -> io::Result<Self> {
         let res = Self {
             sink,
             blocks: vec![],
@@ -150,17 +329,13 @@ impl<'a, S, const BLOCK_SIZE: u32> MsfStreamWriter<'a, S, BLOCK_SIZE> {
     where
         S: io::Write + io::Seek,
     {
-        let position = self.sink.stream_position()?;
-        let block_index = position as u32 / BLOCK_SIZE;
-        let cur_block = if block_index % BLOCK_SIZE == 1 {
-            // skip two FPM blocks
-            self.sink.write_all(EMPTY_BLOCK)?;
-            self.sink.write_all(EMPTY_BLOCK)?;
-            BlockIndex(block_index + 1)
-        } else {
-            BlockIndex(block_index - 1)
-        };
-        self.blocks.push(cur_block);
+        // the block that was just filled
+        let block_index = self.sink.position() as u32 / BLOCK_SIZE;
+        self.blocks.push(BlockIndex(block_index - 1));
+        if is_fpm_block(block_index, BLOCK_SIZE) {
+            // skip the two FPM blocks of the next interval
+            self.sink.pad(2 * u64::from(BLOCK_SIZE))?;
+        }
         Ok(())
     }
 
@@ -168,9 +343,14 @@ impl<'a, S, const BLOCK_SIZE: u32> MsfStreamWriter<'a, S, BLOCK_SIZE> {
     where
         S: io::Write + io::Seek,
     {
-        let rem = BLOCK_SIZE - self.position % BLOCK_SIZE;
-        self.sink.write_all(&EMPTY_BLOCK[..rem as usize])?;
-        self.advance_block()?;
+        // empty streams own no blocks and a full last block has not been recorded yet
+        if !self.position.is_multiple_of(BLOCK_SIZE) {
+            let rem = BLOCK_SIZE - self.position % BLOCK_SIZE;
+            self.sink.pad(rem.into())?;
+            self.advance_block()?;
+        } else if self.position != 0 {
+            self.advance_block()?;
+        }
 
         Ok(MsfStreamLayout::new(self.blocks, self.position))
     }
@@ -181,13 +361,15 @@ where
     W: io::Write + io::Seek,
 {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.position == 0 && self.blocks.is_empty() && !buf.is_empty() {
+            self.sink.align_stream(BLOCK_SIZE)?;
+        }
         let rem = BLOCK_SIZE - self.position % BLOCK_SIZE;
-        let len = if rem == 0 && !buf.is_empty() {
+        // the previous block is recorded once the first byte of the next one is written
+        if rem == BLOCK_SIZE && self.position != 0 && !buf.is_empty() {
             self.advance_block()?;
-            BLOCK_SIZE.min(buf.len() as u32)
-        } else {
-            rem.min(buf.len() as u32)
-        };
+        }
+        let len = rem.min(buf.len() as u32);
         let read = self.sink.write(&buf[..len as usize])?;
         self.position += read as u32;
         Ok(read)
@@ -199,10 +381,40 @@ where
 }
 
 #[derive(Debug)]
-pub(crate) struct FreeBlockMap(Vec<u8>);
+pub struct FreeBlockMap(Vec<u8>);
 
 impl FreeBlockMap {
-    pub fn layout(main: &SuperBlock) -> MsfStreamLayout {
+    pub fn is_free(&self, block: u32) -> bool {
+        self.0
+            .get(block as usize / 8)
+            .is_some_and(|byte| byte & (1 << (block % 8)) != 0)
+    }
+
+    pub fn free_blocks(&self, num_blocks: u32) -> impl Iterator<Item = u32> + '_ {
+        (0..num_blocks).filter(|&block| self.is_free(block))
+    }
+
+    pub(crate) fn allocation(&self, referenced: &[bool]) -> BlockAllocation {
+        let mut res = BlockAllocation {
+            num_blocks: referenced.len() as u32,
+            ..BlockAllocation::default()
+        };
+        for (block, &is_referenced) in (0..).zip(referenced) {
+            match (self.is_free(block), is_referenced) {
+                (true, true) => res.free_but_referenced.push(block),
+                (false, false) => res.used_but_unreferenced.push(block),
+                _ => {}
+            }
+            if self.is_free(block) {
+                res.free_blocks += 1;
+            } else {
+                res.used_blocks += 1;
+            }
+        }
+        res
+    }
+
+    pub(crate) fn layout(main: &SuperBlock) -> MsfStreamLayout {
         let intervals = div_ceil(main.num_blocks, 8 * main.block_size);
         let byte_size = div_ceil(main.num_blocks, 8);
         let mut blocks = Vec::with_capacity(intervals as usize);
@@ -214,34 +426,115 @@ impl FreeBlockMap {
         MsfStreamLayout { blocks, byte_size }
     }
 
-    pub fn write<S>(main: &SuperBlock, sink: &mut S) -> Result<()>
-    where
-        S: io::Write + io::Seek,
-    {
-        let layout = Self::layout(main);
-        let mut bit = 0;
-        for block in layout.blocks {
-            sink.seek(io::SeekFrom::Start(block.to_file_pos(main.block_size)))?;
-            let available = (main.num_blocks - bit).min(main.block_size * 8);
-            for _ in 0..available / 8 {
-                sink.write_all(&[0xFF])?;
-                bit += 8;
+    // a map of a file using all of its blocks
+    pub(crate) fn all_used(num_blocks: u32) -> Self {
+        let mut res = Self(vec![0; div_ceil(num_blocks, 8) as usize]);
+        // the bits past the last block are left free
+        for block in num_blocks..res.0.len() as u32 * 8 {
+            res.set_free(block, true);
+        }
+        res
+    }
+
+    pub(crate) fn set_free(&mut self, block: u32, free: bool) {
+        let index = block as usize / 8;
+        if index >= self.0.len() {
+            self.0.resize(index + 1, 0xFF);
+        }
+        if free {
+            self.0[index] |= 1 << (block % 8);
+        } else {
+            self.0[index] &= !(1 << (block % 8));
+        }
+    }
+
+    // takes blocks that are free in the map, the file grows past `num_blocks` when there are not enough
+    // of them, the FPM blocks of the intervals it grows into are reserved along the way
+    pub(crate) fn allocate(
+        &mut self,
+        count: u32,
+        num_blocks: &mut u32,
+        block_size: u32,
+    ) -> Vec<BlockIndex> {
+        let mut res = Vec::with_capacity(count as usize);
+        let mut block = 0;
+        while res.len() < count as usize && block < *num_blocks {
+            if self.is_free(block) && !is_fpm_block(block, block_size) {
+                self.set_free(block, false);
+                res.push(BlockIndex(block));
             }
+            block += 1;
+        }
+        while res.len() < count as usize {
+            res.push(self.grow(num_blocks, block_size));
+        }
+        res
+    }
 
-            if available % 8 != 0 {
-                let mut byte = 0;
-                for i in 0..available % 8 {
-                    byte |= 1 << i;
+    // takes blocks following each other, like the block map needs, from a run of free blocks or at the
+    // end of the file
+    pub(crate) fn allocate_contiguous(
+        &mut self,
+        count: u32,
+        num_blocks: &mut u32,
+        block_size: u32,
+    ) -> Vec<BlockIndex> {
+        let mut run = 0;
+        for block in 0..*num_blocks {
+            if self.is_free(block) && !is_fpm_block(block, block_size) {
+                run += 1;
+            } else {
+                run = 0;
+            }
+            if run == count {
+                let blocks: Vec<_> = (block + 1 - count..=block).map(BlockIndex).collect();
+                for block in &blocks {
+                    self.set_free(block.0, false);
                 }
-                sink.write_all(&[byte])?;
-                break;
+                return blocks;
             }
         }
+
+        let mut res: Vec<BlockIndex> = Vec::with_capacity(count as usize);
+        while res.len() < count as usize {
+            let block = self.grow(num_blocks, block_size);
+            // starts over past the FPM blocks of an interval that broke the run
+            if res.last().is_some_and(|last| last.0 + 1 != block.0) {
+                for skipped in res.drain(..) {
+                    self.set_free(skipped.0, true);
+                }
+            }
+            res.push(block);
+        }
+        res
+    }
+
+    fn grow(&mut self, num_blocks: &mut u32, block_size: u32) -> BlockIndex {
+        while is_fpm_block(*num_blocks, block_size) {
+            self.set_free(*num_blocks, false);
+            *num_blocks += 1;
+        }
+        let block = *num_blocks;
+        self.set_free(block, false);
+        *num_blocks += 1;
+        BlockIndex(block)
+    }
+
+    // writes the map to the FPM of the superblock, which only has to be written in the blocks of the
+    // intervals covered by `num_blocks`
+    pub(crate) fn write<S>(&self, main: &SuperBlock, sink: &mut S) -> Result<()>
+    where
+        S: io::Write + io::Seek,
+    {
+        let layout = Self::layout(main);
+        let mut bytes = self.0.clone();
+        bytes.resize(layout.byte_size as usize, 0xFF);
+        let mut stream = MsfStream::new(sink, &layout, main.block_size);
+        stream.write_all(&bytes)?;
         Ok(())
     }
 
-    #[allow(unused)]
-    pub fn read<R>(mut inner: BufMsfStream<R>) -> Result<FreeBlockMap>
+    pub(crate) fn read<R>(mut inner: BufMsfStream<R>) -> Result<FreeBlockMap>
     where
         R: io::Read + io::Seek,
     {
@@ -251,7 +544,28 @@ impl FreeBlockMap {
     }
 }
 
-#[derive(Debug, Clone, Copy, Encode, Decode)]
+// the layout parameters of the container as recorded in the superblock
+#[derive(Debug, Clone, Copy)]
+pub struct MsfInfo {
+    pub block_size: u32,
+    pub num_blocks: u32,
+    pub num_dir_bytes: u32,
+    // the block of the active free block map, either 1 or 2
+    pub free_block_map_block: u32,
+    pub block_map_addr: u32,
+    pub num_streams: u32,
+}
+
+#[derive(Debug, Default)]
+pub struct BlockAllocation {
+    pub num_blocks: u32,
+    pub free_blocks: u32,
+    pub used_blocks: u32,
+    pub free_but_referenced: Vec<u32>,
+    pub used_but_unreferenced: Vec<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode)]
 #[declio(ctx_is = "constants::ENDIANESS")]
 pub(crate) struct BlockIndex(pub u32);
 
@@ -262,7 +576,7 @@ impl BlockIndex {
     }
 }
 
-#[derive(Debug, Clone, Copy, Encode, Decode, EncodedSize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode, EncodedSize)]
 #[declio(ctx_is = "constants::ENDIANESS")]
 pub struct StreamIndex(pub(crate) u16);
 
@@ -271,3 +585,9 @@ impl From<StreamIndex> for u16 {
         idx.0
     }
 }
+
+impl From<u16> for StreamIndex {
+    fn from(idx: u16) -> Self {
+        Self(idx)
+    }
+}
@@ -0,0 +1,87 @@
+use std::collections::HashMap;
+
+use object::pe::{IMAGE_DIRECTORY_ENTRY_EXCEPTION, IMAGE_FILE_MACHINE_AMD64};
+use object::read::pe::PeFile64;
+use object::LittleEndian as LE;
+
+use crate::builders::{ModuleBuilder, PdbBuilder};
+use crate::codeview::symbols::{Procedure, ProcedureProperties};
+use crate::codeview::types::BuiltinType;
+use crate::codeview::DataRegionOffset;
+use crate::dbi::{SectionContrib, SectionHeader};
+use crate::exports::section_header;
+use crate::result::{Error, Result};
+use crate::utils::StrBuf;
+use crate::SymbolOffset;
+
+const MODULE_NAME: &str = "* Functions *";
+const RUNTIME_FUNCTION_SIZE: usize = 12;
+
+// adds a module with a global procedure for every RUNTIME_FUNCTION of an x64 image, the exact function
+// boundaries of binaries that come without symbols, procedures are named after the `names` entry of
+// their RVA or `sub_<rva>` otherwise, the sections are only added when the builder has none yet,
+// returns the number of procedures added
+pub fn add_functions(
+    builder: &mut PdbBuilder,
+    image: &[u8],
+    names: &HashMap<u32, String>,
+) -> Result<usize> {
+    let pe = PeFile64::parse(image).map_err(Error::invalid_image)?;
+    if pe.nt_headers().file_header.machine.get(LE) != IMAGE_FILE_MACHINE_AMD64 {
+        return Err(Error::UnsupportedFeature(
+            "function tables of images other than x64",
+        ));
+    }
+    let sections: Vec<SectionHeader> = pe.section_table().iter().map(section_header).collect();
+    let pdata = match pe.data_directory(IMAGE_DIRECTORY_ENTRY_EXCEPTION) {
+        Some(dir) => dir
+            .data(image, &pe.section_table())
+            .map_err(Error::invalid_image)?,
+        None => &[],
+    };
+
+    let mut module: Option<ModuleBuilder> = None;
+    let mut count = 0;
+    for entry in pdata.chunks_exact(RUNTIME_FUNCTION_SIZE) {
+        let begin = u32::from_le_bytes([entry[0], entry[1], entry[2], entry[3]]);
+        let end = u32::from_le_bytes([entry[4], entry[5], entry[6], entry[7]]);
+        let Some((segment, header)) = (1..).zip(&sections).find(|(_, header)| {
+            (header.virtual_address..header.virtual_address + header.virtual_size).contains(&begin)
+        }) else {
+            continue;
+        };
+        let offset = begin - header.virtual_address;
+        let size = end.saturating_sub(begin);
+        let contrib = || SectionContrib::new(segment, offset as i32, size, header.characteristics, 0);
+        let module = module.get_or_insert_with(|| {
+            ModuleBuilder::new(MODULE_NAME.to_owned(), MODULE_NAME.to_owned(), contrib())
+        });
+        let name = names
+            .get(&begin)
+            .cloned()
+            .unwrap_or_else(|| format!("sub_{begin:X}"));
+        let proc = Procedure {
+            parent: None,
+            end: SymbolOffset(0),
+            next: None,
+            code_size: size,
+            dbg_start_offset: 0,
+            dbg_end_offset: size,
+            function_type: BuiltinType::NotTranslated.into(),
+            code_offset: DataRegionOffset::new(offset, segment),
+            properties: ProcedureProperties::new(),
+            name: StrBuf::new(name),
+        };
+        module.add_procedure(proc, true);
+        module.add_section_contrib(contrib());
+        count += 1;
+    }
+
+    if builder.dbi().section_headers().is_empty() {
+        builder.dbi().add_sections(sections);
+    }
+    if let Some(module) = module {
+        builder.dbi().add_module(module);
+    }
+    Ok(count)
+}
@@ -0,0 +1,431 @@
+use std::cell::{OnceCell, RefCell};
+use std::io;
+use std::ops::Range;
+
+use crate::address_index::{AddressIndex, AddressKind, AddressRange};
+use crate::codeview::symbols::{Procedure, SymbolRecord};
+use crate::codeview::types::TypeRecord;
+use crate::codeview::DataRegionOffset;
+use crate::dbi::{DbiStream, SectionHeaderStream};
+use crate::diagnostics::ParseOptions;
+use crate::functions::{as_procedure, Function};
+use crate::info::PdbInfo;
+use crate::module::{Module, SourceLine};
+use crate::publics::Publics;
+use crate::result::{Error, Result};
+use crate::search::{is_searchable, SymbolMatch, SymbolPattern, SymbolSource};
+use crate::strings::Strings;
+use crate::symbol_map::SymbolMap;
+use crate::symbols::Symbols;
+use crate::types::{IpiStream, TpiStream};
+use crate::unwind::{UnwindRule, UnwindTable};
+use crate::variables::GlobalVariable;
+use crate::PdbFile;
+
+// parses every stream at most once, on first access, and hands out shared references to the results,
+// with helpers joining the records of one stream with another
+#[derive(Debug)]
+pub struct Pdb<R> {
+    file: RefCell<PdbFile<R>>,
+    info: OnceCell<PdbInfo>,
+    dbi: OnceCell<DbiStream>,
+    tpi: OnceCell<TpiStream>,
+    ipi: OnceCell<IpiStream>,
+    strings: OnceCell<Strings>,
+    publics: OnceCell<Publics>,
+    globals: OnceCell<SymbolMap>,
+    symbols: OnceCell<Symbols>,
+    section_headers: OnceCell<SectionHeaderStream>,
+    modules: OnceCell<Vec<OnceCell<Module>>>,
+    unwind: OnceCell<UnwindTable>,
+    addresses: OnceCell<AddressIndex>,
+}
+
+impl<R> Pdb<R>
+where
+    R: io::Read + io::Seek,
+{
+    pub fn load(reader: R) -> Result<Self> {
+        Ok(Self::new(PdbFile::open(reader)?))
+    }
+
+    pub fn load_with_options(reader: R, options: ParseOptions) -> Result<Self> {
+        Ok(Self::new(PdbFile::open_with_options(reader, options)?))
+    }
+
+    pub fn new(file: PdbFile<R>) -> Self {
+        Self {
+            file: RefCell::new(file),
+            info: OnceCell::new(),
+            dbi: OnceCell::new(),
+            tpi: OnceCell::new(),
+            ipi: OnceCell::new(),
+            strings: OnceCell::new(),
+            publics: OnceCell::new(),
+            globals: OnceCell::new(),
+            symbols: OnceCell::new(),
+            section_headers: OnceCell::new(),
+            modules: OnceCell::new(),
+            unwind: OnceCell::new(),
+            addresses: OnceCell::new(),
+        }
+    }
+
+    // gives access to the streams that are not cached
+    pub fn file_mut(&mut self) -> &mut PdbFile<R> {
+        self.file.get_mut()
+    }
+
+    pub fn into_file(self) -> PdbFile<R> {
+        self.file.into_inner()
+    }
+
+    pub fn info(&self) -> Result<&PdbInfo> {
+        load(&self.info, || self.file.borrow_mut().get_info())
+    }
+
+    pub fn dbi(&self) -> Result<&DbiStream> {
+        load(&self.dbi, || self.file.borrow_mut().get_dbi())
+    }
+
+    pub fn tpi(&self) -> Result<&TpiStream> {
+        load(&self.tpi, || self.file.borrow_mut().get_tpi())
+    }
+
+    pub fn ipi(&self) -> Result<&IpiStream> {
+        load(&self.ipi, || self.file.borrow_mut().get_ipi())
+    }
+
+    pub fn strings(&self) -> Result<&Strings> {
+        load(&self.strings, || {
+            let info = self.info()?;
+            self.file.borrow_mut().get_strings(info)
+        })
+    }
+
+    pub fn publics(&self) -> Result<&Publics> {
+        load(&self.publics, || {
+            let dbi = self.dbi()?;
+            self.file.borrow_mut().get_publics(dbi)
+        })
+    }
+
+    pub fn globals(&self) -> Result<&SymbolMap> {
+        load(&self.globals, || {
+            let dbi = self.dbi()?;
+            self.file.borrow_mut().get_globals(dbi)
+        })
+    }
+
+    pub fn symbols(&self) -> Result<&Symbols> {
+        load(&self.symbols, || {
+            let dbi = self.dbi()?;
+            self.file.borrow_mut().get_symbols(dbi)
+        })
+    }
+
+    pub fn section_headers(&self) -> Result<&SectionHeaderStream> {
+        load(&self.section_headers, || {
+            let dbi = self.dbi()?;
+            self.file.borrow_mut().get_section_headers(dbi)
+        })
+    }
+
+    // the debug info of the module at `index` in the DBI stream
+    pub fn module(&self, index: usize) -> Result<&Module> {
+        let dbi = self.dbi()?;
+        let modules = self
+            .modules
+            .get_or_init(|| dbi.modules().iter().map(|_| OnceCell::new()).collect());
+        let (cell, module) = modules
+            .get(index)
+            .zip(dbi.modules().get(index))
+            .ok_or(Error::StreamNotFound("module debug info"))?;
+        load(cell, || self.file.borrow_mut().get_module(module))
+    }
+
+    // the line tables of the module at `index` resolved to their source files
+    pub fn module_lines(&self, index: usize) -> Result<Vec<SourceLine<'_>>> {
+        self.module(index)?.lines(self.strings()?)
+    }
+
+    pub fn module_il_lines(&self, index: usize) -> Result<Vec<SourceLine<'_>>> {
+        self.module(index)?.il_lines(self.strings()?)
+    }
+
+    // the first procedure of the name, the modules are searched in order
+    pub fn function_by_name(&self, name: &str) -> Result<Option<Function<'_>>> {
+        self.find_function(|proc, _| proc.name.as_ref() == name)
+    }
+
+    // the procedure whose code covers an RVA
+    pub fn function_at(&self, rva: u32) -> Result<Option<Function<'_>>> {
+        let Some(range) = self.address_index()?.procedure_at(rva) else {
+            return Ok(None);
+        };
+        let AddressKind::Procedure { module, symbol } = range.kind else {
+            return Ok(None);
+        };
+        let symbols = &self.module(module)?.symbols()[symbol..];
+        let lines = self.module_lines(module)?;
+        let ipi = optional(self.ipi())?;
+        Ok(Function::new(
+            module,
+            range.start,
+            symbols,
+            lines,
+            self.tpi()?,
+            ipi,
+        ))
+    }
+
+    // the ranges of RVAs covered by the section contributions, the procedures of the modules and
+    // the publics, built on first access
+    pub fn address_index(&self) -> Result<&AddressIndex> {
+        load(&self.addresses, || {
+            let headers = self.section_headers()?.headers();
+            let rva = |segment: u16, offset: u32| {
+                usize::from(segment)
+                    .checked_sub(1)
+                    .and_then(|index| headers.get(index))
+                    .map(|header| header.virtual_address.wrapping_add(offset))
+            };
+
+            let mut ranges = vec![];
+            for contrib in self.dbi()?.section_contribs() {
+                if let Some(start) = rva(contrib.i_sect, contrib.offset as u32) {
+                    let kind = AddressKind::Contribution {
+                        module: usize::from(contrib.i_mod),
+                    };
+                    ranges.push(AddressRange::new(start, start.saturating_add(contrib.size), kind));
+                }
+            }
+            for index in 0..self.dbi()?.modules().len() {
+                let Some(module) = optional(self.module(index))? else {
+                    continue;
+                };
+                for (symbol, record) in module.symbols().iter().enumerate() {
+                    let Some(proc) = as_procedure(record) else {
+                        continue;
+                    };
+                    if let Some(start) = rva(proc.code_offset.segment, proc.code_offset.offset) {
+                        let kind = AddressKind::Procedure {
+                            module: index,
+                            symbol,
+                        };
+                        ranges.push(AddressRange::new(
+                            start,
+                            start.saturating_add(proc.code_size),
+                            kind,
+                        ));
+                    }
+                }
+            }
+
+            // publics have no size, each one is taken to end where the next one of its section starts
+            if let Some(publics) = optional(self.publics())? {
+                let symbols = self.symbols()?;
+                let mut starts: Vec<_> = publics
+                    .address_map()
+                    .iter()
+                    .filter_map(|&offset| match symbols.get(offset)? {
+                        SymbolRecord::Public32(public) => {
+                            let segment = public.offset.segment;
+                            Some((segment, rva(segment, public.offset.offset)?, offset))
+                        }
+                        _ => None,
+                    })
+                    .collect();
+                starts.sort_by_key(|&(segment, start, _)| (segment, start));
+                for (i, &(segment, start, offset)) in starts.iter().enumerate() {
+                    let end = match starts.get(i + 1) {
+                        Some(&(next_segment, next, _)) if next_segment == segment => next,
+                        _ => {
+                            let header = &headers[usize::from(segment) - 1];
+                            header.virtual_address + header.virtual_size
+                        }
+                    };
+                    ranges.push(AddressRange::new(start, end, AddressKind::Public { offset }));
+                }
+            }
+            Ok(AddressIndex::new(ranges))
+        })
+    }
+
+    fn find_function(&self, matches: impl Fn(&Procedure, u32) -> bool) -> Result<Option<Function<'_>>> {
+        for index in 0..self.dbi()?.modules().len() {
+            // modules without a stream of their own have no procedures
+            let Some(module) = optional(self.module(index))? else {
+                continue;
+            };
+            for (pos, symbol) in module.symbols().iter().enumerate() {
+                let Some(proc) = as_procedure(symbol) else {
+                    continue;
+                };
+                let Some(rva) = self.rva(DataRegionOffset::new(
+                    proc.code_offset.offset,
+                    proc.code_offset.segment,
+                ))?
+                else {
+                    continue;
+                };
+                if matches(proc, rva) {
+                    let lines = self.module_lines(index)?;
+                    let ipi = optional(self.ipi())?;
+                    return Ok(Function::new(
+                        index,
+                        rva,
+                        &module.symbols()[pos..],
+                        lines,
+                        self.tpi()?,
+                        ipi,
+                    ));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    // the global and static variables of the symbol record stream
+    pub fn global_variables(&self) -> Result<Vec<GlobalVariable<'_>>> {
+        let contribs = self.dbi()?.section_contribs();
+        let headers = self.section_headers()?.headers();
+        let res = self
+            .symbols()?
+            .records()
+            .iter()
+            .filter_map(|symbol| GlobalVariable::new(symbol, contribs, headers))
+            .collect();
+        Ok(res)
+    }
+
+    // the first variable of the name in the symbol record stream
+    pub fn global_variable_by_name(&self, name: &str) -> Result<Option<GlobalVariable<'_>>> {
+        Ok(self.global_variables()?.into_iter().find(|var| var.name == name))
+    }
+
+    // the variables that start within a range of RVAs, ordered by their RVA
+    pub fn global_variables_in(&self, range: Range<u32>) -> Result<Vec<GlobalVariable<'_>>> {
+        let mut res: Vec<_> = self
+            .global_variables()?
+            .into_iter()
+            .filter(|var| var.rva.is_some_and(|rva| range.contains(&rva)))
+            .collect();
+        res.sort_by_key(|var| var.rva);
+        Ok(res)
+    }
+
+    // the publics, globals and module symbols with a name matching a pattern like `module!name`, where
+    // both parts may use `*` and `?` wildcards and the module part limits the search to the modules
+    // matching it
+    pub fn search_symbols(&self, pattern: &str) -> Result<Vec<SymbolMatch<'_>>> {
+        let pattern = SymbolPattern::parse(pattern);
+        let mut res = vec![];
+        if pattern.includes_streams() {
+            let symbols = self.symbols()?;
+            for (source, map) in [
+                (SymbolSource::Publics, optional(self.publics())?.map(Publics::map)),
+                (SymbolSource::Globals, optional(self.globals())?),
+            ] {
+                for record in map.map_or(&[][..], SymbolMap::hash_records) {
+                    let offset = record.symbol_offset();
+                    let Some(symbol) = symbols.get(offset) else {
+                        continue;
+                    };
+                    match symbol.name() {
+                        Some(name) if pattern.matches(name) => res.push(SymbolMatch {
+                            name,
+                            source,
+                            offset,
+                            symbol,
+                        }),
+                        _ => {}
+                    }
+                }
+            }
+        }
+        for (index, module) in self.dbi()?.modules().iter().enumerate() {
+            if !pattern.includes_module(module) {
+                continue;
+            }
+            let Some(module) = optional(self.module(index))? else {
+                continue;
+            };
+            for (offset, symbol) in module.symbols_with_offsets() {
+                match symbol.name() {
+                    Some(name) if is_searchable(symbol) && pattern.matches(name) => res.push(SymbolMatch {
+                        name,
+                        source: SymbolSource::Module(index),
+                        offset,
+                        symbol,
+                    }),
+                    _ => {}
+                }
+            }
+        }
+        Ok(res)
+    }
+
+    // the type of a symbol like a procedure, a variable or a constant
+    pub fn symbol_type(&self, symbol: &SymbolRecord) -> Result<Option<&TypeRecord>> {
+        match symbol.referenced_types().first() {
+            Some(&index) => Ok(self.tpi()?.record(index)),
+            None => Ok(None),
+        }
+    }
+
+    // the virtual address of a location like that of a public, relative to the image base
+    pub fn rva(&self, offset: DataRegionOffset) -> Result<Option<u32>> {
+        let headers = self.section_headers()?.headers();
+        let header = usize::from(offset.segment)
+            .checked_sub(1)
+            .and_then(|index| headers.get(index));
+        Ok(header.map(|header| header.virtual_address + offset.offset))
+    }
+
+    // how to recover the caller's frame at an RVA, images without FPO or frame data have no rules
+    pub fn unwind_info_at(&self, rva: u32) -> Result<Option<&UnwindRule>> {
+        let table = load(&self.unwind, || {
+            let dbi = self.dbi()?;
+            let mut file = self.file.borrow_mut();
+            let fpo = optional(file.get_fpo(dbi))?;
+            let frames = optional(file.get_frame_data(dbi))?;
+            Ok(UnwindTable::new(
+                fpo.as_ref().map_or(&[], |fpo| fpo.records()),
+                frames.as_ref().map_or(&[], |frames| frames.frames()),
+            ))
+        })?;
+        Ok(table.unwind_info_at(rva))
+    }
+
+    // the postfix program of a frame data rule, see `unwind::eval_program`
+    pub fn unwind_program(&self, rule: &UnwindRule) -> Result<Option<&str>> {
+        Ok(rule.program(self.strings()?))
+    }
+}
+
+impl<'a> Pdb<io::Cursor<&'a [u8]>> {
+    pub fn from_bytes(bytes: &'a [u8]) -> Result<Self> {
+        Ok(Self::new(PdbFile::from_bytes(bytes)?))
+    }
+
+    pub fn from_bytes_with_options(bytes: &'a [u8], options: ParseOptions) -> Result<Self> {
+        Ok(Self::new(PdbFile::from_bytes_with_options(bytes, options)?))
+    }
+}
+
+fn load<A>(cell: &OnceCell<A>, init: impl FnOnce() -> Result<A>) -> Result<&A> {
+    if let Some(val) = cell.get() {
+        return Ok(val);
+    }
+    let val = init()?;
+    Ok(cell.get_or_init(|| val))
+}
+
+fn optional<A>(res: Result<A>) -> Result<Option<A>> {
+    match res {
+        Ok(val) => Ok(Some(val)),
+        Err(Error::StreamNotFound(_)) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
@@ -6,48 +6,92 @@ use declio::{Decode, Encode, EncodedSize};
 use derive_getters::Getters;
 
 use crate::codeview::symbols::Public;
+use crate::dbi::SectionHeader;
+use crate::diagnostics::ParseLimits;
 use crate::result::Result;
 use crate::symbol_map::SymbolMap;
+use crate::symbols::Symbols;
+use crate::utils::NameMatching;
 use crate::{constants, SymbolOffset};
 
 #[derive(Debug, Getters)]
 pub struct Publics {
+    header: PublicsHeader,
     map: SymbolMap,
     address_map: Vec<SymbolOffset>,
     thunk_map: Vec<u32>,
+    section_offsets: Vec<SectionOffset>,
 }
 
 impl Publics {
-    pub(crate) fn from_publics(publics: &BTreeMap<SymbolOffset, Public>) -> Self {
-        let index = SymbolMap::from_symbols(publics);
+    // the section offsets are taken from the section headers of the image, the builder writes no
+    // incremental linking thunks, so the thunk fields are left zeroed
+    pub(crate) fn from_publics(
+        publics: &BTreeMap<SymbolOffset, Public>,
+        sections: &[SectionHeader],
+    ) -> Self {
+        let index = SymbolMap::from_symbols(publics, &BTreeMap::new());
 
         let mut address_map: Vec<_> = publics.keys().copied().collect();
         address_map.sort_by_key(|off| publics.get(off).map(|sym| &sym.offset));
 
+        let section_offsets: Vec<_> = (1..)
+            .zip(sections)
+            .map(|(section, header)| SectionOffset {
+                offset: header.virtual_address,
+                section,
+                pad: Default::default(),
+            })
+            .collect();
+        let header = PublicsHeader {
+            sym_hash: (index.get_header().encoded_size(()) + index.encoded_size(())) as u32,
+            addr_map: address_map.encoded_size(()) as u32,
+            num_thunks: 0,
+            size_of_thunk: 0,
+            i_sect_thunk_table: 0,
+            reserved: [0; 2],
+            off_thunk_table: 0,
+            num_sections: section_offsets.len() as u32,
+        };
+
         Self {
+            header,
             map: index,
             address_map,
             thunk_map: vec![],
+            section_offsets,
         }
     }
 
-    pub(crate) fn read_with_header<R>(mut input: R) -> Result<Self>
+    pub fn find(&self, symbols: &Symbols, name: &str, matching: NameMatching) -> Option<SymbolOffset> {
+        self.map.find(symbols, name, matching)
+    }
+
+    pub(crate) fn read_with_header<R>(mut input: R, limits: &ParseLimits) -> Result<Self>
     where
         R: io::Read,
     {
         let header = PublicsHeader::decode((), &mut input)?;
-        let globals = SymbolMap::read_with_header(&mut input)?;
+        let globals = SymbolMap::read_with_header(&mut input, limits)?;
+        limits.check_memory(
+            u64::from(header.addr_map)
+                + u64::from(header.num_thunks) * 4
+                + u64::from(header.num_sections) * 8,
+        )?;
         let address_count = header.addr_map / 4;
         let address_map = Decode::decode((Len(address_count as usize), constants::ENDIANESS), &mut input)?;
         let thunk_map = Decode::decode(
             (Len(header.num_thunks as usize), constants::ENDIANESS),
             &mut input,
         )?;
+        let section_offsets = Decode::decode(Len(header.num_sections as usize), &mut input)?;
 
         Ok(Self {
+            header,
             map: globals,
             address_map,
             thunk_map,
+            section_offsets,
         })
     }
 
@@ -55,35 +99,43 @@ impl Publics {
     where
         S: io::Write,
     {
-        let gsi_header = self.map.get_header();
-        let header = PublicsHeader {
-            sym_hash: (gsi_header.encoded_size(()) + self.map.encoded_size(())) as u32,
-            addr_map: self.address_map.encoded_size(()) as u32,
-            num_thunks: 0,
-            size_of_thunk: 0,
-            i_sect_thunk_table: 0,
-            reserved: [0; 2],
-            off_thunk_table: 0,
-            num_sections: 0,
-        };
-        header.encode((), sink)?;
-        gsi_header.encode((), sink)?;
+        self.header.encode((), sink)?;
+        self.map.get_header().encode((), sink)?;
         self.map.encode((), sink)?;
         self.address_map.encode(((),), sink)?;
+        self.thunk_map.encode((constants::ENDIANESS,), sink)?;
+        self.section_offsets.encode(((),), sink)?;
 
         Ok(())
     }
 }
 
-#[derive(Debug, Encode, Decode)]
+// the header of the publics stream, the sizes are in bytes and count what follows it in this order:
+// the hash table of the names, the address map, the thunk map and the section offsets
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+#[declio(ctx_is = "constants::ENDIANESS")]
+pub struct PublicsHeader {
+    // the size of the hash table of the names, including its own header
+    pub sym_hash: u32,
+    // the size of the address map, four bytes for every public
+    pub addr_map: u32,
+    // the incremental linking thunks in the thunk table of the image, one entry of the thunk map each
+    pub num_thunks: u32,
+    pub size_of_thunk: u32,
+    // the section and the offset within it of the thunk table, zero without thunks
+    pub i_sect_thunk_table: u16,
+    pub reserved: [u8; 2],
+    pub off_thunk_table: u32,
+    // the number of entries of the section offsets, the count of the section headers of the image
+    pub num_sections: u32,
+}
+
+// the RVA of the start of a section, used to turn the addresses of thunks into RVAs
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
 #[declio(ctx_is = "constants::ENDIANESS")]
-struct PublicsHeader {
-    sym_hash: u32,
-    addr_map: u32,
-    num_thunks: u32,
-    size_of_thunk: u32,
-    i_sect_thunk_table: u16,
-    reserved: [u8; 2],
-    off_thunk_table: u32,
-    num_sections: u32,
+pub struct SectionOffset {
+    pub offset: u32,
+    // the index of the section, starting from one
+    pub section: u16,
+    pad: u16,
 }
@@ -1,5 +1,7 @@
 use thiserror::Error;
 
+use crate::StreamIndex;
+
 pub type Result<A, E = Error> = std::result::Result<A, E>;
 
 #[derive(Debug, Error)]
@@ -14,4 +16,81 @@ pub enum Error {
     StreamNotFound(&'static str),
     #[error("invalid padding: {0}")]
     InvalidPadding(u8),
+    #[error("parse limit exceeded: {0}")]
+    LimitExceeded(&'static str),
+    #[error("symbol outside of the sections: {0}")]
+    AddressOutOfSection(String),
+    #[error("register {register} is not valid on {machine:?}")]
+    InvalidRegister {
+        register: u16,
+        machine: crate::dbi::MachineType,
+    },
+    #[error("symbol record at {actual:#x} was handed out offset {expected:#x}")]
+    SymbolOffsetMismatch { expected: u32, actual: u32 },
+    #[error("symbol handle {0} was never bound to a record")]
+    UnboundSymbol(u32),
+    #[error("duplicate symbol: {0}")]
+    DuplicateSymbol(String),
+    #[error("type not found: {0}")]
+    TypeNotFound(String),
+    #[error("invalid unwind program: {0}")]
+    InvalidUnwindProgram(String),
+    #[error("record {record:#x} references {kind} index {index:#x} which was not added before it")]
+    InvalidTypeIndex {
+        record: u32,
+        kind: &'static str,
+        index: u32,
+    },
+    #[error("type server does not match the object: {0}")]
+    TypeServerMismatch(String),
+    #[error("failed to decode record {kind:#06x}: {source}")]
+    RecordFailed { kind: u16, source: declio::Error },
+    // the source is the error of the HTTP client, which isn't part of the API
+    #[error("download failed: {0}")]
+    DownloadFailed(#[source] Box<dyn std::error::Error + Send + Sync>),
+    // the source is the error of the image parser, which isn't part of the API
+    #[error("invalid image: {0}")]
+    InvalidImage(#[source] Box<dyn std::error::Error + Send + Sync>),
+    #[error("invalid argument: {0}")]
+    InvalidArgument(&'static str),
+    #[error("malformed input: {0}")]
+    MalformedInput(&'static str),
+    // the offset is from the start of the stream, the index is missing for streams read from memory
+    #[error("failed to read {stream} at offset {offset:#x}: {source}")]
+    StreamFailed {
+        stream: &'static str,
+        index: Option<StreamIndex>,
+        offset: u64,
+        source: Box<Error>,
+    },
+}
+
+impl Error {
+    #[cfg(feature = "object")]
+    pub(crate) fn invalid_image(err: object::Error) -> Self {
+        Error::InvalidImage(Box::new(err))
+    }
+
+    pub(crate) fn in_stream(self, stream: &'static str, index: Option<StreamIndex>, offset: u64) -> Self {
+        match self {
+            // the innermost stream pinpoints the error, the outer one can only tell its index
+            Error::StreamFailed {
+                stream,
+                index: inner,
+                offset,
+                source,
+            } => Error::StreamFailed {
+                stream,
+                index: inner.or(index),
+                offset,
+                source,
+            },
+            source => Error::StreamFailed {
+                stream,
+                index,
+                offset,
+                source: Box::new(source),
+            },
+        }
+    }
 }
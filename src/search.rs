@@ -0,0 +1,85 @@
+use crate::codeview::symbols::SymbolRecord;
+use crate::dbi::DbiModule;
+use crate::functions::as_procedure;
+use crate::utils::matches_glob;
+use crate::SymbolOffset;
+
+// where a symbol found by a search lives
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolSource {
+    Publics,
+    Globals,
+    // the index of the module in the DBI stream
+    Module(usize),
+}
+
+#[derive(Debug)]
+pub struct SymbolMatch<'a> {
+    pub name: &'a str,
+    pub source: SymbolSource,
+    // the offset in the symbol record stream, or in the module stream for module symbols
+    pub offset: SymbolOffset,
+    pub symbol: &'a SymbolRecord,
+}
+
+// a pattern like `module!name` where either part may use `*` and `?` wildcards, without a module the
+// publics and globals are searched along with every module
+#[derive(Debug)]
+pub(crate) struct SymbolPattern<'a> {
+    module: Option<String>,
+    name: &'a str,
+}
+
+impl<'a> SymbolPattern<'a> {
+    pub fn parse(pattern: &'a str) -> Self {
+        match pattern.split_once('!') {
+            Some((module, name)) => Self {
+                module: Some(module.to_ascii_lowercase()),
+                name,
+            },
+            None => Self {
+                module: None,
+                name: pattern,
+            },
+        }
+    }
+
+    pub fn includes_streams(&self) -> bool {
+        self.module.is_none()
+    }
+
+    // modules are matched like images in debuggers, by their file name with or without the extension
+    // and ignoring case, or by their whole path
+    pub fn includes_module(&self, module: &DbiModule) -> bool {
+        let Some(pattern) = &self.module else {
+            return true;
+        };
+        let path = module.module_name.as_ref().to_ascii_lowercase();
+        let file_name = path.rsplit(['/', '\\']).next().unwrap_or_default();
+        let stem = file_name.rsplit_once('.').map_or(file_name, |(stem, _)| stem);
+        [path.as_str(), file_name, stem]
+            .iter()
+            .any(|name| matches_glob(pattern, name))
+    }
+
+    pub fn matches(&self, name: &str) -> bool {
+        matches_glob(self.name, name)
+    }
+}
+
+// the records of a module worth searching for, leaving out locals, which only make sense within their
+// procedures
+pub(crate) fn is_searchable(symbol: &SymbolRecord) -> bool {
+    as_procedure(symbol).is_some()
+        || matches!(
+            symbol,
+            SymbolRecord::Thunk32 { .. }
+                | SymbolRecord::Label { .. }
+                | SymbolRecord::Udt(_)
+                | SymbolRecord::Constant(_)
+                | SymbolRecord::Data(_)
+                | SymbolRecord::GlobalData(_)
+                | SymbolRecord::ThreadLocalStorage(_)
+                | SymbolRecord::GlobalThreadLocalStorage(_)
+        )
+}
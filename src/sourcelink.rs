@@ -0,0 +1,97 @@
+use crate::json::{self, JsonValue};
+use crate::module::Module;
+use crate::result::{Error, Result};
+use crate::strings::Strings;
+
+pub(crate) const SOURCE_LINK_STREAM: &str = "sourcelink";
+
+// maps local source paths to URLs, patterns can end with a `*` wildcard
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SourceLink {
+    documents: Vec<(String, String)>,
+}
+
+impl SourceLink {
+    pub fn documents(&self) -> &[(String, String)] {
+        &self.documents
+    }
+
+    pub fn add<P: Into<String>, U: Into<String>>(&mut self, pattern: P, url: U) -> &mut Self {
+        self.documents.push((pattern.into(), url.into()));
+        self
+    }
+
+    pub fn parse(json: &str) -> Result<Self> {
+        let entries = match json::parse(json)? {
+            JsonValue::Object(entries) => entries,
+            _ => return Err(Error::MalformedInput("source link JSON is not an object")),
+        };
+        let mut documents = vec![];
+        for (key, value) in entries {
+            if key == "documents" {
+                match value {
+                    JsonValue::Object(entries) => {
+                        for (pattern, url) in entries {
+                            match url {
+                                JsonValue::String(url) => documents.push((pattern, url)),
+                                _ => return Err(Error::MalformedInput("non-string source link URL")),
+                            }
+                        }
+                    }
+                    _ => return Err(Error::MalformedInput("invalid source link documents")),
+                }
+            }
+        }
+        Ok(Self { documents })
+    }
+
+    pub fn to_json(&self) -> String {
+        let mut json = String::from("{\"documents\":{");
+        for (i, (pattern, url)) in self.documents.iter().enumerate() {
+            if i != 0 {
+                json.push(',');
+            }
+            json::write_string(&mut json, pattern);
+            json.push(':');
+            json::write_string(&mut json, url);
+        }
+        json.push_str("}}");
+        json
+    }
+
+    pub fn url_for(&self, path: &str) -> Option<String> {
+        // the most specific pattern wins
+        self.documents
+            .iter()
+            .filter_map(|(pattern, url)| match pattern.strip_suffix('*') {
+                Some(prefix) => {
+                    let rest = strip_prefix_ignore_case(path, prefix)?;
+                    let url = url.replacen('*', &rest.replace('\\', "/"), 1);
+                    Some((prefix.len(), url))
+                }
+                None if pattern.eq_ignore_ascii_case(path) => Some((pattern.len(), url.clone())),
+                None => None,
+            })
+            .max_by_key(|(len, _)| *len)
+            .map(|(_, url)| url)
+    }
+
+    // resolves the URL of every file listed in the checksums of a module
+    pub fn module_urls(&self, module: &Module, strings: &Strings) -> Result<Vec<(String, Option<String>)>> {
+        let res = module
+            .source_files(strings)?
+            .into_iter()
+            .map(|path| (path.to_owned(), self.url_for(path)))
+            .collect();
+        Ok(res)
+    }
+
+    pub(crate) fn merge(&mut self, other: SourceLink) {
+        self.documents.extend(other.documents);
+    }
+}
+
+fn strip_prefix_ignore_case<'a>(str: &'a str, prefix: &str) -> Option<&'a str> {
+    let head = str.get(..prefix.len())?;
+    head.eq_ignore_ascii_case(prefix).then(|| &str[prefix.len()..])
+}
@@ -0,0 +1,196 @@
+use crate::codeview::RawRecord;
+use crate::module::DebugSubsectionRecordType;
+use crate::types::FIRST_NON_BUILTIN_TYPE;
+
+#[derive(Debug)]
+pub struct SizeStats {
+    pub streams: Vec<StreamSize>,
+    pub modules: Vec<ModuleSize>,
+    pub subsections: Vec<SubsectionSize>,
+    pub largest_types: Vec<RecordSize>,
+    pub largest_symbols: Vec<RecordSize>,
+}
+
+#[derive(Debug)]
+pub struct StreamSize {
+    pub index: u16,
+    pub name: Option<String>,
+    // absent for nil streams
+    pub bytes: Option<u32>,
+}
+
+#[derive(Debug)]
+pub struct ModuleSize {
+    pub name: String,
+    pub stream_bytes: u32,
+    pub symbol_bytes: u32,
+    pub c11_bytes: u32,
+    pub c13_bytes: u32,
+}
+
+#[derive(Debug)]
+pub struct SubsectionSize {
+    pub kind: DebugSubsectionRecordType,
+    pub count: u32,
+    pub bytes: u64,
+}
+
+#[derive(Debug)]
+pub struct RecordSize {
+    // the type index for types and the ordinal of the record for symbols
+    pub index: u32,
+    pub kind: u16,
+    pub bytes: u32,
+}
+
+pub(crate) fn add_subsection(stats: &mut Vec<SubsectionSize>, kind: DebugSubsectionRecordType, bytes: u64) {
+    match stats.iter_mut().find(|s| s.kind == kind) {
+        Some(entry) => {
+            entry.count += 1;
+            entry.bytes += bytes;
+        }
+        None => stats.push(SubsectionSize {
+            kind,
+            count: 1,
+            bytes,
+        }),
+    }
+}
+
+pub(crate) fn largest_records<'a, I>(records: I, top_n: usize) -> Vec<RecordSize>
+where
+    I: IntoIterator<Item = (u32, &'a RawRecord)>,
+{
+    let mut sizes: Vec<RecordSize> = records
+        .into_iter()
+        .map(|(index, record)| RecordSize {
+            index,
+            kind: record.kind,
+            // length prefix and kind
+            bytes: record.data.len() as u32 + 4,
+        })
+        .collect();
+    sizes.sort_by_key(|size| std::cmp::Reverse(size.bytes));
+    sizes.truncate(top_n);
+    sizes
+}
+
+// a SizeBench-like breakdown of the TPI and IPI streams by module
+#[derive(Debug)]
+pub struct ModuleTypeCosts {
+    pub modules: Vec<ModuleTypeCost>,
+    // records not reachable from any module
+    pub unattributed_type_bytes: u64,
+    pub unattributed_id_bytes: u64,
+}
+
+#[derive(Debug)]
+pub struct ModuleTypeCost {
+    pub name: String,
+    pub type_count: u32,
+    // records shared by several modules are split evenly between them
+    pub type_bytes: u64,
+    // records referenced by this module only
+    pub exclusive_type_bytes: u64,
+    pub id_bytes: u64,
+}
+
+// raw indices of the TPI and IPI records referenced by a module or a record
+#[derive(Debug, Default)]
+pub(crate) struct RecordRefs {
+    pub types: Vec<u32>,
+    pub ids: Vec<u32>,
+}
+
+// walks the records transitively referenced by every module, `types` and `ids` hold the size
+// and the references of every record in index order
+pub(crate) fn module_type_costs(
+    modules: Vec<(String, RecordRefs)>,
+    types: &[(u32, RecordRefs)],
+    ids: &[(u32, RecordRefs)],
+) -> ModuleTypeCosts {
+    let slot = |index: u32, len: usize| {
+        let slot = index.checked_sub(FIRST_NON_BUILTIN_TYPE)? as usize;
+        (slot < len).then_some(slot)
+    };
+
+    let mut type_refs = vec![0u32; types.len()];
+    let mut id_refs = vec![0u32; ids.len()];
+    let mut seen_types = vec![false; types.len()];
+    let mut seen_ids = vec![false; ids.len()];
+    let mut reached = vec![];
+
+    for (name, roots) in modules {
+        let mut module_types = vec![];
+        let mut module_ids = vec![];
+        let mut type_stack = roots.types;
+        let mut id_stack = roots.ids;
+        loop {
+            if let Some(index) = id_stack.pop() {
+                let Some(slot) = slot(index, ids.len()).filter(|&slot| !seen_ids[slot]) else {
+                    continue;
+                };
+                seen_ids[slot] = true;
+                module_ids.push(slot);
+                type_stack.extend(&ids[slot].1.types);
+                id_stack.extend(&ids[slot].1.ids);
+            } else if let Some(index) = type_stack.pop() {
+                let Some(slot) = slot(index, types.len()).filter(|&slot| !seen_types[slot]) else {
+                    continue;
+                };
+                seen_types[slot] = true;
+                module_types.push(slot);
+                type_stack.extend(&types[slot].1.types);
+            } else {
+                break;
+            }
+        }
+        for &slot in &module_types {
+            seen_types[slot] = false;
+            type_refs[slot] += 1;
+        }
+        for &slot in &module_ids {
+            seen_ids[slot] = false;
+            id_refs[slot] += 1;
+        }
+        reached.push((name, module_types, module_ids));
+    }
+
+    let modules = reached
+        .into_iter()
+        .map(|(name, module_types, module_ids)| {
+            let mut cost = ModuleTypeCost {
+                name,
+                type_count: module_types.len() as u32,
+                type_bytes: 0,
+                exclusive_type_bytes: 0,
+                id_bytes: 0,
+            };
+            for slot in module_types {
+                let bytes = u64::from(types[slot].0);
+                cost.type_bytes += bytes / u64::from(type_refs[slot]);
+                if type_refs[slot] == 1 {
+                    cost.exclusive_type_bytes += bytes;
+                }
+            }
+            for slot in module_ids {
+                cost.id_bytes += u64::from(ids[slot].0) / u64::from(id_refs[slot]);
+            }
+            cost
+        })
+        .collect();
+
+    let unattributed = |records: &[(u32, RecordRefs)], refs: &[u32]| {
+        records
+            .iter()
+            .zip(refs)
+            .filter(|(_, &refs)| refs == 0)
+            .map(|((bytes, _), _)| u64::from(*bytes))
+            .sum()
+    };
+    ModuleTypeCosts {
+        modules,
+        unattributed_type_bytes: unattributed(types, &type_refs),
+        unattributed_id_bytes: unattributed(ids, &id_refs),
+    }
+}
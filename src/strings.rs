@@ -2,18 +2,18 @@ use std::io::Write;
 
 use declio::util::{Bytes, PrefixVec};
 use declio::{magic_bytes, Decode, Encode, EncodedSize};
-use modular_bitfield::BitfieldSpecifier;
 
-use crate::hash::hash_v1;
+use crate::hash::{hash_v1, hash_v2};
 use crate::result::Result;
-use crate::{constants, impl_bitfield_specifier_codecs, StringOffset};
+use crate::utils::NameMatching;
+use crate::{constants, u32_enum, StringOffset};
 
 magic_bytes! {
     #[derive(Debug)]
     StringsSignature(&0xEFFEEFFEu32.to_le_bytes());
 }
 
-#[derive(Debug, Encode, Decode, EncodedSize)]
+#[derive(Debug, PartialEq, Eq, Encode, Decode, EncodedSize)]
 #[declio(ctx_is = "constants::ENDIANESS")]
 pub struct Strings {
     signature: StringsSignature,
@@ -30,22 +30,91 @@ impl Strings {
         let str = &self.bytes[offset.0 as usize..].split(|&n| n == 0).next()?;
         std::str::from_utf8(str).ok()
     }
+
+    pub fn offset_of(&self, str: &str) -> Option<StringOffset> {
+        self.find(str, NameMatching::Exact)
+    }
+
+    // every string of the table in the order of the buffer, skipping the empty one at offset zero
+    pub fn iter(&self) -> impl Iterator<Item = (StringOffset, &str)> {
+        let mut offset = 0;
+        self.bytes.split(|&n| n == 0).filter_map(move |str| {
+            let cur = offset;
+            offset += str.len() as u32 + 1;
+            let str = std::str::from_utf8(str).ok().filter(|str| !str.is_empty())?;
+            Some((StringOffset(cur), str))
+        })
+    }
+
+    pub fn hash_version(&self) -> HashVersion {
+        self.hash_version
+    }
+
+    pub fn find(&self, name: &str, matching: NameMatching) -> Option<StringOffset> {
+        let buckets = self.ids.len() as u32;
+        let slots: Box<dyn Iterator<Item = u32>> = match (self.hash_version, matching) {
+            // the V2 hash is case-sensitive, so other cases can be in any bucket
+            (HashVersion::V2, NameMatching::CaseInsensitive | NameMatching::PreferExact) => {
+                Box::new(self.ids.iter().copied().filter(|&offset| offset != 0))
+            }
+            _ => {
+                let start = self.hash_version.hash(name.as_bytes()) % buckets.max(1);
+                let probe = (0..buckets).map(move |i| self.ids[((start + i) % buckets) as usize]);
+                Box::new(probe.take_while(|&offset| offset != 0))
+            }
+        };
+        let candidates =
+            slots.filter_map(|offset| Some((StringOffset(offset), self.get(StringOffset(offset))?)));
+        matching.find(name, candidates)
+    }
 }
 
 #[derive(Debug)]
 pub(crate) struct StringsBuilder {
     bytes: Vec<u8>,
-    offsets: Vec<(u32, u32)>,
+    offsets: Vec<u32>,
+    hash_version: HashVersion,
 }
 
 impl StringsBuilder {
-    #[allow(unused)]
-    pub fn add(&mut self, str: &str) -> Result<()> {
-        let offset = self.bytes.len();
+    pub fn new(hash_version: HashVersion) -> Self {
+        Self {
+            bytes: vec![0],
+            offsets: vec![],
+            hash_version,
+        }
+    }
+
+    // keeps the buffer of an existing table as it is, so the offsets referring to it stay valid
+    pub fn from_strings(strings: Strings) -> Self {
+        let mut bytes = strings.bytes;
+        if bytes.is_empty() {
+            bytes.push(0);
+        }
+        let mut offsets: Vec<u32> = strings.ids.into_iter().filter(|&offset| offset != 0).collect();
+        offsets.sort_unstable();
+        Self {
+            bytes,
+            offsets,
+            hash_version: strings.hash_version,
+        }
+    }
+
+    pub fn add(&mut self, str: &str) -> Result<StringOffset> {
+        let offset = self.bytes.len() as u32;
         self.bytes.write_all(str.as_bytes())?;
         self.bytes.write_all(b"\0")?;
-        self.offsets.push((hash_v1(str.as_bytes()), offset as u32));
-        Ok(())
+        self.offsets.push(offset);
+        Ok(StringOffset(offset))
+    }
+
+    // the strings are hashed when the table is built, so the version can change until then
+    pub fn set_hash_version(&mut self, hash_version: HashVersion) {
+        self.hash_version = hash_version;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.offsets.is_empty()
     }
 
     pub fn build(self) -> Strings {
@@ -53,12 +122,15 @@ impl StringsBuilder {
         let mut ids = vec![0; buckets as usize];
         let count = self.offsets.len() as u32;
 
-        for (hash, offset) in self.offsets {
+        for offset in self.offsets {
+            let str = &self.bytes[offset as usize..];
+            let len = str.iter().position(|&b| b == 0).unwrap_or(str.len());
+            let hash = self.hash_version.hash(&str[..len]);
             for i in 0..buckets {
-                let slot = (hash + i) % buckets;
+                let slot = (hash % buckets + i) % buckets;
                 match ids.get_mut(slot as usize) {
-                    Some(el) if *el != 0 => {
-                        *el = offset as u32;
+                    Some(el) if *el == 0 => {
+                        *el = offset;
                         break;
                     }
                     _ => {}
@@ -68,7 +140,7 @@ impl StringsBuilder {
 
         Strings {
             signature: StringsSignature,
-            hash_version: HashVersion::V1,
+            hash_version: self.hash_version,
             bytes: self.bytes,
             ids,
             name_count: count,
@@ -78,21 +150,27 @@ impl StringsBuilder {
 
 impl Default for StringsBuilder {
     fn default() -> Self {
-        Self {
-            bytes: vec![0],
-            offsets: vec![],
-        }
+        Self::new(HashVersion::V1)
     }
 }
 
-#[derive(Debug, Clone, Copy, BitfieldSpecifier)]
-#[bits = 32]
-enum HashVersion {
-    V1 = 1,
-    V2 = 2,
+u32_enum! {
+    #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+    pub enum HashVersion {
+        #[default]
+        V1 = 1,
+        V2 = 2,
+    }
 }
 
-impl_bitfield_specifier_codecs!(HashVersion);
+impl HashVersion {
+    pub(crate) fn hash(self, bytes: &[u8]) -> u32 {
+        match self {
+            HashVersion::V1 => hash_v1(bytes),
+            HashVersion::V2 => hash_v2(bytes),
+        }
+    }
+}
 
 mod bucket_counts {
     static BUCKET_COUNTS: [(u32, u32); 28] = generate_buckets();
@@ -6,15 +6,20 @@ use declio::ctx::Len;
 use declio::{magic_bytes, Decode, Encode, EncodedSize};
 
 use crate::codeview::NamedSymbol;
+use crate::diagnostics::ParseLimits;
 use crate::hash::hash_v1;
 use crate::result::Result;
-use crate::utils::CaseInsensitiveStr;
+use crate::symbols::Symbols;
+use crate::utils::{CaseInsensitiveStr, NameMatching};
 use crate::{constants, SymbolOffset};
 
 const HDR_VERSION: u32 = 0xeffe0000 + 19990810;
 const IPHR_HASH: usize = 4096;
 const BITMAP_SIZE: usize = (IPHR_HASH + 32) / 32;
 
+// bucket offsets are computed for the in-memory records of 32-bit MSVC, which take 12 bytes
+const HASH_RECORD_CALC_SIZE: u32 = 12;
+
 type Bitmap = [u32; BITMAP_SIZE];
 
 magic_bytes! {
@@ -26,56 +31,52 @@ magic_bytes! {
 
 pub type Globals = SymbolMap;
 
-#[derive(Debug, EncodedSize)]
+// the hash table of a GSI, records are grouped into buckets by the hash of their name and ordered by
+// name within a bucket, case-insensitively
+#[derive(Debug, PartialEq, Eq, EncodedSize)]
 pub struct SymbolMap {
-    hash_records: Vec<IndexRecord>,
+    hash_records: Vec<HashRecord>,
     bitmap: Bitmap,
     buckets: Vec<u32>,
 }
 
 impl SymbolMap {
-    pub(crate) fn from_symbols<S>(mapping: &BTreeMap<SymbolOffset, S>) -> Self
+    // symbols missing from `ref_counts` are referenced once
+    pub(crate) fn from_symbols<S>(
+        mapping: &BTreeMap<SymbolOffset, S>,
+        ref_counts: &BTreeMap<SymbolOffset, u32>,
+    ) -> Self
     where
         S: NamedSymbol,
     {
+        let mut records: Vec<(u32, Option<CaseInsensitiveStr>, SymbolOffset)> = mapping
+            .iter()
+            .map(|(offset, el)| {
+                let bucket = Self::bucket_of(el.name().unwrap_or_default());
+                (bucket, el.name().map(CaseInsensitiveStr), *offset)
+            })
+            .collect();
+        records.sort();
+
+        // record counts turned into the index of the first record of every bucket
         let mut bucket_starts = [0u32; IPHR_HASH];
-        let mut hash_records = Vec::with_capacity(mapping.len());
-
-        for (offset, el) in mapping {
-            let hash = hash_v1(el.name().unwrap_or_default().as_bytes());
-            let bucket_index = hash % IPHR_HASH as u32;
-            bucket_starts[bucket_index as usize] += 1;
-            hash_records.push(IndexRecord::new(SymbolOffset(offset.0 + 1)));
+        for (bucket, _, _) in &records {
+            bucket_starts[*bucket as usize] += 1;
         }
-
         let mut sum = 0;
         for start in bucket_starts.iter_mut() {
-            let val = *start;
-            *start += sum;
-            sum += val;
-        }
-
-        let mut slice = &bucket_starts[..];
-        while let [start, tail @ ..] = slice {
-            let end = tail.first().copied().unwrap_or(mapping.len() as u32);
-
-            hash_records[*start as usize..end as usize].sort_by(|lhs, rhs| {
-                let lhs_name = mapping
-                    .get(&lhs.offset())
-                    .and_then(S::name)
-                    .map(CaseInsensitiveStr);
-                let rhs_name = mapping
-                    .get(&rhs.offset())
-                    .and_then(S::name)
-                    .map(CaseInsensitiveStr);
-                lhs_name
-                    .cmp(&rhs_name)
-                    .then_with(|| lhs.offset().cmp(&rhs.offset()))
-            });
-
-            slice = tail;
+            let count = *start;
+            *start = sum;
+            sum += count;
         }
 
+        let hash_records = records
+            .iter()
+            .map(|(_, _, offset)| {
+                let ref_count = ref_counts.get(offset).copied().unwrap_or(1);
+                HashRecord::new(*offset, ref_count)
+            })
+            .collect();
         let (bitmap, buckets) = allocate_buckets(&bucket_starts, mapping.len() as u32);
         Self {
             hash_records,
@@ -84,11 +85,58 @@ impl SymbolMap {
         }
     }
 
-    pub fn read_with_header<R>(mut input: R) -> Result<Self>
+    // the records in the order they are stored in, bucket by bucket
+    pub fn hash_records(&self) -> &[HashRecord] {
+        &self.hash_records
+    }
+
+    // the non-empty buckets along with their index, which is the name hash modulo the bucket count
+    pub fn buckets(&self) -> impl Iterator<Item = (u32, &[HashRecord])> + '_ {
+        let indices = (0..IPHR_HASH as u32)
+            .filter(|&index| self.bitmap[index as usize / 32] & (1 << (index % 32)) != 0);
+        let starts: Vec<usize> = self
+            .buckets
+            .iter()
+            .map(|&offset| (offset / HASH_RECORD_CALC_SIZE) as usize)
+            .collect();
+        indices.zip(0..).map(move |(index, i)| {
+            let len = self.hash_records.len();
+            let start = starts[i].min(len);
+            let end = starts.get(i + 1).map_or(len, |&end| end.clamp(start, len));
+            (index, &self.hash_records[start..end])
+        })
+    }
+
+    // the bucket that a symbol with this name is placed in
+    pub fn bucket_of(name: &str) -> u32 {
+        hash_v1(name.as_bytes()) % IPHR_HASH as u32
+    }
+
+    // looks up a symbol by name, `symbols` has to be the symbol record stream the map points into
+    pub fn find(&self, symbols: &Symbols, name: &str, matching: NameMatching) -> Option<SymbolOffset> {
+        let bucket = Self::bucket_of(name);
+        let (_, records) = self.buckets().find(|&(index, _)| index == bucket)?;
+        let candidates = records.iter().filter_map(|record| {
+            let offset = record.symbol_offset();
+            Some((offset, symbols.get(offset)?.name()?))
+        });
+        matching.find(name, candidates)
+    }
+
+    // the number of references to the symbol at an offset of the symbol record stream
+    pub fn ref_count(&self, offset: SymbolOffset) -> Option<u32> {
+        self.hash_records
+            .iter()
+            .find(|record| record.symbol_offset() == offset)
+            .map(|record| record.ref_count)
+    }
+
+    pub fn read_with_header<R>(mut input: R, limits: &ParseLimits) -> Result<Self>
     where
         R: io::Read,
     {
         let gsi_header = GsiHashHeader::decode((), &mut input)?;
+        limits.check_memory(gsi_header.hr_size.into())?;
         let num_records = gsi_header.hr_size / 8;
         let hash_records = Decode::decode(Len(num_records as usize), &mut input)?;
         let bitmap: Bitmap = Decode::decode(constants::ENDIANESS, &mut input)?;
@@ -134,7 +182,7 @@ impl<Ctx> Encode<Ctx> for SymbolMap {
     }
 }
 
-#[derive(Debug, Encode, Decode, EncodedSize)]
+#[derive(Debug, PartialEq, Eq, Encode, Decode, EncodedSize)]
 #[declio(ctx_is = "constants::ENDIANESS")]
 pub(crate) struct GsiHashHeader {
     signature: SignatureVersion,
@@ -143,20 +191,29 @@ pub(crate) struct GsiHashHeader {
     num_buckets: u32,
 }
 
-#[derive(Debug, Encode, Decode, EncodedSize)]
+#[derive(Debug, PartialEq, Eq, Encode, Decode, EncodedSize)]
 #[declio(ctx_is = "constants::ENDIANESS")]
-struct IndexRecord {
+pub struct HashRecord {
+    // biased by one so that zero can mean no symbol
     offset: SymbolOffset,
     ref_count: u32,
 }
 
-impl IndexRecord {
-    pub(crate) fn new(offset: SymbolOffset) -> Self {
-        Self { offset, ref_count: 1 }
+impl HashRecord {
+    pub(crate) fn new(symbol_offset: SymbolOffset, ref_count: u32) -> Self {
+        Self {
+            offset: SymbolOffset(symbol_offset.0 + 1),
+            ref_count,
+        }
+    }
+
+    // the offset of the symbol in the symbol record stream, as accepted by `SymbolStream::get`
+    pub fn symbol_offset(&self) -> SymbolOffset {
+        SymbolOffset(self.offset.0.saturating_sub(1))
     }
 
-    pub fn offset(&self) -> SymbolOffset {
-        self.offset
+    pub fn ref_count(&self) -> u32 {
+        self.ref_count
     }
 }
 
@@ -174,7 +231,7 @@ fn allocate_buckets(bucket_starts: &[u32], size: u32) -> (Bitmap, Vec<u32>) {
                 None => {}
                 Some(&start) => {
                     *elem |= 1 << bit;
-                    buckets.push(start * 12);
+                    buckets.push(start * HASH_RECORD_CALC_SIZE);
                 }
             }
         }
@@ -1,25 +1,190 @@
 use std::io::{self, Read};
 
+use declio::Decode;
 use derive_getters::Getters;
+#[cfg(feature = "regex")]
+use regex::Regex;
 
 use crate::codeview::symbols::SymbolRecord;
-use crate::codeview::PrefixedRecord;
-use crate::result::Result;
-use crate::BufMsfStream;
+use crate::diagnostics::ReadContext;
+use crate::result::{Error, Result};
+use crate::{constants, BufMsfStream, SymbolOffset};
 
 #[derive(Debug, Getters)]
 pub struct Symbols {
     records: Vec<SymbolRecord>,
+    // the offset of every record, as referenced by the GSI hash records
+    offsets: Vec<SymbolOffset>,
 }
 
 impl Symbols {
-    pub(crate) fn read<R: io::Read + io::Seek>(mut input: BufMsfStream<R>) -> Result<Self> {
-        let mut records: Vec<SymbolRecord> = vec![];
+    pub(crate) fn read<R: io::Read + io::Seek>(
+        input: &mut BufMsfStream<R>,
+        ctx: &mut ReadContext,
+    ) -> Result<Self> {
         let len = input.get_ref().length();
         let mut sym_stream = input.by_ref().take(len.into());
-        while sym_stream.limit() > 0 {
-            records.push(PrefixedRecord::decode(&mut sym_stream)?.into_inner());
-        }
-        Ok(Self { records })
+        let decoder = ctx.records();
+        let records = ctx.read_records("symbols", 0, &mut sym_stream, |stream| {
+            let offset = SymbolOffset((u64::from(len) - stream.limit()) as u32);
+            Ok((offset, decoder.decode(stream)?))
+        })?;
+        let (offsets, records) = records.into_iter().unzip();
+        Ok(Self { records, offsets })
+    }
+
+    // the records along with their offsets, in stream order
+    pub fn into_records(self) -> impl Iterator<Item = (SymbolOffset, SymbolRecord)> {
+        self.offsets.into_iter().zip(self.records)
+    }
+
+    pub fn get(&self, offset: SymbolOffset) -> Option<&SymbolRecord> {
+        let index = self.offsets.binary_search(&offset).ok()?;
+        self.records.get(index)
+    }
+}
+
+// a symbol stream kept as bytes, to walk its records without decoding every one of them
+#[derive(Debug)]
+pub struct SymbolStreamBytes {
+    bytes: Vec<u8>,
+    // the offset of the first record, past the signature of module streams
+    start: u32,
+}
+
+impl SymbolStreamBytes {
+    pub(crate) fn new(bytes: Vec<u8>, start: u32) -> Self {
+        Self { bytes, start }
+    }
+
+    pub fn iter(&self) -> RawSymbols<'_> {
+        RawSymbols::new(&self.bytes, self.start)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RawSymbols<'a> {
+    bytes: &'a [u8],
+    offset: u32,
+}
+
+impl<'a> RawSymbols<'a> {
+    pub(crate) fn new(bytes: &'a [u8], start: u32) -> Self {
+        Self { bytes, offset: start }
+    }
+
+    // the records with a name accepted by the predicate, the names at a fixed position in their records
+    // are checked before decoding, so that records of other names are skipped without decoding them
+    pub fn filter_names<F>(
+        self,
+        mut predicate: F,
+    ) -> impl Iterator<Item = Result<(SymbolOffset, SymbolRecord)>> + 'a
+    where
+        F: FnMut(&str) -> bool + 'a,
+    {
+        self.filter_map(move |res| {
+            let (offset, raw) = match res {
+                Ok(res) => res,
+                Err(err) => return Some(Err(err)),
+            };
+            if let Some(name) = raw.name() {
+                return predicate(name).then(|| raw.decode().map(|symbol| (offset, symbol)));
+            }
+            match raw.decode() {
+                Ok(symbol) => symbol
+                    .name()
+                    .is_some_and(&mut predicate)
+                    .then_some(Ok((offset, symbol))),
+                Err(err) => Some(Err(err)),
+            }
+        })
+    }
+
+    #[cfg(feature = "regex")]
+    pub fn matching(
+        self,
+        regex: &'a Regex,
+    ) -> impl Iterator<Item = Result<(SymbolOffset, SymbolRecord)>> + 'a {
+        self.filter_names(|name| regex.is_match(name))
+    }
+}
+
+impl<'a> Iterator for RawSymbols<'a> {
+    type Item = Result<(SymbolOffset, RawSymbol<'a>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let rest = self
+            .bytes
+            .get(self.offset as usize..)
+            .filter(|rest| !rest.is_empty())?;
+        let offset = SymbolOffset(self.offset);
+        // the length covers the kind and the body
+        let len = rest
+            .get(..2)
+            .map(|len| usize::from(u16::from_le_bytes([len[0], len[1]])));
+        let Some(record) = len
+            .and_then(|len| rest.get(2..2 + len))
+            .filter(|record| record.len() >= 2)
+        else {
+            self.offset = self.bytes.len() as u32;
+            return Some(Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()));
+        };
+        self.offset += 2 + record.len() as u32;
+        let symbol = RawSymbol {
+            kind: u16::from_le_bytes([record[0], record[1]]),
+            data: &record[2..],
+        };
+        Some(Ok((offset, symbol)))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RawSymbol<'a> {
+    pub kind: u16,
+    pub data: &'a [u8],
+}
+
+impl<'a> RawSymbol<'a> {
+    // the name of the record when it's at a fixed position, which excludes records like S_CONSTANT with
+    // a variable-length value before the name
+    pub fn name(&self) -> Option<&'a str> {
+        let start = match self.kind {
+            constants::S_UNAMESPACE => 0,
+            constants::S_UDT | constants::S_COBOLUDT => 4,
+            constants::S_REGISTER | constants::S_LOCAL => 6,
+            constants::S_LABEL32 => 7,
+            constants::S_BPREL32 => 8,
+            constants::S_PUB32
+            | constants::S_LDATA32
+            | constants::S_GDATA32
+            | constants::S_LMANDATA
+            | constants::S_GMANDATA
+            | constants::S_LTHREAD32
+            | constants::S_GTHREAD32
+            | constants::S_PROCREF
+            | constants::S_LPROCREF
+            | constants::S_DATAREF
+            | constants::S_ANNOTATIONREF
+            | constants::S_REGREL32 => 10,
+            constants::S_THUNK32 => 21,
+            constants::S_LPROC32
+            | constants::S_GPROC32
+            | constants::S_LPROC32_ID
+            | constants::S_GPROC32_ID
+            | constants::S_LPROC32_DPC
+            | constants::S_LPROC32_DPC_ID => 35,
+            _ => return None,
+        };
+        let name = self.data.get(start..)?;
+        let end = name.iter().position(|&byte| byte == 0)?;
+        std::str::from_utf8(&name[..end]).ok()
+    }
+
+    pub fn decode(&self) -> Result<SymbolRecord> {
+        let kind = self.kind.to_le_bytes();
+        SymbolRecord::decode((), &mut kind.chain(self.data)).map_err(|source| Error::RecordFailed {
+            kind: self.kind,
+            source,
+        })
     }
 }
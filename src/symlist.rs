@@ -0,0 +1,239 @@
+use crate::builders::{ModuleBuilder, PdbBuilder};
+use crate::codeview::symbols::{Procedure, ProcedureProperties, Public, PublicProperties};
+use crate::codeview::types::BuiltinType;
+use crate::codeview::DataRegionOffset;
+use crate::dbi::{SectionCharacteristics, SectionContrib, SectionHeader};
+use crate::json::{self, JsonValue};
+use crate::result::{Error, Result};
+use crate::utils::StrBuf;
+use crate::SymbolOffset;
+
+const MODULE_NAME: &str = "* Symbols *";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListedSymbolKind {
+    Function,
+    Data,
+}
+
+#[derive(Debug)]
+pub struct ListedSymbol {
+    pub address: u64,
+    // zero when unknown, functions then extend up to the next symbol
+    pub size: u32,
+    pub name: String,
+    // inferred from the section when absent
+    pub kind: Option<ListedSymbolKind>,
+}
+
+// a flat symbol list as exported by disassemblers, e.g. from IDA or Ghidra scripts
+#[derive(Debug, Default)]
+pub struct SymbolList {
+    pub symbols: Vec<ListedSymbol>,
+}
+
+impl SymbolList {
+    // accepts an array of objects with `address`, `size`, `name` and `type` keys,
+    // either at the top level or under `symbols`, string addresses are read as hex
+    pub fn parse_json(json: &str) -> Result<Self> {
+        let root = json::parse(json)?;
+        let entries = match root.get("symbols").unwrap_or(&root) {
+            JsonValue::Array(entries) => entries,
+            _ => return Err(malformed()),
+        };
+
+        let mut symbols = Vec::with_capacity(entries.len());
+        for entry in entries {
+            let number = |key: &str, radix| match entry.get(key) {
+                Some(JsonValue::Number(num)) => parse_number(num, 10),
+                Some(JsonValue::String(num)) => parse_number(num, radix),
+                None | Some(JsonValue::Null) => Ok(0),
+                _ => Err(malformed()),
+            };
+            let name = entry
+                .get("name")
+                .and_then(JsonValue::as_str)
+                .ok_or_else(malformed)?;
+            symbols.push(ListedSymbol {
+                address: number("address", 16)?,
+                size: number("size", 10)? as u32,
+                name: name.to_owned(),
+                kind: entry.get("type").and_then(JsonValue::as_str).and_then(parse_kind),
+            });
+        }
+        Ok(Self { symbols })
+    }
+
+    // accepts `address,size,name[,type]` rows with an optional header, addresses are read as hex
+    pub fn parse_csv(csv: &str) -> Result<Self> {
+        let mut symbols = vec![];
+        for (i, line) in csv.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let fields = split_csv_line(line)?;
+            let (address, size, name) = match &fields[..] {
+                [address, size, name, ..] => (address, size, name),
+                _ => return Err(malformed()),
+            };
+            let address = match parse_number(address, 16) {
+                Ok(address) => address,
+                // the first row can be a header
+                Err(_) if i == 0 => continue,
+                Err(err) => return Err(err),
+            };
+            let size = if size.trim().is_empty() {
+                0
+            } else {
+                parse_number(size, 10)? as u32
+            };
+            symbols.push(ListedSymbol {
+                address,
+                size,
+                name: name.clone(),
+                kind: fields.get(3).and_then(|kind| parse_kind(kind)),
+            });
+        }
+        Ok(Self { symbols })
+    }
+
+    // adds the sections, a public for every symbol and a procedure for every function,
+    // symbols outside of the sections are skipped, pass an `image_base` of zero for RVAs
+    pub fn populate(
+        &self,
+        builder: &mut PdbBuilder,
+        image_base: u64,
+        sections: Vec<SectionHeader>,
+    ) -> Result<()> {
+        let ranges: Vec<(u64, u64, u32)> = sections
+            .iter()
+            .map(|header| {
+                let start = u64::from(header.virtual_address);
+                (
+                    start,
+                    start + u64::from(header.virtual_size),
+                    header.characteristics,
+                )
+            })
+            .collect();
+        builder.dbi().add_sections(sections);
+
+        let mut located = vec![];
+        for symbol in &self.symbols {
+            let Some(rva) = symbol.address.checked_sub(image_base) else {
+                continue;
+            };
+            let Some((index, &(start, end, characteristics))) = (1..)
+                .zip(&ranges)
+                .find(|(_, (start, end, _))| (*start..*end).contains(&rva))
+            else {
+                continue;
+            };
+            let offset = (rva - start) as u32;
+            located.push((symbol, index, offset, (end - start) as u32, characteristics));
+        }
+        located.sort_by_key(|&(_, segment, offset, _, _)| (segment, offset));
+
+        let mut module: Option<ModuleBuilder> = None;
+        for (i, &(symbol, segment, offset, section_size, characteristics)) in located.iter().enumerate() {
+            let is_code = SectionCharacteristics::from(characteristics).cnt_code();
+            let is_function = symbol
+                .kind
+                .map_or(is_code, |kind| kind == ListedSymbolKind::Function);
+            builder.dbi().symbols().add(Public {
+                properties: PublicProperties::new()
+                    .with_is_code(is_code)
+                    .with_is_function(is_function),
+                offset: DataRegionOffset::new(offset, segment),
+                name: StrBuf::new(symbol.name.clone()),
+            })?;
+
+            let size = if symbol.size != 0 {
+                symbol.size
+            } else {
+                let end = located[i + 1..]
+                    .iter()
+                    .find(|next| next.1 != segment || next.2 > offset)
+                    .filter(|next| next.1 == segment)
+                    .map_or(section_size, |next| next.2);
+                end - offset
+            };
+            if size != 0 {
+                let contrib = SectionContrib::new(segment, offset as i32, size, characteristics, 0);
+                builder.dbi().add_section_contrib(contrib);
+            }
+            if is_function {
+                let module = module.get_or_insert_with(|| {
+                    let contrib = SectionContrib::new(segment, offset as i32, size, characteristics, 0);
+                    ModuleBuilder::new(MODULE_NAME.to_owned(), MODULE_NAME.to_owned(), contrib)
+                });
+                let proc = Procedure {
+                    parent: None,
+                    end: SymbolOffset(0),
+                    next: None,
+                    code_size: size,
+                    dbg_start_offset: 0,
+                    dbg_end_offset: size,
+                    function_type: BuiltinType::NotTranslated.into(),
+                    code_offset: DataRegionOffset::new(offset, segment),
+                    properties: ProcedureProperties::new(),
+                    name: StrBuf::new(symbol.name.clone()),
+                };
+                module.add_procedure(proc, true);
+            }
+        }
+        if let Some(module) = module {
+            builder.dbi().add_module(module);
+        }
+        Ok(())
+    }
+}
+
+fn parse_kind(str: &str) -> Option<ListedSymbolKind> {
+    match str.trim().to_ascii_lowercase().as_str() {
+        "function" | "func" | "code" | "f" => Some(ListedSymbolKind::Function),
+        "data" | "label" | "object" | "d" => Some(ListedSymbolKind::Data),
+        _ => None,
+    }
+}
+
+// a `0x` prefix always means hex
+fn parse_number(str: &str, radix: u32) -> Result<u64> {
+    let str = str.trim();
+    let res = match str.strip_prefix("0x").or_else(|| str.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => u64::from_str_radix(str, radix),
+    };
+    res.map_err(|_| malformed())
+}
+
+fn split_csv_line(line: &str) -> Result<Vec<String>> {
+    let mut fields = vec![];
+    let mut chars = line.chars().peekable();
+    loop {
+        let mut field = String::new();
+        if chars.next_if_eq(&'"').is_some() {
+            loop {
+                match chars.next() {
+                    Some('"') if chars.next_if_eq(&'"').is_some() => field.push('"'),
+                    Some('"') => break,
+                    Some(c) => field.push(c),
+                    None => return Err(malformed()),
+                }
+            }
+            while chars.next_if(|&c| c != ',').is_some() {}
+        } else {
+            while let Some(c) = chars.next_if(|&c| c != ',') {
+                field.push(c);
+            }
+        }
+        fields.push(field);
+        if chars.next().is_none() {
+            return Ok(fields);
+        }
+    }
+}
+
+fn malformed() -> Error {
+    Error::MalformedInput("symbol list")
+}
@@ -0,0 +1,82 @@
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::result::{Error, Result};
+use crate::symstore::store_path;
+use crate::{Guid, PdbFile};
+
+pub const MICROSOFT_SYMBOL_SERVER: &str = "https://msdl.microsoft.com/download/symbols";
+
+#[derive(Debug, Clone)]
+pub struct SymbolServer {
+    url: String,
+    cache_dir: PathBuf,
+}
+
+impl SymbolServer {
+    pub fn new<S: Into<String>, P: Into<PathBuf>>(url: S, cache_dir: P) -> Self {
+        Self {
+            url: url.into(),
+            cache_dir: cache_dir.into(),
+        }
+    }
+
+    pub fn microsoft<P: Into<PathBuf>>(cache_dir: P) -> Self {
+        Self::new(MICROSOFT_SYMBOL_SERVER, cache_dir)
+    }
+
+    // the PDB name, GUID and age come from the CodeView debug directory entry of the PE
+    pub fn fetch(&self, pdb_name: &str, guid: &Guid, age: u32) -> Result<PdbFile<File>> {
+        let path = self.fetch_path(pdb_name, guid, age)?;
+        PdbFile::open(File::open(path)?)
+    }
+
+    // downloads the PDB unless it's already in the cache and returns its local path
+    pub fn fetch_path(&self, pdb_name: &str, guid: &Guid, age: u32) -> Result<PathBuf> {
+        let key = store_path(pdb_name, guid, age);
+        let path = self.cache_dir.join(&key);
+        if path.is_file() {
+            return Ok(path);
+        }
+
+        let url = format!("{}/{}", self.url.trim_end_matches('/'), key);
+        match ureq::get(&url).call() {
+            Ok(response) => download(response.into_reader(), &path)?,
+            // servers often only have the compressed `.pd_` variant
+            #[cfg(feature = "cab")]
+            Err(ureq::Error::Status(404, _)) => {
+                let mut url = url;
+                url.pop();
+                url.push('_');
+                let mut buf = vec![];
+                let response = ureq::get(&url)
+                    .call()
+                    .map_err(|err| Error::DownloadFailed(Box::new(err)))?;
+                io::Read::read_to_end(&mut response.into_reader(), &mut buf)?;
+                let file = crate::cab::extract(io::Cursor::new(buf))?
+                    .into_iter()
+                    .next()
+                    .ok_or(Error::UnsupportedFeature("empty cabinet"))?;
+                download(&file.data[..], &path)?;
+            }
+            Err(err) => return Err(Error::DownloadFailed(Box::new(err))),
+        }
+        Ok(path)
+    }
+}
+
+// writes to a temporary file first so that an interrupted download does not poison the cache
+fn download<R: io::Read>(mut reader: R, path: &Path) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let tmp = path.with_extension("part");
+    let res = File::create(&tmp).and_then(|mut file| io::copy(&mut reader, &mut file));
+    if let Err(err) = res {
+        let _ = fs::remove_file(&tmp);
+        return Err(Error::IoFailed(err));
+    }
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
@@ -0,0 +1,179 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::result::{Error, Result};
+use crate::{Guid, PdbFile};
+
+const ADMIN_DIR: &str = "000Admin";
+
+// the relative location of a PDB in a symbol store, `name/<GUID><age>/name`
+pub fn store_path(pdb_name: &str, guid: &Guid, age: u32) -> String {
+    let name = file_name(pdb_name);
+    format!("{name}/{}{age:X}/{name}", guid_key(guid))
+}
+
+// the relative location of an executable in a symbol store, `name/<timestamp><image size>/name`
+pub fn pe_store_path(pe_name: &str, timestamp: u32, image_size: u32) -> String {
+    let name = file_name(pe_name);
+    format!("{name}/{timestamp:08X}{image_size:x}/{name}")
+}
+
+// publishes files into a directory laid out like the ones produced by `symstore.exe add`
+#[derive(Debug)]
+pub struct SymbolStoreWriter {
+    root: PathBuf,
+    product: String,
+    version: String,
+    comment: String,
+    files: Vec<(String, String, Vec<u8>)>,
+}
+
+impl SymbolStoreWriter {
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        Self {
+            root: root.into(),
+            product: String::new(),
+            version: String::new(),
+            comment: String::new(),
+            files: vec![],
+        }
+    }
+
+    pub fn product<S: Into<String>>(&mut self, product: S) -> &mut Self {
+        self.product = product.into();
+        self
+    }
+
+    pub fn version<S: Into<String>>(&mut self, version: S) -> &mut Self {
+        self.version = version.into();
+        self
+    }
+
+    pub fn comment<S: Into<String>>(&mut self, comment: S) -> &mut Self {
+        self.comment = comment.into();
+        self
+    }
+
+    // the GUID is taken from the PDB stream and the age from the DBI stream, as referenced by executables
+    pub fn add_pdb<S: Into<String>>(&mut self, name: S, data: Vec<u8>) -> Result<&mut Self> {
+        let name = name.into();
+        let mut pdb = PdbFile::open(io::Cursor::new(&data))?;
+        let info = pdb.get_info()?;
+        let dbi = pdb.get_dbi()?;
+        let key = store_path(&name, &info.header().guid, dbi.header().age);
+        self.files.push((key, name, data));
+        Ok(self)
+    }
+
+    pub fn add_pe<S: Into<String>>(&mut self, name: S, data: Vec<u8>) -> Result<&mut Self> {
+        let name = name.into();
+        let (timestamp, image_size) = read_pe_key(&data)?;
+        let key = pe_store_path(&name, timestamp, image_size);
+        self.files.push((key, name, data));
+        Ok(self)
+    }
+
+    // writes the files and records a new transaction, returns the transaction ID
+    pub fn commit(self) -> Result<String> {
+        let admin = self.root.join(ADMIN_DIR);
+        fs::create_dir_all(&admin)?;
+        if !self.root.join("pingme.txt").exists() {
+            fs::write(self.root.join("pingme.txt"), b"")?;
+        }
+
+        let last_id = match fs::read_to_string(admin.join("lastid.txt")) {
+            Ok(str) => str
+                .trim()
+                .parse()
+                .map_err(|_| Error::MalformedInput("lastid.txt"))?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => 0u32,
+            Err(err) => return Err(err.into()),
+        };
+        let id = format!("{:010}", last_id + 1);
+
+        let mut transaction = vec![];
+        for (key, name, data) in &self.files {
+            let path = self.root.join(key);
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            fs::write(&path, data)?;
+            let dir = key.rsplit_once('/').map_or(key.as_str(), |(dir, _)| dir);
+            write!(transaction, "\"{}\",\"{}\"\r\n", dir.replace('/', "\\"), name)?;
+        }
+        fs::write(admin.join(&id), transaction)?;
+
+        let (date, time) = format_timestamp(SystemTime::now());
+        let entry = format!(
+            "{id},add,file,{date},{time},\"{}\",\"{}\",\"{}\",\r\n",
+            self.product, self.version, self.comment
+        );
+        for index in ["history.txt", "server.txt"] {
+            let mut file = fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(admin.join(index))?;
+            file.write_all(entry.as_bytes())?;
+        }
+        fs::write(admin.join("lastid.txt"), format!("{id}\r\n"))?;
+
+        Ok(id)
+    }
+}
+
+fn file_name(path: &str) -> &str {
+    path.rsplit(['\\', '/']).next().unwrap_or(path)
+}
+
+fn guid_key(guid: &Guid) -> String {
+    let bytes = guid.bytes();
+    let data1 = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    let data2 = u16::from_le_bytes([bytes[4], bytes[5]]);
+    let data3 = u16::from_le_bytes([bytes[6], bytes[7]]);
+    let data4: String = bytes[8..].iter().map(|b| format!("{b:02X}")).collect();
+    format!("{data1:08X}{data2:04X}{data3:04X}{data4}")
+}
+
+// the COFF timestamp and the image size from the optional header
+fn read_pe_key(data: &[u8]) -> Result<(u32, u32)> {
+    let u32_at = |offset: usize| {
+        data.get(offset..offset + 4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .ok_or(Error::MalformedInput("truncated PE header"))
+    };
+    if !data.starts_with(b"MZ") {
+        return Err(Error::MalformedInput("not a PE file"));
+    }
+    let pe_offset = u32_at(0x3C)? as usize;
+    if data.get(pe_offset..pe_offset + 4) != Some(b"PE\0\0") {
+        return Err(Error::MalformedInput("not a PE file"));
+    }
+    let timestamp = u32_at(pe_offset + 8)?;
+    // the signature and the COFF header precede the optional header
+    let image_size = u32_at(pe_offset + 24 + 56)?;
+    Ok((timestamp, image_size))
+}
+
+// formats the time as `MM/DD/YYYY` and `HH:MM:SS` in UTC
+fn format_timestamp(time: SystemTime) -> (String, String) {
+    let secs = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let days = (secs / 86400) as i64;
+    let rem = secs % 86400;
+
+    // days to a civil date, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    let date = format!("{month:02}/{day:02}/{year}");
+    let time = format!("{:02}:{:02}:{:02}", rem / 3600, rem / 60 % 60, rem % 60);
+    (date, time)
+}
@@ -0,0 +1,151 @@
+use crate::builders::TpiBuilder;
+use crate::codeview::types::{StructRecord, TypeRecord, UnionRecord};
+use crate::result::{Error, Result};
+use crate::types::FIRST_NON_BUILTIN_TYPE;
+use crate::{Integer, TypeIndex};
+
+// a graph of type records in no particular order, e.g. converted from another debug format,
+// records reference each other by the symbolic indices handed out by the graph and builtins as usual
+#[derive(Debug, Default)]
+pub struct TypeGraph {
+    records: Vec<Option<TypeRecord>>,
+}
+
+impl TypeGraph {
+    // reserves a symbolic index for a record defined later, e.g. one referencing itself
+    pub fn declare(&mut self) -> TypeIndex {
+        self.records.push(None);
+        symbolic(self.records.len() - 1)
+    }
+
+    pub fn define(&mut self, index: TypeIndex, record: TypeRecord) -> &mut Self {
+        if let Some(slot) = node_of(index).and_then(|node| self.records.get_mut(node)) {
+            *slot = Some(record);
+        }
+        self
+    }
+
+    pub fn add(&mut self, record: TypeRecord) -> TypeIndex {
+        self.records.push(Some(record));
+        symbolic(self.records.len() - 1)
+    }
+
+    // adds the records so that every one of them comes after the records it references, cycles are
+    // broken with forward references to the classes, structs or unions they pass through,
+    // returns the final index of every symbolic index in the order they were handed out
+    pub fn commit(self, tpi: &mut TpiBuilder) -> Result<Vec<TypeIndex>> {
+        let count = self.records.len();
+        let mut commit = Commit {
+            records: self.records,
+            indices: vec![None; count],
+            forward_refs: vec![None; count],
+            visiting: vec![false; count],
+        };
+        for node in 0..count {
+            commit.visit(tpi, node)?;
+        }
+        Ok(commit.indices.into_iter().flatten().collect())
+    }
+}
+
+#[derive(Debug)]
+struct Commit {
+    records: Vec<Option<TypeRecord>>,
+    indices: Vec<Option<TypeIndex>>,
+    forward_refs: Vec<Option<TypeIndex>>,
+    visiting: Vec<bool>,
+}
+
+impl Commit {
+    fn visit(&mut self, tpi: &mut TpiBuilder, node: usize) -> Result<()> {
+        if self.indices[node].is_some() {
+            return Ok(());
+        }
+        let Some(record) = &self.records[node] else {
+            return Err(undefined(node, symbolic(node)));
+        };
+        self.visiting[node] = true;
+        for referenced in record.referenced_types() {
+            let Some(dep) = node_of(referenced) else {
+                continue;
+            };
+            if dep >= self.records.len() {
+                return Err(undefined(node, referenced));
+            }
+            if self.visiting[dep] {
+                self.add_forward_ref(tpi, dep)?;
+            } else {
+                self.visit(tpi, dep)?;
+            }
+        }
+        self.visiting[node] = false;
+
+        let mut record = self.records[node].take().unwrap();
+        // references to records still being visited go through their forward references
+        record.remap_indices(&mut |_, index| {
+            let dep = (index - FIRST_NON_BUILTIN_TYPE) as usize;
+            self.indices[dep]
+                .or(self.forward_refs[dep])
+                .map_or(index, u32::from)
+        });
+        self.indices[node] = Some(tpi.insert("", &record)?);
+        Ok(())
+    }
+
+    fn add_forward_ref(&mut self, tpi: &mut TpiBuilder, node: usize) -> Result<()> {
+        if self.forward_refs[node].is_some() {
+            return Ok(());
+        }
+        let record = match &self.records[node] {
+            Some(TypeRecord::Class(class)) => TypeRecord::Class(forward_class(class)),
+            Some(TypeRecord::Struct(class)) => TypeRecord::Struct(forward_class(class)),
+            Some(TypeRecord::Interface(class)) => TypeRecord::Interface(forward_class(class)),
+            Some(TypeRecord::Union(union)) => TypeRecord::Union(UnionRecord {
+                member_count: 0,
+                properties: union.properties.with_is_forward_ref(true),
+                field_list: None,
+                size: Integer::U16(0),
+                name: union.name.clone(),
+                unique_name: union.unique_name.clone(),
+            }),
+            _ => {
+                return Err(Error::UnsupportedFeature(
+                    "type cycles not passing through a class, struct or union",
+                ))
+            }
+        };
+        self.forward_refs[node] = Some(tpi.insert("", &record)?);
+        Ok(())
+    }
+}
+
+fn forward_class(class: &StructRecord) -> StructRecord {
+    StructRecord {
+        member_count: 0,
+        properties: class.properties.with_is_forward_ref(true),
+        field_list: None,
+        derivation_list: None,
+        vtable_shape: None,
+        size: Integer::U16(0),
+        name: class.name.clone(),
+        unique_name: class.unique_name.clone(),
+    }
+}
+
+fn symbolic(node: usize) -> TypeIndex {
+    TypeIndex::try_from(FIRST_NON_BUILTIN_TYPE + node as u32).unwrap()
+}
+
+fn node_of(index: TypeIndex) -> Option<usize> {
+    u32::from(index)
+        .checked_sub(FIRST_NON_BUILTIN_TYPE)
+        .map(|node| node as usize)
+}
+
+fn undefined(node: usize, index: TypeIndex) -> Error {
+    Error::InvalidTypeIndex {
+        record: u32::from(symbolic(node)),
+        kind: "type",
+        index: index.into(),
+    }
+}
@@ -1,21 +1,88 @@
 use std::fmt::Debug;
 use std::io::{self, Read};
+use std::sync::OnceLock;
 
 use declio::ctx::Len;
-use declio::{magic_bytes, Decode, Encode, EncodedSize};
+use declio::{magic_bytes, Decode, Encode};
 use derive_getters::Getters;
-use modular_bitfield::BitfieldSpecifier;
 
 use crate::codeview::types::{IdRecord, TypeRecord};
-use crate::codeview::PrefixedRecord;
+use crate::codeview::RawRecord;
+use crate::diagnostics::{ParseLimits, ReadContext};
 use crate::hash::{hash_v1, Table};
 use crate::msf::MsfStreamWriter;
 use crate::result::{Error, Result};
-use crate::{constants, impl_bitfield_specifier_codecs, IdIndex, StreamIndex, TypeIndex};
+use crate::strings::Strings;
+use crate::utils::{glob_prefix, jam_crc32, matches_glob};
+use crate::{codecs, constants, u32_enum, IdIndex, StreamIndex, TypeIndex};
 
 pub(crate) const HASH_BUCKET_NUMBER: u32 = 0x40000u32 - 1;
 pub(crate) const FIRST_NON_BUILTIN_TYPE: u32 = 0x1000;
 
+// the name a record is hashed and looked up by, or `None` when it's hashed by its contents: user-defined
+// types go by their name, or by their unique name when scoped, and have none when they are forward
+// references, anonymous or scoped without a unique name, whatever the `name`, other records go by
+// `name` unless it's empty
+pub(crate) fn type_record_key<'a>(record: &'a TypeRecord, name: &'a str) -> Option<&'a str> {
+    let (properties, udt_name, unique_name) = match record {
+        TypeRecord::Class(class) | TypeRecord::Struct(class) | TypeRecord::Interface(class) => {
+            (class.properties, &class.name, &class.unique_name)
+        }
+        TypeRecord::Union(union) => (union.properties, &union.name, &union.unique_name),
+        TypeRecord::Enum(enum_) => (enum_.properties, &enum_.name, &enum_.unique_name),
+        _ => return (!name.is_empty()).then_some(name),
+    };
+    let is_anonymous = properties.has_unique_name() && is_anonymous(udt_name.as_ref());
+    if properties.is_forward_ref() || is_anonymous {
+        None
+    } else if !properties.is_scoped() {
+        Some(udt_name.as_ref())
+    } else if properties.has_unique_name() {
+        Some(unique_name.as_ref())
+    } else {
+        None
+    }
+}
+
+// the name and the unique name of a user-defined type, the latter is empty when missing
+fn udt_names(record: &TypeRecord) -> Option<(&str, &str)> {
+    let (properties, name, unique_name) = match record {
+        TypeRecord::Class(class) | TypeRecord::Struct(class) | TypeRecord::Interface(class) => {
+            (class.properties, &class.name, &class.unique_name)
+        }
+        TypeRecord::Union(union) => (union.properties, &union.name, &union.unique_name),
+        TypeRecord::Enum(enum_) => (enum_.properties, &enum_.name, &enum_.unique_name),
+        _ => return None,
+    };
+    let unique_name = if properties.has_unique_name() {
+        unique_name.as_ref()
+    } else {
+        ""
+    };
+    Some((name.as_ref(), unique_name))
+}
+
+// source line records are hashed by the type they describe
+pub(crate) fn id_record_hash(record: &IdRecord, name: &str) -> Option<u32> {
+    match record {
+        IdRecord::UdtSourceLine { udt, .. } | IdRecord::UdtModSourceLine { udt, .. } => {
+            Some(hash_v1(&u32::from(*udt).to_le_bytes()))
+        }
+        _ => (!name.is_empty()).then(|| hash_v1(name.as_bytes())),
+    }
+}
+
+// records without a name are hashed by their bytes, including the length prefix and padding
+pub(crate) fn contents_hash(encoded: &[u8]) -> u32 {
+    jam_crc32(encoded)
+}
+
+fn is_anonymous(name: &str) -> bool {
+    ["<unnamed-tag>", "__unnamed"]
+        .iter()
+        .any(|anon| name == *anon || name.strip_suffix(anon).is_some_and(|scope| scope.ends_with("::")))
+}
+
 magic_bytes! {
     #[derive(Debug)]
     pub HeaderSize(&TypeStreamHeader::BYTE_SIZE.to_le_bytes());
@@ -27,50 +94,234 @@ magic_bytes! {
 
 pub type TpiStream = TypeStream<TypeRecord>;
 pub type IpiStream = TypeStream<IdRecord>;
+pub type RawTypeStream = TypeStream<RawRecord>;
 
+// in lenient mode the records that fail to decode are left out of `records`, they still take up an
+// index though, so lookups by index and `iter` account for them
 #[derive(Debug, Getters)]
 pub struct TypeStream<A> {
     header: TypeStreamHeader,
     records: Vec<A>,
+    // the zero-based positions of the records left out, in order
+    #[getter(skip)]
+    skipped: Vec<u32>,
+    // built on the first search by name
+    #[getter(skip)]
+    names: OnceLock<NameIndex>,
+}
+
+// the positions of the named user-defined types, sorted by name and by unique name
+#[derive(Debug)]
+struct NameIndex {
+    by_name: Vec<u32>,
+    by_unique_name: Vec<u32>,
 }
 
 impl<A> TypeStream<A> {
-    pub(crate) fn read<R>(mut input: R) -> Result<Self>
+    pub(crate) fn read<R>(mut input: R, ctx: &mut ReadContext) -> Result<Self>
     where
         A: Decode,
         R: io::Read,
     {
-        let header = TypeStreamHeader::decode((), &mut input)?;
-        if !matches!(header.version, TypeStreamVersion::V80) {
-            return Err(Error::UnsupportedFeature("TPI version older than V80"));
+        let header = TypeStreamHeader::read(&mut input)?;
+        let mut type_record_stream = input.by_ref().take(header.type_record_bytes.into());
+        let decoder = ctx.records();
+        let mut records = vec![];
+        let mut skipped = vec![];
+        ctx.visit_records(
+            "type records",
+            header.byte_size(),
+            &mut type_record_stream,
+            |stream| decoder.decode(stream),
+            |record| {
+                match record {
+                    Some(record) => records.push(record),
+                    None => skipped.push((records.len() + skipped.len()) as u32),
+                }
+                Ok(())
+            },
+        )?;
+
+        Ok(TypeStream {
+            header,
+            records,
+            skipped,
+            names: OnceLock::new(),
+        })
+    }
+
+    // the records along with their indices, in stream order
+    pub fn iter(&self) -> impl Iterator<Item = (u32, &A)> {
+        (0..)
+            .zip(&self.records)
+            .map(|(pos, record)| (self.index_of(pos), record))
+    }
+
+    // the number of records in the stream, including the ones left out
+    pub fn record_count(&self) -> usize {
+        self.records.len() + self.skipped.len()
+    }
+
+    pub(crate) fn get(&self, index: u32) -> Option<&A> {
+        // builtins have no record
+        let slot = index.checked_sub(FIRST_NON_BUILTIN_TYPE)?;
+        if self.skipped.binary_search(&slot).is_ok() {
+            return None;
         }
+        let before = self.skipped.partition_point(|&skipped| skipped < slot);
+        self.records.get(slot as usize - before)
+    }
 
-        let mut records: Vec<A> = vec![];
-        let mut type_record_stream = input.by_ref().take(header.type_record_bytes.into());
-        while type_record_stream.limit() > 0 {
-            let record = PrefixedRecord::decode(&mut type_record_stream)?;
-            records.push(record.into_inner());
+    // the index of the record at a position of `records`
+    fn index_of(&self, pos: u32) -> u32 {
+        let mut slot = pos;
+        for &skipped in &self.skipped {
+            if skipped > slot {
+                break;
+            }
+            slot += 1;
         }
+        FIRST_NON_BUILTIN_TYPE + slot
+    }
 
-        Ok(TypeStream { header, records })
+    // decodes the records in chunks of `chunk_len`, handing each one to `visit` along with the index of
+    // its first record before decoding the next one into the same buffer, so that no more than a chunk
+    // is held in memory at a time
+    pub(crate) fn visit_chunks<R, F>(
+        mut input: R,
+        ctx: &mut ReadContext,
+        chunk_len: usize,
+        mut visit: F,
+    ) -> Result<TypeStreamHeader>
+    where
+        A: Decode,
+        R: io::Read,
+        F: FnMut(u32, &[A]) -> Result<()>,
+    {
+        let header = TypeStreamHeader::read(&mut input)?;
+        let mut type_record_stream = input.by_ref().take(header.type_record_bytes.into());
+        let decoder = ctx.records();
+        let chunk_len = chunk_len.max(1);
+        let mut chunk = Vec::with_capacity(chunk_len);
+        let mut first = FIRST_NON_BUILTIN_TYPE;
+        ctx.visit_records(
+            "type records",
+            header.byte_size(),
+            &mut type_record_stream,
+            |stream| decoder.decode(stream),
+            |record| {
+                let Some(record) = record else {
+                    // the chunks hold consecutive records, so the one left out ends the chunk
+                    if !chunk.is_empty() {
+                        visit(first, &chunk)?;
+                    }
+                    first += chunk.len() as u32 + 1;
+                    chunk.clear();
+                    return Ok(());
+                };
+                chunk.push(record);
+                if chunk.len() == chunk_len {
+                    visit(first, &chunk)?;
+                    first += chunk.len() as u32;
+                    chunk.clear();
+                }
+                Ok(())
+            },
+        )?;
+        if !chunk.is_empty() {
+            visit(first, &chunk)?;
+        }
+        Ok(header)
     }
 }
 
 impl TypeStream<TypeRecord> {
     pub fn record(&self, idx: TypeIndex) -> Option<&TypeRecord> {
-        self.records
-            .get((u32::from(idx) - FIRST_NON_BUILTIN_TYPE) as usize)
+        self.get(idx.into())
+    }
+
+    // the classes, structs, unions and enums with a name accepted by the predicate, in stream order
+    pub fn find(&self, mut predicate: impl FnMut(&str) -> bool) -> Vec<(TypeIndex, &TypeRecord)> {
+        let mut positions: Vec<u32> = self
+            .name_index()
+            .by_name
+            .iter()
+            .copied()
+            .filter(|&pos| udt_names(&self.records[pos as usize]).is_some_and(|(name, _)| predicate(name)))
+            .collect();
+        positions.sort_unstable();
+        self.with_indices(positions)
+    }
+
+    // the classes, structs, unions and enums with a name or a unique name matching a pattern where `*`
+    // stands for any run of characters and `?` for a single one, in stream order
+    pub fn find_matching(&self, pattern: &str) -> Vec<(TypeIndex, &TypeRecord)> {
+        let index = self.name_index();
+        let prefix = glob_prefix(pattern);
+        let mut positions = vec![];
+        for (sorted, by_unique_name) in [(&index.by_name, false), (&index.by_unique_name, true)] {
+            let key = |pos: u32| {
+                let (name, unique_name) = udt_names(&self.records[pos as usize]).unwrap_or_default();
+                if by_unique_name {
+                    unique_name
+                } else {
+                    name
+                }
+            };
+            // only the names starting with the literal part of the pattern can match
+            let start = sorted.partition_point(|&pos| key(pos) < prefix);
+            let end = start + sorted[start..].partition_point(|&pos| key(pos).starts_with(prefix));
+            positions.extend(
+                sorted[start..end]
+                    .iter()
+                    .copied()
+                    .filter(|&pos| matches_glob(pattern, key(pos))),
+            );
+        }
+        positions.sort_unstable();
+        positions.dedup();
+        self.with_indices(positions)
+    }
+
+    fn name_index(&self) -> &NameIndex {
+        self.names.get_or_init(|| {
+            let named: Vec<(u32, &str, &str)> = (0..)
+                .zip(&self.records)
+                .filter_map(|(pos, record)| udt_names(record).map(|(name, unique)| (pos, name, unique)))
+                .collect();
+            let mut by_name: Vec<_> = named.iter().map(|&(pos, name, _)| (name, pos)).collect();
+            by_name.sort();
+            let mut by_unique_name: Vec<_> = named
+                .iter()
+                .filter(|(_, _, unique)| !unique.is_empty())
+                .map(|&(pos, _, unique)| (unique, pos))
+                .collect();
+            by_unique_name.sort();
+            NameIndex {
+                by_name: by_name.into_iter().map(|(_, pos)| pos).collect(),
+                by_unique_name: by_unique_name.into_iter().map(|(_, pos)| pos).collect(),
+            }
+        })
+    }
+
+    fn with_indices(&self, positions: Vec<u32>) -> Vec<(TypeIndex, &TypeRecord)> {
+        positions
+            .into_iter()
+            .filter_map(|pos| {
+                let index = TypeIndex::try_from(self.index_of(pos)).ok()?;
+                Some((index, &self.records[pos as usize]))
+            })
+            .collect()
     }
 }
 
 impl TypeStream<IdRecord> {
     pub fn record(&self, idx: IdIndex) -> Option<&IdRecord> {
-        self.records
-            .get((u32::from(idx) - FIRST_NON_BUILTIN_TYPE) as usize)
+        self.get(idx.into())
     }
 }
 
-#[derive(Debug, Encode, Decode)]
+#[derive(Debug, PartialEq, Eq, Encode, Decode)]
 #[declio(ctx_is = "constants::ENDIANESS")]
 pub struct TypeStreamHeader {
     pub version: TypeStreamVersion,
@@ -79,8 +330,11 @@ pub struct TypeStreamHeader {
     pub type_index_end: TypeIndex,
     pub type_record_bytes: u32,
 
-    pub hash_stream_index: StreamIndex,
-    pub hash_aux_stream_index: StreamIndex,
+    // `None` for versions older than V80, whose hashes are not compatible with `TypeHash`
+    #[declio(with = "codecs::optional_stream")]
+    pub hash_stream_index: Option<StreamIndex>,
+    #[declio(with = "codecs::optional_stream")]
+    pub hash_aux_stream_index: Option<StreamIndex>,
     pub hash_key_size: HashKeySize,
     pub num_hash_buckets: HashBucketNumber,
 
@@ -90,6 +344,32 @@ pub struct TypeStreamHeader {
 impl TypeStreamHeader {
     const BYTE_SIZE: u32 = 56;
 
+    // the size of the header as read, which the records follow
+    pub(crate) fn byte_size(&self) -> u64 {
+        match self.version {
+            TypeStreamVersion::V40 | TypeStreamVersion::V41 => 16,
+            _ => Self::BYTE_SIZE.into(),
+        }
+    }
+
+    pub(crate) fn read<R: io::Read>(mut input: R) -> Result<Self> {
+        let mut version_bytes = [0; 4];
+        input.read_exact(&mut version_bytes)?;
+        let version = TypeStreamVersion::decode(constants::ENDIANESS, &mut &version_bytes[..])?;
+        let header = match version {
+            TypeStreamVersion::V80 => {
+                TypeStreamHeader::decode((), &mut (&version_bytes[..]).chain(input.by_ref()))?
+            }
+            TypeStreamVersion::V50 | TypeStreamVersion::V70 => {
+                LegacyTypeStreamHeader::decode((), &mut input)?.into_header(version)
+            }
+            TypeStreamVersion::V40 | TypeStreamVersion::V41 => {
+                TypeStreamHeader16::decode((), &mut input)?.into_header(version)?
+            }
+        };
+        Ok(header)
+    }
+
     pub(crate) fn new(
         last_type: TypeIndex,
         type_bytes: u32,
@@ -102,8 +382,8 @@ impl TypeStreamHeader {
             type_index_begin: TypeIndex::try_from(FIRST_NON_BUILTIN_TYPE).unwrap(),
             type_index_end: last_type,
             type_record_bytes: type_bytes,
-            hash_stream_index: hash_stream,
-            hash_aux_stream_index: StreamIndex(u16::MAX),
+            hash_stream_index: Some(hash_stream),
+            hash_aux_stream_index: None,
             hash_key_size: HashKeySize,
             num_hash_buckets: HashBucketNumber,
             hash_layout,
@@ -111,7 +391,72 @@ impl TypeStreamHeader {
     }
 }
 
-#[derive(Debug, Encode, Decode)]
+// V50 and V70 share the V80 layout, but their hashes are not compatible with `TypeHash`
+#[derive(Debug, PartialEq, Eq, Decode)]
+#[declio(ctx_is = "constants::ENDIANESS")]
+#[allow(unused)]
+struct LegacyTypeStreamHeader {
+    header_size: u32,
+    type_index_begin: TypeIndex,
+    type_index_end: TypeIndex,
+    type_record_bytes: u32,
+    hash_stream_index: StreamIndex,
+    hash_aux_stream_index: StreamIndex,
+    hash_key_size: u32,
+    num_hash_buckets: u32,
+    hash_layout: TypeHashLayout,
+}
+
+impl LegacyTypeStreamHeader {
+    fn into_header(self, version: TypeStreamVersion) -> TypeStreamHeader {
+        TypeStreamHeader {
+            version,
+            header_size: HeaderSize,
+            type_index_begin: self.type_index_begin,
+            type_index_end: self.type_index_end,
+            type_record_bytes: self.type_record_bytes,
+            hash_stream_index: None,
+            hash_aux_stream_index: None,
+            hash_key_size: HashKeySize,
+            num_hash_buckets: HashBucketNumber,
+            hash_layout: TypeHashLayout::default(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Decode)]
+#[declio(ctx_is = "constants::ENDIANESS")]
+#[allow(unused)]
+struct TypeStreamHeader16 {
+    type_index_begin: u16,
+    type_index_end: u16,
+    type_record_bytes: u32,
+    hash_stream_index: StreamIndex,
+    pad: [u8; 2],
+}
+
+impl TypeStreamHeader16 {
+    fn into_header(self, version: TypeStreamVersion) -> Result<TypeStreamHeader> {
+        let type_index = |idx: u16| {
+            TypeIndex::try_from(u32::from(idx)).map_err(|_| Error::UnsupportedFeature("null type index"))
+        };
+        let res = TypeStreamHeader {
+            version,
+            header_size: HeaderSize,
+            type_index_begin: type_index(self.type_index_begin)?,
+            type_index_end: type_index(self.type_index_end)?,
+            type_record_bytes: self.type_record_bytes,
+            hash_stream_index: None,
+            hash_aux_stream_index: None,
+            hash_key_size: HashKeySize,
+            num_hash_buckets: HashBucketNumber,
+            hash_layout: TypeHashLayout::default(),
+        };
+        Ok(res)
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Encode, Decode)]
 pub struct TypeHashLayout {
     hash_values: EmbeddedBuf,
     index_offsets: EmbeddedBuf,
@@ -132,10 +477,26 @@ impl TypeHash {
         TypeIndex::try_from(FIRST_NON_BUILTIN_TYPE + i as u32).ok()
     }
 
-    pub(crate) fn read<R>(mut input: R, layout: &TypeHashLayout) -> Result<Self>
+    // like `get_index`, but names of types defined more than once resolve to the definition
+    // chosen by the hash adjusters
+    pub fn get_adjusted_index(&self, name: &str, strings: &Strings) -> Option<TypeIndex> {
+        let adjusted = strings.offset_of(name).and_then(|offset| {
+            let entry = self
+                .hash_adjusters
+                .entries()
+                .iter()
+                .find(|kv| kv.key == offset.0)?;
+            TypeIndex::try_from(entry.val).ok()
+        });
+        adjusted.or_else(|| self.get_index(name))
+    }
+
+    pub(crate) fn read<R>(mut input: R, layout: &TypeHashLayout, limits: &ParseLimits) -> Result<Self>
     where
         R: io::Read + io::Seek,
     {
+        limits
+            .check_memory(u64::from(layout.hash_values.length) + u64::from(layout.index_offsets.length))?;
         input.seek(io::SeekFrom::Start(layout.hash_values.offset.into()))?;
         let num_hash_values = layout.hash_values.length / 4;
         let hash_values =
@@ -176,14 +537,14 @@ impl TypeHash {
     }
 }
 
-#[derive(Debug, Encode, Decode)]
+#[derive(Debug, PartialEq, Eq, Encode, Decode)]
 #[declio(ctx_is = "constants::ENDIANESS")]
 pub(crate) struct IndexOffset {
     index: TypeIndex,
     offset: u32,
 }
 
-#[derive(Debug, Encode, Decode)]
+#[derive(Debug, Default, PartialEq, Eq, Encode, Decode)]
 #[declio(ctx_is = "constants::ENDIANESS")]
 struct EmbeddedBuf {
     offset: u32,
@@ -207,14 +568,13 @@ impl EmbeddedBuf {
     }
 }
 
-#[derive(Debug, Clone, Copy, BitfieldSpecifier)]
-#[bits = 32]
-pub enum TypeStreamVersion {
-    V40 = 19950410,
-    V41 = 19951122,
-    V50 = 19961031,
-    V70 = 19990903,
-    V80 = 20040203,
+u32_enum! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum TypeStreamVersion {
+        V40 = 19950410,
+        V41 = 19951122,
+        V50 = 19961031,
+        V70 = 19990903,
+        V80 = 20040203,
+    }
 }
-
-impl_bitfield_specifier_codecs!(TypeStreamVersion);
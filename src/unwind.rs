@@ -0,0 +1,236 @@
+use std::collections::HashMap;
+
+use crate::dbi::{FpoData, FrameData};
+use crate::result::{Error, Result};
+use crate::strings::Strings;
+use crate::StringOffset;
+
+const FPO_PROLOG_MASK: u16 = 0xFF;
+const FPO_SAVED_REGS_SHIFT: u16 = 8;
+const FPO_SAVED_REGS_MASK: u16 = 0x7;
+const FPO_USES_BASE_POINTER: u16 = 1 << 12;
+const FPO_FRAME_SHIFT: u16 = 14;
+
+// the stack layout of a function as described by its FPO or frame data record, sizes are in bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameLayout {
+    pub function_start: u32,
+    pub code_size: u32,
+    pub prolog_size: u32,
+    pub locals_size: u32,
+    pub params_size: u32,
+    pub saved_regs_size: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FpoFrameKind {
+    Fpo,
+    Trap,
+    Tss,
+    NonFpo,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnwindRule {
+    // the caller's registers are computed by a postfix program stored in the `/names` stream
+    Program {
+        layout: FrameLayout,
+        program: StringOffset,
+    },
+    // the return address follows the saved registers and locals, or `ebp` when it's used as a base pointer
+    Layout {
+        layout: FrameLayout,
+        kind: FpoFrameKind,
+        uses_base_pointer: bool,
+    },
+}
+
+impl UnwindRule {
+    pub fn layout(&self) -> &FrameLayout {
+        match self {
+            UnwindRule::Program { layout, .. } | UnwindRule::Layout { layout, .. } => layout,
+        }
+    }
+
+    pub fn program<'a>(&self, strings: &'a Strings) -> Option<&'a str> {
+        match self {
+            UnwindRule::Program { program, .. } => strings.get(*program),
+            UnwindRule::Layout { .. } => None,
+        }
+    }
+}
+
+// a single view over the FPO and frame data streams of x86 images, keyed by RVA,
+// other architectures keep their unwind info in the image and have neither of the two
+#[derive(Debug, Default)]
+pub struct UnwindTable {
+    // frame data is preferred over FPO records covering the same code, like the debuggers do
+    frames: Vec<UnwindRule>,
+    fpo: Vec<UnwindRule>,
+}
+
+impl UnwindTable {
+    pub fn new(fpo: &[FpoData], frames: &[FrameData]) -> Self {
+        let mut fpo: Vec<_> = fpo.iter().map(fpo_rule).collect();
+        let mut frames: Vec<_> = frames.iter().map(frame_data_rule).collect();
+        fpo.sort_by_key(|rule| rule.layout().function_start);
+        frames.sort_by_key(|rule| rule.layout().function_start);
+        Self { frames, fpo }
+    }
+
+    pub fn unwind_info_at(&self, rva: u32) -> Option<&UnwindRule> {
+        find(&self.frames, rva).or_else(|| find(&self.fpo, rva))
+    }
+}
+
+// runs a frame data program like `$T0 .raSearch = $eip $T0 ^ = $esp $T0 4 + =`, the variables hold
+// the registers of the frame on entry (`$eip`, `$esp`, `$ebp`...) and those of the caller on return,
+// `read` loads a dword of the stack for the `^` operator
+pub fn eval_program(
+    program: &str,
+    layout: &FrameLayout,
+    variables: &mut HashMap<String, u32>,
+    mut read: impl FnMut(u32) -> Option<u32>,
+) -> Result<()> {
+    variables.insert("$L".to_owned(), layout.locals_size);
+    variables.insert("$P".to_owned(), layout.params_size);
+    variables.insert(".cbLocals".to_owned(), layout.locals_size);
+    variables.insert(".cbParams".to_owned(), layout.params_size);
+    variables.insert(".cbSavedRegs".to_owned(), layout.saved_regs_size);
+    // the return address is expected right above the locals and saved registers unless told otherwise
+    if let Some(&esp) = variables.get("$esp") {
+        let search = esp
+            .wrapping_add(layout.locals_size)
+            .wrapping_add(layout.saved_regs_size);
+        variables.entry(".raSearchStart".to_owned()).or_insert(search);
+        variables.entry(".raSearch".to_owned()).or_insert(search);
+    }
+
+    let invalid = |msg: &str| Error::InvalidUnwindProgram(format!("{msg} in `{program}`"));
+    let mut stack: Vec<Operand<'_>> = vec![];
+    for token in program.split_whitespace() {
+        let op = match token {
+            "+" | "-" | "*" | "/" | "%" | "@" | "^" | "=" => token,
+            _ if token.starts_with(['$', '.']) => {
+                stack.push(Operand::Variable(token));
+                continue;
+            }
+            _ => {
+                let num = match token.strip_prefix("0x") {
+                    Some(hex) => u32::from_str_radix(hex, 16),
+                    None => token.parse(),
+                };
+                stack.push(Operand::Value(num.map_err(|_| invalid("unknown token"))?));
+                continue;
+            }
+        };
+        let rhs = stack.pop().ok_or_else(|| invalid("missing operand"))?;
+        if op == "=" {
+            let Some(Operand::Variable(name)) = stack.pop() else {
+                return Err(invalid("assignment to a non-variable"));
+            };
+            let val = rhs
+                .value(variables)
+                .ok_or_else(|| invalid("undefined variable"))?;
+            variables.insert(name.to_owned(), val);
+            continue;
+        }
+        let rhs = rhs
+            .value(variables)
+            .ok_or_else(|| invalid("undefined variable"))?;
+        if op == "^" {
+            let val = read(rhs).ok_or_else(|| invalid("unreadable memory"))?;
+            stack.push(Operand::Value(val));
+            continue;
+        }
+        let lhs = stack
+            .pop()
+            .and_then(|lhs| lhs.value(variables))
+            .ok_or_else(|| invalid("missing operand"))?;
+        let val = match op {
+            "+" => lhs.wrapping_add(rhs),
+            "-" => lhs.wrapping_sub(rhs),
+            "*" => lhs.wrapping_mul(rhs),
+            "/" => lhs.checked_div(rhs).ok_or_else(|| invalid("division by zero"))?,
+            "%" => lhs.checked_rem(rhs).ok_or_else(|| invalid("division by zero"))?,
+            // aligns down to a power of two
+            _ => lhs & !rhs.wrapping_sub(1),
+        };
+        stack.push(Operand::Value(val));
+    }
+    if !stack.is_empty() {
+        return Err(invalid("unused operands"));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Operand<'a> {
+    Variable(&'a str),
+    Value(u32),
+}
+
+impl Operand<'_> {
+    fn value(self, variables: &HashMap<String, u32>) -> Option<u32> {
+        match self {
+            Operand::Variable(name) => variables.get(name).copied(),
+            Operand::Value(val) => Some(val),
+        }
+    }
+}
+
+// records can be nested, e.g. a separate one for the prolog, the innermost one wins
+fn find(rules: &[UnwindRule], rva: u32) -> Option<&UnwindRule> {
+    let end = rules.partition_point(|rule| rule.layout().function_start <= rva);
+    rules[..end].iter().rev().find(|rule| {
+        let layout = rule.layout();
+        rva - layout.function_start < layout.code_size
+    })
+}
+
+fn fpo_rule(fpo: &FpoData) -> UnwindRule {
+    let kind = match fpo.attributes >> FPO_FRAME_SHIFT {
+        0 => FpoFrameKind::Fpo,
+        1 => FpoFrameKind::Trap,
+        2 => FpoFrameKind::Tss,
+        _ => FpoFrameKind::NonFpo,
+    };
+    let saved_regs = (fpo.attributes >> FPO_SAVED_REGS_SHIFT) & FPO_SAVED_REGS_MASK;
+    // locals and params are counted in dwords
+    let layout = FrameLayout {
+        function_start: fpo.offset,
+        code_size: fpo.size,
+        prolog_size: u32::from(fpo.attributes & FPO_PROLOG_MASK),
+        locals_size: fpo.num_locals * 4,
+        params_size: u32::from(fpo.num_params) * 4,
+        saved_regs_size: u32::from(saved_regs) * 4,
+    };
+    UnwindRule::Layout {
+        layout,
+        kind,
+        uses_base_pointer: fpo.attributes & FPO_USES_BASE_POINTER != 0,
+    }
+}
+
+fn frame_data_rule(frame: &FrameData) -> UnwindRule {
+    let layout = FrameLayout {
+        function_start: frame.rva_start,
+        code_size: frame.code_size,
+        prolog_size: u32::from(frame.prolog_size),
+        locals_size: frame.local_size,
+        params_size: frame.params_size,
+        saved_regs_size: u32::from(frame.saved_regs_size),
+    };
+    // an offset of zero is the empty string
+    if frame.frame_func == 0 {
+        return UnwindRule::Layout {
+            layout,
+            kind: FpoFrameKind::Fpo,
+            uses_base_pointer: false,
+        };
+    }
+    UnwindRule::Program {
+        layout,
+        program: StringOffset(frame.frame_func),
+    }
+}
@@ -1,17 +1,70 @@
 use std::cmp::Ordering;
+use std::collections::HashSet;
 use std::io;
+use std::sync::Arc;
 
 use declio::{Decode, Encode, EncodedSize};
 
+// runs both closures on the thread pool with the `parallel` feature, or one after the other without it
+#[cfg(feature = "parallel")]
+pub(crate) fn join<A, B, RA, RB>(a: A, b: B) -> (RA, RB)
+where
+    A: FnOnce() -> RA + Send,
+    B: FnOnce() -> RB + Send,
+    RA: Send,
+    RB: Send,
+{
+    rayon::join(a, b)
+}
+
+#[cfg(not(feature = "parallel"))]
+pub(crate) fn join<A, B, RA, RB>(a: A, b: B) -> (RA, RB)
+where
+    A: FnOnce() -> RA,
+    B: FnOnce() -> RB,
+{
+    (a(), b())
+}
+
+// enters a span lasting until the end of the enclosing scope with the `tracing` feature
+macro_rules! trace_span {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!($($arg)*).entered();
+    };
+}
+pub(crate) use trace_span;
+
+// emits an event with the `tracing` feature
+macro_rules! trace_event {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        tracing::debug!($($arg)*);
+    };
+}
+pub(crate) use trace_event;
+
 pub(crate) fn div_ceil(lhs: u32, rhs: u32) -> u32 {
     (lhs + rhs - 1) / rhs
 }
 
+// CRC-32 starting from zero and without the final inversion, as stored for injected sources
+pub(crate) fn jam_crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0;
+    for &byte in bytes {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xEDB88320 & (crc & 1).wrapping_neg());
+        }
+    }
+    crc
+}
+
 pub(crate) const fn align_to(val: usize, align: usize) -> usize {
     (val + align - 1) / align * align
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
 pub struct StrBuf(Box<str>);
 
 impl StrBuf {
@@ -60,6 +113,23 @@ impl<Ctx> EncodedSize<Ctx> for StrBuf {
     }
 }
 
+// keeps a single copy of equal strings, like the file paths repeated by the modules of large PDBs
+#[derive(Debug, Default, Clone)]
+pub(crate) struct Interner(HashSet<Arc<str>>);
+
+impl Interner {
+    pub fn intern(&mut self, str: &str) -> Arc<str> {
+        match self.0.get(str) {
+            Some(existing) => existing.clone(),
+            None => {
+                let str: Arc<str> = str.into();
+                self.0.insert(str.clone());
+                str
+            }
+        }
+    }
+}
+
 #[derive(Debug)]
 pub(crate) struct CaseInsensitiveStr<'a>(pub &'a str);
 
@@ -117,3 +187,69 @@ impl Ord for CaseInsensitiveChar {
         }
     }
 }
+
+// how lookups compare names, the hashes used by PDBs ignore ASCII case so that either can be served
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum NameMatching {
+    #[default]
+    Exact,
+    CaseInsensitive,
+    // the exact match if there is one, otherwise the first case-insensitive one
+    PreferExact,
+}
+
+impl NameMatching {
+    pub(crate) fn find<'a, A>(
+        self,
+        name: &str,
+        candidates: impl IntoIterator<Item = (A, &'a str)>,
+    ) -> Option<A> {
+        let mut fallback = None;
+        for (item, candidate) in candidates {
+            match self {
+                NameMatching::Exact | NameMatching::PreferExact if candidate == name => return Some(item),
+                NameMatching::CaseInsensitive if candidate.eq_ignore_ascii_case(name) => return Some(item),
+                NameMatching::PreferExact if fallback.is_none() && candidate.eq_ignore_ascii_case(name) => {
+                    fallback = Some(item);
+                }
+                _ => {}
+            }
+        }
+        fallback
+    }
+}
+
+// whether a name matches a pattern where `*` stands for any run of characters and `?` for a single one
+pub(crate) fn matches_glob(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // the position of the last star and the part of the name it covers up to
+    let mut backtrack = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((star, covered)) => {
+                    p = star + 1;
+                    n = covered + 1;
+                    backtrack = Some((star, covered + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+// the part of a glob pattern before its first wildcard
+pub(crate) fn glob_prefix(pattern: &str) -> &str {
+    pattern.find(['*', '?']).map_or(pattern, |end| &pattern[..end])
+}
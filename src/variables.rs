@@ -0,0 +1,60 @@
+use crate::codeview::symbols::SymbolRecord;
+use crate::codeview::DataRegionOffset;
+use crate::dbi::{SectionContrib, SectionHeader};
+use crate::TypeIndex;
+
+// a global or a static variable of the symbol record stream joined with its address and the module
+// that defines it
+#[derive(Debug)]
+pub struct GlobalVariable<'a> {
+    pub name: &'a str,
+    pub data_type: TypeIndex,
+    pub offset: &'a DataRegionOffset,
+    // none when the section is unknown
+    pub rva: Option<u32>,
+    // the index of the module whose contribution covers the variable
+    pub module: Option<usize>,
+    // S_LDATA32 and S_LTHREAD32 records of variables with internal linkage
+    pub is_static: bool,
+    // the offset of thread-local variables is relative to the TLS block of a thread
+    pub is_thread_local: bool,
+}
+
+impl<'a> GlobalVariable<'a> {
+    // managed data is not considered
+    pub(crate) fn new(
+        symbol: &'a SymbolRecord,
+        contribs: &[SectionContrib],
+        headers: &[SectionHeader],
+    ) -> Option<Self> {
+        let (data_type, offset, name, is_static, is_thread_local) = match symbol {
+            SymbolRecord::Data(data) => (data.data_type, &data.offset, &data.name, true, false),
+            SymbolRecord::GlobalData(data) => (data.data_type, &data.offset, &data.name, false, false),
+            SymbolRecord::ThreadLocalStorage(tls) => (tls.data_type, &tls.offset, &tls.name, true, true),
+            SymbolRecord::GlobalThreadLocalStorage(tls) => {
+                (tls.data_type, &tls.offset, &tls.name, false, true)
+            }
+            _ => return None,
+        };
+        let module = contribs
+            .iter()
+            .find(|contrib| {
+                let start = contrib.offset as i64;
+                contrib.i_sect == offset.segment
+                    && (start..start + i64::from(contrib.size)).contains(&offset.offset.into())
+            })
+            .map(|contrib| usize::from(contrib.i_mod));
+        Some(Self {
+            name: name.as_ref(),
+            data_type,
+            offset,
+            rva: usize::from(offset.segment)
+                .checked_sub(1)
+                .and_then(|index| headers.get(index))
+                .map(|header| header.virtual_address + offset.offset),
+            module,
+            is_static,
+            is_thread_local,
+        })
+    }
+}
@@ -0,0 +1,75 @@
+use std::io::{self, Read};
+
+use declio::{Decode, Encode, EncodedSize};
+
+use crate::codeview::PrefixedRecord;
+use crate::dbi::{DbiModule, DbiStream};
+use crate::result::Result;
+use crate::{constants, StreamIndex};
+
+#[derive(Debug, Clone, Copy)]
+pub enum RoundtripStream<'a> {
+    Tpi,
+    Ipi,
+    Symbols(&'a DbiStream),
+    Module(&'a DbiModule),
+}
+
+// the first record whose re-encoded bytes differ from the original ones
+#[derive(Debug)]
+pub struct Divergence {
+    pub stream: &'static str,
+    pub record_index: usize,
+    pub record_offset: u64,
+    pub byte_offset: usize,
+    pub kind: u16,
+    pub original: Vec<u8>,
+    pub reencoded: Vec<u8>,
+}
+
+// `start` is the offset of the records in the stream, which the reported offsets are relative to
+pub(crate) fn verify_records<A, R>(
+    stream: &'static str,
+    index: Option<StreamIndex>,
+    start: u64,
+    reader: &mut io::Take<R>,
+) -> Result<Option<Divergence>>
+where
+    A: Decode + Encode + EncodedSize,
+    R: io::Read,
+{
+    let total = reader.limit();
+    let mut record_index = 0;
+    while reader.limit() > 0 {
+        let record_offset = start + total - reader.limit();
+        let len = u16::decode(constants::ENDIANESS, reader)?;
+        let mut original = len.to_le_bytes().to_vec();
+        reader.by_ref().take(len.into()).read_to_end(&mut original)?;
+
+        let record: PrefixedRecord<A> = PrefixedRecord::decode(&mut &original[..])
+            .map_err(|err| err.in_stream(stream, index, record_offset))?;
+        let mut reencoded = vec![];
+        record.encode((), &mut reencoded)?;
+
+        if original != reencoded {
+            let byte_offset = original
+                .iter()
+                .zip(&reencoded)
+                .position(|(lhs, rhs)| lhs != rhs)
+                .unwrap_or_else(|| original.len().min(reencoded.len()));
+            let kind = original.get(2..4).map_or(0, |b| u16::from_le_bytes([b[0], b[1]]));
+            let res = Divergence {
+                stream,
+                record_index,
+                record_offset,
+                byte_offset,
+                kind,
+                original,
+                reencoded,
+            };
+            return Ok(Some(res));
+        }
+        record_index += 1;
+    }
+    Ok(None)
+}
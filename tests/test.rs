@@ -1,16 +1,49 @@
+use std::collections::HashMap;
 use std::fs::File;
 use std::io;
 
 use assert_matches::assert_matches;
-use pdb_sdk::builders::PdbBuilder;
-use pdb_sdk::codeview::symbols::{Constant, ProcedureProperties, Public, PublicProperties, SymbolRecord};
-use pdb_sdk::codeview::types::{BuiltinType, IdRecord, PointerKind, PointerProperties, TypeRecord};
-use pdb_sdk::codeview::DataRegionOffset;
-use pdb_sdk::dbi::SectionHeader;
-use pdb_sdk::info::PdbFeature;
-use pdb_sdk::result::Result;
-use pdb_sdk::utils::StrBuf;
-use pdb_sdk::{Integer, PdbFile};
+use declio::{Decode, Encode};
+use pdb_sdk::address_index::{AddressIndex, AddressKind, AddressRange};
+use pdb_sdk::bindings::BindingGenerator;
+use pdb_sdk::builders::{
+    CompilerInfo, DuplicatePublics, LocalLocation, LocalVarBuilder, ModuleBuilder, MovedRange, PdbBuilder, SymbolOrder, TpiBuilder
+};
+use pdb_sdk::codeview::symbols::{
+    Constant, Data, Procedure, ProcedureProperties, ProcedureRef, Public, PublicProperties, SourceLanguage, SymbolRecord, ThunkOrdinal, TrampolineType, Version
+};
+use pdb_sdk::codeview::types::{
+    BuiltinType, CallingConvention, ClassProperties, FunctionProperties, IdRecord, MemberProperties, ModifierProperties, PointerKind, PointerProperties, StructRecord, TypeRecord
+};
+use pdb_sdk::codeview::{DataRegionOffset, Register};
+use pdb_sdk::coff::{add_object, DebugRelocation, DebugRelocationKind, TypeServerRef, TypeServers};
+use pdb_sdk::dbi::{
+    DbiStream, FpoData, FrameData, MachineType, SectionCharacteristics, SectionContrib, SectionContribVersion, SectionHeader
+};
+use pdb_sdk::diagnostics::{ParseLimits, ParseOptions, Warning, WarningKind};
+use pdb_sdk::diff::diff;
+use pdb_sdk::info::{InfoPatch, PdbFeature, PdbVersion};
+use pdb_sdk::lines::LineProgramBuilder;
+use pdb_sdk::locals::LocalVariable;
+use pdb_sdk::map::MapFile;
+use pdb_sdk::merge::TypeMerger;
+use pdb_sdk::module::{
+    ChecksumType, DebugSubsectionEntry, DebugSubsectionRecord, DebugSubsectionRecordType, FileChecksumEntry, LineColumnEntry, LineFlags, LineFragmentHeader, LineNumberEntry, SourceLine
+};
+use pdb_sdk::msf::StreamPadding;
+use pdb_sdk::pdb::Pdb;
+use pdb_sdk::result::{Error, Result};
+use pdb_sdk::search::SymbolSource;
+use pdb_sdk::sourcelink::SourceLink;
+use pdb_sdk::strings::HashVersion;
+use pdb_sdk::symbol_map::SymbolMap;
+use pdb_sdk::symlist::SymbolList;
+use pdb_sdk::symstore::{store_path, SymbolStoreWriter};
+use pdb_sdk::typegraph::TypeGraph;
+use pdb_sdk::unwind::{eval_program, FpoFrameKind, FrameLayout, UnwindRule, UnwindTable};
+use pdb_sdk::utils::{NameMatching, StrBuf};
+use pdb_sdk::verify::RoundtripStream;
+use pdb_sdk::{DbgHeader, Guid, Integer, PdbFile, SymbolOffset, TypeIndex};
 
 #[test]
 fn roundtrip() -> Result<()> {
@@ -41,6 +74,23 @@ fn roundtrip() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn write_module_subsections() -> Result<()> {
+    let mut pdb = PdbFile::open(write_dummy()?)?;
+    let dbi = pdb.get_dbi()?;
+    let module = pdb.get_module(&dbi.modules()[0])?;
+    assert!(module.c13_records().iter().all(|entry| entry.data.len() % 4 == 0));
+    assert_matches!(
+        module.c13_records()[0].decoded()?,
+        DebugSubsectionRecord::FileChecksums { entries } if entries.len() == 1
+    );
+    assert_matches!(
+        module.c13_records()[1].decoded()?,
+        DebugSubsectionRecord::Lines { entries, .. } if entries[0].line_numbers.len() == 1
+    );
+    Ok(())
+}
+
 #[test]
 fn read_llvm_pdb() -> Result<()> {
     let mut pdb = PdbFile::open(File::open("tests/llvm.pdb")?)?;
@@ -81,44 +131,3866 @@ fn read_llvm_pdb() -> Result<()> {
     Ok(())
 }
 
-fn write_dummy() -> Result<io::Cursor<Vec<u8>>> {
-    let mut builder = PdbBuilder::default();
-    builder.tpi().add("pointer_type", TypeRecord::Pointer {
-        referent: BuiltinType::I64.into(),
-        properties: PointerProperties::new()
-            .with_is_const(true)
-            .with_is_volatile(true)
-            .with_kind(PointerKind::Near64),
-        containing_class: None,
-    });
-    builder.ipi().add("string_id", IdRecord::StringId {
-        id: None,
-        string: StrBuf::new("test"),
-    });
+#[test]
+fn read_section_characteristics() -> Result<()> {
+    let mut pdb = PdbFile::open(File::open("tests/llvm.pdb")?)?;
+    let dbi = pdb.get_dbi()?;
+    let dbg = pdb.get_section_headers(&dbi)?;
+    let text = dbg.headers()[0].flags();
+    assert!(text.cnt_code() && text.mem_execute() && text.mem_read() && !text.mem_write());
+    assert_eq!(u32::from(text), dbg.headers()[0].characteristics);
+    assert_eq!(SectionCharacteristics::from(0x0050_0020).alignment(), Some(16));
+    Ok(())
+}
 
-    let mut sym_builder = builder.dbi().symbols();
-    sym_builder.add(Public {
-        properties: PublicProperties::new().with_is_msil(true),
-        offset: DataRegionOffset::new(0, 0),
-        name: StrBuf::new("hello"),
+#[test]
+fn decode_frame_pointer_registers() -> Result<()> {
+    let mut pdb = PdbFile::open(File::open("tests/llvm.pdb")?)?;
+    let dbi = pdb.get_dbi()?;
+    let module = pdb.get_module(&dbi.modules()[1])?;
+    let frame = module.symbols().iter().find_map(|sym| match sym {
+        SymbolRecord::FrameProcedure { properties, .. } => Some(properties),
+        _ => None,
     });
-    let sym_builder = sym_builder.finish_publics();
-    sym_builder.add(SymbolRecord::Label {
-        code_offset: DataRegionOffset::new(0, 0),
-        properties: ProcedureProperties::new()
-            .with_has_fp(true)
-            .with_is_no_return(true),
-        name: StrBuf::new("label"),
+    assert_matches!(
+        frame.unwrap().local_base_pointer(dbi.header().machine_type),
+        Some(Register::RSP | Register::RBP)
+    );
+    Ok(())
+}
+
+#[test]
+fn look_up_dependent_streams() -> Result<()> {
+    let mut pdb = PdbFile::open(File::open("tests/llvm.pdb")?)?;
+    let dbi = pdb.get_dbi()?;
+    let info = pdb.get_info()?;
+    let syms = pdb.get_symbols(&dbi)?;
+    assert_eq!(pdb.symbols()?.records().len(), syms.records().len());
+    assert_eq!(
+        pdb.globals()?.hash_records().len(),
+        pdb.get_globals(&dbi)?.hash_records().len()
+    );
+    assert_eq!(
+        pdb.strings()?.iter().count(),
+        pdb.get_strings(&info)?.iter().count()
+    );
+    Ok(())
+}
+
+#[test]
+fn report_module_type_costs() -> Result<()> {
+    let mut pdb = PdbFile::open(File::open("tests/llvm.pdb")?)?;
+    let dbi = pdb.get_dbi()?;
+    let costs = pdb.get_module_type_costs()?;
+    assert_eq!(costs.modules.len(), dbi.modules().len());
+    assert!(costs.modules.iter().any(|module| module.type_count > 0));
+    assert!(costs
+        .modules
+        .iter()
+        .all(|module| module.exclusive_type_bytes <= module.type_bytes));
+    Ok(())
+}
+
+#[test]
+fn read_llvm_injected_sources() -> Result<()> {
+    let mut pdb = PdbFile::open(File::open("tests/llvm.pdb")?)?;
+    let dbi = pdb.get_dbi()?;
+    let info = pdb.get_info()?;
+    assert_eq!(pdb.get_natvis(&info)?.len(), 8);
+
+    let module = pdb.get_module(&dbi.modules()[1])?;
+    let sources = pdb.get_module_sources(&info, &module)?;
+    assert!(!sources.is_empty());
+    assert!(sources.iter().all(|(_, embedded)| embedded.is_none()));
+    Ok(())
+}
+
+#[test]
+fn report_size_stats() -> Result<()> {
+    let mut pdb = PdbFile::open(File::open("tests/llvm.pdb")?)?;
+    let dbi = pdb.get_dbi()?;
+    let stats = pdb.get_size_stats(5)?;
+    assert_eq!(stats.modules.len(), dbi.modules().len());
+    assert_eq!(stats.largest_types.len(), 5);
+    assert!(stats.largest_types[0].bytes >= stats.largest_types[4].bytes);
+    Ok(())
+}
+
+#[test]
+fn read_block_allocation() -> Result<()> {
+    let mut pdb = PdbFile::open(File::open("tests/llvm.pdb")?)?;
+    let allocation = pdb.get_block_allocation()?;
+    assert_eq!(
+        allocation.free_blocks + allocation.used_blocks,
+        allocation.num_blocks
+    );
+    assert!(allocation.used_but_unreferenced.is_empty());
+    Ok(())
+}
+
+#[test]
+fn read_msf_info() -> Result<()> {
+    let mut pdb = PdbFile::open(File::open("tests/llvm.pdb")?)?;
+    let allocation = pdb.get_block_allocation()?;
+    let msf = pdb.msf_info();
+    assert_eq!(msf.block_size, 4096);
+    assert_eq!(msf.num_blocks, allocation.num_blocks);
+    assert_matches!(msf.free_block_map_block, 1 | 2);
+    Ok(())
+}
+
+#[test]
+fn load_streams_once() -> Result<()> {
+    let pdb = Pdb::load(File::open("tests/llvm.pdb")?)?;
+
+    assert!(std::ptr::eq(pdb.tpi()?, pdb.tpi()?));
+    assert!(std::ptr::eq(pdb.module(1)?, pdb.module(1)?));
+    assert!(std::ptr::eq(pdb.globals()?, pdb.globals()?));
+    assert!(pdb.strings()?.iter().count() > 0);
+    assert_matches!(pdb.module(usize::MAX), Err(Error::StreamNotFound(_)));
+
+    let lines = pdb.module_lines(1)?;
+    assert!(!lines.is_empty());
+    assert!(lines.iter().all(|line| line.line != 0 && !line.file.is_empty()));
+    let proc = pdb
+        .module(1)?
+        .symbols()
+        .iter()
+        .find(|sym| matches!(sym, SymbolRecord::GlobalProc(_) | SymbolRecord::Proc(_)));
+    assert_matches!(
+        pdb.symbol_type(proc.unwrap())?,
+        Some(TypeRecord::Procedure { .. } | TypeRecord::MemberFunction { .. })
+    );
+    let text = pdb.section_headers()?.headers()[0].virtual_address;
+    assert_eq!(pdb.rva(DataRegionOffset::new(16, 1))?, Some(text + 16));
+    assert_eq!(pdb.rva(DataRegionOffset::new(0, 0))?, None);
+    // x64 images keep their unwind info in the image
+    assert_matches!(pdb.unwind_info_at(text + 16)?, None);
+
+    Ok(())
+}
+
+#[test]
+fn find_functions() -> Result<()> {
+    let pdb = Pdb::load_with_options(File::open("tests/llvm.pdb")?, ParseOptions::lenient())?;
+    let name = pdb
+        .module(1)?
+        .symbols()
+        .iter()
+        .find_map(|sym| match sym {
+            SymbolRecord::GlobalProc(proc) | SymbolRecord::Proc(proc) => Some(proc.name.as_ref()),
+            _ => None,
+        })
+        .unwrap();
+
+    let function = pdb.function_by_name(name)?.unwrap();
+    assert_eq!(function.procedure.name.as_ref(), name);
+    assert_matches!(function.frame, Some(SymbolRecord::FrameProcedure { .. }));
+    assert_matches!(
+        function.signature,
+        Some(TypeRecord::Procedure { .. } | TypeRecord::MemberFunction { .. })
+    );
+    assert!(!function.lines.is_empty());
+    let start = function.procedure.code_offset.offset;
+    assert!(function
+        .lines
+        .iter()
+        .all(|line| (start..start + function.procedure.code_size).contains(&line.offset.offset)));
+
+    let last = function.rva + function.procedure.code_size - 1;
+    let at = pdb.function_at(last)?.unwrap();
+    assert_eq!((at.module, at.rva), (function.module, function.rva));
+    assert!(pdb.function_by_name("no such function")?.is_none());
+    Ok(())
+}
+
+#[test]
+fn query_address_index() -> Result<()> {
+    let contrib = |start, end, module| AddressRange::new(start, end, AddressKind::Contribution { module });
+    let index = AddressIndex::new(vec![
+        contrib(0x1000, 0x2000, 0),
+        contrib(0x1100, 0x1200, 1),
+        contrib(0x1180, 0x1190, 2),
+        contrib(0x3000, 0x3000, 3),
+        contrib(0x2000, 0x2800, 4),
+    ]);
+    assert_eq!(index.ranges().len(), 4);
+    let modules = |ranges: Vec<&AddressRange>| {
+        ranges
+            .iter()
+            .map(|range| match range.kind {
+                AddressKind::Contribution { module } => module,
+                _ => unreachable!(),
+            })
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(modules(index.at(0x1185)), [0, 1, 2]);
+    assert_eq!(modules(index.at(0x1fff)), [0]);
+    assert_eq!(modules(index.at(0x2000)), [4]);
+    assert!(index.at(0x3000).is_empty());
+    assert_eq!(modules(index.overlapping(0x11f0..0x2001)), [0, 1, 4]);
+    assert_eq!(
+        index.contribution_at(0x1185).map(|range| range.start),
+        Some(0x1180)
+    );
+
+    let pdb = Pdb::load_with_options(File::open("tests/llvm.pdb")?, ParseOptions::lenient())?;
+    let index = pdb.address_index()?;
+    let name = pdb
+        .module(1)?
+        .symbols()
+        .iter()
+        .find_map(|sym| match sym {
+            SymbolRecord::GlobalProc(proc) | SymbolRecord::Proc(proc) => Some(proc.name.as_ref()),
+            _ => None,
+        })
+        .unwrap();
+    let function = pdb.function_by_name(name)?.unwrap();
+    let procedure = index.procedure_at(function.rva + 1).unwrap();
+    assert_eq!(procedure.start, function.rva);
+    assert_matches!(procedure.kind, AddressKind::Procedure { module, .. } if module == function.module);
+    assert_matches!(
+        index.contribution_at(function.rva).map(|range| range.kind),
+        Some(AddressKind::Contribution { module }) if module == function.module
+    );
+    let public = index.public_at(function.rva).unwrap();
+    assert_matches!(public.kind, AddressKind::Public { .. });
+    Ok(())
+}
+
+#[test]
+fn search_symbols_across_streams() -> Result<()> {
+    let pdb = Pdb::load_with_options(File::open("tests/llvm.pdb")?, ParseOptions::lenient())?;
+    let found = pdb.search_symbols("*lang_start*")?;
+    assert!(found.iter().all(|found| found.name.contains("lang_start")));
+    assert!(found.iter().any(|found| found.source == SymbolSource::Publics));
+    assert!(found
+        .iter()
+        .any(|found| matches!(found.source, SymbolSource::Module(_))));
+
+    // the procedure references of the globals point at the offsets of module symbols
+    let symbols = pdb.symbols()?;
+    let (name, referent, module) = pdb
+        .globals()?
+        .hash_records()
+        .iter()
+        .find_map(|record| match symbols.get(record.symbol_offset())? {
+            SymbolRecord::ProcedureRef(proc) if proc.module == 2 => {
+                Some((proc.name.as_ref(), proc.referent, usize::from(proc.module) - 1))
+            }
+            _ => None,
+        })
+        .unwrap();
+    let path = pdb.dbi()?.modules()[module]
+        .module_name
+        .as_ref()
+        .to_ascii_uppercase();
+    let stem = path
+        .rsplit(['/', '\\'])
+        .next()
+        .unwrap()
+        .split('.')
+        .next()
+        .unwrap();
+    // module names match ignoring case
+    let found = pdb.search_symbols(&format!("{stem}*!{name}"))?;
+    assert_matches!(&found[..], [found] if found.source == SymbolSource::Module(module) && found.offset == referent);
+    assert!(pdb.search_symbols(&format!("no-such-module!{name}"))?.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn walk_raw_symbols() -> Result<()> {
+    let mut pdb = PdbFile::open(File::open("tests/llvm.pdb")?)?;
+    let dbi = pdb.get_dbi()?;
+    let symbols = pdb.get_symbols(&dbi)?;
+    let bytes = pdb.get_symbol_bytes(&dbi)?;
+    let raw = bytes.iter().collect::<Result<Vec<_>>>()?;
+    assert_eq!(raw.len(), symbols.records().len());
+    for ((offset, raw), (expected_offset, symbol)) in
+        raw.iter().zip(symbols.offsets().iter().zip(symbols.records()))
+    {
+        assert_eq!(offset, expected_offset);
+        // names read from the bytes agree with the decoded ones
+        if let Some(name) = raw.name() {
+            assert_eq!(Some(name), symbol.name());
+        }
+    }
+
+    let module = &dbi.modules()[1];
+    let decoded = pdb.get_module(module)?;
+    let bytes = pdb.get_module_symbol_bytes(module)?;
+    let offsets = bytes
+        .iter()
+        .map(|res| res.map(|(offset, _)| offset))
+        .collect::<Result<Vec<_>>>()?;
+    assert_eq!(
+        offsets,
+        decoded
+            .symbols_with_offsets()
+            .map(|(offset, _)| offset)
+            .collect::<Vec<_>>()
+    );
+
+    Ok(())
+}
+
+#[test]
+fn visit_type_records_in_chunks() -> Result<()> {
+    let mut pdb = PdbFile::open(File::open("tests/llvm.pdb")?)?;
+    let tpi = pdb.get_tpi()?;
+    let mut chunks = vec![];
+    let mut count = 0;
+    let header = pdb.visit_tpi_chunks(1000, |first, records| {
+        assert!(records.len() <= 1000);
+        chunks.push(u32::from(first));
+        for (record, expected) in records.iter().zip(&tpi.records()[count..]) {
+            assert_eq!(record.name(), expected.name());
+        }
+        count += records.len();
+        Ok(())
+    })?;
+    assert_eq!(count, tpi.records().len());
+    assert_eq!(header.type_record_bytes, tpi.header().type_record_bytes);
+    assert_eq!(chunks[..2], [0x1000, 0x1000 + 1000]);
+
+    let ipi = pdb.get_ipi()?;
+    let mut count = 0;
+    pdb.visit_ipi_chunks(64, |_, records| {
+        count += records.len();
+        Ok(())
+    })?;
+    assert_eq!(count, ipi.records().len());
+
+    // errors from the visitor stop the walk
+    let mut visited = 0;
+    let res = pdb.visit_tpi_chunks(10, |_, _| {
+        visited += 1;
+        Err(Error::TypeNotFound("stop".to_owned()))
     });
-    sym_builder.add(SymbolRecord::Constant(Constant {
-        constant_type: BuiltinType::I32.into(),
-        value: Integer::I32(2),
-        name: StrBuf::new("myconstant"),
-    }));
+    assert!(res.is_err());
+    assert_eq!(visited, 1);
+    Ok(())
+}
 
-    let mut output = io::Cursor::new(vec![]);
-    builder.commit(&mut output)?;
+#[test]
+fn read_modules_lazily() -> Result<()> {
+    let mut pdb = PdbFile::open(File::open("tests/llvm.pdb")?)?;
+    let dbi = pdb.get_dbi()?;
+    let info = pdb.get_info()?;
+    let strings = pdb.get_strings(&info)?;
+    let module = &dbi.modules()[1];
+    let decoded = pdb.get_module(module)?;
+    let lazy = pdb.get_lazy_module(module)?;
 
-    output.set_position(0);
-    Ok(output)
+    assert_eq!(lazy.symbols()?.len(), decoded.symbols().len());
+    assert_eq!(lazy.subsections().len(), decoded.c13_records().len());
+    for (extent, entry) in lazy.subsections().iter().zip(decoded.c13_records()) {
+        assert_eq!(extent.record_type, entry.record_type);
+        assert_eq!(lazy.subsection_data(extent), &entry.data[..]);
+    }
+    let checksums = lazy
+        .subsections()
+        .iter()
+        .find(|extent| extent.record_type == DebugSubsectionRecordType::FileChecksums)
+        .unwrap();
+    assert_matches!(
+        lazy.decode_subsection(checksums)?,
+        DebugSubsectionRecord::FileChecksums { .. }
+    );
+
+    let lines = decoded.lines(&strings)?;
+    assert_eq!(lazy.lines(&strings)?.len(), lines.len());
+    let proc = decoded
+        .symbols()
+        .iter()
+        .find_map(|sym| match sym {
+            SymbolRecord::GlobalProc(proc) | SymbolRecord::Proc(proc) => Some(proc),
+            _ => None,
+        })
+        .unwrap();
+    let start = DataRegionOffset::new(proc.code_offset.offset, proc.code_offset.segment);
+    let at = lazy.lines_at(&strings, start)?;
+    assert!(!at.is_empty() && at.len() < lines.len());
+    assert!(at
+        .iter()
+        .any(|line| line.offset.offset == proc.code_offset.offset));
+    Ok(())
+}
+
+#[cfg(feature = "regex")]
+#[test]
+fn filter_raw_symbols_by_regex() -> Result<()> {
+    let mut pdb = PdbFile::open(File::open("tests/llvm.pdb")?)?;
+    let dbi = pdb.get_dbi()?;
+    let symbols = pdb.get_symbols(&dbi)?;
+    let bytes = pdb.get_symbol_bytes(&dbi)?;
+    let regex = regex::Regex::new("lang_start").unwrap();
+
+    let found = bytes.iter().matching(&regex).collect::<Result<Vec<_>>>()?;
+    let expected: Vec<_> = symbols
+        .offsets()
+        .iter()
+        .zip(symbols.records())
+        .filter(|(_, symbol)| symbol.name().is_some_and(|name| regex.is_match(name)))
+        .map(|(offset, _)| *offset)
+        .collect();
+    assert!(!expected.is_empty());
+    assert_eq!(
+        found.iter().map(|(offset, _)| *offset).collect::<Vec<_>>(),
+        expected
+    );
+
+    Ok(())
+}
+
+#[test]
+fn find_global_variables() -> Result<()> {
+    let pdb = Pdb::load(File::open("tests/llvm.pdb")?)?;
+    let vars = pdb.global_variables()?;
+    let var = vars
+        .iter()
+        .find(|var| !var.is_static && !var.is_thread_local)
+        .unwrap();
+    let rva = var.rva.unwrap();
+    let module = var.module.unwrap();
+    assert!(pdb
+        .dbi()?
+        .section_contribs()
+        .iter()
+        .any(|contrib| { usize::from(contrib.i_mod) == module && contrib.i_sect == var.offset.segment }));
+
+    let found = pdb.global_variable_by_name(var.name)?.unwrap();
+    assert_eq!(found.rva, var.rva);
+    assert_eq!(u32::from(found.data_type), u32::from(var.data_type));
+    let in_range = pdb.global_variables_in(rva..rva + 1)?;
+    assert!(in_range.iter().any(|found| found.name == var.name));
+    assert!(in_range.iter().all(|found| found.rva == Some(rva)));
+    assert!(pdb.global_variable_by_name("no such variable")?.is_none());
+    Ok(())
+}
+
+#[test]
+fn digest_stream_contents() -> Result<()> {
+    let bytes = std::fs::read("tests/llvm.pdb")?;
+    let mut pdb = PdbFile::open(io::Cursor::new(bytes.clone()))?;
+    let digests = pdb.get_stream_digests()?;
+    let content = pdb.get_content_digest()?;
+    assert_eq!(
+        digests[1].digest.to_string(),
+        "a31b1e56ec38d6d7d5fe8a1836b925471e54c4446b1ddf7331a33e2c9635f3ad"
+    );
+    assert_eq!(
+        digests[2].digest.to_string(),
+        "4a7d2a8ca7bd7920af68b748237874f3f08799f011e34cc3859f3c689ab0a168"
+    );
+
+    // the info stream takes the first 1249 bytes of block 1408
+    let info_block = 1408 * 4096;
+    let mut padded = bytes.clone();
+    padded[info_block + 3000] ^= 0xFF;
+    let mut pdb = PdbFile::open(io::Cursor::new(padded))?;
+    assert_eq!(pdb.get_content_digest()?, content);
+
+    let mut corrupted = bytes;
+    corrupted[info_block + 10] ^= 0xFF;
+    let mut pdb = PdbFile::open(io::Cursor::new(corrupted))?;
+    let changed: Vec<_> = pdb
+        .get_stream_digests()?
+        .iter()
+        .zip(&digests)
+        .filter(|(a, b)| a.digest != b.digest)
+        .map(|(a, _)| u16::from(a.index))
+        .collect();
+    assert_eq!(changed, [1]);
+    assert_ne!(pdb.get_content_digest()?, content);
+
+    Ok(())
+}
+
+#[test]
+fn digest_streams_with_sha256() -> Result<()> {
+    // the known answers of FIPS 180-2
+    let vectors: [(&[u8], &str); 3] = [
+        (
+            b"",
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+        ),
+        (
+            b"abc",
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+        ),
+        (
+            b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq",
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1",
+        ),
+    ];
+    let mut builder = PdbBuilder::default();
+    for (i, (data, _)) in (0..).zip(vectors) {
+        builder.add_named_stream(format!("/sha{i}"), data.to_vec());
+    }
+    let mut pdb = reopen(builder)?;
+    let info = pdb.get_info()?;
+    let digests = pdb.get_stream_digests()?;
+    for (i, (_, expected)) in (0..).zip(vectors) {
+        let index = info.named_streams().get(&format!("/sha{i}")).unwrap();
+        let digest = digests.iter().find(|digest| digest.index == index).unwrap();
+        assert_eq!(digest.digest.to_string(), expected);
+    }
+    Ok(())
+}
+
+#[test]
+fn patch_info_in_place() -> Result<()> {
+    let bytes = std::fs::read("tests/llvm.pdb")?;
+    let mut pdb = PdbFile::open(io::Cursor::new(bytes.clone()))?;
+    let guid = Guid::new([0xAB; 16]);
+    let patch = InfoPatch {
+        age: Some(7),
+        guid: Some(guid.clone()),
+        ..Default::default()
+    };
+    let header = pdb.patch_info(patch)?;
+    assert_eq!(header.age, 7);
+
+    let info = pdb.get_info()?;
+    assert_eq!(info.header().age, 7);
+    assert_eq!(info.header().guid, guid);
+    assert!(info.named_streams().get("/names").is_some());
+
+    // only the header at the start of the info stream's block is rewritten
+    let patched = pdb.into_inner().into_inner();
+    let info_block = 1408 * 4096;
+    assert!(bytes
+        .iter()
+        .zip(&patched)
+        .enumerate()
+        .all(|(i, (a, b))| a == b || (info_block + 8..info_block + 28).contains(&i)));
+
+    Ok(())
+}
+
+#[test]
+fn query_unwind_rules() {
+    let fpo = FpoData {
+        offset: 0x1000,
+        size: 0x40,
+        num_locals: 2,
+        num_params: 1,
+        // 3 bytes of prolog, 1 saved register and ebp as the base pointer
+        attributes: 0x3 | (1 << 8) | (1 << 12),
+    };
+    let frame = |rva_start, code_size, frame_func| FrameData {
+        rva_start,
+        code_size,
+        local_size: 8,
+        params_size: 4,
+        max_stack_size: 0,
+        frame_func,
+        prolog_size: 6,
+        saved_regs_size: 4,
+        flags: 0,
+    };
+    let table = UnwindTable::new(&[fpo], &[
+        frame(0x2000, 0x20, 0),
+        frame(0x1000, 0x10, 12),
+        frame(0x1000, 0x4, 0),
+    ]);
+
+    assert_matches!(
+        table.unwind_info_at(0x1020),
+        Some(UnwindRule::Layout {
+            layout: FrameLayout {
+                locals_size: 8,
+                params_size: 4,
+                saved_regs_size: 4,
+                prolog_size: 3,
+                ..
+            },
+            kind: FpoFrameKind::Fpo,
+            uses_base_pointer: true
+        })
+    );
+    assert_matches!(table.unwind_info_at(0x1008), Some(UnwindRule::Program { .. }));
+    assert_matches!(
+        table.unwind_info_at(0x1002),
+        Some(UnwindRule::Layout {
+            uses_base_pointer: false,
+            ..
+        })
+    );
+    assert_matches!(table.unwind_info_at(0x201F), Some(UnwindRule::Layout { .. }));
+    assert_matches!(table.unwind_info_at(0x2020), None);
+    assert_matches!(table.unwind_info_at(0xFFF), None);
+
+    let layout = *table.unwind_info_at(0x1008).unwrap().layout();
+    let stack = [0x200, 0x401000];
+    let read = |addr: u32| stack.get(addr.checked_sub(0x100)? as usize / 4).copied();
+    let mut regs = HashMap::from([("$ebp".to_owned(), 0x100), ("$esp".to_owned(), 0xF0)]);
+    let program = "$T0 $ebp = $eip $T0 4 + ^ = $ebp $T0 ^ = $esp $T0 8 + = $T1 $esp 7 + 8 @ =";
+    eval_program(program, &layout, &mut regs, read).unwrap();
+    assert_eq!(regs["$eip"], 0x401000);
+    assert_eq!(regs["$ebp"], 0x200);
+    assert_eq!(regs["$esp"], 0x108);
+    assert_eq!(regs["$T1"], 0x108);
+    assert_eq!(regs[".raSearch"], 0xF0 + 8 + 4);
+
+    assert_matches!(
+        eval_program("4 $eip =", &layout, &mut regs, read),
+        Err(Error::InvalidUnwindProgram(_))
+    );
+    assert_matches!(
+        eval_program("$eip $esp ^ =", &layout, &mut regs, read),
+        Err(Error::InvalidUnwindProgram(_))
+    );
+    assert_matches!(
+        eval_program("$eip $T9 =", &layout, &mut regs, read),
+        Err(Error::InvalidUnwindProgram(_))
+    );
+}
+
+#[test]
+fn read_corrupted_symbols_leniently() -> Result<()> {
+    let mut dummy = write_dummy()?.into_inner();
+    // the label symbol is preceded by its kind, code offset and properties
+    let name_pos = dummy.windows(6).position(|w| w == b"label\0").unwrap();
+    dummy[name_pos - 9..name_pos - 7].copy_from_slice(&[0xFF, 0xFF]);
+
+    let mut pdb = PdbFile::open(io::Cursor::new(&dummy))?;
+    let dbi = pdb.get_dbi()?;
+    assert_matches!(
+        pdb.get_symbols(&dbi),
+        Err(Error::StreamFailed { stream: "symbols", source, .. })
+            if matches!(*source, Error::RecordFailed { kind: 0xFFFF, .. })
+    );
+
+    let mut pdb = PdbFile::open_with_options(io::Cursor::new(&dummy), ParseOptions::lenient())?;
+    let dbi = pdb.get_dbi()?;
+    let syms = pdb.get_symbols(&dbi)?;
+    assert_matches!(syms.records().as_slice(), [
+        SymbolRecord::Public32(_),
+        SymbolRecord::Constant(_)
+    ]);
+    assert_eq!(pdb.diagnostics().len(), 1);
+
+    Ok(())
+}
+
+// the constant symbol is given an LF_REAL32 value, a leaf that isn't an integer
+fn dummy_with_unknown_leaf() -> Result<Vec<u8>> {
+    let mut dummy = write_dummy()?.into_inner();
+    let name_pos = dummy.windows(11).position(|w| w == b"myconstant\0").unwrap();
+    dummy[name_pos - 6..name_pos - 4].copy_from_slice(&[0x05, 0x80]);
+    Ok(dummy)
+}
+
+#[test]
+fn reject_unknown_numeric_leaves_in_symbols() -> Result<()> {
+    let dummy = dummy_with_unknown_leaf()?;
+    let mut pdb = PdbFile::open(io::Cursor::new(&dummy))?;
+    let dbi = pdb.get_dbi()?;
+    assert_matches!(
+        pdb.get_symbols(&dbi),
+        Err(Error::StreamFailed { stream: "symbols", source, .. })
+            if matches!(*source, Error::RecordFailed { kind: 0x1107, .. })
+    );
+
+    let mut pdb = PdbFile::open_with_options(io::Cursor::new(&dummy), ParseOptions::lenient())?;
+    let dbi = pdb.get_dbi()?;
+    let syms = pdb.get_symbols(&dbi)?;
+    assert_matches!(syms.records().as_slice(), [
+        SymbolRecord::Public32(_),
+        SymbolRecord::Label { .. }
+    ]);
+    assert_eq!(pdb.diagnostics().len(), 1);
+    Ok(())
+}
+
+#[test]
+fn verify_roundtrip_of_records() -> Result<()> {
+    let mut pdb = PdbFile::open(write_dummy()?)?;
+    let dbi = pdb.get_dbi()?;
+    assert_matches!(pdb.verify_roundtrip(RoundtripStream::Tpi)?, None);
+    assert_matches!(pdb.verify_roundtrip(RoundtripStream::Symbols(&dbi))?, None);
+    Ok(())
+}
+
+#[test]
+fn verify_roundtrip_of_unknown_numeric_leaves() -> Result<()> {
+    let mut pdb = PdbFile::open(io::Cursor::new(dummy_with_unknown_leaf()?))?;
+    let dbi = pdb.get_dbi()?;
+    assert_matches!(
+        pdb.verify_roundtrip(RoundtripStream::Symbols(&dbi)),
+        Err(Error::StreamFailed { stream: "symbols", offset, .. }) if offset > 0
+    );
+    assert_matches!(pdb.verify_roundtrip(RoundtripStream::Tpi)?, None);
+    Ok(())
+}
+
+#[test]
+fn enforce_parse_limits() -> Result<()> {
+    let options = ParseOptions {
+        limits: ParseLimits {
+            max_streams: 10,
+            ..ParseLimits::default()
+        },
+        ..ParseOptions::default()
+    };
+    assert_matches!(
+        PdbFile::open_with_options(File::open("tests/llvm.pdb")?, options),
+        Err(Error::LimitExceeded(_))
+    );
+
+    Ok(())
+}
+
+const NATVIS: &str =
+    r#"<AutoVisualizer><Type Name="Dummy"><DisplayString>dummy</DisplayString></Type></AutoVisualizer>"#;
+
+#[test]
+fn write_with_padding_strategies() -> Result<()> {
+    let expected = write_dummy()?.into_inner();
+    let mut builder = write_dummy_builder()?;
+    builder.padding(StreamPadding::Sparse);
+    let mut output = io::Cursor::new(vec![]);
+    builder.commit(&mut output)?;
+    assert_eq!(output.into_inner(), expected);
+
+    // streams start every 4 blocks and the blocks in between are free
+    let mut builder = write_dummy_builder()?;
+    builder.padding(StreamPadding::Fill(0xDD)).stream_alignment(4);
+    let mut output = io::Cursor::new(vec![]);
+    builder.commit(&mut output)?;
+    let bytes = output.get_ref().clone();
+    assert!(bytes.len() > expected.len());
+    output.set_position(0);
+    let mut pdb = PdbFile::open(output)?;
+    let info = pdb.get_info()?;
+    assert_eq!(pdb.get_named_stream(&info, "/TMCache")?, [1, 0, 0, 0, 7, 0, 0, 0]);
+    let allocation = pdb.get_block_allocation()?;
+    assert!(allocation.free_blocks > 0);
+    assert!(allocation.free_but_referenced.is_empty());
+    assert!(allocation.used_but_unreferenced.is_empty());
+    // the rest of the block of the stream is filled
+    let tmcache = bytes
+        .chunks(4096)
+        .find(|block| block.starts_with(&[1, 0, 0, 0, 7, 0, 0, 0]))
+        .unwrap();
+    assert!(tmcache[8..].iter().all(|&byte| byte == 0xDD));
+    Ok(())
+}
+
+#[test]
+fn maintain_free_block_map() -> Result<()> {
+    // large enough to span more than one FPM interval
+    let large: Vec<u8> = (0..20 << 20).map(|i: u32| (i % 251) as u8).collect();
+    let mut builder = write_dummy_builder()?;
+    builder.add_named_stream("large", large.clone());
+    let mut pdb = reopen(builder)?;
+    let info = pdb.get_info()?;
+    assert_eq!(pdb.get_named_stream(&info, "large")?, large);
+    let allocation = pdb.get_block_allocation()?;
+    assert!(allocation.num_blocks > 4096);
+    assert_eq!(allocation.free_blocks, 0);
+    assert!(allocation.free_but_referenced.is_empty());
+    assert!(allocation.used_but_unreferenced.is_empty());
+
+    // the blocks of the old contents are freed and the other FPM takes over
+    let index = info.named_streams().get("large").unwrap();
+    pdb.replace_stream(index, b"small")?;
+    let added = pdb.add_stream(b"added")?;
+    let mut output = pdb.into_inner();
+    output.set_position(0);
+    let mut pdb = PdbFile::open(output)?;
+    let info = pdb.get_info()?;
+    assert_eq!(pdb.get_named_stream(&info, "large")?, b"small");
+    assert_eq!(pdb.get_named_stream(&info, "/TMCache")?, [1, 0, 0, 0, 7, 0, 0, 0]);
+    assert_eq!(pdb.msf_info().num_streams, u32::from(u16::from(added)) + 1);
+    assert_eq!(pdb.msf_info().free_block_map_block, 1);
+    let allocation = pdb.get_block_allocation()?;
+    assert!(allocation.free_blocks >= (20 << 20) / 4096);
+    assert!(allocation.free_but_referenced.is_empty());
+    assert!(allocation.used_but_unreferenced.is_empty());
+
+    // freed blocks are recycled by later changes
+    let num_blocks = pdb.msf_info().num_blocks;
+    pdb.replace_stream(index, &large[..1 << 20])?;
+    assert_eq!(pdb.msf_info().num_blocks, num_blocks);
+    let mut output = pdb.into_inner();
+    output.set_position(0);
+    let mut pdb = PdbFile::open(output)?;
+    assert_eq!(pdb.get_named_stream(&info, "large")?, &large[..1 << 20]);
+    assert!(pdb.get_tpi().is_ok());
+    Ok(())
+}
+
+fn write_dummy() -> Result<io::Cursor<Vec<u8>>> {
+    let builder = write_dummy_builder()?;
+    let mut output = io::Cursor::new(vec![]);
+    builder.commit(&mut output)?;
+
+    output.set_position(0);
+    Ok(output)
+}
+
+fn reopen(builder: PdbBuilder) -> Result<PdbFile<io::Cursor<Vec<u8>>>> {
+    let mut output = io::Cursor::new(vec![]);
+    builder.commit(&mut output)?;
+    output.set_position(0);
+    PdbFile::open(output)
+}
+
+fn write_dummy_builder() -> Result<PdbBuilder> {
+    let mut builder = PdbBuilder::default();
+    builder.add_named_stream("/TMCache", vec![1, 0, 0, 0, 7, 0, 0, 0]);
+    builder.tpi().add("pointer_type", TypeRecord::Pointer {
+        referent: BuiltinType::I64.into(),
+        properties: PointerProperties::new()
+            .with_is_const(true)
+            .with_is_volatile(true)
+            .with_kind(PointerKind::Near64),
+        containing_class: None,
+    });
+    builder.ipi().add("string_id", IdRecord::StringId {
+        id: None,
+        string: StrBuf::new("test"),
+    });
+
+    let mut sym_builder = builder.dbi().symbols();
+    sym_builder.add(Public {
+        properties: PublicProperties::new().with_is_msil(true),
+        offset: DataRegionOffset::new(0, 0),
+        name: StrBuf::new("hello"),
+    })?;
+    let sym_builder = sym_builder.finish_publics();
+    sym_builder.add(SymbolRecord::Label {
+        code_offset: DataRegionOffset::new(0, 0),
+        properties: ProcedureProperties::new()
+            .with_has_fp(true)
+            .with_is_no_return(true),
+        name: StrBuf::new("label"),
+    });
+    sym_builder.add(SymbolRecord::Constant(Constant {
+        constant_type: BuiltinType::I32.into(),
+        value: Integer::I32(2),
+        name: StrBuf::new("myconstant"),
+    }));
+
+    let mut module = ModuleBuilder::new(
+        "main.obj".to_owned(),
+        "main.obj".to_owned(),
+        SectionContrib::new(1, 0, 0x10, 0x6000_0020, 0),
+    );
+    module.add_subsection(DebugSubsectionRecord::FileChecksums {
+        entries: vec![FileChecksumEntry {
+            file_name_offset: 0,
+            checksum_size: 0,
+            checksum_type: ChecksumType::None,
+            bytes: vec![],
+        }],
+    })?;
+    module.add_subsection(DebugSubsectionRecord::Lines {
+        header: LineFragmentHeader {
+            reloc: DataRegionOffset::new(0, 1),
+            flags: LineFlags::new(),
+            code_size: 0x10,
+        },
+        entries: vec![LineColumnEntry {
+            name_index: 0,
+            num_lines: 1,
+            code_size: 0x10,
+            line_numbers: vec![LineNumberEntry { offset: 0, flags: 1 }],
+            columns: vec![],
+        }],
+    })?;
+    builder.dbi().add_module(module);
+    Ok(builder)
+}
+
+#[test]
+fn write_names_with_v2_hash() -> Result<()> {
+    let mut builder = PdbBuilder::default();
+    builder.names_hash_version(HashVersion::V2);
+    builder.add_source_file(r"C:\src\main.rs", b"fn main() {}".to_vec());
+    builder.add_source_file(r"C:\src\lib.rs", b"".to_vec());
+    let mut pdb = reopen(builder)?;
+    let info = pdb.get_info()?;
+    let strings = pdb.get_strings(&info)?;
+    assert_eq!(strings.hash_version(), HashVersion::V2);
+    assert!(strings.find(r"C:\src\lib.rs", NameMatching::Exact).is_some());
+    assert!(strings
+        .find(r"C:\SRC\LIB.RS", NameMatching::CaseInsensitive)
+        .is_some());
+    assert_eq!(strings.find(r"C:\SRC\LIB.RS", NameMatching::Exact), None);
+    assert_eq!(pdb.get_injected_sources(&info)?.len(), 2);
+
+    Ok(())
+}
+
+// the /LinkInfo stream of MSVC, the output file is found within the command line
+fn link_info_stream(command_offset: u32, output_file_index: u32) -> Vec<u8> {
+    let strings: &[u8] = b"C:\\build\0link.exe /OUT:app.exe\0kernel32.lib\0user32.lib\0\0";
+    let mut data = vec![];
+    for value in [
+        24 + strings.len() as u32,
+        1,
+        24,
+        command_offset,
+        output_file_index,
+        55,
+    ] {
+        data.extend_from_slice(&value.to_le_bytes());
+    }
+    data.extend_from_slice(strings);
+    data
+}
+
+#[test]
+fn read_link_info() -> Result<()> {
+    let read = |data: Vec<u8>| -> Result<_> {
+        let mut builder = PdbBuilder::default();
+        builder.add_named_stream("/LinkInfo", data);
+        let mut pdb = reopen(builder)?;
+        let info = pdb.get_info()?;
+        pdb.get_link_info(&info)
+    };
+
+    let link_info = read(link_info_stream(33, 14))?;
+    assert_eq!(link_info.cwd().as_ref(), r"C:\build");
+    assert_eq!(link_info.command().as_ref(), "link.exe /OUT:app.exe");
+    assert_eq!(link_info.output_file().as_ref(), "app.exe");
+    let libs: Vec<_> = link_info.libs().iter().map(StrBuf::as_ref).collect();
+    assert_eq!(libs, ["kernel32.lib", "user32.lib"]);
+
+    assert_matches!(
+        read(link_info_stream(33, u32::MAX)),
+        Err(Error::StreamFailed {
+            stream: "link info",
+            source,
+            ..
+        }) if matches!(*source, Error::MalformedInput(_))
+    );
+    Ok(())
+}
+
+#[test]
+fn read_llvm_link_info() -> Result<()> {
+    let mut pdb = PdbFile::open(File::open("tests/llvm.pdb")?)?;
+    let info = pdb.get_info()?;
+    let link_info = pdb.get_link_info(&info)?;
+    assert!(link_info.libs().is_empty());
+    Ok(())
+}
+
+#[test]
+fn write_named_stream_table() -> Result<()> {
+    let mut builder = PdbBuilder::default();
+    let streams = [
+        ("a", vec![1]),
+        ("/longer/name", vec![2, 2]),
+        ("mid", vec![3, 3, 3]),
+    ];
+    for (name, data) in &streams {
+        builder.add_named_stream(*name, data.clone());
+    }
+    // every name has to point at its own stream, not at the one of the name before it
+    let mut pdb = reopen(builder)?;
+    let info = pdb.get_info()?;
+    for (name, data) in &streams {
+        assert_eq!(&pdb.get_named_stream(&info, name)?, data);
+    }
+    Ok(())
+}
+
+#[test]
+fn read_tm_cache() -> Result<()> {
+    let mut pdb = PdbFile::open(write_dummy()?)?;
+    let info = pdb.get_info()?;
+    let tm_cache = pdb.get_tm_cache(&info)?;
+    assert_eq!(tm_cache.version(), Some(1));
+    assert_eq!(tm_cache.entries().collect::<Vec<_>>(), vec![7]);
+    Ok(())
+}
+
+#[test]
+fn keep_tm_cache_on_rebuild() -> Result<()> {
+    let mut pdb = PdbFile::open(write_dummy()?)?;
+    let mut rebuilt = reopen(PdbBuilder::from_pdb(&mut pdb)?)?;
+    let info = rebuilt.get_info()?;
+    let tm_cache = rebuilt.get_tm_cache(&info)?;
+    assert_eq!(tm_cache.version(), Some(1));
+    assert_eq!(tm_cache.entries().collect::<Vec<_>>(), vec![7]);
+    Ok(())
+}
+
+#[test]
+fn write_srcsrv() -> Result<()> {
+    let mut builder = PdbBuilder::default();
+    builder.srcsrv("SRCSRV: ini ------------------------------------------------\r\n");
+    let mut pdb = reopen(builder)?;
+    let info = pdb.get_info()?;
+    assert_eq!(
+        pdb.get_srcsrv(&info)?,
+        "SRCSRV: ini ------------------------------------------------\r\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn write_source_link() -> Result<()> {
+    let mut builder = PdbBuilder::default();
+    builder
+        .source_link(SourceLink::default().add(r"C:\src\pdb-sdk\*", "https://raw.example.com/pdb-sdk/*"));
+    let mut pdb = reopen(builder)?;
+    let info = pdb.get_info()?;
+    let source_link = pdb.get_source_link(&info)?;
+    assert_eq!(
+        source_link.url_for(r"C:\src\pdb-sdk\src\lib.rs").as_deref(),
+        Some("https://raw.example.com/pdb-sdk/src/lib.rs")
+    );
+    assert_eq!(source_link.url_for(r"D:\other.rs"), None);
+    Ok(())
+}
+
+#[test]
+fn reject_malformed_source_link() {
+    for json in [
+        r#"{"documents": {"C:\\src\\*": "https://raw.example.com/*""#,
+        r#"["C:\\src\\*"]"#,
+        r#"{"documents": {"C:\\src\\*": 1}}"#,
+        r#"{"documents": []}"#,
+    ] {
+        assert_matches!(SourceLink::parse(json), Err(Error::MalformedInput(_)));
+    }
+    assert!(SourceLink::parse(r#"{"documents": {"C:\\src\\*": "https://raw.example.com/*"}}"#).is_ok());
+}
+
+#[test]
+fn find_names_by_case() -> Result<()> {
+    let mut builder = write_dummy_builder()?;
+    builder.add_name(r"C:\src\main.rs")?;
+    let mut pdb = reopen(builder)?;
+    let dbi = pdb.get_dbi()?;
+    let syms = pdb.get_symbols(&dbi)?;
+    let globals = pdb.get_globals(&dbi)?;
+    assert_eq!(globals.find(&syms, "MyConstant", NameMatching::Exact), None);
+    assert_eq!(
+        globals.find(&syms, "MyConstant", NameMatching::CaseInsensitive),
+        Some(SymbolOffset::from(40))
+    );
+    assert_eq!(
+        globals.find(&syms, "myconstant", NameMatching::PreferExact),
+        Some(SymbolOffset::from(40))
+    );
+    let publics = pdb.get_publics(&dbi)?;
+    assert_eq!(
+        publics.find(&syms, "HELLO", NameMatching::CaseInsensitive),
+        Some(SymbolOffset::from(0))
+    );
+
+    let info = pdb.get_info()?;
+    let strings = pdb.get_strings(&info)?;
+    let source = strings.find(r"C:\src\main.rs", NameMatching::Exact);
+    assert!(source.is_some());
+    assert_eq!(
+        strings.find(r"c:\SRC\main.rs", NameMatching::CaseInsensitive),
+        source
+    );
+    assert_eq!(strings.find(r"c:\SRC\main.rs", NameMatching::Exact), None);
+    Ok(())
+}
+
+#[test]
+fn iterate_gsi_buckets() -> Result<()> {
+    let mut pdb = PdbFile::open(write_dummy()?)?;
+    let dbi = pdb.get_dbi()?;
+    let syms = pdb.get_symbols(&dbi)?;
+    let globals = pdb.get_globals(&dbi)?;
+    assert_eq!(globals.hash_records().len(), 2);
+    for (bucket, records) in globals.buckets() {
+        for record in records {
+            let name = syms
+                .get(record.symbol_offset())
+                .and_then(SymbolRecord::name)
+                .unwrap();
+            assert_eq!(SymbolMap::bucket_of(name), bucket);
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn write_module_ec_names() -> Result<()> {
+    let mut builder = PdbBuilder::default();
+    let mut module = ModuleBuilder::new(
+        "main.obj".to_owned(),
+        "main.obj".to_owned(),
+        SectionContrib::new(1, 0, 0x10, 0x6000_0020, 0),
+    );
+    module.ec_names(r"C:\src\main.rs".to_owned(), r"C:\src\vc140.pdb".to_owned());
+    builder.dbi().add_module(module);
+    let mut pdb = reopen(builder)?;
+    let dbi = pdb.get_dbi()?;
+    assert_eq!(dbi.src_file_name(&dbi.modules()[0]), Some(r"C:\src\main.rs"));
+    assert_eq!(dbi.pdb_file_path(&dbi.modules()[0]), Some(r"C:\src\vc140.pdb"));
+    assert!(dbi.modules()[0].header.flags.is_ec_enabled());
+    Ok(())
+}
+
+#[test]
+fn emit_module_compiler_symbols() -> Result<()> {
+    let mut builder = PdbBuilder::default();
+    let mut module = ModuleBuilder::new(
+        "main.obj".to_owned(),
+        "main.obj".to_owned(),
+        SectionContrib::new(1, 0, 0x10, 0x6000_0020, 0),
+    );
+    module.compiler(CompilerInfo {
+        name: "rustc".to_owned(),
+        language: SourceLanguage::Rust,
+        machine: MachineType::Amd64.cpu_type().unwrap(),
+        frontend_version: Version {
+            major: 1,
+            minor: 80,
+            build: 0,
+        },
+        backend_version: Version {
+            major: 18,
+            minor: 1,
+            build: 0,
+        },
+    })?;
+    builder.dbi().add_module(module);
+    let mut pdb = reopen(builder)?;
+    let dbi = pdb.get_dbi()?;
+    let module = pdb.get_module(&dbi.modules()[0])?;
+    assert_matches!(&module.symbols()[0], SymbolRecord::ObjectName { name, .. } if name.as_ref() == "main.obj");
+    assert_matches!(
+        &module.symbols()[1],
+        SymbolRecord::Compile3 { machine: 0xD0, version, .. } if version.as_ref() == "rustc"
+    );
+    Ok(())
+}
+
+#[test]
+fn write_dbi_versions() -> Result<()> {
+    let mut builder = PdbBuilder::default();
+    builder.dbi().dll_version(33135).rbld(1);
+    let mut pdb = reopen(builder)?;
+    let dbi = pdb.get_dbi()?;
+    assert_eq!(dbi.header().dll_version, 33135);
+    assert_eq!(dbi.header().rbld, 1);
+    assert_eq!(dbi.header().mfc_type_server_index, 0);
+    Ok(())
+}
+
+#[test]
+fn write_incremental_link_metadata() -> Result<()> {
+    let mut builder = PdbBuilder::default();
+    builder
+        .dbi()
+        .incrementally_linked(true)
+        .type_server_map(vec![1, 0, 0, 0, 7, 0, 0, 0]);
+    let mut module = ModuleBuilder::new(
+        "main.obj".to_owned(),
+        "main.obj".to_owned(),
+        SectionContrib::new(1, 0, 0x10, 0x6000_0020, 0),
+    );
+    module.dirty(true).type_server_index(1);
+    builder.dbi().add_module(module);
+    let mut pdb = reopen(builder)?;
+    let dbi = pdb.get_dbi()?;
+    assert!(dbi.modules()[0].header.flags.is_dirty());
+    assert_eq!(dbi.modules()[0].header.type_server_index, 1);
+    assert!(dbi.header().flags.is_incrementally_linked());
+    assert_eq!(dbi.type_server_stream(), &[1, 0, 0, 0, 7, 0, 0, 0]);
+    Ok(())
+}
+
+#[test]
+fn look_up_names_by_value() -> Result<()> {
+    let mut builder = PdbBuilder::default();
+    builder.add_name(r"C:\src\main.rs")?;
+    builder.add_name(r"C:\src\lib.rs")?;
+    let mut pdb = reopen(builder)?;
+    let strings = pdb.strings()?;
+    assert_eq!(strings.iter().count(), 2);
+    for (offset, str) in strings.iter() {
+        assert_eq!(strings.offset_of(str), Some(offset));
+    }
+    Ok(())
+}
+
+#[test]
+fn write_injected_sources() -> Result<()> {
+    let mut builder = PdbBuilder::default();
+    builder.add_source_file(r"C:\src\main.rs", b"fn main() {}".to_vec());
+    let mut pdb = reopen(builder)?;
+    let info = pdb.get_info()?;
+    let sources = pdb.get_injected_sources(&info)?;
+    assert_eq!(sources.len(), 1);
+    assert_eq!(sources[0].name, r"C:\src\main.rs");
+    assert_eq!(pdb.get_injected_source_data(&info, &sources[0])?, b"fn main() {}");
+    Ok(())
+}
+
+#[test]
+fn write_natvis() -> Result<()> {
+    let mut builder = PdbBuilder::default();
+    builder.add_natvis("Dummy.natvis", NATVIS);
+    let mut pdb = reopen(builder)?;
+    let info = pdb.get_info()?;
+    let natvis = pdb.get_natvis(&info)?;
+    assert_eq!(natvis, vec![("Dummy.natvis".to_owned(), NATVIS.to_owned())]);
+    Ok(())
+}
+
+#[test]
+fn look_up_names_by_hash() -> Result<()> {
+    let mut builder = PdbBuilder::default();
+    let names: Vec<_> = (0..200).map(|i| format!(r"C:\include\{i}.h")).collect();
+    let mut offsets = vec![];
+    for name in &names {
+        offsets.push(builder.add_name(name)?);
+    }
+    let mut pdb = reopen(builder)?;
+    let strings = pdb.strings()?;
+    for (name, offset) in names.iter().zip(offsets) {
+        assert_eq!(strings.offset_of(name), Some(offset));
+    }
+    assert_eq!(strings.offset_of(r"C:\include\missing.h"), None);
+
+    Ok(())
+}
+
+#[test]
+fn diff_pdbs() -> Result<()> {
+    let mut llvm = PdbFile::open(File::open("tests/llvm.pdb")?)?;
+    let mut same = PdbFile::open(File::open("tests/llvm.pdb")?)?;
+    let res = diff(&mut llvm, &mut same)?;
+    assert!(res.types.is_empty() && res.symbols.is_empty() && res.modules.is_empty());
+    assert!(res.streams.is_empty());
+
+    let mut dummy = PdbFile::open(write_dummy()?)?;
+    let res = diff(&mut dummy, &mut llvm)?;
+    assert!(res.types.removed.is_empty());
+    assert!(res
+        .types
+        .added
+        .iter()
+        .any(|typ| typ.name == "core::fmt::rt::v1::FormatSpec"));
+    assert!(res.symbols.removed.iter().any(|sym| sym.name == "label"));
+    assert!(res
+        .streams
+        .iter()
+        .any(|stream| stream.name == "TPI" && stream.delta() > 0));
+
+    Ok(())
+}
+
+#[test]
+fn symbol_store_path() {
+    let guid = Guid::new([
+        0x78, 0x56, 0x34, 0x12, 0x34, 0x12, 0x78, 0x56, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08,
+    ]);
+    assert_eq!(
+        store_path(r"C:\build\app.pdb", &guid, 26),
+        "app.pdb/123456781234567801020304050607081A/app.pdb"
+    );
+}
+
+#[test]
+fn publish_to_symbol_store() -> Result<()> {
+    let root = std::env::temp_dir().join(format!("pdb-sdk-symstore-{}", std::process::id()));
+    let dummy = write_dummy()?.into_inner();
+
+    let mut writer = SymbolStoreWriter::new(&root);
+    assert_matches!(
+        writer.add_pe("app.exe", b"MZ".to_vec()),
+        Err(Error::MalformedInput(_))
+    );
+    writer.product("dummy").add_pdb("dummy.pdb", dummy.clone())?;
+    assert_eq!(writer.commit()?, "0000000001");
+
+    let key = store_path("dummy.pdb", &Guid::default(), 1);
+    assert_eq!(std::fs::read(root.join(key))?, dummy);
+    assert_eq!(
+        std::fs::read_to_string(root.join("000Admin/lastid.txt"))?,
+        "0000000001\r\n"
+    );
+    assert_eq!(
+        std::fs::read_to_string(root.join("000Admin/0000000001"))?,
+        format!("\"dummy.pdb\\{:032}1\",\"dummy.pdb\"\r\n", 0)
+    );
+
+    std::fs::remove_dir_all(root)?;
+    Ok(())
+}
+
+#[cfg(feature = "cab")]
+#[test]
+fn extract_mszip_cabinet() -> Result<()> {
+    let files = pdb_sdk::cab::extract(File::open("tests/lines.cab")?)?;
+    let expected: String = (0..5000).map(|i| format!("line {i}\n")).collect();
+    assert_matches!(&files[..], [file] if file.name == "lines.txt" && file.data == expected.as_bytes());
+
+    // the size of the file entry, which precedes its offset, folder, date, time, attributes and name
+    let mut cab = std::fs::read("tests/lines.cab")?;
+    let name_pos = cab.windows(10).position(|w| w == b"lines.txt\0").unwrap();
+    cab[name_pos - 16..name_pos - 12].copy_from_slice(&u32::MAX.to_le_bytes());
+    assert_matches!(
+        pdb_sdk::cab::extract(io::Cursor::new(cab)),
+        Err(Error::MalformedInput(_))
+    );
+
+    Ok(())
+}
+
+#[test]
+fn import_map_file() -> Result<()> {
+    let map = MapFile::parse(&std::fs::read_to_string("tests/app.map")?)?;
+    assert_eq!(map.module_name, "app");
+    assert_eq!(map.contributions.len(), 5);
+    assert_eq!(map.symbols.len(), 6);
+    assert_matches!(
+        MapFile::parse(" app\n Timestamp is zz (Mon Jul 27 02:03:40 2020)\n"),
+        Err(Error::MalformedInput(_))
+    );
+
+    let mut builder = PdbBuilder::default();
+    map.populate(&mut builder, true)?;
+    let mut pdb = reopen(builder)?;
+    let dbi = pdb.get_dbi()?;
+    let headers = pdb.get_section_headers(&dbi)?;
+    assert_matches!(headers.headers()[..], [
+        SectionHeader {
+            name: [b'.', b't', b'e', b'x', b't', 0, 0, 0],
+            virtual_address: 0x1000,
+            virtual_size: 0x120,
+            ..
+        },
+        SectionHeader {
+            virtual_address: 0x2000,
+            ..
+        },
+        SectionHeader {
+            virtual_address: 0x3000,
+            ..
+        }
+    ]);
+
+    let symbols = pdb.get_symbols(&dbi)?;
+    assert!(symbols
+        .records()
+        .iter()
+        .any(|sym| matches!(sym, SymbolRecord::Public32(public) if public.name.as_ref() == "main")));
+    let publics = pdb.get_publics(&dbi)?;
+    let header = publics.header();
+    assert_eq!(header.num_sections, 3);
+    assert_eq!(header.num_thunks, 0);
+    assert_eq!(header.addr_map as usize, publics.address_map().len() * 4);
+    let sections: Vec<_> = publics
+        .section_offsets()
+        .iter()
+        .map(|section| (section.section, section.offset))
+        .collect();
+    assert_eq!(sections, [(1, 0x1000), (2, 0x2000), (3, 0x3000)]);
+
+    let names: Vec<_> = dbi.modules().iter().map(|m| m.module_name.as_ref()).collect();
+    assert_eq!(names, ["main.obj", "util.obj"]);
+    let module = pdb.get_module(&dbi.modules()[0])?;
+    assert_matches!(module.symbols()[..], [
+        SymbolRecord::GlobalProc(Procedure { code_size: 0x40, .. }),
+        SymbolRecord::ScopeEnd,
+        SymbolRecord::Proc(Procedure { code_size: 0x40, .. }),
+        SymbolRecord::ScopeEnd
+    ]);
+
+    Ok(())
+}
+
+#[test]
+fn import_symbol_list() -> Result<()> {
+    let json = r#"{"symbols": [
+        {"address": "0x140001000", "size": 32, "name": "main", "type": "function"},
+        {"address": "140001020", "name": "helper"},
+        {"address": 5368717312, "size": 8, "name": "counter", "type": "data"}
+    ]}"#;
+    let csv = "address,size,name,type\n0x140001000,32,main,function\n140001020,,helper,\n140002000,8,\"counter\",data\n";
+    let from_json = SymbolList::parse_json(json)?;
+    let from_csv = SymbolList::parse_csv(csv)?;
+    assert_eq!(from_json.symbols.len(), 3);
+    assert_eq!(from_csv.symbols.len(), 3);
+    assert_eq!(from_json.symbols[2].address, 0x140002000);
+    assert_eq!(from_csv.symbols[2].address, 0x140002000);
+    assert_matches!(
+        SymbolList::parse_json(r#"{"symbols": ["#),
+        Err(Error::MalformedInput(_))
+    );
+    assert_matches!(
+        SymbolList::parse_json(r#"{"symbols": [{"address": 1}]}"#),
+        Err(Error::MalformedInput(_))
+    );
+    assert_matches!(
+        SymbolList::parse_csv("0x10,zz,main\n"),
+        Err(Error::MalformedInput(_))
+    );
+
+    let section = |name: &[u8; 8], virtual_address, characteristics| SectionHeader {
+        name: *name,
+        virtual_size: 0x100,
+        virtual_address,
+        size_of_raw_data: 0,
+        pointer_to_raw_data: 0,
+        pointer_to_relocations: 0,
+        pointer_to_line_numbers: 0,
+        number_of_relocations: 0,
+        number_of_line_numbers: 0,
+        characteristics,
+    };
+    let sections = vec![
+        section(b".text\0\0\0", 0x1000, 0x6000_0020),
+        section(b".data\0\0\0", 0x2000, 0xC000_0040),
+    ];
+    let mut builder = PdbBuilder::default();
+    from_csv.populate(&mut builder, 0x140000000, sections)?;
+    builder.dbi().validate_addresses(true);
+    let mut pdb = reopen(builder)?;
+
+    let mut invalid = PdbBuilder::default();
+    invalid
+        .dbi()
+        .add_sections(vec![section(b".text\0\0\0", 0x1000, 0x6000_0020)])
+        .validate_addresses(true);
+    invalid.dbi().symbols().add(Public {
+        properties: PublicProperties::new(),
+        offset: DataRegionOffset::new(0x100, 1),
+        name: StrBuf::new("past_end"),
+    })?;
+    assert_matches!(
+        invalid.commit(&mut io::Cursor::new(vec![])),
+        Err(Error::AddressOutOfSection(name)) if name == "past_end"
+    );
+
+    let dbi = pdb.get_dbi()?;
+    assert_eq!(pdb.get_section_headers(&dbi)?.headers().len(), 2);
+    assert_eq!(dbi.section_contribs().len(), 3);
+    let module = pdb.get_module(&dbi.modules()[0])?;
+    assert_matches!(module.symbols()[..], [
+        SymbolRecord::GlobalProc(Procedure { code_size: 32, .. }),
+        SymbolRecord::ScopeEnd,
+        // extends to the end of the section
+        SymbolRecord::GlobalProc(Procedure { code_size: 0xE0, .. }),
+        SymbolRecord::ScopeEnd
+    ]);
+
+    Ok(())
+}
+
+#[test]
+fn unescape_json_strings() -> Result<()> {
+    let name = |escaped: &str| -> Result<String> {
+        let json = format!(r#"{{"symbols": [{{"address": 1, "name": "{escaped}"}}]}}"#);
+        Ok(SymbolList::parse_json(&json)?.symbols.remove(0).name)
+    };
+    assert_eq!(name(r"caf\u00e9")?, "caf\u{e9}");
+    assert_eq!(name(r"\ud83d\ude00")?, "\u{1F600}");
+    assert_eq!(name(r"\ud83dx")?, "\u{FFFD}x");
+    assert_matches!(name(r"\u12"), Err(Error::MalformedInput(_)));
+    assert_matches!(name(r"\u+123"), Err(Error::MalformedInput(_)));
+    Ok(())
+}
+
+#[test]
+fn write_empty_and_multi_block_streams() -> Result<()> {
+    let large: Vec<u8> = (0..9000).map(|i| i as u8).collect();
+    let mut builder = PdbBuilder::default();
+    builder.add_named_stream("/empty", vec![]);
+    builder.add_named_stream("/large", large.clone());
+    builder.add_named_stream("/small", vec![7; 10]);
+    let mut pdb = reopen(builder)?;
+    let info = pdb.get_info()?;
+    assert!(pdb.get_named_stream(&info, "/empty")?.is_empty());
+    assert_eq!(pdb.get_named_stream(&info, "/large")?, large);
+    assert_eq!(pdb.get_named_stream(&info, "/small")?, vec![7; 10]);
+
+    Ok(())
+}
+
+#[test]
+fn validate_type_indices() -> Result<()> {
+    let index = |index: u32| TypeIndex::try_from(index).unwrap();
+    let mut builder = PdbBuilder::default();
+    let pointer = |referent| TypeRecord::Pointer {
+        referent,
+        properties: PointerProperties::new().with_kind(PointerKind::Near64),
+        containing_class: None,
+    };
+    let first = builder.tpi().try_add("", pointer(BuiltinType::I64.into()))?;
+    builder.tpi().try_add("", pointer(first))?;
+    assert_matches!(
+        builder.tpi().try_add("", pointer(index(0x1002))),
+        Err(Error::InvalidTypeIndex {
+            record: 0x1002,
+            kind: "type",
+            index: 0x1002
+        })
+    );
+
+    let func_id = |function_type| IdRecord::FuncId {
+        parent_scope: None,
+        function_type,
+        name: StrBuf::new("main"),
+    };
+    // types are only checked once there's a limit
+    builder.ipi().try_add("", func_id(index(0x1003)))?;
+    let limit = builder.tpi().next_index();
+    assert_eq!(u32::from(limit), 0x1002);
+    assert_matches!(
+        builder
+            .ipi()
+            .type_limit(limit)
+            .try_add("", func_id(index(0x1003))),
+        Err(Error::InvalidTypeIndex {
+            record: 0x1001,
+            kind: "type",
+            index: 0x1003
+        })
+    );
+
+    Ok(())
+}
+
+#[test]
+fn ingest_coff_debug_sections() -> Result<()> {
+    let index = |index: u32| TypeIndex::try_from(index).unwrap();
+    let mut debug_t = 4u32.to_le_bytes().to_vec();
+    debug_t.extend(cv_record(&TypeRecord::ArgList {
+        count: 0,
+        arg_list: vec![],
+    })?);
+    debug_t.extend(cv_record(&TypeRecord::Procedure {
+        return_type: Some(BuiltinType::I32.into()),
+        calling_conv: CallingConvention::NearC,
+        properties: FunctionProperties::new(),
+        arg_count: 0,
+        arg_list: index(0x1000),
+    })?);
+    debug_t.extend(cv_record(&IdRecord::FuncId {
+        parent_scope: None,
+        function_type: index(0x1001),
+        name: StrBuf::new("main"),
+    })?);
+
+    let debug_s = object_symbols(0x1002)?;
+
+    let relocations = [
+        DebugRelocation {
+            offset: 44,
+            kind: DebugRelocationKind::SectionRelative,
+            segment: 1,
+            target_offset: 0x20,
+        },
+        DebugRelocation {
+            offset: 48,
+            kind: DebugRelocationKind::SectionIndex,
+            segment: 1,
+            target_offset: 0x20,
+        },
+    ];
+
+    let mut builder = PdbBuilder::default();
+    // shifts the indices of the object by one
+    builder.tpi().add("pointer_type", TypeRecord::Pointer {
+        referent: BuiltinType::I64.into(),
+        properties: PointerProperties::new().with_kind(PointerKind::Near64),
+        containing_class: None,
+    });
+    let mut merger = TypeMerger::default();
+    // the types of the second object are deduplicated
+    for name in ["a.obj", "b.obj"] {
+        let contrib = SectionContrib::new(1, 0x20, 0x10, 0x6000_0020, 0);
+        let mut module = ModuleBuilder::new(name.to_owned(), name.to_owned(), contrib);
+        add_object(
+            &mut builder,
+            &mut merger,
+            &mut module,
+            &debug_s,
+            Some(&debug_t),
+            &relocations,
+        )?;
+        builder.dbi().add_module(module);
+    }
+    let mut pdb = reopen(builder)?;
+    let tpi = pdb.get_tpi()?;
+    assert_eq!(tpi.records().len(), 3);
+    assert_matches!(
+        tpi.records()[2],
+        TypeRecord::Procedure { arg_list, .. } if u32::from(arg_list) == 0x1001
+    );
+    let ipi = pdb.get_ipi()?;
+    assert_matches!(
+        ipi.records()[..],
+        [IdRecord::FuncId { function_type, .. }] if u32::from(function_type) == 0x1002
+    );
+
+    let dbi = pdb.get_dbi()?;
+    let module = pdb.get_module(&dbi.modules()[0])?;
+    assert_matches!(module.symbols()[..], [
+        SymbolRecord::GlobalProcId(Procedure {
+            function_type,
+            code_offset: DataRegionOffset { offset: 0x20, segment: 1 },
+            end,
+            ..
+        }),
+        SymbolRecord::ProcEnd
+    ] if u32::from(function_type) == 0x1000 && u32::from(end) == 48);
+
+    let outside = DebugRelocation {
+        offset: debug_s.len() as u32,
+        ..relocations[0]
+    };
+    let contrib = SectionContrib::new(1, 0x20, 0x10, 0x6000_0020, 0);
+    let mut module = ModuleBuilder::new("c.obj".to_owned(), "c.obj".to_owned(), contrib);
+    let mut builder = PdbBuilder::default();
+    assert_matches!(
+        add_object(&mut builder, &mut merger, &mut module, &debug_s, None, &[outside]),
+        Err(Error::MalformedInput(_))
+    );
+
+    Ok(())
+}
+
+#[test]
+fn splice_type_server() -> Result<()> {
+    let index = |index: u32| TypeIndex::try_from(index).unwrap();
+    let guid = Guid::new([7; 16]);
+    let mut server = PdbBuilder::default();
+    server.info().guid(guid.clone());
+    server.tpi().add("", TypeRecord::ArgList {
+        count: 0,
+        arg_list: vec![],
+    });
+    server.tpi().add("", TypeRecord::Procedure {
+        return_type: None,
+        calling_conv: CallingConvention::NearC,
+        properties: FunctionProperties::new(),
+        arg_count: 0,
+        arg_list: index(0x1000),
+    });
+    server.ipi().add("", IdRecord::FuncId {
+        parent_scope: None,
+        function_type: index(0x1001),
+        name: StrBuf::new("main"),
+    });
+    let mut server_pdb = io::Cursor::new(vec![]);
+    server.commit(&mut server_pdb)?;
+
+    let mut debug_t = 4u32.to_le_bytes().to_vec();
+    debug_t.extend(cv_record(&TypeRecord::TypeServer2 {
+        guid,
+        age: 1,
+        name: StrBuf::new(r"C:\build\vc140.pdb"),
+    })?);
+    let debug_s = object_symbols(0x1000)?;
+
+    let mut builder = write_dummy_builder()?;
+    let mut merger = TypeMerger::default();
+    let mut resolved = 0;
+    let mut servers = TypeServers::new(|server: &TypeServerRef| {
+        assert_eq!(server.path, r"C:\build\vc140.pdb");
+        resolved += 1;
+        PdbFile::open(io::Cursor::new(server_pdb.get_ref().clone()))
+    });
+    for name in ["a.obj", "b.obj"] {
+        let contrib = SectionContrib::new(1, 0, 0x10, 0x6000_0020, 0);
+        let mut module = ModuleBuilder::new(name.to_owned(), name.to_owned(), contrib);
+        servers.add_object(
+            &mut builder,
+            &mut merger,
+            &mut module,
+            &debug_s,
+            Some(&debug_t),
+            &[],
+        )?;
+        builder.dbi().add_module(module);
+    }
+    drop(servers);
+    assert_eq!(resolved, 1);
+
+    let mut pdb = reopen(builder)?;
+    assert_eq!(pdb.get_tpi()?.records().len(), 3);
+    assert_matches!(
+        pdb.get_ipi()?.records()[..],
+        [IdRecord::StringId { .. }, IdRecord::FuncId { function_type, .. }] if u32::from(function_type) == 0x1002
+    );
+    let dbi = pdb.get_dbi()?;
+    for module in dbi.modules().iter().skip(1) {
+        let module = pdb.get_module(module)?;
+        assert_matches!(
+            module.symbols()[..],
+            [SymbolRecord::GlobalProcId(Procedure { function_type, .. }), SymbolRecord::ProcEnd]
+                if u32::from(function_type) == 0x1001
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn merge_types_by_content_and_unique_name() -> Result<()> {
+    let pointer = || TypeRecord::Pointer {
+        referent: BuiltinType::I64.into(),
+        properties: PointerProperties::new().with_kind(PointerKind::Near64),
+        containing_class: None,
+    };
+    let udt = |size: u64| {
+        TypeRecord::Struct(StructRecord {
+            member_count: 0,
+            properties: ClassProperties::new().with_has_unique_name(true),
+            field_list: None,
+            derivation_list: None,
+            vtable_shape: None,
+            size: Integer::U64(size),
+            name: StrBuf::new("Foo"),
+            unique_name: StrBuf::new(".?AUFoo@@"),
+        })
+    };
+
+    let mut builder = PdbBuilder::default();
+    let mut merger = TypeMerger::default();
+    let first = merger.add_type(&mut builder, pointer())?;
+    assert_eq!(
+        u32::from(merger.add_type(&mut builder, pointer())?),
+        u32::from(first)
+    );
+    let foo = merger.add_type(&mut builder, udt(8))?;
+    assert_eq!(u32::from(merger.add_type(&mut builder, udt(16))?), u32::from(foo));
+    assert_eq!(u32::from(foo), 0x1001);
+
+    Ok(())
+}
+
+#[test]
+fn derive_msvc_unique_names() -> Result<()> {
+    let class = |name: &str| StructRecord {
+        member_count: 0,
+        properties: ClassProperties::new().with_is_forward_ref(true),
+        field_list: None,
+        derivation_list: None,
+        vtable_shape: None,
+        size: Integer::U16(0),
+        name: StrBuf::new(name),
+        unique_name: StrBuf::default(),
+    };
+    let unique_name = |mut record: TypeRecord| match (record.derive_unique_name(), record) {
+        (true, TypeRecord::Class(class) | TypeRecord::Struct(class)) => {
+            Some(class.unique_name.as_ref().to_owned())
+        }
+        _ => None,
+    };
+    assert_eq!(
+        unique_name(TypeRecord::Struct(class("Foo"))).as_deref(),
+        Some(".?AUFoo@@")
+    );
+    assert_eq!(
+        unique_name(TypeRecord::Class(class("ns::Outer::Inner"))).as_deref(),
+        Some(".?AVInner@Outer@ns@@")
+    );
+    // repeated components refer back to the first one
+    assert_eq!(
+        unique_name(TypeRecord::Struct(class("ns::ns::Foo"))).as_deref(),
+        Some(".?AUFoo@ns@1@")
+    );
+    assert_eq!(unique_name(TypeRecord::Struct(class("Vec<int>"))), None);
+
+    let mut builder = PdbBuilder::default();
+    builder.tpi().add_udt(TypeRecord::Struct(class("ns::Foo")));
+    let mut pdb = reopen(builder)?;
+    let tpi = pdb.get_tpi()?;
+    assert_matches!(
+        &tpi.records()[..],
+        [TypeRecord::Struct(StructRecord { properties, unique_name, .. })]
+            if properties.has_unique_name() && unique_name.as_ref() == ".?AUFoo@ns@@"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn hash_udts_like_msvc() -> Result<()> {
+    let udt = |name: &str, properties: ClassProperties| {
+        let mut record = TypeRecord::Struct(StructRecord {
+            member_count: 0,
+            properties,
+            field_list: None,
+            derivation_list: None,
+            vtable_shape: None,
+            size: Integer::U16(0),
+            name: StrBuf::new(name),
+            unique_name: StrBuf::default(),
+        });
+        record.derive_unique_name();
+        record
+    };
+    let mut builder = PdbBuilder::default();
+    builder
+        .tpi()
+        .add("", udt("Foo", ClassProperties::new().with_is_forward_ref(true)));
+    builder.tpi().add("", udt("Foo", ClassProperties::new()));
+    builder
+        .tpi()
+        .add("", udt("Local", ClassProperties::new().with_is_scoped(true)));
+    // a local type of another function, named like the first definition
+    builder.tpi().add("", udt("Foo", ClassProperties::new()));
+    let mut pdb = reopen(builder)?;
+    let tpi = pdb.get_tpi()?;
+    let hash = pdb.get_tpi_hash(&tpi)?;
+    // forward references are hashed by their contents, so the definition is found by name
+    assert_eq!(hash.get_index("Foo").map(u32::from), Some(0x1001));
+    // scoped types are only found by their unique name
+    assert_eq!(hash.get_index(".?AULocal@@").map(u32::from), Some(0x1002));
+    let strings = pdb.strings()?;
+    assert_eq!(
+        hash.get_adjusted_index("Foo", &strings).map(u32::from),
+        Some(0x1001)
+    );
+    assert_eq!(
+        hash.get_adjusted_index(".?AULocal@@", &strings).map(u32::from),
+        Some(0x1002)
+    );
+
+    Ok(())
+}
+
+#[test]
+fn commit_type_graph_in_order() -> Result<()> {
+    let mut graph = TypeGraph::default();
+    // a linked list node, which can only be written with a forward reference to itself
+    let node = graph.declare();
+    let next = graph.add(TypeRecord::Pointer {
+        referent: node,
+        properties: PointerProperties::new().with_kind(PointerKind::Near64),
+        containing_class: None,
+    });
+    let fields = graph.add(TypeRecord::FieldList {
+        fields: vec![TypeRecord::DataMember {
+            properties: MemberProperties::new(),
+            field_type: Some(next),
+            offset: Integer::U16(0),
+            name: StrBuf::new("next"),
+        }],
+    });
+    graph.define(
+        node,
+        TypeRecord::Struct(StructRecord {
+            member_count: 1,
+            properties: ClassProperties::new(),
+            field_list: Some(fields),
+            derivation_list: None,
+            vtable_shape: None,
+            size: Integer::U16(8),
+            name: StrBuf::new("Node"),
+            unique_name: StrBuf::default(),
+        }),
+    );
+
+    let mut builder = PdbBuilder::default();
+    let indices = graph.commit(builder.tpi())?;
+    assert_eq!(indices.iter().map(|&idx| u32::from(idx)).collect::<Vec<_>>(), [
+        0x1003, 0x1001, 0x1002
+    ]);
+    let mut pdb = reopen(builder)?;
+    let tpi = pdb.get_tpi()?;
+    assert_matches!(&tpi.records()[..], [
+        TypeRecord::Struct(StructRecord { field_list: None, properties: forward, .. }),
+        TypeRecord::Pointer { referent, .. },
+        TypeRecord::FieldList { .. },
+        TypeRecord::Struct(StructRecord { field_list: Some(fields), .. }),
+    ] if forward.is_forward_ref() && u32::from(*referent) == 0x1000 && u32::from(*fields) == 0x1002);
+
+    let mut dangling = TypeGraph::default();
+    let undefined = dangling.declare();
+    dangling.add(TypeRecord::Modifier {
+        modified_type: undefined,
+        properties: ModifierProperties::new().with_is_const(true),
+    });
+    assert_matches!(
+        dangling.commit(&mut TpiBuilder::default()),
+        Err(Error::InvalidTypeIndex { .. })
+    );
+
+    Ok(())
+}
+
+#[test]
+fn extract_bit_field_units() -> Result<()> {
+    let mut graph = TypeGraph::default();
+    let bit_field = |graph: &mut TypeGraph, field_type: BuiltinType, bit_offset, bit_size| {
+        graph.add(TypeRecord::BitField {
+            field_type: field_type.into(),
+            bit_size,
+            bit_offset,
+        })
+    };
+    let member = |field_type, offset, name| TypeRecord::DataMember {
+        properties: MemberProperties::new(),
+        field_type: Some(field_type),
+        offset: Integer::U16(offset),
+        name: StrBuf::new(name),
+    };
+    let a = bit_field(&mut graph, BuiltinType::U32, 0, 3);
+    let b = bit_field(&mut graph, BuiltinType::U32, 3, 5);
+    let c = bit_field(&mut graph, BuiltinType::U16, 0, 1);
+    // the members continue in another list
+    let rest = graph.add(TypeRecord::FieldList {
+        fields: vec![member(BuiltinType::I32.into(), 8, "count"), member(c, 12, "c")],
+    });
+    let fields = graph.add(TypeRecord::FieldList {
+        fields: vec![
+            member(a, 0, "a"),
+            member(b, 0, "b"),
+            TypeRecord::ListContinuation(rest),
+        ],
+    });
+    let flags = graph.add(TypeRecord::Struct(StructRecord {
+        member_count: 4,
+        properties: ClassProperties::new(),
+        field_list: Some(fields),
+        derivation_list: None,
+        vtable_shape: None,
+        size: Integer::U16(16),
+        name: StrBuf::new("Flags"),
+        unique_name: StrBuf::default(),
+    }));
+
+    let mut builder = PdbBuilder::default();
+    let indices = graph.commit(builder.tpi())?;
+    let mut pdb = reopen(builder)?;
+    let tpi = pdb.get_tpi()?;
+    let flags = indices[usize::try_from(u32::from(flags) - 0x1000).unwrap()];
+    let units: Vec<_> = tpi
+        .bit_field_units(flags)
+        .iter()
+        .map(|unit| {
+            let fields: Vec<_> = unit
+                .fields
+                .iter()
+                .map(|field| (field.name, field.bit_offset, field.bit_width))
+                .collect();
+            (unit.byte_offset, u32::from(unit.underlying_type), fields)
+        })
+        .collect();
+    assert_eq!(units, [
+        (0, u32::from(TypeIndex::from(BuiltinType::U32)), vec![
+            ("a", 0, 3),
+            ("b", 3, 5)
+        ]),
+        (12, u32::from(TypeIndex::from(BuiltinType::U16)), vec![(
+            "c", 0, 1
+        )]),
+    ]);
+    assert!(tpi.bit_field_units(BuiltinType::U32.into()).is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn compute_type_sizes() -> Result<()> {
+    let mut graph = TypeGraph::default();
+    let udt = |properties, size| {
+        TypeRecord::Struct(StructRecord {
+            member_count: 0,
+            properties,
+            field_list: None,
+            derivation_list: None,
+            vtable_shape: None,
+            size: Integer::U16(size),
+            name: StrBuf::new("Node"),
+            unique_name: StrBuf::default(),
+        })
+    };
+    let forward = graph.add(udt(ClassProperties::new().with_is_forward_ref(true), 0));
+    graph.add(udt(ClassProperties::new(), 24));
+    let pointer = |graph: &mut TypeGraph, kind, size| {
+        graph.add(TypeRecord::Pointer {
+            referent: forward,
+            properties: PointerProperties::new().with_kind(kind).with_size(size),
+            containing_class: None,
+        })
+    };
+    let near64 = pointer(&mut graph, PointerKind::Near64, 8);
+    // based pointers without a size fall back to the machine
+    let based = pointer(&mut graph, PointerKind::BasedOnSelf, 0);
+    let constant = graph.add(TypeRecord::Modifier {
+        modified_type: BuiltinType::U16.into(),
+        properties: ModifierProperties::new().with_is_const(true),
+    });
+    let array = graph.add(TypeRecord::Array {
+        element_type: BuiltinType::U32.into(),
+        index_type: BuiltinType::U64.into(),
+        dimensions: vec![Integer::U16(12)],
+    });
+
+    let mut builder = PdbBuilder::default();
+    let indices = graph.commit(builder.tpi())?;
+    let mut pdb = reopen(builder)?;
+    let tpi = pdb.get_tpi()?;
+    let index = |index: TypeIndex| indices[usize::try_from(u32::from(index) - 0x1000).unwrap()];
+    let size = |index| tpi.type_size(index, MachineType::X86);
+    assert_eq!(size(index(forward)), Some(24));
+    assert_eq!(size(index(near64)), Some(8));
+    assert_eq!(size(index(based)), Some(4));
+    assert_eq!(tpi.type_size(index(based), MachineType::Amd64), Some(8));
+    assert_eq!(size(index(constant)), Some(2));
+    assert_eq!(size(index(array)), Some(12));
+    assert_eq!(size(BuiltinType::F80.into()), Some(10));
+    // a near 32-bit pointer to a U32
+    assert_eq!(size(TypeIndex::try_from(0x0475).unwrap()), Some(4));
+    assert_eq!(size(BuiltinType::Void.into()), None);
+
+    Ok(())
+}
+
+#[test]
+fn generate_rust_bindings() -> Result<()> {
+    let mut pdb = PdbFile::open(File::open("tests/llvm.pdb")?)?;
+    let tpi = pdb.get_tpi()?;
+    let mut generator = BindingGenerator::new(&tpi, MachineType::Amd64);
+    generator.add_type("core::fmt::rt::v1::Argument")?;
+    assert_matches!(generator.add_type("Missing"), Err(Error::TypeNotFound(_)));
+    let bindings = generator.generate()?;
+
+    assert!(bindings.contains(
+        "pub struct core_fmt_rt_v1_Argument {\n    pub position: u64,\n    pub format: core_fmt_rt_v1_FormatSpec,\n}"
+    ));
+    assert!(
+        bindings.contains("const _: () = assert!(core::mem::size_of::<core_fmt_rt_v1_Argument>() == 56);")
+    );
+    // the padding up to the size of the struct is explicit
+    assert!(bindings.contains("    pub align: core_fmt_rt_v1_Alignment,\n    pub _pad_29: [u8; 7],\n"));
+    assert!(bindings.contains("pub struct core_fmt_rt_v1_Alignment(pub u8);"));
+    assert!(bindings.contains("    pub const Center: Self = Self(2);"));
+    assert!(bindings.contains("pub union enum_core_fmt_rt_v1_Count {"));
+
+    Ok(())
+}
+
+#[test]
+fn search_types_by_name() -> Result<()> {
+    let mut pdb = PdbFile::open(File::open("tests/llvm.pdb")?)?;
+    let tpi = pdb.get_tpi()?;
+
+    let names = |found: Vec<(TypeIndex, &TypeRecord)>| {
+        let mut names: Vec<_> = found
+            .iter()
+            .filter_map(|(_, record)| record.name().map(str::to_owned))
+            .collect();
+        names.dedup();
+        names
+    };
+    assert_eq!(names(tpi.find_matching("core::fmt::rt::v1::A?gument")), [
+        "core::fmt::rt::v1::Argument"
+    ]);
+    let formatters = tpi.find_matching("*fmt::Formatter");
+    assert!(!formatters.is_empty());
+    assert!(names(formatters)
+        .iter()
+        .all(|name| name.ends_with("fmt::Formatter")));
+    assert!(tpi.find_matching("*NoSuchType*").is_empty());
+
+    let found = tpi.find(|name| name.contains("XSAVE"));
+    assert!(found
+        .iter()
+        .any(|(_, record)| record.name() == Some("_XSAVE_FORMAT")));
+    // results come in stream order and point back at their records
+    assert!(found
+        .windows(2)
+        .all(|pair| u32::from(pair[0].0) < u32::from(pair[1].0)));
+    assert!(found
+        .iter()
+        .all(|(index, record)| std::ptr::eq(tpi.record(*index).unwrap(), *record)));
+
+    Ok(())
+}
+
+// a `.debug$S` section with a single procedure
+fn object_symbols(function_type: u32) -> Result<Vec<u8>> {
+    let mut symbols = cv_record(&SymbolRecord::GlobalProcId(Procedure {
+        parent: None,
+        end: SymbolOffset::from(0),
+        next: None,
+        code_size: 0x10,
+        dbg_start_offset: 0,
+        dbg_end_offset: 0x10,
+        function_type: TypeIndex::try_from(function_type).unwrap(),
+        code_offset: DataRegionOffset::new(0, 0),
+        properties: ProcedureProperties::new(),
+        name: StrBuf::new("main"),
+    }))?;
+    symbols.extend(cv_record(&SymbolRecord::ProcEnd)?);
+    let mut debug_s = 4u32.to_le_bytes().to_vec();
+    debug_s.extend(0xF1u32.to_le_bytes());
+    debug_s.extend((symbols.len() as u32).to_le_bytes());
+    debug_s.extend(symbols);
+    Ok(debug_s)
+}
+
+// a length-prefixed CodeView record padded like the ones emitted by compilers
+fn cv_record<A: Encode>(record: &A) -> Result<Vec<u8>> {
+    let mut res = vec![0, 0];
+    record.encode((), &mut res)?;
+    let padding = (4 - res.len() % 4) % 4;
+    if padding != 0 {
+        res.push(0xF0 | padding as u8);
+        res.resize(res.len() + padding - 1, 0);
+    }
+    let len = (res.len() - 2) as u16;
+    res[..2].copy_from_slice(&len.to_le_bytes());
+    Ok(res)
+}
+
+#[test]
+fn build_line_program() -> Result<()> {
+    let mut builder = PdbBuilder::default();
+    builder.dbi().add_sections(vec![SectionHeader {
+        name: *b".text\0\0\0",
+        virtual_size: 0x1000,
+        virtual_address: 0x1000,
+        size_of_raw_data: 0x1000,
+        pointer_to_raw_data: 0x400,
+        pointer_to_relocations: 0,
+        pointer_to_line_numbers: 0,
+        number_of_relocations: 0,
+        number_of_line_numbers: 0,
+        characteristics: 0x6000_0020,
+    }]);
+    let mut module = ModuleBuilder::new(
+        "lines.obj".to_owned(),
+        "lines.obj".to_owned(),
+        SectionContrib::new(1, 0x10, 0x40, 0x6000_0020, 0),
+    );
+    let mut program = LineProgramBuilder::default();
+    program
+        .add_function(0x1010, 0x20)
+        .add_function(0x1030, 0x20)
+        .add_line(0x1038, r"C:\src\lib.rs", 20, None)
+        .add_line(0x1010, r"C:\src\main.rs", 1, None)
+        .add_line(0x1030, r"C:\src\lib.rs", 10, Some((5, 12)))
+        .add_line(0x1018, r"C:\src\lib.rs", 2, None)
+        .add_line(0x1014, r"C:\src\main.rs", 3, None)
+        .file_checksum(r"C:\src\main.rs", ChecksumType::Md5, vec![0xAB; 16]);
+    program.commit(&mut builder, &mut module)?;
+    builder.dbi().add_module(module);
+    let pdb = Pdb::new(reopen(builder)?);
+    let fragments: Vec<_> = pdb.module(0)?.c13_records()[1..]
+        .iter()
+        .map(|entry| entry.decoded())
+        .collect::<Result<_>>()?;
+    assert_matches!(
+        &fragments[..],
+        [
+            DebugSubsectionRecord::Lines { header: first, entries: first_blocks },
+            DebugSubsectionRecord::Lines { header: second, entries: second_blocks },
+        ] if first.reloc.offset == 0x10 && first.code_size == 0x20 && first_blocks.len() == 2
+            && first_blocks[0].code_size == 8 && first_blocks[1].code_size == 0x18
+            && second.reloc.offset == 0x30 && second_blocks.len() == 1
+    );
+    let lines: Vec<_> = pdb
+        .module_lines(0)?
+        .iter()
+        .map(|line| (line.file, line.line, line.columns, line.offset.offset))
+        .collect();
+    assert_eq!(lines, vec![
+        (r"C:\src\main.rs", 1, None, 0x10),
+        (r"C:\src\main.rs", 3, None, 0x14),
+        (r"C:\src\lib.rs", 2, None, 0x18),
+        (r"C:\src\lib.rs", 10, Some((5, 12)), 0x30),
+        (r"C:\src\lib.rs", 20, Some((0, 0)), 0x38),
+    ]);
+
+    let mut outside = LineProgramBuilder::default();
+    outside.add_line(0x3000, "main.rs", 1, None);
+    let mut module = ModuleBuilder::new(String::new(), String::new(), SectionContrib::new(1, 0, 0, 0, 0));
+    assert_matches!(
+        outside.commit(&mut PdbBuilder::default(), &mut module),
+        Err(Error::AddressOutOfSection(_))
+    );
+    Ok(())
+}
+
+#[test]
+fn read_il_lines() -> Result<()> {
+    let mut builder = PdbBuilder::default();
+    let file_name_offset = u32::from(builder.add_name(r"C:\src\Program.cs")?);
+    let mut module = ModuleBuilder::new(
+        "managed.obj".to_owned(),
+        "managed.obj".to_owned(),
+        SectionContrib::new(1, 0, 0x10, 0x6000_0020, 0),
+    );
+    let fragment = |offset, line| LineColumnEntry {
+        name_index: 0,
+        num_lines: 1,
+        code_size: 0x10,
+        line_numbers: vec![LineNumberEntry { offset, flags: line }],
+        columns: vec![],
+    };
+    let header = || LineFragmentHeader {
+        reloc: DataRegionOffset::new(0, 1),
+        flags: LineFlags::new(),
+        code_size: 0x10,
+    };
+    module
+        .add_subsection(DebugSubsectionRecord::FileChecksums {
+            entries: vec![FileChecksumEntry {
+                file_name_offset,
+                checksum_size: 0,
+                checksum_type: ChecksumType::None,
+                bytes: vec![],
+            }],
+        })?
+        .add_subsection(DebugSubsectionRecord::Lines {
+            header: header(),
+            entries: vec![fragment(4, 7)],
+        })?
+        .add_subsection(DebugSubsectionRecord::ILLines {
+            header: header(),
+            entries: vec![fragment(2, 9)],
+        })?;
+    builder.dbi().add_module(module);
+    let pdb = Pdb::new(reopen(builder)?);
+    assert_matches!(
+        pdb.module(0)?.c13_records()[2].decoded()?,
+        DebugSubsectionRecord::ILLines { .. }
+    );
+    assert_matches!(pdb.module_lines(0)?[..], [SourceLine {
+        file: r"C:\src\Program.cs",
+        line: 7,
+        offset: DataRegionOffset { offset: 4, .. },
+        ..
+    }]);
+    assert_matches!(pdb.module_il_lines(0)?[..], [SourceLine {
+        file: r"C:\src\Program.cs",
+        line: 9,
+        offset: DataRegionOffset { offset: 2, .. },
+        ..
+    }]);
+    Ok(())
+}
+
+#[test]
+fn write_section_contribs_v2() -> Result<()> {
+    let mut builder = write_dummy_builder()?;
+    builder
+        .dbi()
+        .section_contrib_version(SectionContribVersion::V2)
+        .add_section_contrib_v2(SectionContrib::new(2, 0, 0x10, 0xC000_0040, 0), 7);
+    let mut pdb = reopen(builder)?;
+    let dbi = pdb.get_dbi()?;
+    assert_eq!(dbi.section_contrib_version(), &Some(SectionContribVersion::V2));
+    assert_eq!(dbi.coff_section_indices().len(), dbi.section_contribs().len());
+    let (contrib, index) = dbi
+        .section_contribs()
+        .iter()
+        .zip(dbi.coff_section_indices())
+        .find(|(contrib, _)| contrib.i_sect == 2)
+        .unwrap();
+    assert_eq!((contrib.size, *index), (0x10, 7));
+
+    let mut llvm = PdbFile::open(File::open("tests/llvm.pdb")?)?;
+    let dbi = llvm.get_dbi()?;
+    assert_eq!(dbi.section_contrib_version(), &Some(SectionContribVersion::Ver60));
+    assert!(dbi.coff_section_indices().is_empty());
+    Ok(())
+}
+
+#[test]
+fn patch_dbi_stream() -> Result<()> {
+    let original = std::fs::read("tests/llvm.pdb")?;
+    let mut pdb = PdbFile::open(io::Cursor::new(original.clone()))?;
+    let mut dbi = pdb.get_dbi()?;
+    // an untouched stream is written back byte for byte
+    pdb.patch_dbi(&dbi)?;
+    assert!(pdb.into_inner().into_inner() == original);
+
+    let mut pdb = PdbFile::open(io::Cursor::new(original))?;
+    dbi.modules_mut()[1].header.flags.set_is_dirty(true);
+    dbi.modules_mut()[1].header.type_server_index = 3;
+    pdb.patch_dbi(&dbi)?;
+    let mut pdb = PdbFile::open(io::Cursor::new(pdb.into_inner().into_inner()))?;
+    let patched = pdb.get_dbi()?;
+    let header = &patched.modules()[1].header;
+    assert!(header.flags.is_dirty());
+    assert_eq!(header.type_server_index, 3);
+    assert_eq!(patched.modules().len(), dbi.modules().len());
+    assert_eq!(patched.section_contribs().len(), dbi.section_contribs().len());
+    Ok(())
+}
+
+#[test]
+fn keep_unknown_debug_streams() -> Result<()> {
+    let mut builder = write_dummy_builder()?;
+    builder
+        .dbi()
+        .debug_stream(DbgHeader::Fixup as usize, vec![1, 2, 3, 4])
+        .debug_stream(DbgHeader::Xdata as usize, vec![5, 6])
+        .debug_stream(DbgHeader::Max as usize + 1, vec![7]);
+    let mut pdb = reopen(builder)?;
+
+    let mut rebuilt = reopen(PdbBuilder::from_pdb(&mut pdb)?)?;
+    let dbi = rebuilt.get_dbi()?;
+    assert_eq!(dbi.dbg_streams().len(), DbgHeader::Max as usize + 2);
+    assert_eq!(rebuilt.get_debug_stream(&dbi, DbgHeader::Fixup as usize)?, [
+        1, 2, 3, 4
+    ]);
+    assert_eq!(rebuilt.get_debug_stream(&dbi, DbgHeader::Xdata as usize)?, [5, 6]);
+    assert_eq!(rebuilt.get_debug_stream(&dbi, DbgHeader::Max as usize + 1)?, [7]);
+    assert_matches!(
+        rebuilt.get_debug_stream(&dbi, DbgHeader::Max as usize),
+        Err(Error::StreamNotFound(_))
+    );
+    Ok(())
+}
+
+#[test]
+fn iter_symbols_past_failed_records() -> Result<()> {
+    let mut pdb = PdbFile::open(File::open("tests/llvm.pdb")?)?;
+    let mut lenient = PdbFile::open_with_options(File::open("tests/llvm.pdb")?, ParseOptions::lenient())?;
+    let dbi = pdb.get_dbi()?;
+    let symbols = pdb.get_symbols(&dbi)?;
+    let offsets: Vec<_> = pdb.iter_symbols(&dbi)?.map(|res| res.unwrap().0).collect();
+    assert_eq!(&offsets, symbols.offsets());
+
+    // the last module has a single record with a name that isn't UTF-8
+    let module = dbi.modules().last().unwrap();
+    assert_matches!(pdb.get_module(module), Err(_));
+    let results: Vec<_> = pdb.iter_module_symbols(module)?.collect();
+    let failed = results.iter().position(|res| res.is_err()).unwrap();
+    assert_matches!(
+        &results[failed],
+        Err(Error::StreamFailed {
+            stream: "module symbols",
+            ..
+        })
+    );
+    assert!(results[failed + 1..].iter().all(|res| res.is_ok()));
+    let decoded = lenient.get_module(module)?;
+    assert_eq!(results.len(), decoded.symbols().len() + 1);
+    Ok(())
+}
+
+#[test]
+fn iter_symbols_past_unknown_numeric_leaves() -> Result<()> {
+    let mut pdb = PdbFile::open(io::Cursor::new(dummy_with_unknown_leaf()?))?;
+    let dbi = pdb.get_dbi()?;
+    let results: Vec<_> = pdb.iter_symbols(&dbi)?.collect();
+    assert_eq!(results.len(), 3);
+    assert_matches!(results[0], Ok((_, SymbolRecord::Public32(_))));
+    assert_matches!(results[1], Ok((_, SymbolRecord::Label { .. })));
+    assert_matches!(
+        &results[2],
+        Err(Error::StreamFailed { stream: "symbols", source, .. })
+            if matches!(**source, Error::RecordFailed { kind: 0x1107, .. })
+    );
+    Ok(())
+}
+
+#[test]
+fn report_parse_warnings() -> Result<()> {
+    let mut pdb = PdbFile::open(File::open("tests/llvm.pdb")?)?;
+    let dbi = pdb.get_dbi()?;
+    pdb.get_section_headers(&dbi)?;
+    assert_eq!(pdb.warnings(), []);
+
+    let mut builder = write_dummy_builder()?;
+    builder.dbi().add_section_header(SectionHeader {
+        name: *b".text\0\0\0",
+        virtual_size: 0x100,
+        virtual_address: 0x1000,
+        size_of_raw_data: 0,
+        pointer_to_raw_data: 0,
+        pointer_to_relocations: 0,
+        pointer_to_line_numbers: 0,
+        number_of_relocations: 0,
+        number_of_line_numbers: 0,
+        characteristics: 0x6000_0020,
+    });
+    let mut pdb = reopen(builder)?;
+    let dbi = pdb.get_dbi()?;
+    let slot = DbgHeader::SectionHdr as usize;
+    let mut headers = pdb.get_debug_stream(&dbi, slot)?;
+    let len = headers.len() as u64;
+    headers.extend([0xAA, 0xBB, 0xCC]);
+    pdb.replace_stream(dbi.dbg_streams()[slot], &headers)?;
+    assert_eq!(pdb.get_section_headers(&dbi)?.headers().len(), 1);
+    assert_eq!(pdb.take_warnings(), [Warning {
+        stream: "section HDR",
+        offset: len,
+        kind: WarningKind::TrailingBytes(3),
+    }]);
+    Ok(())
+}
+
+#[test]
+fn collect_metrics() -> Result<()> {
+    let metrics = write_dummy_builder()?.commit_with_metrics(io::Cursor::new(vec![]))?;
+    assert_eq!(metrics.stream_bytes.get("/TMCache"), Some(&8));
+    assert!(metrics.stream_bytes.contains_key("TPI"));
+    assert!(metrics.phases.contains_key("encode"));
+
+    let mut pdb = PdbFile::open(File::open("tests/llvm.pdb")?)?;
+    pdb.get_tpi()?;
+    assert!(pdb.metrics().is_none());
+
+    let options = ParseOptions {
+        metrics: true,
+        ..ParseOptions::default()
+    };
+    let mut pdb = PdbFile::open_with_options(File::open("tests/llvm.pdb")?, options)?;
+    let tpi = pdb.get_tpi()?;
+    let metrics = pdb.take_metrics().unwrap();
+    assert_eq!(metrics.total_records(), tpi.records().len() as u64);
+    assert!(metrics.stream_bytes["TPI"] > 0);
+    assert!(metrics.phases.contains_key("TPI"));
+    assert_eq!(pdb.take_metrics().unwrap().total_records(), 0);
+    Ok(())
+}
+
+#[test]
+fn read_stream_data_by_index() -> Result<()> {
+    let mut pdb = PdbFile::open(write_dummy()?)?;
+    let info = pdb.get_info()?;
+    let index = info.named_streams().get("/TMCache").unwrap();
+    assert_eq!(pdb.get_stream_data(index)?, [1, 0, 0, 0, 7, 0, 0, 0]);
+    let count = pdb.msf_info().num_streams as u16;
+    assert_matches!(pdb.get_stream_data(count.into()), Err(Error::StreamNotFound(_)));
+    Ok(())
+}
+
+#[test]
+fn rebuild_from_pdb() -> Result<()> {
+    // a few module records fail to decode and are left out
+    let mut pdb = PdbFile::open_with_options(File::open("tests/llvm.pdb")?, ParseOptions::lenient())?;
+    let mut builder = PdbBuilder::from_pdb(&mut pdb)?;
+    assert!(!pdb.take_diagnostics().is_empty());
+    builder.info().age(2);
+    let mut rebuilt = reopen(builder)?;
+
+    let info = rebuilt.get_info()?;
+    assert_eq!(info.header().age, 2);
+    assert_eq!(info.header().guid, pdb.get_info()?.header().guid);
+    assert!(diff(&mut pdb, &mut rebuilt)?.types.is_empty());
+    assert_eq!(
+        rebuilt.get_raw_ipi()?.records().len(),
+        pdb.get_raw_ipi()?.records().len()
+    );
+
+    let (dbi, rebuilt_dbi) = (pdb.get_dbi()?, rebuilt.get_dbi()?);
+    assert_eq!(rebuilt_dbi.modules().len(), dbi.modules().len());
+    assert_eq!(rebuilt_dbi.section_contribs().len(), dbi.section_contribs().len());
+    assert_eq!(rebuilt_dbi.module_source_files(), dbi.module_source_files());
+    assert_eq!(
+        rebuilt.get_symbols(&rebuilt_dbi)?.offsets(),
+        pdb.get_symbols(&dbi)?.offsets()
+    );
+    assert_eq!(
+        rebuilt.get_section_headers(&rebuilt_dbi)?.headers().len(),
+        pdb.get_section_headers(&dbi)?.headers().len()
+    );
+
+    let (strings, rebuilt_strings) = (pdb.strings()?, rebuilt.strings()?);
+    let module = pdb.get_module(&dbi.modules()[1])?;
+    let rebuilt_module = rebuilt.get_module(&rebuilt_dbi.modules()[1])?;
+    assert_eq!(rebuilt_module.global_refs(), module.global_refs());
+    let lines = |lines: Vec<SourceLine>| -> Vec<_> {
+        lines
+            .into_iter()
+            .map(|line| (line.file.to_owned(), line.line))
+            .collect()
+    };
+    assert_eq!(
+        lines(rebuilt_module.lines(&rebuilt_strings)?),
+        lines(module.lines(&strings)?)
+    );
+    Ok(())
+}
+
+#[test]
+fn keep_names_offsets_on_rebuild() -> Result<()> {
+    let mut builder = PdbBuilder::default();
+    // the empty string is not listed by `Strings::iter`, but it still takes up a byte of the table
+    builder.add_name("")?;
+    let source_file = builder.add_name(r"C:\src\point.h")?;
+    builder.ipi().add("", IdRecord::UdtModSourceLine {
+        udt: TypeIndex::try_from(0x1000).unwrap(),
+        source_file: TypeIndex::try_from(u32::from(source_file)).unwrap(),
+        line_number: 12,
+        module: 1,
+    });
+    let mut pdb = reopen(builder)?;
+    let mut rebuilt = reopen(PdbBuilder::from_pdb(&mut pdb)?)?;
+
+    let strings = rebuilt.strings()?;
+    let ipi = rebuilt.get_ipi()?;
+    let Some(IdRecord::UdtModSourceLine { source_file, .. }) = ipi.records().first() else {
+        panic!("expected a UDT source line record");
+    };
+    let offset = strings.offset_of(r"C:\src\point.h").unwrap();
+    assert_eq!(u32::from(*source_file), u32::from(offset));
+    assert_eq!(strings.get(offset), Some(r"C:\src\point.h"));
+
+    Ok(())
+}
+
+#[test]
+fn build_local_def_ranges() {
+    let mut local = LocalVarBuilder::new("x".to_owned(), BuiltinType::I32.into());
+    local
+        .add_range(
+            LocalLocation::Register(Register(18)),
+            DataRegionOffset::new(0x10, 1),
+            0x10,
+        )
+        .add_range(
+            LocalLocation::Register(Register(18)),
+            DataRegionOffset::new(0x30, 1),
+            0x10,
+        )
+        .add_range(
+            LocalLocation::FrameRel(-8),
+            DataRegionOffset::new(0x40, 1),
+            0x1_2000,
+        );
+    let records = local.build();
+
+    assert_matches!(&records[0], SymbolRecord::Local { name, .. } if name.as_ref() == "x");
+    assert_matches!(
+        &records[1],
+        SymbolRecord::DefRangeRegister { register: Register(18), range, gaps, .. }
+            if range.offset_start == 0x10 && range.range == 0x30
+                && gaps[0].gap_start_offset == 0x10 && gaps[0].range == 0x10
+    );
+    assert_matches!(
+        &records[2],
+        SymbolRecord::DefRangeFramePointerRel { offset: -8, range, gaps }
+            if range.offset_start == 0x40 && range.range == 0xF000 && gaps.is_empty()
+    );
+    assert_matches!(
+        &records[3],
+        SymbolRecord::DefRangeFramePointerRel { range, .. }
+            if range.offset_start == 0xF040 && range.range == 0x3000
+    );
+    assert_eq!(records.len(), 4);
+
+    let locals = LocalVariable::collect(&records);
+    assert_eq!(locals.len(), 1);
+    let at = |offset| locals[0].location_at(&DataRegionOffset::new(offset, 1));
+    assert_eq!(at(0x18), Some(LocalLocation::Register(Register(18))));
+    assert_eq!(at(0x28), None);
+    assert_eq!(at(0x30), Some(LocalLocation::Register(Register(18))));
+    assert_eq!(at(0x10000), Some(LocalLocation::FrameRel(-8)));
+    assert_eq!(at(0x12040), None);
+}
+
+#[test]
+fn validate_arm64_registers() -> Result<()> {
+    let build = |location: LocalLocation| {
+        let mut builder = PdbBuilder::default();
+        let mut module = ModuleBuilder::new(
+            "arm64.obj".to_owned(),
+            "arm64.obj".to_owned(),
+            SectionContrib::new(1, 0, 0x10, 0x6000_0020, 0),
+        );
+        let mut local = LocalVarBuilder::new("x".to_owned(), BuiltinType::I64.into());
+        local.add_range(location, DataRegionOffset::new(0, 1), 0x10);
+        module.add_local(local);
+        builder
+            .dbi()
+            .machine_type(MachineType::Arm64)
+            .validate_registers(true)
+            .add_module(module);
+        reopen(builder)
+    };
+
+    assert_matches!(
+        build(LocalLocation::RegisterRel(Register::RSP, 8)),
+        Err(Error::InvalidRegister {
+            register: 335,
+            machine: MachineType::Arm64
+        })
+    );
+    // vector registers don't address memory
+    assert_matches!(
+        build(LocalLocation::RegisterRel(Register::arm64_q(0), 8)),
+        Err(Error::InvalidRegister { register: 180, .. })
+    );
+    assert_matches!(
+        build(LocalLocation::Register(Register(400))),
+        Err(Error::InvalidRegister { register: 400, .. })
+    );
+    build(LocalLocation::Register(Register::arm64_d(8)))?;
+
+    let mut pdb = build(LocalLocation::RegisterRel(Register::ARM64_SP, 8))?;
+    let dbi = pdb.get_dbi()?;
+    assert_matches!(dbi.header().machine_type, MachineType::Arm64);
+    Ok(())
+}
+
+#[test]
+fn write_linker_thunks() -> Result<()> {
+    let mut builder = write_dummy_builder()?;
+    let mut module = ModuleBuilder::new(
+        "* Linker *".to_owned(),
+        String::new(),
+        SectionContrib::new(1, 0x100, 0x10, 0x6000_0020, 1),
+    );
+    let thunk = module.add_thunk(
+        "ilt".to_owned(),
+        DataRegionOffset::new(0x200, 1),
+        5,
+        ThunkOrdinal::Standard,
+    );
+    module.add_trampoline(
+        TrampolineType::TrampIncremental,
+        DataRegionOffset::new(0x180, 1),
+        5,
+        DataRegionOffset::new(0x10, 1),
+    );
+    builder.dbi().add_module(module);
+
+    let mut pdb = reopen(builder)?;
+    let dbi = pdb.get_dbi()?;
+
+    let contribs: Vec<_> = dbi
+        .section_contribs()
+        .iter()
+        .map(|contrib| (contrib.offset, contrib.size, contrib.i_mod))
+        .collect();
+    assert_eq!(contribs, vec![(0x180, 5, 1), (0x200, 5, 1)]);
+
+    let module = pdb.get_module(&dbi.modules()[1])?;
+    assert_eq!(u32::from(thunk), 4);
+    assert_matches!(
+        &module.symbols()[0],
+        SymbolRecord::Thunk32 { end, ordinal: ThunkOrdinal::Standard, .. } if u32::from(*end) == 36
+    );
+    assert_matches!(module.symbols()[1], SymbolRecord::ScopeEnd);
+    assert_matches!(module.symbols()[2], SymbolRecord::Trampoline {
+        thunk_offset: 0x180,
+        target_offset: 0x10,
+        ..
+    });
+
+    Ok(())
+}
+
+#[test]
+fn resolve_duplicate_publics() -> Result<()> {
+    let public = |name: &str, offset| Public {
+        properties: PublicProperties::new(),
+        offset: DataRegionOffset::new(offset, 1),
+        name: StrBuf::new(name),
+    };
+    let mut builder = PdbBuilder::default();
+    let mut publics = builder.dbi().symbols();
+    let first = publics.add(public("a", 0))?;
+    let kept = publics.add(public("a", 0x8))?;
+    assert_ne!(u32::from(first), u32::from(kept));
+
+    publics.on_duplicate(DuplicatePublics::FirstWins);
+    assert_eq!(u32::from(publics.add(public("a", 0x10))?), u32::from(first));
+    publics.on_duplicate(DuplicatePublics::LastWins);
+    assert_eq!(u32::from(publics.add(public("a", 0x20))?), u32::from(first));
+    publics.on_duplicate(DuplicatePublics::Reject);
+    assert_matches!(publics.add(public("a", 0x30)), Err(Error::DuplicateSymbol(name)) if name == "a");
+
+    let conflicts = publics.add_all([public("b", 0x40), public("a", 0x50)]);
+    assert_eq!(conflicts.len(), 1);
+    assert_eq!(u32::from(conflicts[0].existing), u32::from(first));
+
+    let mut pdb = reopen(builder)?;
+    let dbi = pdb.get_dbi()?;
+    let syms = pdb.get_symbols(&dbi)?;
+    assert_eq!(syms.records().len(), 3);
+    assert_matches!(
+        syms.get(first),
+        Some(SymbolRecord::Public32(public)) if public.offset == DataRegionOffset::new(0x20, 1)
+    );
+
+    Ok(())
+}
+
+#[test]
+fn write_global_data() -> Result<()> {
+    let mut builder = write_dummy_builder()?;
+    let data = builder.dbi().symbols().finish_publics().add_data(
+        Data {
+            data_type: BuiltinType::I32.into(),
+            offset: DataRegionOffset::new(0x20, 2),
+            name: StrBuf::new("counter"),
+        },
+        false,
+        true,
+    );
+    let mut module = ModuleBuilder::new(
+        "data.obj".to_owned(),
+        "data.obj".to_owned(),
+        SectionContrib::new(2, 0x20, 4, 0xC000_0040, 1),
+    );
+    module.add_global_ref(data);
+    builder.dbi().add_module(module);
+
+    let mut pdb = reopen(builder)?;
+    let dbi = pdb.get_dbi()?;
+    let syms = pdb.get_symbols(&dbi)?;
+    assert_matches!(syms.get(data), Some(SymbolRecord::GlobalData(Data { name, .. })) if name.as_ref() == "counter");
+
+    let globals = pdb.get_globals(&dbi)?;
+    let publics = pdb.get_publics(&dbi)?;
+    let found = globals.find(&syms, "counter", NameMatching::Exact);
+    assert_eq!(found.map(u32::from), Some(u32::from(data)));
+    let public = publics.find(&syms, "counter", NameMatching::Exact).unwrap();
+    assert_matches!(
+        syms.get(public),
+        Some(SymbolRecord::Public32(public)) if public.offset == DataRegionOffset::new(0x20, 2)
+    );
+
+    let module = pdb.get_module(&dbi.modules()[1])?;
+    assert_eq!(
+        module
+            .global_refs()
+            .into_iter()
+            .map(u32::from)
+            .collect::<Vec<_>>(),
+        vec![u32::from(data)]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn order_symbol_records() -> Result<()> {
+    let mut builder = PdbBuilder::default();
+    let symbols = builder.dbi().symbols().finish_publics();
+    let data = symbols.order(SymbolOrder::GlobalsFirst).add_data(
+        Data {
+            data_type: BuiltinType::I32.into(),
+            offset: DataRegionOffset::new(0x20, 1),
+            name: StrBuf::new("counter"),
+        },
+        false,
+        true,
+    );
+    // the public added along with the data comes first in insertion order
+    assert_ne!(u32::from(data), 0);
+    let mut module = ModuleBuilder::new(
+        "order.obj".to_owned(),
+        "order.obj".to_owned(),
+        SectionContrib::new(1, 0, 0x10, 0x6000_0020, 0),
+    );
+    module.add_global_ref(data);
+    builder.dbi().add_module(module);
+    let mut pdb = reopen(builder)?;
+    let dbi = pdb.get_dbi()?;
+    let syms = pdb.get_symbols(&dbi)?;
+    assert_matches!(syms.records()[..], [
+        SymbolRecord::GlobalData(_),
+        SymbolRecord::Public32(_)
+    ]);
+    let globals = pdb.get_globals(&dbi)?;
+    let data = globals.find(&syms, "counter", NameMatching::Exact).unwrap();
+    assert_eq!(u32::from(data), 0);
+    let publics = pdb.get_publics(&dbi)?;
+    assert!(publics.find(&syms, "counter", NameMatching::Exact).is_some());
+    let module = pdb.get_module(&dbi.modules()[0])?;
+    assert_eq!(
+        module
+            .global_refs()
+            .into_iter()
+            .map(u32::from)
+            .collect::<Vec<_>>(),
+        vec![0]
+    );
+    Ok(())
+}
+
+#[test]
+fn resolve_symbol_handles() -> Result<()> {
+    let mut builder = write_dummy_builder()?;
+    let main = builder.dbi().reserve_symbol();
+    let counter = builder.dbi().reserve_symbol();
+    let unbound = builder.dbi().reserve_symbol();
+    let proc_ref = builder.dbi().symbols().finish_publics().add_ref(
+        SymbolRecord::ProcedureRef(ProcedureRef {
+            sum_name: 0,
+            referent: SymbolOffset::from(0),
+            module: 0,
+            name: StrBuf::new("main"),
+        }),
+        main,
+    );
+
+    let mut module = ModuleBuilder::new(
+        "handles.obj".to_owned(),
+        "handles.obj".to_owned(),
+        SectionContrib::new(1, 0x10, 0x20, 0x6000_0020, 1),
+    );
+    module.add_global_ref_to(counter);
+    let proc = module.add_procedure(
+        Procedure {
+            parent: None,
+            end: SymbolOffset::from(0),
+            next: None,
+            code_size: 0x20,
+            dbg_start_offset: 0,
+            dbg_end_offset: 0x20,
+            function_type: BuiltinType::NotTranslated.into(),
+            code_offset: DataRegionOffset::new(0x10, 1),
+            properties: ProcedureProperties::new(),
+            name: StrBuf::new("main"),
+        },
+        true,
+    );
+    module.bind(main, proc);
+    builder.dbi().add_module(module);
+    let symbols = builder.dbi().symbols().finish_publics();
+    let data = symbols.add_data(
+        Data {
+            data_type: BuiltinType::I32.into(),
+            offset: DataRegionOffset::new(0x20, 2),
+            name: StrBuf::new("counter"),
+        },
+        false,
+        false,
+    );
+    symbols.bind(counter, data);
+
+    let mut pdb = reopen(builder)?;
+    let dbi = pdb.get_dbi()?;
+    let syms = pdb.get_symbols(&dbi)?;
+    let Some(SymbolRecord::ProcedureRef(resolved)) = syms.get(proc_ref) else {
+        panic!("expected a procedure reference");
+    };
+    assert_eq!(resolved.module, 2);
+    assert_eq!(u32::from(resolved.referent), u32::from(proc));
+    let module = pdb.get_module(&dbi.modules()[1])?;
+    assert_eq!(
+        module
+            .global_refs()
+            .into_iter()
+            .map(u32::from)
+            .collect::<Vec<_>>(),
+        vec![u32::from(data)]
+    );
+
+    let mut builder = PdbBuilder::default();
+    let mut module = ModuleBuilder::new(
+        "unbound.obj".to_owned(),
+        "unbound.obj".to_owned(),
+        SectionContrib::new(1, 0, 0x10, 0x6000_0020, 0),
+    );
+    module.add_global_ref_to(unbound);
+    builder.dbi().add_module(module);
+    assert_matches!(
+        builder.commit(&mut io::Cursor::new(vec![])),
+        Err(Error::UnboundSymbol(_))
+    );
+    Ok(())
+}
+
+#[test]
+fn rename_symbols() -> Result<()> {
+    let mut builder = write_dummy_builder()?;
+    let data = builder.dbi().symbols().finish_publics().add_data(
+        Data {
+            data_type: BuiltinType::I32.into(),
+            offset: DataRegionOffset::new(0x20, 2),
+            name: StrBuf::new("counter"),
+        },
+        false,
+        true,
+    );
+    let mut module = ModuleBuilder::new(
+        "rename.obj".to_owned(),
+        "rename.obj".to_owned(),
+        SectionContrib::new(1, 0x10, 0x20, 0x6000_0020, 1),
+    );
+    let proc = module.add_procedure(
+        Procedure {
+            parent: None,
+            end: SymbolOffset::from(0),
+            next: None,
+            code_size: 0x20,
+            dbg_start_offset: 0,
+            dbg_end_offset: 0x20,
+            function_type: BuiltinType::NotTranslated.into(),
+            code_offset: DataRegionOffset::new(0x10, 1),
+            properties: ProcedureProperties::new(),
+            name: StrBuf::new("main"),
+        },
+        true,
+    );
+    module.add_global_ref(data);
+    builder.dbi().add_module(module);
+    builder
+        .dbi()
+        .symbols()
+        .finish_publics()
+        .add(SymbolRecord::ProcedureRef(ProcedureRef {
+            sum_name: 0,
+            referent: proc,
+            module: 2,
+            name: StrBuf::new("main"),
+        }));
+
+    builder
+        .dbi()
+        .rename_symbols(|name| (name != "hello").then(|| format!("renamed::{name}")));
+
+    let mut pdb = reopen(builder)?;
+    let dbi = pdb.get_dbi()?;
+    let syms = pdb.get_symbols(&dbi)?;
+    let globals = pdb.get_globals(&dbi)?;
+    let publics = pdb.get_publics(&dbi)?;
+    assert!(publics.find(&syms, "hello", NameMatching::Exact).is_some());
+    assert!(publics.find(&syms, "counter", NameMatching::Exact).is_none());
+    let public = publics
+        .find(&syms, "renamed::counter", NameMatching::Exact)
+        .unwrap();
+    assert_matches!(syms.get(public), Some(SymbolRecord::Public32(_)));
+    let data = globals
+        .find(&syms, "renamed::counter", NameMatching::Exact)
+        .unwrap();
+    assert_matches!(syms.get(data), Some(SymbolRecord::GlobalData(_)));
+    let proc_ref = globals.find(&syms, "renamed::main", NameMatching::Exact).unwrap();
+    let Some(SymbolRecord::ProcedureRef(proc_ref)) = syms.get(proc_ref) else {
+        panic!("expected a procedure reference");
+    };
+
+    let module = pdb.get_module(&dbi.modules()[1])?;
+    assert_eq!(
+        module
+            .global_refs()
+            .into_iter()
+            .map(u32::from)
+            .collect::<Vec<_>>(),
+        vec![u32::from(data)]
+    );
+    // the module stream starts with its signature
+    let mut offset = 4;
+    let mut symbols = HashMap::new();
+    for symbol in module.symbols() {
+        let mut bytes = vec![];
+        symbol.encode((), &mut bytes)?;
+        symbols.insert(offset, symbol);
+        offset += (bytes.len() as u32 + 2).next_multiple_of(4);
+    }
+    let Some(SymbolRecord::GlobalProc(proc)) = symbols.get(&u32::from(proc_ref.referent)) else {
+        panic!("expected a procedure");
+    };
+    assert_eq!(proc.name.as_ref(), "renamed::main");
+    assert_matches!(symbols.get(&u32::from(proc.end)), Some(SymbolRecord::ScopeEnd));
+
+    Ok(())
+}
+
+#[test]
+fn relayout_sections() -> Result<()> {
+    let section = |name: &[u8; 8], virtual_address, virtual_size, characteristics| SectionHeader {
+        name: *name,
+        virtual_size,
+        virtual_address,
+        size_of_raw_data: 0,
+        pointer_to_raw_data: 0,
+        pointer_to_relocations: 0,
+        pointer_to_line_numbers: 0,
+        number_of_relocations: 0,
+        number_of_line_numbers: 0,
+        characteristics,
+    };
+    let build = |emit_omap| -> Result<PdbFile<io::Cursor<Vec<u8>>>> {
+        let mut builder = PdbBuilder::default();
+        builder.dbi().add_sections(vec![
+            section(b".text\0\0\0", 0x1000, 0x1000, 0x6000_0020),
+            section(b".data\0\0\0", 0x2000, 0x1000, 0xC000_0040),
+        ]);
+        let mut publics = builder.dbi().symbols();
+        publics.add(Public {
+            properties: PublicProperties::new().with_is_function(true),
+            offset: DataRegionOffset::new(0x10, 1),
+            name: StrBuf::new("main"),
+        })?;
+        publics.add(Public {
+            properties: PublicProperties::new(),
+            offset: DataRegionOffset::new(0x20, 2),
+            name: StrBuf::new("counter"),
+        })?;
+        let mut module = ModuleBuilder::new(
+            "main.obj".to_owned(),
+            "main.obj".to_owned(),
+            SectionContrib::new(1, 0x10, 0x20, 0x6000_0020, 0),
+        );
+        module.add_procedure(
+            Procedure {
+                parent: None,
+                end: SymbolOffset::from(0),
+                next: None,
+                code_size: 0x20,
+                dbg_start_offset: 0,
+                dbg_end_offset: 0x20,
+                function_type: BuiltinType::NotTranslated.into(),
+                code_offset: DataRegionOffset::new(0x10, 1),
+                properties: ProcedureProperties::new(),
+                name: StrBuf::new("main"),
+            },
+            true,
+        );
+        builder.dbi().add_module(module);
+
+        // the code grows and moves past the start of the section, the data moves as a whole
+        let moved = [
+            MovedRange {
+                old_rva: 0x1000,
+                new_rva: 0x1800,
+                len: 0x100,
+            },
+            MovedRange {
+                old_rva: 0x2000,
+                new_rva: 0x3000,
+                len: 0x1000,
+            },
+        ];
+        builder.dbi().relayout_sections(
+            vec![
+                section(b".text\0\0\0", 0x1000, 0x2000, 0x6000_0020),
+                section(b".data\0\0\0", 0x3000, 0x1000, 0xC000_0040),
+            ],
+            &moved,
+            emit_omap,
+        )?;
+        reopen(builder)
+    };
+
+    let mut pdb = build(false)?;
+    let dbi = pdb.get_dbi()?;
+    let syms = pdb.get_symbols(&dbi)?;
+    let offsets: Vec<_> = syms
+        .records()
+        .iter()
+        .filter_map(|sym| match sym {
+            SymbolRecord::Public32(public) => Some((public.offset.segment, public.offset.offset)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(offsets, [(1, 0x810), (2, 0x20)]);
+    assert_eq!(
+        pdb.get_section_headers(&dbi)?.headers()[1].virtual_address,
+        0x3000
+    );
+    let contrib = &dbi.modules()[0].header.section_contrib;
+    assert_eq!((contrib.i_sect, contrib.offset), (1, 0x810));
+    let module = pdb.get_module(&dbi.modules()[0])?;
+    assert_matches!(
+        module.symbols().first(),
+        Some(SymbolRecord::GlobalProc(proc)) if proc.code_offset == DataRegionOffset::new(0x810, 1)
+    );
+    assert_matches!(pdb.get_omap_to_src(&dbi), Err(Error::StreamNotFound(_)));
+
+    let mut pdb = build(true)?;
+    let dbi = pdb.get_dbi()?;
+    let syms = pdb.get_symbols(&dbi)?;
+    assert_matches!(
+        syms.records().first(),
+        Some(SymbolRecord::Public32(public)) if public.offset == DataRegionOffset::new(0x10, 1)
+    );
+    let to_src = pdb.get_omap_to_src(&dbi)?;
+    assert_eq!(to_src.translate(0x1810), Some(0x1010));
+    assert_eq!(to_src.translate(0x3020), Some(0x2020));
+    let from_src = pdb.get_omap_from_src(&dbi)?;
+    assert_eq!(from_src.translate(0x1010), Some(0x1810));
+    assert_eq!(from_src.translate(0x1200), Some(0x1200));
+    let original = pdb.get_original_section_headers(&dbi)?;
+    assert_eq!(original.headers()[1].virtual_address, 0x2000);
+    assert_eq!(
+        pdb.get_section_headers(&dbi)?.headers()[1].virtual_address,
+        0x3000
+    );
+
+    Ok(())
+}
+
+#[cfg(feature = "object")]
+#[test]
+fn add_pe_exports() -> Result<()> {
+    let image = dummy_pe_image();
+    let mut builder = PdbBuilder::default();
+    assert_eq!(pdb_sdk::exports::add_exports(&mut builder, &image)?, 2);
+    let mut pdb = reopen(builder)?;
+    let dbi = pdb.get_dbi()?;
+    let syms = pdb.get_symbols(&dbi)?;
+    let publics: Vec<_> = syms
+        .records()
+        .iter()
+        .filter_map(|sym| match sym {
+            SymbolRecord::Public32(public) => Some((
+                public.name.as_ref(),
+                public.offset.segment,
+                public.offset.offset,
+                public.properties.is_function(),
+            )),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(publics, [("run", 1, 0x10, true), ("table", 2, 0x100, false)]);
+    assert_eq!(pdb.get_section_headers(&dbi)?.headers().len(), 2);
+
+    assert_matches!(
+        pdb_sdk::exports::add_exports(&mut PdbBuilder::default(), b"not an image"),
+        Err(Error::InvalidImage(_))
+    );
+
+    Ok(())
+}
+
+#[cfg(feature = "object")]
+#[test]
+fn add_pdata_functions() -> Result<()> {
+    let image = dummy_pe_image();
+    let mut builder = PdbBuilder::default();
+    pdb_sdk::exports::add_exports(&mut builder, &image)?;
+    let names = HashMap::from([(0x1010, "run".to_owned())]);
+    assert_eq!(pdb_sdk::pdata::add_functions(&mut builder, &image, &names)?, 2);
+    let mut pdb = reopen(builder)?;
+    let dbi = pdb.get_dbi()?;
+    assert_eq!(pdb.get_section_headers(&dbi)?.headers().len(), 2);
+    assert_eq!(dbi.section_contribs().len(), 2);
+    let module = pdb.get_module(&dbi.modules()[0])?;
+    assert_matches!(&module.symbols()[..], [
+        SymbolRecord::GlobalProc(Procedure { code_size: 0x10, name: first, .. }),
+        SymbolRecord::ScopeEnd,
+        SymbolRecord::GlobalProc(Procedure { code_size: 0x30, name: second, .. }),
+        SymbolRecord::ScopeEnd
+    ] if first.as_ref() == "run" && second.as_ref() == "sub_1020");
+
+    Ok(())
+}
+
+// an x64 image with .text and .data sections, exports and a function table in .data
+#[cfg(feature = "object")]
+fn dummy_pe_image() -> Vec<u8> {
+    let mut image = vec![0u8; 0x600];
+    let mut put = |offset: usize, bytes: &[u8]| image[offset..offset + bytes.len()].copy_from_slice(bytes);
+    put(0, b"MZ");
+    put(0x3C, &0x40u32.to_le_bytes());
+    put(0x40, b"PE\0\0");
+    // file header of an x64 image with two sections
+    put(0x44, &0x8664u16.to_le_bytes());
+    put(0x46, &2u16.to_le_bytes());
+    put(0x54, &0xF0u16.to_le_bytes());
+    // optional header with export and exception data directories
+    put(0x58, &0x20Bu16.to_le_bytes());
+    put(0x58 + 32, &0x1000u32.to_le_bytes());
+    put(0x58 + 36, &0x200u32.to_le_bytes());
+    put(0x58 + 56, &0x3000u32.to_le_bytes());
+    put(0x58 + 60, &0x200u32.to_le_bytes());
+    put(0x58 + 108, &16u32.to_le_bytes());
+    put(0x58 + 112, &0x2000u32.to_le_bytes());
+    put(0x58 + 116, &0x80u32.to_le_bytes());
+    put(0x58 + 136, &0x2080u32.to_le_bytes());
+    put(0x58 + 140, &24u32.to_le_bytes());
+    for (i, (name, rva, offset, characteristics)) in [
+        (b".text\0\0\0", 0x1000u32, 0x200u32, 0x6000_0020u32),
+        (b".data\0\0\0", 0x2000, 0x400, 0xC000_0040),
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        let header = 0x148 + i * 40;
+        put(header, name);
+        put(header + 8, &0x200u32.to_le_bytes());
+        put(header + 12, &rva.to_le_bytes());
+        put(header + 16, &0x200u32.to_le_bytes());
+        put(header + 20, &offset.to_le_bytes());
+        put(header + 36, &characteristics.to_le_bytes());
+    }
+    // an export directory at the start of .data with a function, a variable and an export by ordinal
+    let dir = 0x400;
+    for (offset, val) in [
+        (12, 0x2040),
+        (16, 1),
+        (20, 3),
+        (24, 2),
+        (28, 0x2028),
+        (32, 0x2034),
+        (36, 0x203C),
+    ] {
+        put(dir + offset, &u32::to_le_bytes(val));
+    }
+    for (i, rva) in [0x1010u32, 0x2100, 0x1020].into_iter().enumerate() {
+        put(dir + 0x28 + i * 4, &rva.to_le_bytes());
+    }
+    put(dir + 0x34, &0x2049u32.to_le_bytes());
+    put(dir + 0x38, &0x204Du32.to_le_bytes());
+    put(dir + 0x3C, &[0, 0, 1, 0]);
+    put(dir + 0x40, b"test.dll\0run\0table\0");
+    // a function table right after it, covering two functions of .text
+    let pdata = 0x480;
+    for (i, (begin, end)) in [(0x1010u32, 0x1020u32), (0x1020, 0x1050)].into_iter().enumerate() {
+        put(pdata + i * 12, &begin.to_le_bytes());
+        put(pdata + i * 12 + 4, &end.to_le_bytes());
+    }
+    image
+}
+
+#[test]
+fn write_many_source_files() -> Result<()> {
+    let mut builder = PdbBuilder::default();
+    for name in ["a.obj", "b.obj"] {
+        let mut module = ModuleBuilder::new(
+            name.to_owned(),
+            name.to_owned(),
+            SectionContrib::new(1, 0, 0, 0x6000_0020, 0),
+        );
+        for i in 0..40_000 {
+            module.add_source_file(format!("{i}.h"));
+        }
+        builder.dbi().add_module(module);
+    }
+
+    let mut pdb = reopen(builder)?;
+    let dbi = pdb.get_dbi()?;
+    assert_eq!(dbi.file_info().num_source_files, (80_000 % 0x10000) as u16);
+    assert_eq!(dbi.file_info().file_name_offsets.len(), 80_000);
+    assert_eq!(dbi.file_info().module_indicies, vec![0, 40_000]);
+
+    Ok(())
+}
+
+#[test]
+fn read_annotations() -> Result<()> {
+    let mut builder = write_dummy_builder()?;
+    let mut module = ModuleBuilder::new(
+        "annotated.obj".to_owned(),
+        "annotated.obj".to_owned(),
+        SectionContrib::new(1, 0x100, 0x10, 0x6000_0020, 1),
+    );
+    module.add_procedure(
+        Procedure {
+            parent: None,
+            end: SymbolOffset::from(0),
+            next: None,
+            code_size: 0x10,
+            dbg_start_offset: 0,
+            dbg_end_offset: 0x10,
+            function_type: BuiltinType::NotTranslated.into(),
+            code_offset: DataRegionOffset::new(0x100, 1),
+            properties: ProcedureProperties::new(),
+            name: StrBuf::new("annotated"),
+        },
+        true,
+    );
+    module.add_symbol(SymbolRecord::Annotation {
+        code_offset: DataRegionOffset::new(0x104, 1),
+        strings: vec![StrBuf::new("trace"), StrBuf::new("level=2")],
+    });
+    builder.dbi().add_module(module);
+
+    let mut pdb = reopen(builder)?;
+    let dbi = pdb.get_dbi()?;
+    let module = pdb.get_module(&dbi.modules()[1])?;
+    let annotations = module.annotations();
+    assert_eq!(annotations.len(), 1);
+    assert_eq!(annotations[0].function, Some("annotated"));
+    assert_eq!(annotations[0].strings, vec!["trace", "level=2"]);
+
+    Ok(())
+}
+
+#[cfg(feature = "ffi")]
+#[test]
+fn query_through_c_abi() -> Result<()> {
+    use std::ffi::{c_void, CStr};
+
+    use pdb_sdk::ffi::*;
+
+    extern "C" fn count_modules(ctx: *mut c_void, _index: u32, _name: *const u8, _len: usize) -> bool {
+        unsafe { *(ctx as *mut u32) += 1 };
+        true
+    }
+    extern "C" fn first_public(ctx: *mut c_void, symbol: *const PdbSymbol) -> bool {
+        unsafe { *(ctx as *mut PdbSymbol) = *symbol };
+        false
+    }
+    let name = |symbol: &PdbSymbol| unsafe {
+        std::str::from_utf8(std::slice::from_raw_parts(symbol.name, symbol.name_len)).unwrap()
+    };
+
+    let data = std::fs::read("tests/llvm.pdb")?;
+    let pdb = Pdb::load_with_options(io::Cursor::new(data.clone()), ParseOptions::lenient())?;
+    let expected = pdb
+        .module(1)?
+        .symbols()
+        .iter()
+        .find_map(|sym| match sym {
+            SymbolRecord::GlobalProc(proc) | SymbolRecord::Proc(proc) => Some(proc.name.as_ref()),
+            _ => None,
+        })
+        .unwrap();
+    let function = pdb.function_by_name(expected)?.unwrap();
+
+    unsafe {
+        let handle = pdb_open_bytes(data.as_ptr(), data.len());
+        assert!(!handle.is_null());
+
+        let mut guid = [0u8; 16];
+        let mut age = 0;
+        assert_eq!(pdb_guid(handle, guid.as_mut_ptr(), &mut age), PdbStatus::Found);
+        assert_eq!(&guid, pdb.info()?.header().guid.bytes());
+        assert_eq!(age, pdb.info()?.header().age);
+
+        let mut symbol = std::mem::zeroed::<PdbSymbol>();
+        let c_name = std::ffi::CString::new(expected).unwrap();
+        assert_eq!(
+            pdb_function_by_name(handle, c_name.as_ptr(), &mut symbol),
+            PdbStatus::Found
+        );
+        assert_eq!(name(&symbol), expected);
+        assert_eq!(
+            (symbol.rva, symbol.module),
+            (function.rva, function.module as u32)
+        );
+        assert_eq!(
+            pdb_function_at(handle, function.rva + 1, &mut symbol),
+            PdbStatus::Found
+        );
+        assert_eq!(name(&symbol), expected);
+        assert_eq!(pdb_public_at(handle, function.rva, &mut symbol), PdbStatus::Found);
+        assert_eq!(symbol.module, u32::MAX);
+
+        let mut modules = 0u32;
+        assert_eq!(
+            pdb_enumerate_modules(handle, count_modules, &mut modules as *mut _ as *mut c_void),
+            PdbStatus::Found
+        );
+        assert_eq!(modules as usize, pdb.dbi()?.modules().len());
+        let mut public = std::mem::zeroed::<PdbSymbol>();
+        pdb_enumerate_publics(handle, first_public, &mut public as *mut _ as *mut c_void);
+        assert!(public.name_len > 0);
+
+        assert_eq!(
+            pdb_function_at(std::ptr::null(), 0, &mut symbol),
+            PdbStatus::Failed
+        );
+        assert!(!CStr::from_ptr(pdb_last_error()).to_bytes().is_empty());
+        pdb_close(handle);
+
+        assert!(pdb_open_bytes([0u8; 16].as_ptr(), 16).is_null());
+        assert!(!pdb_last_error().is_null());
+    }
+    Ok(())
+}
+
+#[test]
+fn read_from_byte_slice() -> Result<()> {
+    let bytes = std::fs::read("tests/llvm.pdb")?;
+    let mut file = PdbFile::open(File::open("tests/llvm.pdb")?)?;
+    let dbi = file.get_dbi()?;
+
+    let pdb = Pdb::from_bytes_with_options(&bytes, ParseOptions::lenient())?;
+    assert_eq!(pdb.info()?.header().age, file.get_info()?.header().age);
+    assert_eq!(pdb.dbi()?.modules().len(), dbi.modules().len());
+    assert_eq!(
+        pdb.symbols()?.records().len(),
+        file.get_symbols(&dbi)?.records().len()
+    );
+    assert_matches!(PdbFile::from_bytes(&bytes[..0x100]), Err(_));
+    Ok(())
+}
+
+#[test]
+fn compare_records_structurally() -> Result<()> {
+    let mut pdb = PdbFile::open_with_options(File::open("tests/llvm.pdb")?, ParseOptions::lenient())?;
+    let mut copy = reopen(PdbBuilder::from_pdb(&mut pdb)?)?;
+
+    assert_eq!(copy.get_info()?.header(), pdb.get_info()?.header());
+    assert_eq!(copy.get_ipi()?.records(), pdb.get_ipi()?.records());
+    let (dbi, copy_dbi) = (pdb.get_dbi()?, copy.get_dbi()?);
+    assert_eq!(copy_dbi.section_contribs(), dbi.section_contribs());
+
+    let mut again = PdbFile::open(File::open("tests/llvm.pdb")?)?;
+    let (tpi, again_tpi) = (pdb.get_tpi()?, again.get_tpi()?);
+    assert_eq!(again_tpi.records(), tpi.records());
+    assert_ne!(tpi.records().first(), tpi.records().last());
+    Ok(())
+}
+
+#[test]
+fn clone_records_and_builders() -> Result<()> {
+    let mut pdb = PdbFile::open(File::open("tests/llvm.pdb")?)?;
+    let dbi = pdb.get_dbi()?;
+    let symbols = pdb.get_symbols(&dbi)?;
+    let public = symbols
+        .records()
+        .iter()
+        .find_map(|record| match record {
+            SymbolRecord::Public32(public) => Some(public),
+            _ => None,
+        })
+        .unwrap();
+    let mut renamed = public.clone();
+    renamed.name = StrBuf::new("renamed");
+    assert_eq!(renamed.offset, public.offset);
+    assert_ne!(&renamed, public);
+
+    let mut builder = write_dummy_builder()?;
+    let mut module = ModuleBuilder::new(
+        "first.obj".to_owned(),
+        "first.obj".to_owned(),
+        SectionContrib::new(1, 0x100, 0x10, 0x6000_0020, 1),
+    );
+    module.add_symbol(SymbolRecord::Public32(renamed));
+    let mut copy = module.clone();
+    copy.add_symbol(SymbolRecord::Public32(public.clone()));
+    builder.dbi().add_module(module);
+    builder.dbi().add_module(copy);
+
+    let mut pdb = reopen(builder)?;
+    let dbi = pdb.get_dbi()?;
+    let first = pdb.get_module(&dbi.modules()[1])?;
+    let second = pdb.get_module(&dbi.modules()[2])?;
+    assert_eq!(first.symbols().len(), 1);
+    assert_eq!(second.symbols().len(), 2);
+    assert_eq!(first.symbols()[0], second.symbols()[0]);
+    Ok(())
+}
+
+#[test]
+fn add_records_by_reference() -> Result<()> {
+    let constant = SymbolRecord::Constant(Constant {
+        constant_type: BuiltinType::I32.into(),
+        value: Integer::I32(3),
+        name: StrBuf::new("shared"),
+    });
+    let pointer = TypeRecord::Pointer {
+        referent: BuiltinType::I32.into(),
+        properties: PointerProperties::new().with_kind(PointerKind::Near64),
+        containing_class: None,
+    };
+
+    let mut builder = write_dummy_builder()?;
+    let first = builder.tpi().add("", &pointer);
+    let second = builder.tpi().add("", pointer.clone());
+    assert_eq!(u32::from(second), u32::from(first) + 1);
+    let mut module = ModuleBuilder::new(
+        "shared.obj".to_owned(),
+        "shared.obj".to_owned(),
+        SectionContrib::new(1, 0x100, 0x10, 0x6000_0020, 1),
+    );
+    module.add_symbol(&constant);
+    let globals = builder.dbi().symbols().finish_publics();
+    let offset = globals.add_shared(&constant);
+    assert_eq!(globals.add_shared(constant.clone()), offset);
+    builder.dbi().add_module(module);
+
+    let mut pdb = reopen(builder)?;
+    let tpi = pdb.get_tpi()?;
+    assert_eq!(tpi.record(first), Some(&pointer));
+    assert_eq!(tpi.record(second), Some(&pointer));
+    let dbi = pdb.get_dbi()?;
+    assert_eq!(pdb.get_symbols(&dbi)?.get(offset), Some(&constant));
+    let module = pdb.get_module(&dbi.modules()[1])?;
+    assert_eq!(module.symbols().first(), Some(&constant));
+    Ok(())
+}
+
+#[test]
+fn share_globals_on_request() -> Result<()> {
+    let constant = SymbolRecord::Constant(Constant {
+        constant_type: BuiltinType::I32.into(),
+        value: Integer::I32(3),
+        name: StrBuf::new("shared"),
+    });
+    let mut builder = PdbBuilder::default();
+    let globals = builder.dbi().symbols().finish_publics();
+    let first = globals.add(&constant);
+    let second = globals.add(&constant);
+    assert_ne!(first, second);
+    let shared = globals.add_shared(&constant);
+    assert_eq!(globals.add_shared(&constant), shared);
+    assert_ne!(shared, second);
+
+    let mut pdb = reopen(builder)?;
+    let dbi = pdb.get_dbi()?;
+    assert_eq!(pdb.get_symbols(&dbi)?.records().len(), 3);
+    let globals = pdb.get_globals(&dbi)?;
+    assert_eq!(globals.ref_count(first), Some(1));
+    assert_eq!(globals.ref_count(second), Some(1));
+    assert_eq!(globals.ref_count(shared), Some(2));
+    Ok(())
+}
+
+#[test]
+fn share_duplicate_source_files() -> Result<()> {
+    let mut builder = write_dummy_builder()?;
+    for name in ["first.obj", "second.obj"] {
+        let contrib = SectionContrib::new(1, 0x100, 0x10, 0x6000_0020, 1);
+        let mut module = ModuleBuilder::new(name.to_owned(), name.to_owned(), contrib);
+        module.add_source_file("C:\\include\\xstring".to_owned());
+        builder.dbi().add_module(module);
+    }
+    let modules = builder.dbi().modules();
+    let files: Vec<&str> = modules[1..]
+        .iter()
+        .flat_map(ModuleBuilder::source_files)
+        .collect();
+    assert_eq!(files, ["C:\\include\\xstring"; 2]);
+    assert!(std::ptr::eq(files[0], files[1]));
+
+    let mut pdb = reopen(builder)?;
+    let dbi = pdb.get_dbi()?;
+    assert_eq!(dbi.module_source_files()[1..], [
+        vec!["C:\\include\\xstring"],
+        vec!["C:\\include\\xstring"]
+    ]);
+    Ok(())
+}
+
+#[test]
+fn keep_type_indices_past_corrupted_records() -> Result<()> {
+    let udt = |name: &str| {
+        TypeRecord::Struct(StructRecord {
+            member_count: 0,
+            properties: ClassProperties::new().with_is_forward_ref(true),
+            field_list: None,
+            derivation_list: None,
+            vtable_shape: None,
+            size: Integer::U16(0),
+            name: StrBuf::new(name),
+            unique_name: StrBuf::default(),
+        })
+    };
+    let mut builder = PdbBuilder::default();
+    let first = builder.tpi().add("", udt("First"));
+    let broken = builder.tpi().add("", udt("Broken"));
+    let last = builder.tpi().add("", udt("Last"));
+    let mut output = io::Cursor::new(vec![]);
+    builder.commit(&mut output)?;
+
+    // the kind is followed by the counts, properties, indices and size that precede the name
+    let mut data = output.into_inner();
+    let kind_pos = data.windows(7).position(|w| w == b"Broken\0").unwrap() - 20;
+    assert_eq!(data[kind_pos..kind_pos + 2], 0x1505u16.to_le_bytes());
+    data[kind_pos..kind_pos + 2].copy_from_slice(&[0xFF, 0xFF]);
+
+    let mut pdb = PdbFile::open_with_options(io::Cursor::new(&data), ParseOptions::lenient())?;
+    let tpi = pdb.get_tpi()?;
+    assert_eq!(pdb.diagnostics().len(), 1);
+    assert_eq!(tpi.records().len(), 2);
+    assert_eq!(tpi.record_count(), 3);
+    assert_eq!(tpi.record(first), Some(&udt("First")));
+    assert_eq!(tpi.record(broken), None);
+    assert_eq!(tpi.record(last), Some(&udt("Last")));
+    let indices: Vec<_> = tpi.iter().map(|(index, _)| index).collect();
+    assert_eq!(indices, [u32::from(first), u32::from(last)]);
+    assert_matches!(tpi.find(|name| name == "Last")[..], [(index, _)] if index == last);
+    Ok(())
+}
+
+#[test]
+fn report_offsets_within_streams() -> Result<()> {
+    let mut builder = PdbBuilder::default();
+    for name in ["First", "Broken", "Last"] {
+        builder.tpi().add(
+            "",
+            TypeRecord::Struct(StructRecord {
+                member_count: 0,
+                properties: ClassProperties::new().with_is_forward_ref(true),
+                field_list: None,
+                derivation_list: None,
+                vtable_shape: None,
+                size: Integer::U16(0),
+                name: StrBuf::new(name),
+                unique_name: StrBuf::default(),
+            }),
+        );
+    }
+    let mut output = io::Cursor::new(vec![]);
+    builder.commit(&mut output)?;
+    let mut data = output.into_inner();
+    let kind_pos = data.windows(7).position(|w| w == b"Broken\0").unwrap() - 20;
+    data[kind_pos..kind_pos + 2].copy_from_slice(&[0xFF, 0xFF]);
+
+    // the offset of the length prefix of the record within the TPI stream, past the header
+    let mut pdb = PdbFile::open(io::Cursor::new(&data))?;
+    let tpi = pdb.get_stream_data(2.into())?;
+    let expected = tpi.windows(7).position(|w| w == b"Broken\0").unwrap() as u64 - 22;
+    assert!(expected > 56);
+    assert_matches!(
+        pdb.get_tpi(),
+        Err(Error::StreamFailed { stream: "type records", index: Some(index), offset, .. })
+            if u16::from(index) == 2 && offset == expected
+    );
+    let mut pdb = PdbFile::open_with_options(io::Cursor::new(&data), ParseOptions::lenient())?;
+    pdb.get_tpi()?;
+    assert_eq!(pdb.diagnostics()[0].offset, expected);
+
+    // module symbols are counted from the start of the module stream, like the offsets of the iterator
+    let mut pdb = PdbFile::open(File::open("tests/llvm.pdb")?)?;
+    let dbi = pdb.get_dbi()?;
+    let module = dbi.modules().last().unwrap();
+    let failed = pdb.iter_module_symbols(module)?.find_map(Result::err);
+    let Some(Error::StreamFailed { offset: expected, .. }) = failed else {
+        panic!("expected a failed module symbol");
+    };
+    assert_matches!(
+        pdb.get_module(module),
+        Err(Error::StreamFailed { stream: "module symbols", index: Some(index), offset, .. })
+            if index == module.header.debug_info_stream && offset == expected
+    );
+    Ok(())
+}
+
+#[test]
+fn read_legacy_type_streams() -> Result<()> {
+    // two records with bodies that aren't decoded, a length prefix followed by a kind and four bytes
+    let records = [6, 0, 1, 0, 1, 2, 3, 4, 6, 0, 2, 0, 5, 6, 7, 8];
+    // the 16-bit indices of V40 and V41, then the layout shared with V80 with an empty hash layout
+    let short = vec![0x00, 0x10, 0x02, 0x10, 16, 0, 0, 0, 3, 0, 0, 0];
+    let mut long = vec![
+        56, 0, 0, 0, 0, 0x10, 0, 0, 0x02, 0x10, 0, 0, 16, 0, 0, 0, 3, 0, 0xFF, 0xFF,
+    ];
+    long.resize(52, 0);
+    let headers = [
+        (19950410u32, &short),
+        (19951122, &short),
+        (19961031, &long),
+        (19990903, &long),
+    ];
+    for (version, header) in headers {
+        let mut data = version.to_le_bytes().to_vec();
+        data.extend_from_slice(header);
+        data.extend_from_slice(&records);
+
+        let mut pdb = PdbFile::open(write_dummy()?)?;
+        pdb.replace_stream(2.into(), &data)?;
+        let mut output = pdb.into_inner();
+        output.set_position(0);
+        let mut pdb = PdbFile::open(output)?;
+
+        let tpi = pdb.get_raw_tpi()?;
+        assert_eq!(u32::from(tpi.header().type_index_begin), 0x1000);
+        assert_eq!(u32::from(tpi.header().type_index_end), 0x1002);
+        let kinds: Vec<_> = tpi.records().iter().map(|record| record.kind).collect();
+        assert_eq!(kinds, [1, 2]);
+        assert_eq!(tpi.records()[1].data, [5, 6, 7, 8]);
+        // the hashes of these versions can't be read, so the hash stream is left out
+        assert_eq!(tpi.header().hash_stream_index, None);
+        assert_matches!(pdb.get_tpi_hash(&tpi), Err(Error::StreamNotFound(_)));
+    }
+    Ok(())
+}
+
+#[test]
+fn read_raw_type_records() -> Result<()> {
+    let mut pdb = PdbFile::open(File::open("tests/llvm.pdb")?)?;
+    let tpi = pdb.get_tpi()?;
+    let raw_tpi = pdb.get_raw_tpi()?;
+    assert_eq!(raw_tpi.records().len(), tpi.records().len());
+    Ok(())
+}
+
+#[test]
+fn read_legacy_info_streams() -> Result<()> {
+    let mut pdb = PdbFile::open(write_dummy()?)?;
+    let current = pdb.get_stream_data(1.into())?;
+    // the name map follows the version, signature, age and GUID, the feature list ends the stream
+    let named_streams = &current[28..current.len() - 4];
+
+    // VC 2 and 4 have no name map, the later versions up to the GUID keep it
+    let versions = [
+        (PdbVersion::Vc2, false),
+        (PdbVersion::Vc4, false),
+        (PdbVersion::Vc98, true),
+        (PdbVersion::Vc70Dep, true),
+    ];
+    for (version, has_names) in versions {
+        let mut data = vec![];
+        for value in [version as u32, 0x1234_5678, 3] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        if has_names {
+            data.extend_from_slice(named_streams);
+        }
+
+        let mut pdb = PdbFile::open(write_dummy()?)?;
+        pdb.replace_stream(1.into(), &data)?;
+        let mut output = pdb.into_inner();
+        output.set_position(0);
+        let mut pdb = PdbFile::open(output)?;
+        let info = pdb.get_info()?;
+        assert_eq!(info.header().version, version);
+        assert_eq!((info.header().signature, info.header().age), (0x1234_5678, 3));
+        assert_eq!(info.header().guid, Guid::default());
+        assert!(info.features().is_empty());
+        assert_eq!(info.named_streams().get("/TMCache").is_some(), has_names);
+        if has_names {
+            assert_eq!(pdb.get_named_stream(&info, "/TMCache")?, [1, 0, 0, 0, 7, 0, 0, 0]);
+        }
+        // there is no room for a GUID in the header
+        let patch = InfoPatch {
+            guid: Some(Guid::new([0xAB; 16])),
+            ..Default::default()
+        };
+        assert_matches!(pdb.patch_info(patch), Err(Error::UnsupportedFeature(_)));
+    }
+    Ok(())
+}
+
+#[test]
+fn budget_dbi_substreams() -> Result<()> {
+    let mut pdb = PdbFile::open(write_dummy()?)?;
+    let mut data = pdb.get_stream_data(3.into())?;
+    assert!(DbiStream::read(&data[..]).is_ok());
+
+    // the type server map and the debug stream list, each within the budget but not together
+    data[40..44].copy_from_slice(&(3u32 << 30).to_le_bytes());
+    data[48..52].copy_from_slice(&(3u32 << 30).to_le_bytes());
+    assert_matches!(DbiStream::read(&data[..]), Err(Error::LimitExceeded(_)));
+    Ok(())
+}
+
+// a DBI stream of an old toolset with one module, its contribution is stored in the SC40 layout
+// before V60 and with the CRCs from then on
+fn legacy_dbi(version: u32, sc40: bool) -> Vec<u8> {
+    let contrib = |out: &mut Vec<u8>, sc40: bool| {
+        out.extend_from_slice(&1u16.to_le_bytes());
+        out.extend_from_slice(&[0; 2]);
+        out.extend_from_slice(&0x10i32.to_le_bytes());
+        out.extend_from_slice(&0x20u32.to_le_bytes());
+        out.extend_from_slice(&0x6000_0020u32.to_le_bytes());
+        out.extend_from_slice(&0u16.to_le_bytes());
+        out.extend_from_slice(&[0; 2]);
+        if !sc40 {
+            out.extend_from_slice(&[0; 8]);
+        }
+    };
+    let mut modules = vec![];
+    modules.extend_from_slice(&0u32.to_le_bytes());
+    contrib(&mut modules, sc40);
+    // the flags, the type server, a missing stream, the layout, the file count and its offset
+    modules.extend_from_slice(&[0, 0, 0xFF, 0xFF]);
+    modules.extend_from_slice(&[0; 20]);
+    modules.extend_from_slice(b"main.obj\0main.obj\0");
+    modules.resize(modules.len().next_multiple_of(4), 0);
+
+    let mut contribs = vec![];
+    if !sc40 {
+        contribs.extend_from_slice(&(0xeffe_0000u32 + 19970605).to_le_bytes());
+    }
+    contrib(&mut contribs, sc40);
+    let section_map = [0u8; 4];
+    let file_info = [1, 0, 0, 0, 0, 0, 0, 0];
+
+    let mut data = vec![];
+    data.extend_from_slice(&(-1i32).to_le_bytes());
+    data.extend_from_slice(&version.to_le_bytes());
+    data.extend_from_slice(&1u32.to_le_bytes());
+    data.extend_from_slice(&[0xFF, 0xFF, 0, 0, 0xFF, 0xFF, 0, 0, 0xFF, 0xFF, 0, 0]);
+    for size in [
+        modules.len(),
+        contribs.len(),
+        section_map.len(),
+        file_info.len(),
+        0,
+        0,
+        0,
+        0,
+    ] {
+        data.extend_from_slice(&(size as u32).to_le_bytes());
+    }
+    data.extend_from_slice(&[0; 8]);
+    data.extend_from_slice(&modules);
+    data.extend_from_slice(&contribs);
+    data.extend_from_slice(&section_map);
+    data.extend_from_slice(&file_info);
+    data
+}
+
+#[test]
+fn read_legacy_section_contribs() -> Result<()> {
+    for (version, sc40) in [(19960307, true), (19970606, false)] {
+        let dbi = DbiStream::read(&legacy_dbi(version, sc40)[..])?;
+        assert_eq!(dbi.section_contrib_version().is_none(), sc40);
+        assert_eq!(dbi.section_contribs().len(), 1);
+        let contrib = &dbi.section_contribs()[0];
+        assert_eq!((contrib.i_sect, contrib.offset, contrib.size), (1, 0x10, 0x20));
+        assert_eq!(dbi.modules().len(), 1);
+        assert_eq!(&dbi.modules()[0].header.section_contrib, contrib);
+        assert_eq!(dbi.modules()[0].obj_file_name.as_ref(), "main.obj");
+    }
+    Ok(())
+}
+
+#[test]
+fn reject_unversioned_section_contribs() -> Result<()> {
+    let mut pdb = PdbFile::open(File::open("tests/llvm.pdb")?)?;
+    let mut data = pdb.get_stream_data(3.into())?;
+    let dbi = DbiStream::read(&data[..])?;
+    assert!(!dbi.section_contribs().is_empty());
+
+    // a V70 substream has to start with the version of its entries
+    let start = 64 + dbi.header().modi_stream_size as usize;
+    data[start..start + 4].copy_from_slice(&[0; 4]);
+    assert!(DbiStream::read(&data[..]).is_err());
+    Ok(())
+}
+
+#[test]
+fn decode_lines_with_a_bogus_count() -> Result<()> {
+    let mut data = vec![];
+    // the fragment header, the entry header claiming u32::MAX lines and the two lines that follow
+    data.extend_from_slice(&[0; 12]);
+    for value in [0, u32::MAX, 0x10, 0, 1, 8, 2] {
+        data.extend_from_slice(&u32::to_le_bytes(value));
+    }
+    let entry = DebugSubsectionEntry {
+        record_type: DebugSubsectionRecordType::Lines,
+        data,
+    };
+    assert!(entry.decoded().is_err());
+    Ok(())
+}
+
+#[test]
+fn decode_enum_records() -> Result<()> {
+    let mut pdb = PdbFile::open(File::open("tests/llvm.pdb")?)?;
+    let tpi = pdb.get_tpi()?;
+    let raw = pdb.get_raw_tpi()?;
+    let index = TypeIndex::try_from(0x1030).unwrap();
+    let Some(TypeRecord::Enum(enum_)) = tpi.record(index) else {
+        panic!("expected an enum at {index:?}");
+    };
+    assert_eq!(enum_.member_count, 1);
+    assert_eq!(u32::from(enum_.underlying_type), 0x23);
+    assert_eq!(u32::from(enum_.field_list), 0x102F);
+    assert!(enum_.name.as_ref().ends_with("::Discriminant$"));
+
+    // LF_ENUM has no size field, the name follows the field list right away
+    let mut encoded = vec![];
+    TypeRecord::Enum(enum_.clone()).encode((), &mut encoded)?;
+    assert_eq!(encoded[2..], raw.records()[0x30].data[..]);
+    Ok(())
+}
+
+#[test]
+fn reject_unknown_numeric_leaves() {
+    // LF_REAL32, LF_VARSTRING and a value past the known leaves
+    for leaf in [0x8005u16, 0x8010, 0xFFFF] {
+        let mut bytes = leaf.to_le_bytes().to_vec();
+        bytes.extend([0; 8]);
+        assert_matches!(Integer::decode((), &mut bytes.as_slice()), Err(_));
+    }
+    let long = [0x03, 0x80, 2, 0, 0, 0];
+    assert_matches!(Integer::decode((), &mut long.as_slice()), Ok(Integer::I32(2)));
 }